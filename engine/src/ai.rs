@@ -1,5 +1,7 @@
 use kurbo::{ BezPath, Affine, Shape };
 use crate::{VectorObject, ShapeType};
+use crate::pdf_text::{fallback_text_ops, PdfFonts};
+use crate::text::FontRegistry;
 use std::collections::HashMap;
 
 pub struct Ai {
@@ -53,7 +55,7 @@ impl PdfWriter {
 }
 
 impl Ai {
-    pub fn export(width: f64, height: f64, objects: &[VectorObject]) -> Vec<u8> {
+    pub fn export(width: f64, height: f64, objects: &[VectorObject], fonts: &FontRegistry) -> Vec<u8> {
         let mut writer = PdfWriter::new();
         
         let _catalog_id = writer.start_obj();
@@ -113,10 +115,31 @@ impl Ai {
             image_map.insert(obj_id, img_id);
         }
 
+        // Text is laid out first so the fonts hold every glyph it uses
+        let mut pdf_fonts = PdfFonts::default();
+        let mut text_ops: HashMap<u32, Vec<u8>> = HashMap::new();
+        fn collect_text<'a>(objs: &[VectorObject], fonts: &'a FontRegistry, pdf_fonts: &mut PdfFonts<'a>, text_ops: &mut HashMap<u32, Vec<u8>>, height: f64) {
+            for obj in objs {
+                if obj.shape_type == ShapeType::Text {
+                    if let Some(ops) = pdf_fonts.text_ops(obj, fonts, height) { text_ops.insert(obj.id, ops); }
+                }
+                if let Some(children) = &obj.children {
+                    collect_text(children, fonts, pdf_fonts, text_ops, height);
+                }
+            }
+        }
+        collect_text(objects, fonts, &mut pdf_fonts, &mut text_ops, height);
+        let fonts_id = writer.offsets.len() + 1;
+        for font_obj in pdf_fonts.objects(fonts_id) {
+            writer.start_obj();
+            writer.write_raw(&font_obj);
+            writer.end_obj();
+        }
+
         let _page_id = writer.start_obj();
         let mut page_dict = format!(
-            "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {} {}] /Contents {} 0 R /Resources << /Font << /F1 << /Type /Font /Subtype /Type1 /BaseFont /Helvetica >> >>",
-            pages_id, width, height, writer.offsets.len() + 1
+            "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {} {}] /Contents {} 0 R /Resources << /Font << /F1 << /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >> /F1B << /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >> {}>>",
+            pages_id, width, height, writer.offsets.len() + 1, pdf_fonts.resources(fonts_id)
         );
         
         if !opacities.is_empty() {
@@ -142,7 +165,7 @@ impl Ai {
         writer.end_obj();
         
         let mut content = Vec::new();
-        fn write_objects(objs: &[VectorObject], content: &mut Vec<u8>, height: f64, opacities: &HashMap<String, f64>, image_map: &HashMap<u32, usize>) {
+        fn write_objects(objs: &[VectorObject], content: &mut Vec<u8>, height: f64, opacities: &HashMap<String, f64>, image_map: &HashMap<u32, usize>, text_ops: &HashMap<u32, Vec<u8>>) {
             for obj in objs {
                 if !obj.visible { continue; }
                 content.extend_from_slice(b"q\n");
@@ -155,7 +178,7 @@ impl Ai {
                 if obj.shape_type == ShapeType::Group {
                     if let Some(children) = &obj.children {
                         content.extend_from_slice(format!("1 0 0 1 {} {} cm\n", obj.x, -obj.y).as_bytes());
-                        write_objects(children, content, height, opacities, image_map);
+                        write_objects(children, content, height, opacities, image_map, text_ops);
                     }
                 } else if obj.shape_type == ShapeType::Image {
                     if image_map.contains_key(&obj.id) {
@@ -164,15 +187,10 @@ impl Ai {
                         content.extend_from_slice(format!("/Im{} Do\n", obj.id).as_bytes());
                     }
                 } else if obj.shape_type == ShapeType::Text {
-                    content.extend_from_slice(b"BT\n");
-                    content.extend_from_slice(format!("/F1 {} Tf\n", obj.font_size).as_bytes());
-                    let cos_r = obj.rotation.cos();
-                    let sin_r = obj.rotation.sin();
-                    content.extend_from_slice(format!("{} {} {} {} {} {} Tm\n", 
-                        cos_r, -sin_r, sin_r, cos_r, obj.x, height - obj.y).as_bytes());
-                    content.extend_from_slice(format!("({})
-", obj.text_content.replace("(", "\\(").replace(")", "\\)")).as_bytes());
-                    content.extend_from_slice(b"ET\n");
+                    match text_ops.get(&obj.id) {
+                        Some(ops) => content.extend_from_slice(ops),
+                        None => content.extend_from_slice(&fallback_text_ops(obj, height)),
+                    }
                 } else {
                     let fill_rgb = self::parse_hex_color(&obj.fill);
                     let stroke_rgb = self::parse_hex_color(&obj.stroke);
@@ -180,6 +198,11 @@ impl Ai {
                     content.extend_from_slice(format!("{} {} {} rg\n", fill_rgb.0 as f32 / 255.0, fill_rgb.1 as f32 / 255.0, fill_rgb.2 as f32 / 255.0).as_bytes());
                     content.extend_from_slice(format!("{} {} {} RG\n", stroke_rgb.0 as f32 / 255.0, stroke_rgb.1 as f32 / 255.0, stroke_rgb.2 as f32 / 255.0).as_bytes());
                     content.extend_from_slice(format!("{} w\n", obj.stroke_width).as_bytes());
+                    let cap = match obj.stroke_cap.as_str() { "round" => 1, "square" => 2, _ => 0 };
+                    let join = match obj.stroke_join.as_str() { "round" => 1, "bevel" => 2, _ => 0 };
+                    content.extend_from_slice(format!("{} J\n{} j\n", cap, join).as_bytes());
+                    let dash = obj.stroke_dash.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(" ");
+                    content.extend_from_slice(format!("[{}] {} d\n", dash, obj.stroke_dash_offset).as_bytes());
                     
                     let path_data = match obj.shape_type {
                         ShapeType::Rectangle => {
@@ -266,10 +289,11 @@ impl Ai {
                             }
                         }
                         
+                        let even_odd = obj.fill_rule == "evenodd";
                         let op = match (obj.fill.as_str() != "transparent" && !obj.fill.is_empty(), 
                                         obj.stroke.as_str() != "transparent" && obj.stroke_width > 0.0) {
-                            (true, true) => "B",
-                            (true, false) => "f",
+                            (true, true) => if even_odd { "B*" } else { "B" },
+                            (true, false) => if even_odd { "f*" } else { "f" },
                             (false, true) => "S",
                             (false, false) => "n",
                         };
@@ -279,7 +303,7 @@ impl Ai {
                 content.extend_from_slice(b"Q\n");
             }
         }
-        write_objects(objects, &mut content, height, &opacities, &image_map);
+        write_objects(objects, &mut content, height, &opacities, &image_map, &text_ops);
 
         let _content_id = writer.start_obj();
         writer.write_raw(format!("<< /Length {} >>\nstream\n", content.len()).as_bytes());
@@ -291,7 +315,7 @@ impl Ai {
     }
 }
 
-fn parse_hex_color(hex: &str) -> (u8, u8, u8) {
+pub(crate) fn parse_hex_color(hex: &str) -> (u8, u8, u8) {
     if hex == "transparent" || hex.is_empty() { return (0, 0, 0); }
     let hex = hex.trim_start_matches('#');
     if hex.len() == 6 {
@@ -779,15 +803,20 @@ impl<'a> AiParser<'a> {
                     }
                 }
                 "d" => {
-                    if i > 0 && tokens[i-1] == "]" {
-                         let mut j = i as i32 - 2;
+                    // `[dash ...] phase d`
+                    let phase = if i > 1 && tokens[i-2] == "]" { tokens[i-1].parse::<f64>().ok() } else { None };
+                    let close = if phase.is_some() { i - 1 } else { i };
+                    if close > 0 && tokens[close-1] == "]" {
+                         let mut j = close as i32 - 2;
                          let mut dash_array = Vec::new();
                          while j >= 0 && tokens[j as usize] != "[" {
                              if let Ok(v) = tokens[j as usize].parse::<f64>() { dash_array.push(v); }
                              j -= 1;
                          }
                          dash_array.reverse();
-                         state_stack.last_mut().unwrap().stroke_dash = dash_array;
+                         let current_state = state_stack.last_mut().unwrap();
+                         current_state.stroke_dash = dash_array;
+                         current_state.stroke_dash_offset = phase.unwrap_or(0.0);
                     }
                 }
                 "gs" => {
@@ -822,7 +851,7 @@ impl<'a> AiParser<'a> {
                                 id: *next_id, shape_type: ShapeType::Image, name: format!("Ai Image {}", name), x: wx, y: wy, width: ww, height: wh, rotation: 0.0, fill: "transparent".to_string(), stroke: "transparent".to_string(), stroke_width: 0.0, opacity: current_state.opacity, visible: true, locked: false, blend_mode: current_state.blend_mode.clone(), stroke_cap: "butt".to_string(), stroke_join: "miter".to_string(), stroke_dash: Vec::new(), layer_style: crate::LayerStyle::default(), mask_id: None, is_mask: false, sides: 0, inner_radius: 0.0, corner_radius: 0.0, path_data: String::new(), 
                                 intelligent_type: String::new(),
                                 intelligent_params: Vec::new(),
-                                brush_id: 0, stroke_points: Vec::new(), text_content: String::new(), font_family: String::new(), font_size: 0.0, font_weight: String::new(), text_align: String::new(), kerning: 0.0, leading: 1.2, tracking: 0.0, shadow_color: "transparent".to_string(), shadow_blur: 0.0, shadow_offset_x: 0.0, shadow_offset_y: 0.0, sx: 0.0, sy: 0.0, sw: ww, sh: wh, brightness: 1.0, contrast: 1.0, saturate: 1.0, hue_rotate: 0.0, blur: 0.0, grayscale: 0.0, sepia: 0.0, invert: 0.0, raw_image: Some(png_bytes), raw_rgba: Some(rgba.clone()), raw_rgba_width: w, raw_rgba_height: h, image: None, fill_gradient: None, stroke_gradient: None, children: None,
+                                brush_id: 0, stroke_points: Vec::new(), text_content: String::new(), font_family: String::new(), font_size: 0.0, font_weight: String::new(), text_align: String::new(), kerning: 0.0, leading: 1.2, tracking: 0.0, shadow_color: "transparent".to_string(), shadow_blur: 0.0, shadow_offset_x: 0.0, shadow_offset_y: 0.0, sx: 0.0, sy: 0.0, sw: ww, sh: wh, brightness: 1.0, contrast: 1.0, saturate: 1.0, hue_rotate: 0.0, blur: 0.0, grayscale: 0.0, sepia: 0.0, invert: 0.0, raw_image: Some(png_bytes), raw_rgba: Some(rgba.clone()), raw_rgba_width: w, raw_rgba_height: h, image: None, fill_gradient: None, fill_rule: "nonzero".to_string(), stroke_gradient: None, children: None, blend: None, envelope: None, warp: None, width_profile: None, start_marker: None, end_marker: None, stroke_dash_offset: 0.0, stroke_dash_align: false, stroke_align: String::new(), appearance: Vec::new(), corners: None, area_text: false, text_path: None, text_runs: Vec::new(), paragraphs: Vec::new(),
                             });
                             *next_id += 1;
                         }
@@ -878,7 +907,7 @@ impl<'a> AiParser<'a> {
                             id: *next_id, shape_type: ShapeType::Text, name: format!("Ai Text {}", *next_id), x: wx, y: wy - text_state.font_size, width: text_content.len() as f64 * (text_state.font_size * 0.6), height: text_state.font_size, rotation: 0.0, fill: text_state.fill.clone(), stroke: text_state.stroke.clone(), stroke_width: text_state.stroke_width, visible: true, locked: false, opacity: text_state.opacity, blend_mode: text_state.blend_mode.clone(), stroke_cap: text_state.stroke_cap.clone(), stroke_join: text_state.stroke_join.clone(), stroke_dash: text_state.stroke_dash.clone(), layer_style: crate::LayerStyle::default(), mask_id: None, is_mask: false, sides: 0, inner_radius: 0.0, corner_radius: 0.0, path_data: String::new(), 
                             intelligent_type: String::new(),
                             intelligent_params: Vec::new(),
-                            brush_id: 0, stroke_points: Vec::new(), text_content, font_family: text_state.font_family.clone(), font_size: text_state.font_size, font_weight: "normal".to_string(), text_align: "left".to_string(), kerning: 0.0, leading: 1.2, tracking: 0.0, shadow_color: "transparent".to_string(), shadow_blur: 0.0, shadow_offset_x: 0.0, shadow_offset_y: 0.0, sx: 0.0, sy: 0.0, sw: 0.0, sh: 0.0, brightness: 1.0, contrast: 1.0, saturate: 1.0, hue_rotate: 0.0, blur: 0.0, grayscale: 0.0, sepia: 0.0, invert: 0.0, raw_image: None, raw_rgba: None, raw_rgba_width: 0, raw_rgba_height: 0, image: None, fill_gradient: None, stroke_gradient: None, children: None,
+                            brush_id: 0, stroke_points: Vec::new(), text_content, font_family: text_state.font_family.clone(), font_size: text_state.font_size, font_weight: "normal".to_string(), text_align: "left".to_string(), kerning: 0.0, leading: 1.2, tracking: 0.0, shadow_color: "transparent".to_string(), shadow_blur: 0.0, shadow_offset_x: 0.0, shadow_offset_y: 0.0, sx: 0.0, sy: 0.0, sw: 0.0, sh: 0.0, brightness: 1.0, contrast: 1.0, saturate: 1.0, hue_rotate: 0.0, blur: 0.0, grayscale: 0.0, sepia: 0.0, invert: 0.0, raw_image: None, raw_rgba: None, raw_rgba_width: 0, raw_rgba_height: 0, image: None, fill_gradient: None, fill_rule: "nonzero".to_string(), stroke_gradient: None, children: None, blend: None, envelope: None, warp: None, width_profile: None, start_marker: None, end_marker: None, stroke_dash_offset: text_state.stroke_dash_offset, stroke_dash_align: false, stroke_align: String::new(), appearance: Vec::new(), corners: None, area_text: false, text_path: None, text_runs: Vec::new(), paragraphs: Vec::new(),
                         });
                         *next_id += 1;
                     }
@@ -924,7 +953,7 @@ impl<'a> AiParser<'a> {
                                             id: *next_id, shape_type: ShapeType::Path, name: format!("Ai Path {}", *next_id), x, y, width: w, height: h, rotation: 0.0, fill: if is_fill { current_state.fill.clone() } else { "transparent".to_string() }, stroke: if is_stroke { current_state.stroke.clone() } else { "transparent".to_string() }, stroke_width: if is_stroke { current_state.stroke_width } else { 0.0 }, visible: true, locked: false, opacity: current_state.opacity, blend_mode: current_state.blend_mode.clone(), stroke_cap: current_state.stroke_cap.clone(), stroke_join: current_state.stroke_join.clone(), stroke_dash: current_state.stroke_dash.clone(), layer_style: crate::LayerStyle::default(), mask_id: None, is_mask: false, sides: 0, inner_radius: 0.0, corner_radius: 0.0, path_data: transformed_path.to_svg(), 
                                             intelligent_type: String::new(),
                                             intelligent_params: Vec::new(),
-                                            brush_id: 0, stroke_points: Vec::new(), text_content: String::new(), font_family: String::new(), font_size: 0.0, font_weight: String::new(), text_align: String::new(), kerning: 0.0, leading: 1.2, tracking: 0.0, shadow_color: "transparent".to_string(), shadow_blur: 0.0, shadow_offset_x: 0.0, shadow_offset_y: 0.0, sx: 0.0, sy: 0.0, sw: 0.0, sh: 0.0, brightness: 1.0, contrast: 1.0, saturate: 1.0, hue_rotate: 0.0, blur: 0.0, grayscale: 0.0, sepia: 0.0, invert: 0.0, raw_image: None, raw_rgba: None, raw_rgba_width: 0, raw_rgba_height: 0, image: None, fill_gradient: None, stroke_gradient: None, children: None,
+                                            brush_id: 0, stroke_points: Vec::new(), text_content: String::new(), font_family: String::new(), font_size: 0.0, font_weight: String::new(), text_align: String::new(), kerning: 0.0, leading: 1.2, tracking: 0.0, shadow_color: "transparent".to_string(), shadow_blur: 0.0, shadow_offset_x: 0.0, shadow_offset_y: 0.0, sx: 0.0, sy: 0.0, sw: 0.0, sh: 0.0, brightness: 1.0, contrast: 1.0, saturate: 1.0, hue_rotate: 0.0, blur: 0.0, grayscale: 0.0, sepia: 0.0, invert: 0.0, raw_image: None, raw_rgba: None, raw_rgba_width: 0, raw_rgba_height: 0, image: None, fill_gradient: None, fill_rule: if token.ends_with('*') { "evenodd" } else { "nonzero" }.to_string(), stroke_gradient: None, children: None, blend: None, envelope: None, warp: None, width_profile: None, start_marker: None, end_marker: None, stroke_dash_offset: current_state.stroke_dash_offset, stroke_dash_align: false, stroke_align: String::new(), appearance: Vec::new(), corners: None, area_text: false, text_path: None, text_runs: Vec::new(), paragraphs: Vec::new(),
                                         });
                                         *next_id += 1;
                                     }
@@ -1021,6 +1050,7 @@ struct GraphicsState {
     stroke_cap: String,
     stroke_join: String,
     stroke_dash: Vec<f64>,
+    stroke_dash_offset: f64,
     font_family: String,
     font_size: f64,
 }
@@ -1037,6 +1067,7 @@ impl Default for GraphicsState {
             stroke_cap: "butt".to_string(),
             stroke_join: "miter".to_string(),
             stroke_dash: Vec::new(),
+            stroke_dash_offset: 0.0,
             font_family: "Inter, sans-serif".to_string(),
             font_size: 12.0,
         }
@@ -0,0 +1,368 @@
+use wasm_bindgen::prelude::*;
+use serde::{Serialize, Deserialize};
+use kurbo::{BezPath, Circle, PathEl, Point, Shape, Vec2};
+use crate::engine::VectorEngine;
+use crate::geometry::shape_path;
+use crate::objects::VectorObject;
+use crate::stroke::{grow, has_stroke, paints};
+use crate::types::{Gradient, ShapeType};
+
+// Appearance stacks: any number of fills and strokes, drawn bottom first.
+// An object with a stack is drawn from it instead of its own fill and stroke,
+// by splitting it into one plain part per layer, so canvas and every exporter
+// see ordinary stacked objects.
+
+const ACCURACY: f64 = 0.1;
+/// Id bit of the parts, with the layer index in the bits below it, so
+/// gradient references stay unique per layer.
+const LAYER_ID_FLAG: u32 = 1 << 28;
+const LAYER_INDEX_SHIFT: u32 = 20;
+const MAX_PATTERN_LINES: i64 = 2_000;
+const MAX_PATTERN_DOTS: i64 = 20_000;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LayerKind {
+    #[default]
+    Fill,
+    Stroke,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PatternKind {
+    Lines,
+    Crosshatch,
+    Dots,
+}
+
+/// A fill of evenly spaced lines or dots in the layer's paint.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Pattern {
+    pub kind: PatternKind,
+    #[serde(default = "default_spacing")]
+    pub spacing: f64,
+    #[serde(default)]
+    pub angle: f64, // Radians
+    #[serde(default = "default_one")]
+    pub weight: f64, // Line width or dot diameter
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AppearanceLayer {
+    #[serde(default)]
+    pub kind: LayerKind,
+    #[serde(default = "default_true")]
+    pub visible: bool,
+    #[serde(default)]
+    pub color: String,
+    #[serde(default)]
+    pub gradient: Option<Gradient>,
+    #[serde(default)]
+    pub pattern: Option<Pattern>, // Fills only
+    #[serde(default = "default_one")]
+    pub opacity: f64, // Times the object's opacity
+    #[serde(default)]
+    pub blend_mode: String, // Empty for the object's own
+    #[serde(default)]
+    pub offset: f64, // Grows the shape, or shrinks it when negative
+    // Strokes, which keep the object's width profile and markers; an empty
+    // cap or join follows the object
+    #[serde(default = "default_one")]
+    pub width: f64,
+    #[serde(default)]
+    pub cap: String,
+    #[serde(default)]
+    pub join: String,
+    #[serde(default)]
+    pub dash: Vec<f64>,
+    #[serde(default)]
+    pub dash_offset: f64,
+    #[serde(default)]
+    pub dash_align: bool,
+    #[serde(default)]
+    pub align: String,
+}
+
+fn default_spacing() -> f64 {
+    8.0
+}
+
+fn default_one() -> f64 {
+    1.0
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl AppearanceLayer {
+    /// The layers matching the object's own fill and stroke.
+    fn from_object(obj: &VectorObject) -> Vec<Self> {
+        let mut layers = Vec::new();
+        let base = AppearanceLayer {
+            kind: LayerKind::Fill, visible: true, color: String::new(), gradient: None, pattern: None,
+            opacity: 1.0, blend_mode: String::new(), offset: 0.0, width: 1.0, cap: String::new(), join: String::new(),
+            dash: Vec::new(), dash_offset: 0.0, dash_align: false, align: String::new(),
+        };
+        if paints(&obj.fill) || obj.fill_gradient.is_some() {
+            layers.push(AppearanceLayer { color: obj.fill.clone(), gradient: obj.fill_gradient.clone(), ..base.clone() });
+        }
+        if has_stroke(obj) {
+            layers.push(AppearanceLayer {
+                kind: LayerKind::Stroke, color: obj.stroke.clone(), gradient: obj.stroke_gradient.clone(),
+                width: obj.stroke_width, cap: obj.stroke_cap.clone(), join: obj.stroke_join.clone(),
+                dash: obj.stroke_dash.clone(), dash_offset: obj.stroke_dash_offset, dash_align: obj.stroke_dash_align,
+                align: obj.stroke_align.clone(), ..base
+            });
+        }
+        layers
+    }
+}
+
+/// The shape flattened into closed polygons.
+fn polygons(path: &BezPath) -> Vec<Vec<Point>> {
+    let mut polys: Vec<Vec<Point>> = Vec::new();
+    kurbo::flatten(path, ACCURACY, |el| match el {
+        PathEl::MoveTo(p) => polys.push(vec![p]),
+        PathEl::LineTo(p) => { if let Some(poly) = polys.last_mut() { poly.push(p); } }
+        _ => {}
+    });
+    polys.retain(|p| p.len() > 2);
+    polys
+}
+
+fn edges(polys: &[Vec<Point>]) -> impl Iterator<Item = (Point, Point)> + '_ {
+    polys.iter().flat_map(|p| (0..p.len()).map(move |i| (p[i], p[(i + 1) % p.len()])))
+}
+
+fn filled(winding: i32, even_odd: bool) -> bool {
+    if even_odd { winding % 2 != 0 } else { winding != 0 }
+}
+
+/// Lowest and highest projection of the shape onto `axis`.
+fn extent(polys: &[Vec<Point>], axis: Vec2) -> (f64, f64) {
+    polys.iter().flatten().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), p| {
+        let d = axis.dot(p.to_vec2());
+        (lo.min(d), hi.max(d))
+    })
+}
+
+/// Lines along `angle` every `spacing`, cut to the inside of the shape.
+fn hatch(polys: &[Vec<Point>], angle: f64, spacing: f64, even_odd: bool, out: &mut BezPath) {
+    let d = Vec2::new(angle.cos(), angle.sin());
+    let n = Vec2::new(-d.y, d.x);
+    let (lo, hi) = extent(polys, n);
+    let (first, last) = ((lo / spacing).ceil() as i64, (hi / spacing).floor() as i64);
+    if last - first > MAX_PATTERN_LINES { return; }
+    for k in first..=last {
+        let c = k as f64 * spacing;
+        let mut hits: Vec<(f64, i32)> = edges(polys).filter_map(|(a, b)| {
+            let (ea, eb) = (n.dot(a.to_vec2()) - c, n.dot(b.to_vec2()) - c);
+            if (ea < 0.0) == (eb < 0.0) { return None; }
+            let p = a + (b - a) * (ea / (ea - eb));
+            Some((d.dot(p.to_vec2()), if ea < 0.0 { 1 } else { -1 }))
+        }).collect();
+        hits.sort_by(|x, y| x.0.total_cmp(&y.0));
+        let (mut winding, mut start) = (0, 0.0);
+        for (s, dir) in hits {
+            let was = filled(winding, even_odd);
+            winding += dir;
+            match (was, filled(winding, even_odd)) {
+                (false, true) => start = s,
+                (true, false) if s - start > 1e-9 => {
+                    out.move_to((n * c + d * start).to_point());
+                    out.line_to((n * c + d * s).to_point());
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Dots on a square grid turned by `angle`, wherever their centre is inside.
+fn dots(polys: &[Vec<Point>], angle: f64, spacing: f64, radius: f64, even_odd: bool, out: &mut BezPath) {
+    let d = Vec2::new(angle.cos(), angle.sin());
+    let n = Vec2::new(-d.y, d.x);
+    let ((dlo, dhi), (nlo, nhi)) = (extent(polys, d), extent(polys, n));
+    // Centred in the grid cells, so a box shape isn't lined with half dots
+    let (i0, i1) = ((dlo / spacing - 0.5).ceil() as i64, (dhi / spacing - 0.5).floor() as i64);
+    let (j0, j1) = ((nlo / spacing - 0.5).ceil() as i64, (nhi / spacing - 0.5).floor() as i64);
+    if (i1 - i0 + 1) * (j1 - j0 + 1) > MAX_PATTERN_DOTS { return; }
+    for j in j0..=j1 {
+        for i in i0..=i1 {
+            let p = (d * ((i as f64 + 0.5) * spacing) + n * ((j as f64 + 0.5) * spacing)).to_point();
+            let winding: i32 = edges(polys).map(|(a, b)| {
+                if (a.y <= p.y) == (b.y <= p.y) { return 0; }
+                let x = a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x);
+                if x <= p.x { 0 } else if b.y > a.y { 1 } else { -1 }
+            }).sum();
+            if filled(winding, even_odd) { out.extend(Circle::new(p, radius).path_elements(ACCURACY)); }
+        }
+    }
+}
+
+impl Pattern {
+    /// The pattern's shapes inside `area`, and whether they are stroked
+    /// (lines) rather than filled (dots).
+    fn tile(&self, area: &BezPath, even_odd: bool) -> (BezPath, bool) {
+        let polys = polygons(area);
+        let spacing = self.spacing.max(0.5);
+        let mut out = BezPath::new();
+        match self.kind {
+            PatternKind::Lines => hatch(&polys, self.angle, spacing, even_odd, &mut out),
+            PatternKind::Crosshatch => {
+                hatch(&polys, self.angle, spacing, even_odd, &mut out);
+                hatch(&polys, self.angle + std::f64::consts::FRAC_PI_2, spacing, even_odd, &mut out);
+            }
+            PatternKind::Dots => dots(&polys, self.angle, spacing, self.weight / 2.0, even_odd, &mut out),
+        }
+        (out, self.kind != PatternKind::Dots)
+    }
+}
+
+fn set_path(part: &mut VectorObject, path: &BezPath) {
+    part.shape_type = ShapeType::Path;
+    part.path_data = path.to_svg();
+    part.corners = None;
+    part.intelligent_type.clear();
+    part.intelligent_params.clear();
+}
+
+/// The object split into one part per visible layer, bottom first, each in
+/// the object's box and transform. `None` without a stack, or for objects
+/// that have no outline to paint.
+pub fn expand_appearance(obj: &VectorObject) -> Option<Vec<VectorObject>> {
+    if obj.appearance.is_empty() { return None; }
+    let path = shape_path(obj)?;
+    let mut parts: Vec<VectorObject> = Vec::new();
+    for (i, layer) in obj.appearance.iter().enumerate() {
+        if !layer.visible { continue; }
+        let mut part = obj.clone();
+        part.appearance.clear();
+        part.id = obj.id | LAYER_ID_FLAG | ((i as u32) << LAYER_INDEX_SHIFT);
+        part.name = format!("{} {} {}", obj.name, if layer.kind == LayerKind::Fill { "Fill" } else { "Stroke" }, i + 1);
+        part.opacity = obj.opacity * layer.opacity;
+        if !layer.blend_mode.is_empty() { part.blend_mode = layer.blend_mode.clone(); }
+        // Effects belong to the whole appearance, so only the bottom part casts them
+        if !parts.is_empty() {
+            part.layer_style.effects.clear();
+            part.shadow_color = "transparent".to_string();
+        }
+        let join = if layer.join.is_empty() { obj.stroke_join.as_str() } else { layer.join.as_str() };
+        let shape = if layer.offset != 0.0 { grow(&path, layer.offset, join) } else { path.clone() };
+        if layer.offset != 0.0 { set_path(&mut part, &shape); }
+        part.stroke = "transparent".to_string();
+        part.stroke_gradient = None;
+        part.fill = "transparent".to_string();
+        part.fill_gradient = None;
+        match (layer.kind, &layer.pattern) {
+            (LayerKind::Fill, Some(pattern)) => {
+                let (tiles, stroked) = pattern.tile(&shape, obj.fill_rule == "evenodd");
+                set_path(&mut part, &tiles);
+                part.fill_rule = "nonzero".to_string();
+                part.width_profile = None;
+                part.start_marker = None;
+                part.end_marker = None;
+                if stroked {
+                    part.stroke = layer.color.clone();
+                    part.stroke_gradient = layer.gradient.clone();
+                    part.stroke_width = pattern.weight;
+                    part.stroke_cap = "butt".to_string();
+                    part.stroke_dash.clear();
+                    part.stroke_dash_align = false;
+                    part.stroke_align.clear();
+                } else {
+                    part.fill = layer.color.clone();
+                    part.fill_gradient = layer.gradient.clone();
+                    part.stroke_width = 0.0;
+                }
+            }
+            (LayerKind::Fill, None) => {
+                part.fill = layer.color.clone();
+                part.fill_gradient = layer.gradient.clone();
+                part.stroke_width = 0.0;
+                part.width_profile = None;
+                part.start_marker = None;
+                part.end_marker = None;
+            }
+            (LayerKind::Stroke, _) => {
+                part.stroke = layer.color.clone();
+                part.stroke_gradient = layer.gradient.clone();
+                part.stroke_width = layer.width;
+                if !layer.cap.is_empty() { part.stroke_cap = layer.cap.clone(); }
+                part.stroke_join = join.to_string();
+                part.stroke_dash = layer.dash.clone();
+                part.stroke_dash_offset = layer.dash_offset;
+                part.stroke_dash_align = layer.dash_align;
+                part.stroke_align = layer.align.clone();
+            }
+        }
+        parts.push(part);
+    }
+    Some(parts)
+}
+
+/// Copies of the objects with their appearance stacks split into parts, for
+/// exporters.
+pub fn expand_appearances(objects: &[VectorObject]) -> Vec<VectorObject> {
+    objects.iter().flat_map(|obj| {
+        if let Some(parts) = expand_appearance(obj) { return parts; }
+        let mut out = obj.clone();
+        if let Some(children) = out.children.as_mut() { *children = expand_appearances(children); }
+        vec![out]
+    }).collect()
+}
+
+fn parse_layer(value: &serde_json::Value) -> Result<AppearanceLayer, String> {
+    serde_json::from_value(value.clone()).map_err(|e| format!("Invalid appearance layer: {}", e))
+}
+
+#[wasm_bindgen]
+impl VectorEngine {
+    /// Adds a layer to each object's stack, at `index` or on top. An object
+    /// without a stack starts one from its own fill and stroke. Only shapes
+    /// and paths take a stack, as it's drawn from their outline.
+    pub(crate) fn add_appearance(&mut self, ids: &[u32], params: &serde_json::Value) -> String {
+        let layer = match parse_layer(&params["layer"]) { Ok(l) => l, Err(e) => return serde_json::json!({ "error": e }).to_string() };
+        let mut changed = Vec::new();
+        for obj in self.objects.iter_mut().filter(|o| ids.contains(&o.id) && shape_path(o).is_some()) {
+            if obj.appearance.is_empty() { obj.appearance = AppearanceLayer::from_object(obj); }
+            let index = params["index"].as_u64().map(|i| (i as usize).min(obj.appearance.len())).unwrap_or(obj.appearance.len());
+            obj.appearance.insert(index, layer.clone());
+            changed.push(obj.id);
+        }
+        if changed.is_empty() { return "{ \"error\": \"Appearance stacks apply to shapes and paths\" }".to_string(); }
+        serde_json::json!({ "success": true, "ids": changed }).to_string()
+    }
+
+    /// Changes the given fields of one layer, leaving the rest alone.
+    pub(crate) fn update_appearance(&mut self, id: u32, params: &serde_json::Value) -> String {
+        let Some(obj) = self.objects.iter_mut().find(|o| o.id == id) else { return "{ \"error\": \"Object not found\" }".to_string() };
+        let index = params["index"].as_u64().unwrap_or(0) as usize;
+        let Some(layer) = obj.appearance.get_mut(index) else { return "{ \"error\": \"Appearance layer not found\" }".to_string() };
+        let mut merged = serde_json::to_value(&*layer).unwrap_or_default();
+        if let (Some(target), Some(changes)) = (merged.as_object_mut(), params["layer"].as_object()) {
+            for (k, v) in changes { target.insert(k.clone(), v.clone()); }
+        }
+        match parse_layer(&merged) {
+            Ok(l) => { *layer = l; format!("{{\"success\": true, \"id\": {}}}", id) }
+            Err(e) => serde_json::json!({ "error": e }).to_string(),
+        }
+    }
+
+    pub(crate) fn remove_appearance(&mut self, id: u32, index: usize) -> String {
+        let Some(obj) = self.objects.iter_mut().find(|o| o.id == id) else { return "{ \"error\": \"Object not found\" }".to_string() };
+        if index >= obj.appearance.len() { return "{ \"error\": \"Appearance layer not found\" }".to_string(); }
+        obj.appearance.remove(index);
+        format!("{{\"success\": true, \"id\": {}}}", id)
+    }
+
+    pub(crate) fn move_appearance(&mut self, id: u32, from: usize, to: usize) -> String {
+        let Some(obj) = self.objects.iter_mut().find(|o| o.id == id) else { return "{ \"error\": \"Object not found\" }".to_string() };
+        if from >= obj.appearance.len() { return "{ \"error\": \"Appearance layer not found\" }".to_string(); }
+        let layer = obj.appearance.remove(from);
+        obj.appearance.insert(to.min(obj.appearance.len()), layer);
+        format!("{{\"success\": true, \"id\": {}}}", id)
+    }
+}
@@ -0,0 +1,260 @@
+use wasm_bindgen::prelude::*;
+use kurbo::{Affine, BezPath, CubicBez, ParamCurve, ParamCurveArclen, PathSeg, Point, Shape, Vec2};
+use crate::engine::VectorEngine;
+use crate::geometry::{set_local_path, world_path};
+use crate::objects::VectorObject;
+use crate::path_ops::{contours, Contour};
+use crate::quantize::parse_hex;
+use crate::types::{BlendSpec, ShapeType};
+
+// Blends: a group holding two objects with generated steps between them.
+// Outlines are matched contour by contour: both sides are converted to cubics,
+// the side with fewer segments is subdivided until the counts agree, and closed
+// contours are rotated so their start points line up. The steps then simply
+// interpolate the control points.
+
+const MAX_STEPS: u32 = 1000;
+
+struct ContourPair {
+    a: Vec<CubicBez>,
+    b: Vec<CubicBez>,
+    closed: bool,
+}
+
+fn to_cubics(c: &Contour) -> Vec<CubicBez> {
+    c.segs.iter().map(|seg| match *seg {
+        PathSeg::Line(l) => CubicBez::new(l.p0, l.p0.lerp(l.p1, 1.0 / 3.0), l.p0.lerp(l.p1, 2.0 / 3.0), l.p1),
+        PathSeg::Quad(q) => q.raise(),
+        PathSeg::Cubic(cb) => cb,
+    }).collect()
+}
+
+/// Splits the longest segments in half until there are `n` of them.
+fn subdivide_to(segs: &mut Vec<CubicBez>, n: usize) {
+    let hull = |c: &CubicBez| c.p0.distance(c.p1) + c.p1.distance(c.p2) + c.p2.distance(c.p3);
+    while segs.len() < n {
+        let Some(i) = (0..segs.len()).max_by(|&i, &j| hull(&segs[i]).total_cmp(&hull(&segs[j]))) else { return };
+        let (first, second) = segs[i].subdivide();
+        segs.splice(i..=i, [first, second]);
+    }
+}
+
+fn cubics_path(segs: &[CubicBez], closed: bool) -> BezPath {
+    let mut path = BezPath::new();
+    let Some(first) = segs.first() else { return path };
+    path.move_to(first.p0);
+    for c in segs { path.curve_to(c.p1, c.p2, c.p3); }
+    if closed { path.close_path(); }
+    path
+}
+
+/// Gives `b` the winding direction of `a` and starts it at the node that
+/// lines up best with `a`, so the steps don't twist.
+fn align(a: &[CubicBez], b: &mut [CubicBez]) {
+    if cubics_path(a, true).area().signum() != cubics_path(b, true).area().signum() {
+        b.reverse();
+        for c in b.iter_mut() { *c = CubicBez::new(c.p3, c.p2, c.p1, c.p0); }
+    }
+    let n = b.len();
+    let cost = |k: usize| (0..n).map(|i| a[i].p0.distance_squared(b[(i + k) % n].p0)).sum::<f64>();
+    if let Some(k) = (0..n).min_by(|&i, &j| cost(i).total_cmp(&cost(j))) { b.rotate_left(k); }
+}
+
+/// A contour collapsed to a single point, standing in for a missing counterpart.
+fn collapsed(p: Point, n: usize) -> Vec<CubicBez> {
+    vec![CubicBez::new(p, p, p, p); n]
+}
+
+fn match_outlines(a: &BezPath, b: &BezPath) -> Vec<ContourPair> {
+    let ca: Vec<Contour> = contours(a).into_iter().filter(|c| !c.segs.is_empty()).collect();
+    let cb: Vec<Contour> = contours(b).into_iter().filter(|c| !c.segs.is_empty()).collect();
+    let centre = |c: &Contour| cubics_path(&to_cubics(c), false).bounding_box().center();
+    (0..ca.len().max(cb.len())).map(|i| {
+        let (mut sa, mut sb, closed) = match (ca.get(i), cb.get(i)) {
+            (Some(x), Some(y)) => (to_cubics(x), to_cubics(y), x.closed && y.closed),
+            (Some(x), None) => (to_cubics(x), collapsed(centre(x), x.segs.len()), x.closed),
+            (None, Some(y)) => (collapsed(centre(y), y.segs.len()), to_cubics(y), y.closed),
+            (None, None) => unreachable!(),
+        };
+        let n = sa.len().max(sb.len());
+        subdivide_to(&mut sa, n);
+        subdivide_to(&mut sb, n);
+        if closed { align(&sa, &mut sb); }
+        ContourPair { a: sa, b: sb, closed }
+    }).collect()
+}
+
+fn interpolate(pairs: &[ContourPair], t: f64) -> BezPath {
+    let mut path = BezPath::new();
+    for pair in pairs {
+        let segs: Vec<CubicBez> = pair.a.iter().zip(&pair.b).map(|(a, b)| {
+            CubicBez::new(a.p0.lerp(b.p0, t), a.p1.lerp(b.p1, t), a.p2.lerp(b.p2, t), a.p3.lerp(b.p3, t))
+        }).collect();
+        path.extend(cubics_path(&segs, pair.closed));
+    }
+    path
+}
+
+/// Parses a CSS colour as RGBA. `Ok(None)` means transparent, `Err` a colour
+/// that can't be interpolated (such as a named colour).
+pub(crate) fn parse_rgba(color: &str) -> Result<Option<[f64; 4]>, ()> {
+    let color = color.trim();
+    if matches!(color, "" | "none" | "transparent") { return Ok(None); }
+    if let Some(rgb) = parse_hex(color) { return Ok(Some([rgb[0] as f64, rgb[1] as f64, rgb[2] as f64, 1.0])); }
+    let inner = color.strip_prefix("rgba(").or_else(|| color.strip_prefix("rgb(")).and_then(|s| s.strip_suffix(')')).ok_or(())?;
+    let parts: Vec<f64> = inner.split(',').map(|p| p.trim().parse::<f64>()).collect::<Result<_, _>>().map_err(|_| ())?;
+    match parts[..] {
+        [r, g, b] => Ok(Some([r, g, b, 1.0])),
+        [r, g, b, a] => Ok(Some([r, g, b, a])),
+        _ => Err(()),
+    }
+}
+
+fn lerp_color(a: &str, b: &str, t: f64) -> String {
+    let (ca, cb) = match (parse_rgba(a), parse_rgba(b)) {
+        (Ok(x), Ok(y)) => (x, y),
+        _ => return if t < 0.5 { a.to_string() } else { b.to_string() },
+    };
+    // A transparent end fades the other colour out instead of towards black
+    let (ca, cb) = match (ca, cb) {
+        (None, None) => return "transparent".to_string(),
+        (Some(x), None) => (x, [x[0], x[1], x[2], 0.0]),
+        (None, Some(y)) => ([y[0], y[1], y[2], 0.0], y),
+        (Some(x), Some(y)) => (x, y),
+    };
+    let c: Vec<f64> = (0..4).map(|i| ca[i] + (cb[i] - ca[i]) * t).collect();
+    if c[3] >= 1.0 {
+        format!("#{:02x}{:02x}{:02x}", c[0].round() as u8, c[1].round() as u8, c[2].round() as u8)
+    } else {
+        format!("rgba({}, {}, {}, {:.3})", c[0].round() as u8, c[1].round() as u8, c[2].round() as u8, c[3])
+    }
+}
+
+/// Point at a fraction of the spine's total length.
+fn spine_point(spine: &BezPath, fraction: f64) -> Option<Point> {
+    let segs: Vec<PathSeg> = spine.segments().collect();
+    let lengths: Vec<f64> = segs.iter().map(|s| s.arclen(1e-3)).collect();
+    let mut remaining = fraction.clamp(0.0, 1.0) * lengths.iter().sum::<f64>();
+    for (i, (seg, len)) in segs.iter().zip(&lengths).enumerate() {
+        if remaining <= *len || i + 1 == segs.len() {
+            return Some(seg.eval(seg.inv_arclen(remaining.min(*len), 1e-3)));
+        }
+        remaining -= len;
+    }
+    None
+}
+
+#[wasm_bindgen]
+impl VectorEngine {
+    /// Generates a blend's children: `start`, the intermediate steps and `end`,
+    /// all in the coordinate space the two objects and the spine live in.
+    fn blend_children(&mut self, start: &VectorObject, end: &VectorObject, spec: &BlendSpec) -> Result<Vec<VectorObject>, String> {
+        for obj in [start, end] {
+            if obj.shape_type == ShapeType::Group || obj.brush_id > 0 { return Err("Only shapes, paths and text can be blended".to_string()); }
+        }
+        let (path_a, path_b) = (world_path(start, &self.fonts)?, world_path(end, &self.fonts)?);
+        let pairs = match_outlines(&path_a, &path_b);
+        let spine = if spec.spine.is_empty() { None } else { BezPath::from_svg(&spec.spine).ok() };
+        let (centre_a, centre_b) = (path_a.bounding_box().center(), path_b.bounding_box().center());
+
+        let count = spec.steps.min(MAX_STEPS) as usize + 1;
+        let mut children = Vec::with_capacity(count + 1);
+        for i in 0..=count {
+            let t = i as f64 / count as f64;
+            // On a spine every object is moved so its centre sits on the spine
+            let offset = spine.as_ref().and_then(|s| spine_point(s, t)).map(|p| p - centre_a.lerp(centre_b, t)).unwrap_or(Vec2::ZERO);
+            if i == 0 || i == count {
+                let mut obj = if i == 0 { start.clone() } else { end.clone() };
+                obj.x += offset.x;
+                obj.y += offset.y;
+                children.push(obj);
+                continue;
+            }
+            let mut step = start.clone();
+            step.id = self.next_id; self.next_id += 1;
+            step.name = format!("Blend Step {}", i);
+            step.x = 0.0; step.y = 0.0; step.width = 0.0; step.height = 0.0; step.rotation = 0.0;
+            step.mask_id = None;
+            step.is_mask = false;
+            let mut path = interpolate(&pairs, t);
+            path.apply_affine(Affine::translate(offset));
+            set_local_path(&mut step, path);
+            step.fill = lerp_color(&start.fill, &end.fill, t);
+            step.stroke = lerp_color(&start.stroke, &end.stroke, t);
+            step.stroke_width = start.stroke_width + (end.stroke_width - start.stroke_width) * t;
+            step.opacity = start.opacity + (end.opacity - start.opacity) * t;
+            children.push(step);
+        }
+        Ok(children)
+    }
+
+    /// Blends two objects into a group of `steps` intermediate objects, optionally
+    /// distributed along the path object `spine_id`, which is consumed.
+    pub(crate) fn blend(&mut self, ids: &[u32], steps: u32, spine_id: Option<u32>) -> String {
+        let mut indices: Vec<usize> = ids.iter().filter(|id| Some(**id) != spine_id).filter_map(|id| self.objects.iter().position(|o| o.id == *id)).collect();
+        indices.sort_unstable();
+        indices.dedup();
+        if indices.len() != 2 { return "{ \"error\": \"Blend needs exactly two objects\" }".to_string(); }
+        // The back object is where the blend starts
+        let (start, end) = (self.objects[indices[0]].clone(), self.objects[indices[1]].clone());
+
+        let spine = match spine_id {
+            Some(sid) => match self.objects.iter().find(|o| o.id == sid).map(|o| world_path(o, &self.fonts)) {
+                Some(Ok(p)) => p.to_svg(),
+                _ => return "{ \"error\": \"Spine path not found\" }".to_string(),
+            },
+            None => String::new(),
+        };
+        let mut spec = BlendSpec { steps: steps.min(MAX_STEPS), spine };
+        let mut children = match self.blend_children(&start, &end, &spec) {
+            Ok(c) => c,
+            Err(e) => return serde_json::json!({ "error": e }).to_string(),
+        };
+
+        // Children of a group are stored relative to its box
+        let (mut x0, mut y0, mut x1, mut y1) = (f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for c in &children {
+            let (a, b, c2, d) = c.get_world_bounds();
+            x0 = x0.min(a); y0 = y0.min(b); x1 = x1.max(c2); y1 = y1.max(d);
+        }
+        for c in children.iter_mut() { c.x -= x0; c.y -= y0; }
+        if let Ok(mut spine) = BezPath::from_svg(&spec.spine) {
+            spine.apply_affine(Affine::translate((-x0, -y0)));
+            spec.spine = spine.to_svg();
+        }
+
+        let group_id = self.next_id; self.next_id += 1;
+        let mut group = self.create_default_object(group_id, ShapeType::Group, x0, y0, x1 - x0, y1 - y0);
+        group.name = format!("Blend {}", group_id);
+        group.fill = "transparent".to_string();
+        group.children = Some(children);
+        group.blend = Some(spec);
+
+        let removed = [Some(start.id), Some(end.id), spine_id];
+        let insert_at = self.objects[..indices[1]].iter().filter(|o| !removed.contains(&Some(o.id))).count();
+        self.objects.retain(|o| !removed.contains(&Some(o.id)));
+        self.objects.insert(insert_at, group);
+        self.selected_ids = vec![group_id];
+        format!("{{\"success\": true, \"id\": {}}}", group_id)
+    }
+
+    /// Regenerates a blend group's steps, e.g. after the step count changed or
+    /// one of the blended objects was edited.
+    pub(crate) fn update_blend(&mut self, id: u32, steps: Option<u32>) -> String {
+        let Some(idx) = self.objects.iter().position(|o| o.id == id) else { return "{ \"error\": \"Object not found\" }".to_string() };
+        let group = self.objects[idx].clone();
+        let (Some(mut spec), Some(children)) = (group.blend.clone(), group.children.as_ref()) else { return "{ \"error\": \"Object is not a blend\" }".to_string() };
+        let (Some(start), Some(end)) = (children.first(), children.last()) else { return "{ \"error\": \"Object is not a blend\" }".to_string() };
+        if children.len() < 2 { return "{ \"error\": \"Object is not a blend\" }".to_string(); }
+        if let Some(s) = steps { spec.steps = s.min(MAX_STEPS); }
+        match self.blend_children(start, end, &spec) {
+            Ok(c) => {
+                let obj = &mut self.objects[idx];
+                obj.children = Some(c);
+                obj.blend = Some(spec);
+                format!("{{\"success\": true, \"id\": {}}}", id)
+            }
+            Err(e) => serde_json::json!({ "error": e }).to_string(),
+        }
+    }
+}
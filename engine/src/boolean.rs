@@ -70,37 +70,7 @@ impl VectorEngine {
     }
 
     fn get_object_path(&self, obj: &VectorObject) -> Result<BezPath, String> {
-        let mut bez = match obj.shape_type {
-            ShapeType::Rectangle => {
-                let mut b = BezPath::new();
-                b.move_to(Point::new(0.0, 0.0));
-                b.line_to(Point::new(obj.width, 0.0));
-                b.line_to(Point::new(obj.width, obj.height));
-                b.line_to(Point::new(0.0, obj.height));
-                b.close_path();
-                b
-            }
-            ShapeType::Circle | ShapeType::Ellipse => {
-                // Approximate with bezier
-                kurbo::Ellipse::new(
-                    Point::new(obj.width/2.0, obj.height/2.0),
-                    (obj.width/2.0, obj.height/2.0),
-                    0.0
-                ).to_path(0.1)
-            }
-            ShapeType::Path => {
-                BezPath::from_svg(&obj.path_data).map_err(|e| e.to_string())?
-            }
-            _ => return Err("Unsupported shape for boolean operation".to_string()),
-        };
-
-        // Apply object's transform to world space
-        let mut affine = Affine::translate((obj.x + obj.width / 2.0, obj.y + obj.height / 2.0));
-        affine *= Affine::rotate(obj.rotation);
-        affine *= Affine::translate((-obj.width / 2.0, -obj.height / 2.0));
-        bez.apply_affine(affine);
-        
-        Ok(bez)
+        crate::geometry::world_path(obj, &self.fonts)
     }
 
     // Simplified polygon operations for the CLI demo
@@ -1,7 +1,9 @@
 use serde::{Serialize, Deserialize};
-use kurbo::{BezPath, Point, ParamCurve, ParamCurveArclen};
+use kurbo::{Affine, BezPath, Point, ParamCurve, ParamCurveArclen};
 use web_sys::{CanvasRenderingContext2d, HtmlImageElement};
 use wasm_bindgen::JsValue;
+use crate::objects::VectorObject;
+use crate::width_profile::{outline, WidthProfile};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum BrushTip {
@@ -175,6 +177,21 @@ impl BrushEngine {
         ctx.restore();
     }
 
+    /// Filled outline of the dabs `render_stroke` lays along a path. The
+    /// elliptical tip sweeps as a round one would in a space squashed to its
+    /// roundness; image tips are taken as round. Scatter and jitter are random
+    /// per draw, so they're left out.
+    pub fn stroke_outline(&self, brush: &Brush, path: &BezPath) -> BezPath {
+        let tip = match brush.tip {
+            BrushTip::Calligraphic { angle, roundness } => Affine::rotate(angle) * Affine::scale_non_uniform(1.0, roundness.max(0.01)),
+            BrushTip::Image { .. } => Affine::IDENTITY,
+        };
+        // Pressure is taken as full, as when drawing
+        let mut outline = outline(&(tip.inverse() * path.clone()), brush.size, &WidthProfile::default(), "round", "round");
+        outline.apply_affine(tip);
+        outline
+    }
+
     // Helper to convert points to an outline (legacy, might still be useful)
     pub fn points_to_outline(&self, brush_id: u32, points: &[StrokePoint]) -> BezPath {
         // ... (existing logic or simplified)
@@ -182,3 +199,26 @@ impl BrushEngine {
     }
 }
 
+/// Copies of the objects with brush strokes replaced by filled outlines in
+/// their colour, for exporters.
+pub fn outline_brushes(objects: &[VectorObject], engine: &BrushEngine) -> Vec<VectorObject> {
+    objects.iter().map(|obj| {
+        let mut out = obj.clone();
+        if let Some(children) = &obj.children { out.children = Some(outline_brushes(children, engine)); }
+        if obj.brush_id == 0 { return out; }
+        let (Some(brush), Ok(path)) = (engine.brushes.iter().find(|b| b.id == obj.brush_id), BezPath::from_svg(&obj.path_data)) else { return out };
+        out.path_data = engine.stroke_outline(brush, &path).to_svg();
+        out.brush_id = 0;
+        out.stroke_points.clear();
+        out.fill_gradient = None;
+        out.fill_rule = "nonzero".to_string();
+        out.stroke = "transparent".to_string();
+        out.stroke_gradient = None;
+        out.stroke_width = 0.0;
+        out.stroke_dash.clear();
+        out.width_profile = None;
+        out.start_marker = None;
+        out.end_marker = None;
+        out
+    }).collect()
+}
@@ -0,0 +1,420 @@
+use std::collections::{HashMap, HashSet};
+use kurbo::{BezPath, Point, Vec2};
+use crate::tracer::Bitmap;
+
+// Centerline tracing for line art: the thresholded bitmap is thinned to a one
+// pixel wide skeleton, the skeleton is split into a graph of edges between end
+// points and junctions, and edges that continue straight through a junction are
+// chained into single open strokes before smoothing and curve fitting.
+
+const NEIGHBOURS: [(i32, i32); 8] = [(0, -1), (1, -1), (1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1)];
+
+#[derive(Clone, Debug)]
+pub struct CenterlineOptions {
+    pub smoothing: u32,      // Laplacian smoothing passes applied to each stroke
+    pub tolerance: f64,      // Max deviation in pixels when simplifying strokes
+    pub spur_length: f64,    // Branches shorter than this ending in a free end are pruned
+    pub min_length: f64,     // Strokes shorter than this are dropped
+    pub join_angle: f64,     // Max bend in degrees for two branches to continue through a junction
+}
+
+impl Default for CenterlineOptions {
+    fn default() -> Self {
+        CenterlineOptions { smoothing: 2, tolerance: 1.0, spur_length: 4.0, min_length: 3.0, join_angle: 45.0 }
+    }
+}
+
+impl CenterlineOptions {
+    pub fn from_params(params: &serde_json::Value) -> Self {
+        let defaults = CenterlineOptions::default();
+        CenterlineOptions {
+            smoothing: params["smoothing"].as_u64().map(|v| v as u32).unwrap_or(defaults.smoothing),
+            tolerance: params["tolerance"].as_f64().unwrap_or(defaults.tolerance),
+            spur_length: params["spur_length"].as_f64().unwrap_or(defaults.spur_length),
+            min_length: params["min_length"].as_f64().unwrap_or(defaults.min_length),
+            join_angle: params["join_angle"].as_f64().unwrap_or(defaults.join_angle),
+        }
+    }
+}
+
+pub struct Centerlines {
+    pub strokes: Vec<BezPath>,
+    pub stroke_width: f64, // Average line thickness of the source in pixels
+}
+
+pub fn trace_centerlines(bm: &Bitmap, options: &CenterlineOptions) -> Centerlines {
+    let skeleton = skeletonize(bm);
+    let fg = bm.data().iter().filter(|v| **v).count();
+    let sk = skeleton.data().iter().filter(|v| **v).count();
+    let stroke_width = if sk > 0 { (fg as f64 / sk as f64).max(1.0) } else { 1.0 };
+
+    let mut graph = SkeletonGraph::build(&skeleton);
+    graph.prune_spurs(options.spur_length.max(stroke_width));
+    let mut strokes = Vec::new();
+    for (mut points, closed) in graph.chain(options.join_angle) {
+        if polyline_length(&points, closed) < options.min_length { continue; }
+        smooth_polyline(&mut points, closed, options.smoothing);
+        let points = if closed {
+            let mut ring = points.clone();
+            ring.push(points[0]);
+            let mut s = simplify(&ring, options.tolerance);
+            s.pop();
+            s
+        } else { simplify(&points, options.tolerance) };
+        strokes.push(fit_catmull_rom(&points, closed));
+    }
+    Centerlines { strokes, stroke_width }
+}
+
+/// Zhang-Suen thinning followed by removal of staircase pixels, leaving an
+/// 8-connected skeleton where every non-junction pixel has at most two neighbours.
+pub fn skeletonize(bm: &Bitmap) -> Bitmap {
+    let mut sk = bm.clone();
+    let (w, h) = (bm.width as i32, bm.height as i32);
+    loop {
+        let mut changed = false;
+        for step in 0..2 {
+            let mut remove = Vec::new();
+            for y in 0..h {
+                for x in 0..w {
+                    if !sk.get(x, y) { continue; }
+                    let n: Vec<bool> = NEIGHBOURS.iter().map(|(dx, dy)| sk.get(x + dx, y + dy)).collect();
+                    let b = n.iter().filter(|v| **v).count();
+                    let a = (0..8).filter(|&i| !n[i] && n[(i + 1) % 8]).count();
+                    if !(2..=6).contains(&b) || a != 1 { continue; }
+                    let (p2, p4, p6, p8) = (n[0], n[2], n[4], n[6]);
+                    let ok = if step == 0 { !(p4 && p6 && (p2 || p8)) } else { !(p2 && p8 && (p4 || p6)) };
+                    if ok { remove.push((x as u32, y as u32)); }
+                }
+            }
+            changed |= !remove.is_empty();
+            for (x, y) in remove { sk.set(x, y, false); }
+        }
+        if !changed { break; }
+    }
+
+    // A pixel sitting in the corner of two 4-connected neighbours is redundant as
+    // long as removing it keeps its neighbourhood connected.
+    for y in 0..h {
+        for x in 0..w {
+            if !sk.get(x, y) { continue; }
+            let n: Vec<bool> = NEIGHBOURS.iter().map(|(dx, dy)| sk.get(x + dx, y + dy)).collect();
+            let corner = (n[0] || n[4]) && (n[2] || n[6]);
+            if corner && n.iter().filter(|v| **v).count() >= 2 && ring_components(&n) == 1 {
+                sk.set(x as u32, y as u32, false);
+            }
+        }
+    }
+    sk
+}
+
+/// Number of 8-connected groups among the set neighbours of a pixel.
+fn ring_components(n: &[bool]) -> usize {
+    let mut seen = [false; 8];
+    let mut count = 0;
+    for start in 0..8 {
+        if !n[start] || seen[start] { continue; }
+        count += 1;
+        let mut stack = vec![start];
+        seen[start] = true;
+        while let Some(i) = stack.pop() {
+            let (xi, yi) = NEIGHBOURS[i];
+            for j in 0..8 {
+                let (xj, yj) = NEIGHBOURS[j];
+                if n[j] && !seen[j] && (xi - xj).abs() <= 1 && (yi - yj).abs() <= 1 {
+                    seen[j] = true;
+                    stack.push(j);
+                }
+            }
+        }
+    }
+    count
+}
+
+struct Edge {
+    points: Vec<Point>,
+    ends: [usize; 2],
+    removed: bool,
+}
+
+struct SkeletonGraph {
+    nodes: Vec<Point>,
+    edges: Vec<Edge>,
+    loops: Vec<Vec<Point>>,
+}
+
+fn centre(x: i32, y: i32) -> Point {
+    Point::new(x as f64 + 0.5, y as f64 + 0.5)
+}
+
+impl SkeletonGraph {
+    fn build(sk: &Bitmap) -> Self {
+        let (w, h) = (sk.width as i32, sk.height as i32);
+        let idx = |x: i32, y: i32| (y * w + x) as usize;
+        let degree = |x: i32, y: i32| NEIGHBOURS.iter().filter(|(dx, dy)| sk.get(x + dx, y + dy)).count();
+
+        // Junction pixels that touch each other form a single node at their centroid
+        let mut node_of: Vec<Option<usize>> = vec![None; (w * h) as usize];
+        let mut nodes = Vec::new();
+        for y in 0..h {
+            for x in 0..w {
+                if !sk.get(x, y) || node_of[idx(x, y)].is_some() { continue; }
+                let d = degree(x, y);
+                if d == 2 || d == 0 { continue; }
+                let id = nodes.len();
+                node_of[idx(x, y)] = Some(id);
+                if d == 1 { nodes.push(centre(x, y)); continue; }
+                let mut stack = vec![(x, y)];
+                let mut sum = Vec2::ZERO;
+                let mut count = 0.0;
+                while let Some((cx, cy)) = stack.pop() {
+                    sum += centre(cx, cy).to_vec2();
+                    count += 1.0;
+                    for (dx, dy) in NEIGHBOURS {
+                        let (nx, ny) = (cx + dx, cy + dy);
+                        if sk.get(nx, ny) && node_of[idx(nx, ny)].is_none() && degree(nx, ny) >= 3 {
+                            node_of[idx(nx, ny)] = Some(id);
+                            stack.push((nx, ny));
+                        }
+                    }
+                }
+                nodes.push((sum / count).to_point());
+            }
+        }
+
+        let mut visited = vec![false; (w * h) as usize];
+        let mut edges = Vec::new();
+        let mut direct = HashSet::new();
+        for y in 0..h {
+            for x in 0..w {
+                let Some(start) = node_of[idx(x, y)] else { continue };
+                for (dx, dy) in NEIGHBOURS {
+                    let (qx, qy) = (x + dx, y + dy);
+                    if !sk.get(qx, qy) { continue; }
+                    if let Some(other) = node_of[idx(qx, qy)] {
+                        if other != start && direct.insert((start.min(other), start.max(other))) {
+                            edges.push(Edge { points: vec![nodes[start], nodes[other]], ends: [start, other], removed: false });
+                        }
+                        continue;
+                    }
+                    if visited[idx(qx, qy)] { continue; }
+                    visited[idx(qx, qy)] = true;
+                    let mut points = vec![nodes[start], centre(qx, qy)];
+                    let (mut prev, mut cur) = ((x, y), (qx, qy));
+                    let end = loop {
+                        let mut next_pixel = None;
+                        let mut next_node = None;
+                        for (dx, dy) in NEIGHBOURS {
+                            let (nx, ny) = (cur.0 + dx, cur.1 + dy);
+                            if (nx, ny) == prev || !sk.get(nx, ny) { continue; }
+                            match node_of[idx(nx, ny)] {
+                                // Don't close back onto the node we just left
+                                Some(n) if n == start && points.len() < 3 => {}
+                                Some(n) => { next_node.get_or_insert(n); }
+                                None if !visited[idx(nx, ny)] => { next_pixel.get_or_insert((nx, ny)); }
+                                None => {}
+                            }
+                        }
+                        if let Some(n) = next_node { break n; }
+                        match next_pixel {
+                            Some(p) => {
+                                visited[idx(p.0, p.1)] = true;
+                                points.push(centre(p.0, p.1));
+                                prev = cur;
+                                cur = p;
+                            }
+                            None => {
+                                // Dead end on a pixel that lost its neighbour to another branch
+                                nodes.push(centre(cur.0, cur.1));
+                                break nodes.len() - 1;
+                            }
+                        }
+                    };
+                    if points.last() != Some(&nodes[end]) { points.push(nodes[end]); }
+                    edges.push(Edge { points, ends: [start, end], removed: false });
+                }
+            }
+        }
+
+        // Whatever is left are closed rings without any junction or end point
+        let mut loops = Vec::new();
+        for y in 0..h {
+            for x in 0..w {
+                if !sk.get(x, y) || visited[idx(x, y)] || node_of[idx(x, y)].is_some() { continue; }
+                let mut ring = vec![centre(x, y)];
+                visited[idx(x, y)] = true;
+                let mut cur = (x, y);
+                while let Some(p) = NEIGHBOURS.iter().map(|(dx, dy)| (cur.0 + dx, cur.1 + dy)).find(|&(nx, ny)| sk.get(nx, ny) && !visited[idx(nx, ny)]) {
+                    visited[idx(p.0, p.1)] = true;
+                    ring.push(centre(p.0, p.1));
+                    cur = p;
+                }
+                if ring.len() > 2 { loops.push(ring); }
+            }
+        }
+        SkeletonGraph { nodes, edges, loops }
+    }
+
+    fn incident(&self) -> HashMap<usize, Vec<(usize, usize)>> {
+        let mut map: HashMap<usize, Vec<(usize, usize)>> = HashMap::new();
+        for (i, e) in self.edges.iter().enumerate() {
+            if e.removed { continue; }
+            map.entry(e.ends[0]).or_default().push((i, 0));
+            map.entry(e.ends[1]).or_default().push((i, 1));
+        }
+        map
+    }
+
+    /// Removes short branches that hang off a junction and end in a free end.
+    fn prune_spurs(&mut self, max_length: f64) {
+        let incident = self.incident();
+        let degree = |n: usize| incident.get(&n).map_or(0, |v| v.len());
+        for e in self.edges.iter_mut() {
+            let (d0, d1) = (degree(e.ends[0]), degree(e.ends[1]));
+            let spur = (d0 == 1 && d1 >= 3) || (d1 == 1 && d0 >= 3);
+            if spur && polyline_length(&e.points, false) < max_length { e.removed = true; }
+        }
+    }
+
+    /// Links edge ends through junctions and walks the links into strokes.
+    fn chain(&self, join_angle: f64) -> Vec<(Vec<Point>, bool)> {
+        let mut link: HashMap<(usize, usize), (usize, usize)> = HashMap::new();
+        let max_dot = -join_angle.to_radians().cos();
+        let mut incident: Vec<_> = self.incident().into_iter().collect();
+        incident.sort_by_key(|(n, _)| *n);
+        for (node, ends) in incident {
+            if ends.len() == 2 {
+                link.insert(ends[0], ends[1]);
+                link.insert(ends[1], ends[0]);
+                continue;
+            }
+            if ends.len() < 3 { continue; }
+            let dirs: Vec<Vec2> = ends.iter().map(|&(e, end)| self.end_direction(e, end, self.nodes[node])).collect();
+            let mut pairs = Vec::new();
+            for i in 0..ends.len() {
+                for j in i + 1..ends.len() {
+                    pairs.push((dirs[i].dot(dirs[j]), i, j));
+                }
+            }
+            pairs.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+            let mut taken = vec![false; ends.len()];
+            for (dot, i, j) in pairs {
+                if dot > max_dot { break; }
+                if taken[i] || taken[j] { continue; }
+                taken[i] = true;
+                taken[j] = true;
+                link.insert(ends[i], ends[j]);
+                link.insert(ends[j], ends[i]);
+            }
+        }
+
+        let mut used = vec![false; self.edges.len()];
+        let mut result = Vec::new();
+        for s in 0..self.edges.len() {
+            if used[s] || self.edges[s].removed { continue; }
+            // Walk backwards to the free end of the chain containing `s`
+            let (mut e, mut end) = (s, 0);
+            while let Some(&(e2, end2)) = link.get(&(e, end)) {
+                if e2 == s { break; }
+                e = e2;
+                end = 1 - end2;
+            }
+            let start = (e, end);
+            let mut points: Vec<Point> = Vec::new();
+            let mut closed = false;
+            loop {
+                used[e] = true;
+                let mut pts = self.edges[e].points.clone();
+                if end == 1 { pts.reverse(); }
+                if !points.is_empty() { pts.remove(0); }
+                points.extend(pts);
+                match link.get(&(e, 1 - end)) {
+                    Some(&next) if next == start => { closed = true; break; }
+                    Some(&(e2, end2)) if !used[e2] => { e = e2; end = end2; }
+                    _ => break,
+                }
+            }
+            if closed && points.len() > 1 { points.pop(); }
+            result.push((points, closed));
+        }
+        for ring in &self.loops {
+            result.push((ring.clone(), true));
+        }
+        result
+    }
+
+    /// Unit direction leaving `node` along an edge, measured a few pixels out so
+    /// pixel noise right at the junction doesn't dominate.
+    fn end_direction(&self, e: usize, end: usize, node: Point) -> Vec2 {
+        let pts = &self.edges[e].points;
+        let probe = 5.min(pts.len() - 1);
+        let p = if end == 0 { pts[probe] } else { pts[pts.len() - 1 - probe] };
+        let v = p - node;
+        if v.hypot() > 0.0 { v / v.hypot() } else { Vec2::ZERO }
+    }
+}
+
+fn polyline_length(points: &[Point], closed: bool) -> f64 {
+    let mut len: f64 = points.windows(2).map(|w| w[0].distance(w[1])).sum();
+    if closed && points.len() > 1 { len += points[points.len() - 1].distance(points[0]); }
+    len
+}
+
+/// Averages each point with its neighbours; end points of open strokes stay put
+/// so strokes still meet at junctions.
+fn smooth_polyline(points: &mut [Point], closed: bool, passes: u32) {
+    let n = points.len();
+    if n < 3 { return; }
+    for _ in 0..passes {
+        let src = points.to_vec();
+        for i in 0..n {
+            if !closed && (i == 0 || i == n - 1) { continue; }
+            let a = src[(i + n - 1) % n];
+            let b = src[(i + 1) % n];
+            points[i] = ((a.to_vec2() + src[i].to_vec2() * 2.0 + b.to_vec2()) / 4.0).to_point();
+        }
+    }
+}
+
+/// Ramer-Douglas-Peucker simplification of an open polyline.
+fn simplify(points: &[Point], tolerance: f64) -> Vec<Point> {
+    if points.len() < 3 { return points.to_vec(); }
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    let mut stack = vec![(0, points.len() - 1)];
+    while let Some((a, b)) = stack.pop() {
+        let (pa, pb) = (points[a], points[b]);
+        let seg = pb - pa;
+        let len = seg.hypot();
+        let mut best = (0.0, a);
+        for (i, p) in points.iter().enumerate().take(b).skip(a + 1) {
+            let d = if len > 0.0 { (seg.cross(*p - pa) / len).abs() } else { p.distance(pa) };
+            if d > best.0 { best = (d, i); }
+        }
+        if best.0 > tolerance {
+            keep[best.1] = true;
+            stack.push((a, best.1));
+            stack.push((best.1, b));
+        }
+    }
+    points.iter().zip(keep).filter(|(_, k)| *k).map(|(p, _)| *p).collect()
+}
+
+/// Passes a Catmull-Rom spline through the points and converts it to cubics.
+fn fit_catmull_rom(points: &[Point], closed: bool) -> BezPath {
+    let mut path = BezPath::new();
+    let n = points.len();
+    if n == 0 { return path; }
+    path.move_to(points[0]);
+    let at = |i: isize| -> Point {
+        if closed { points[i.rem_euclid(n as isize) as usize] } else { points[i.clamp(0, n as isize - 1) as usize] }
+    };
+    let segments = if closed { n } else { n - 1 };
+    for i in 0..segments as isize {
+        let (p0, p1, p2, p3) = (at(i - 1), at(i), at(i + 1), at(i + 2));
+        path.curve_to(p1 + (p2 - p0) / 6.0, p2 - (p3 - p1) / 6.0, p2);
+    }
+    if closed { path.close_path(); }
+    path
+}
@@ -4,7 +4,12 @@ use crate::engine::VectorEngine;
 use crate::types::{ShapeType, GradientStop, Gradient, LayerStyle};
 use crate::objects::VectorObject;
 use crate::brush::{StrokePoint, Brush};
-use crate::tracer::Tracer;
+use crate::width_profile::WidthProfile;
+use crate::corners::LiveCorners;
+use crate::text_layout::{fit_text_box, TEXT_LAYOUT_KEYS};
+use crate::tracer::{Tracer, TraceOptions, Bitmap};
+use crate::centerline::{trace_centerlines, CenterlineOptions};
+use crate::quantize::{quantize, map_to_palette, parse_hex, to_hex, QuantizeMethod};
 use kurbo::{BezPath, Affine, Point, Shape};
 use image::{DynamicImage, ImageOutputFormat, RgbaImage};
 use std::io::Cursor;
@@ -24,7 +29,7 @@ impl VectorEngine {
             Err(e) => return format!("{{\"error\": \"Invalid JSON: {}{{\"}}\"}}", e),
         };
 
-        match cmd.action.as_str() {
+        let result = match cmd.action.as_str() {
             "magic_wand" => {
                 let id = cmd.params["id"].as_u64().map(|v| v as u32).unwrap_or(0);
                 let x = cmd.params["x"].as_f64().unwrap_or(0.0);
@@ -136,6 +141,7 @@ impl VectorEngine {
                 };
                 let id = self.add_object(st, cmd.params["x"].as_f64().unwrap_or(0.0), cmd.params["y"].as_f64().unwrap_or(0.0), cmd.params["width"].as_f64().unwrap_or(100.0), cmd.params["height"].as_f64().unwrap_or(100.0), cmd.params["fill"].as_str().unwrap_or("#4facfe"));
                 self.update_object(id, &cmd.params);
+                if st == ShapeType::Text { self.fit_text(id); }
                 format!("{{\"success\": true, \"id\": {}}}", id)
             }
             "update" => {
@@ -225,22 +231,205 @@ impl VectorEngine {
                 if let Some(v) = cmd.params["enabled"].as_bool() { self.clip_to_artboard = v; }
                 "{ \"success\": true }".to_string()
             }
+            "convert_to_path" => {
+                let ids = self.parse_ids(&cmd.params);
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Convert to Path"); }
+                self.convert_to_path(&ids)
+            }
+            "split_path" => {
+                let id = cmd.params["id"].as_u64().unwrap_or(0) as u32;
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Split Path"); }
+                self.split_path(id, &cmd.params)
+            }
+            "join_paths" => {
+                let ids = self.parse_ids(&cmd.params);
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Join Paths"); }
+                self.join_paths(&ids, cmd.params["mode"].as_str() == Some("average"))
+            }
+            "reverse_path" => {
+                let id = cmd.params["id"].as_u64().unwrap_or(0) as u32;
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Reverse Path"); }
+                self.reverse_path(id, &cmd.params)
+            }
+            "close_path" | "open_path" => {
+                let id = cmd.params["id"].as_u64().unwrap_or(0) as u32;
+                let close = cmd.action == "close_path";
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state(if close { "Close Path" } else { "Open Path" }); }
+                self.set_path_closed(id, close, &cmd.params)
+            }
+            "break_apart" => {
+                let id = cmd.params["id"].as_u64().unwrap_or(0) as u32;
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Break Apart"); }
+                self.break_apart(id)
+            }
+            "make_compound_path" => {
+                let ids = self.parse_ids(&cmd.params);
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Make Compound Path"); }
+                self.make_compound_path(&ids)
+            }
+            "knife" => {
+                let ids = self.parse_ids(&cmd.params);
+                let points: Vec<Point> = cmd.params["points"].as_array().map(|a| a.iter().map(|p| Point::new(p["x"].as_f64().unwrap_or(0.0), p["y"].as_f64().unwrap_or(0.0))).collect()).unwrap_or_default();
+                if points.len() < 2 { return "{ \"error\": \"Knife needs at least 2 points\" }".to_string(); }
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Knife"); }
+                self.knife(&ids, &points)
+            }
+            "scissors" => {
+                let id = cmd.params["id"].as_u64().map(|v| v as u32);
+                let point = Point::new(cmd.params["x"].as_f64().unwrap_or(0.0), cmd.params["y"].as_f64().unwrap_or(0.0));
+                let tolerance = cmd.params["tolerance"].as_f64().unwrap_or(4.0 / self.viewport_zoom);
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Scissors"); }
+                self.scissors(id, point, tolerance)
+            }
+            "warp" => {
+                let ids = self.parse_ids(&cmd.params);
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Warp"); }
+                self.set_warp(&ids, &cmd.params)
+            }
+            "remove_warp" => {
+                let ids = self.parse_ids(&cmd.params);
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Remove Warp"); }
+                self.remove_warp(&ids)
+            }
+            "release_envelope" => {
+                let ids = self.parse_ids(&cmd.params);
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Release Envelope"); }
+                self.release_envelope(&ids)
+            }
+            "width_profile" => {
+                let ids = self.parse_ids(&cmd.params);
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Width Profile"); }
+                self.set_width_profile(&ids, &cmd.params)
+            }
+            "markers" => {
+                let ids = self.parse_ids(&cmd.params);
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Arrowheads"); }
+                self.set_markers(&ids, &cmd.params)
+            }
+            "outline_stroke" => {
+                let ids = self.parse_ids(&cmd.params);
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Outline Stroke"); }
+                self.outline_stroke(&ids)
+            }
+            "add_appearance" => {
+                let ids = self.parse_ids(&cmd.params);
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Add Appearance"); }
+                self.add_appearance(&ids, &cmd.params)
+            }
+            "update_appearance" => {
+                let id = cmd.params["id"].as_u64().map(|v| v as u32).or_else(|| self.selected_ids.first().copied()).unwrap_or(0);
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Edit Appearance"); }
+                self.update_appearance(id, &cmd.params)
+            }
+            "remove_appearance" => {
+                let id = cmd.params["id"].as_u64().map(|v| v as u32).or_else(|| self.selected_ids.first().copied()).unwrap_or(0);
+                let index = cmd.params["index"].as_u64().unwrap_or(0) as usize;
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Remove Appearance"); }
+                self.remove_appearance(id, index)
+            }
+            "move_appearance" => {
+                let id = cmd.params["id"].as_u64().map(|v| v as u32).or_else(|| self.selected_ids.first().copied()).unwrap_or(0);
+                let from = cmd.params["from"].as_u64().unwrap_or(0) as usize;
+                let to = cmd.params["to"].as_u64().unwrap_or(0) as usize;
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Reorder Appearance"); }
+                self.move_appearance(id, from, to)
+            }
+            "live_corners" => {
+                let ids = self.parse_ids(&cmd.params);
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Live Corners"); }
+                self.set_live_corners(&ids, &cmd.params)
+            }
+            "expand_corners" => {
+                let ids = self.parse_ids(&cmd.params);
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Expand Corners"); }
+                self.expand_live_corners(&ids)
+            }
+            "create_outlines" => {
+                let ids = self.parse_ids(&cmd.params);
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Create Outlines"); }
+                self.create_outlines(&ids, &cmd.params)
+            }
+            "text_on_path" => {
+                let path_id = cmd.params["path_id"].as_u64().map(|v| v as u32).unwrap_or(0);
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Type on a Path"); }
+                self.put_text_on_path(path_id, &cmd.params)
+            }
+            "release_text_path" => {
+                let ids = self.parse_ids(&cmd.params);
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Release Type on a Path"); }
+                self.release_text_path(&ids)
+            }
+            "text_style" => {
+                let id = cmd.params["id"].as_u64().map(|v| v as u32).or_else(|| self.selected_ids.first().copied()).unwrap_or(0);
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Character Style"); }
+                self.set_text_style(id, &cmd.params)
+            }
+            "paragraph_style" => {
+                let id = cmd.params["id"].as_u64().map(|v| v as u32).or_else(|| self.selected_ids.first().copied()).unwrap_or(0);
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Paragraph Style"); }
+                self.set_paragraph_style(id, &cmd.params)
+            }
+            "replace_text" => {
+                let id = cmd.params["id"].as_u64().map(|v| v as u32).or_else(|| self.selected_ids.first().copied()).unwrap_or(0);
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Edit Text"); }
+                self.replace_text(id, &cmd.params)
+            }
+            "text_layout" => {
+                let id = cmd.params["id"].as_u64().map(|v| v as u32).or_else(|| self.selected_ids.first().copied()).unwrap_or(0);
+                self.text_layout(id)
+            }
+            "measure_path" => {
+                let id = cmd.params["id"].as_u64().map(|v| v as u32).or_else(|| self.selected_ids.first().copied()).unwrap_or(0);
+                self.measure_path(id)
+            }
+            "point_at" => {
+                let id = cmd.params["id"].as_u64().map(|v| v as u32).or_else(|| self.selected_ids.first().copied()).unwrap_or(0);
+                self.point_at(id, &cmd.params)
+            }
+            "nearest_point" => {
+                let id = cmd.params["id"].as_u64().map(|v| v as u32).or_else(|| self.selected_ids.first().copied()).unwrap_or(0);
+                let point = kurbo::Point::new(cmd.params["x"].as_f64().unwrap_or(0.0), cmd.params["y"].as_f64().unwrap_or(0.0));
+                self.nearest_point(id, point)
+            }
+            "path_intersections" => {
+                let ids = self.parse_ids(&cmd.params);
+                if ids.len() != 2 { return "{ \"error\": \"Select exactly two objects\" }".to_string(); }
+                self.path_intersections(ids[0], ids[1])
+            }
+            "blend" => {
+                let ids = self.parse_ids(&cmd.params);
+                let steps = cmd.params["steps"].as_u64().unwrap_or(5) as u32;
+                let spine_id = cmd.params["spine_id"].as_u64().map(|v| v as u32);
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Blend"); }
+                self.blend(&ids, steps, spine_id)
+            }
+            "update_blend" => {
+                let id = cmd.params["id"].as_u64().map(|v| v as u32).or_else(|| self.selected_ids.first().copied()).unwrap_or(0);
+                let steps = cmd.params["steps"].as_u64().map(|v| v as u32);
+                if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Blend Options"); }
+                self.update_blend(id, steps)
+            }
             "vectorize" => {
                 if cmd.params["save_undo"].as_bool().unwrap_or(true) { self.save_state("Vectorize Image"); }
                 let id = cmd.params["id"].as_u64().map(|v| v as u32).unwrap_or(0);
+                match cmd.params["mode"].as_str() {
+                    Some("color") => return self.vectorize_color(id, &cmd.params),
+                    Some("centerline") => return self.vectorize_centerline(id, &cmd.params),
+                    _ => {}
+                }
                 let threshold = cmd.params["threshold"].as_f64().unwrap_or(128.0) as u8;
+                let options = TraceOptions::from_params(&cmd.params);
                 let obj_info = if let Some(obj) = self.objects.iter().find(|o| o.id == id) { if let Some(raw_image) = &obj.raw_image { Some((obj.x, obj.y, obj.width, obj.height, obj.name.clone(), raw_image.clone())) } else { None } } else { None };
                 if let Some((ox, oy, ow, oh, oname, bytes)) = obj_info {
                     if let Ok(img) = image::load_from_memory(&bytes) {
-                        let grayscale = img.to_luma8(); let (width, height) = grayscale.dimensions();
-                        let tracer = Tracer::new(width, height); let mut path_data = tracer.trace(&grayscale, threshold);
-                        if !path_data.is_empty() {
-                            if let Ok(mut bez) = BezPath::from_svg(&path_data) {
-                                let sx = ow / width as f64; let sy = oh / height as f64;
-                                bez.apply_affine(Affine::scale_non_uniform(sx, sy)); path_data = bez.to_svg();
-                            }
+                        let rgba = img.to_rgba8(); let (width, height) = rgba.dimensions();
+                        let tracer = Tracer::with_options(width, height, options);
+                        let mut bez = tracer.trace_bitmap(&Bitmap::from_rgba(&rgba, threshold));
+                        if !bez.is_empty() {
+                            let sx = ow / width as f64; let sy = oh / height as f64;
+                            bez.apply_affine(Affine::scale_non_uniform(sx, sy));
                             let new_id = self.add_object(ShapeType::Path, ox, oy, ow, oh, "#000000");
-                            self.update_object(new_id, &serde_json::json!({ "path_data": path_data, "name": format!("Traced {}", oname), "fill": "transparent", "stroke": "#000000", "stroke_width": 1.0 }));
+                            self.update_object(new_id, &serde_json::json!({ "path_data": bez.to_svg(), "name": format!("Traced {}", oname), "fill": "#000000", "fill_rule": "evenodd", "stroke": "transparent", "stroke_width": 0.0 }));
                             format!("{{\"success\": true, \"id\": {}}}", new_id)
                         } else { "{ \"error\": \"No path generated\" }".to_string() }
                     } else { "{ \"error\": \"Failed to load image\" }".to_string() }
@@ -295,7 +484,15 @@ impl VectorEngine {
                 } else { "{ \"error\": \"Missing points array\" }".to_string() }
             }
             _ => format!("{{\"error\": \"Unknown action: {}\"}}", cmd.action),
-        }
+        };
+        self.sync_text_paths();
+        self.refresh_render_caches();
+        result
+    }
+
+    /// The ids listed in a command's params, or the selection without any.
+    pub(crate) fn parse_ids(&self, params: &serde_json::Value) -> Vec<u32> {
+        params["ids"].as_array().map(|a| a.iter().filter_map(|v| v.as_u64().map(|id| id as u32)).collect()).unwrap_or_else(|| self.selected_ids.clone())
     }
 
     pub(crate) fn add_object(&mut self, shape_type: ShapeType, x: f64, y: f64, width: f64, height: f64, fill: &str) -> u32 {
@@ -305,7 +502,7 @@ impl VectorEngine {
             id, shape_type, name, x, y, width, height, rotation: 0.0, fill: fill.to_string(), stroke: "#000000".to_string(), stroke_width: 1.0, opacity: 1.0, visible: true, locked: false, blend_mode: "source-over".to_string(), stroke_cap: "butt".to_string(), stroke_join: "miter".to_string(), stroke_dash: Vec::new(), layer_style: LayerStyle::default(), mask_id: None, is_mask: false, sides: 5, inner_radius: 0.5, corner_radius: 0.0, path_data: String::new(), 
             intelligent_type: String::new(),
             intelligent_params: Vec::new(),
-            brush_id: 0, stroke_points: Vec::new(), text_content: "Type here...".to_string(), font_family: "Inter, sans-serif".to_string(), font_size: 24.0, font_weight: "normal".to_string(), text_align: "left".to_string(), kerning: 0.0, leading: 1.2, tracking: 0.0, shadow_color: "transparent".to_string(), shadow_blur: 0.0, shadow_offset_x: 0.0, shadow_offset_y: 0.0, sx: 0.0, sy: 0.0, sw: 0.0, sh: 0.0, brightness: 1.0, contrast: 1.0, saturate: 1.0, hue_rotate: 0.0, blur: 0.0, grayscale: 0.0, sepia: 0.0, invert: 0.0, raw_image: None, raw_rgba: None, raw_rgba_width: 0, raw_rgba_height: 0, image: None, fill_gradient: None, stroke_gradient: None, children: None,
+            brush_id: 0, stroke_points: Vec::new(), text_content: "Type here...".to_string(), font_family: "Inter, sans-serif".to_string(), font_size: 24.0, font_weight: "normal".to_string(), text_align: "left".to_string(), kerning: 0.0, leading: 1.2, tracking: 0.0, shadow_color: "transparent".to_string(), shadow_blur: 0.0, shadow_offset_x: 0.0, shadow_offset_y: 0.0, sx: 0.0, sy: 0.0, sw: 0.0, sh: 0.0, brightness: 1.0, contrast: 1.0, saturate: 1.0, hue_rotate: 0.0, blur: 0.0, grayscale: 0.0, sepia: 0.0, invert: 0.0, raw_image: None, raw_rgba: None, raw_rgba_width: 0, raw_rgba_height: 0, image: None, fill_gradient: None, fill_rule: "nonzero".to_string(), stroke_gradient: None, children: None, blend: None, envelope: None, warp: None, width_profile: None, start_marker: None, end_marker: None, stroke_dash_offset: 0.0, stroke_dash_align: false, stroke_align: String::new(), appearance: Vec::new(), corners: None, area_text: false, text_path: None, text_runs: Vec::new(), paragraphs: Vec::new(),
         });
         self.next_id += 1;
         id
@@ -334,6 +531,7 @@ impl VectorEngine {
                     stops,
                 });
             }
+            if let Some(v) = params["fill_rule"].as_str() { obj.fill_rule = v.to_string(); }
             if let Some(v) = params["stroke"].as_str() { obj.stroke = v.to_string(); obj.stroke_gradient = None; }
             if let Some(grad) = params["stroke_gradient"].as_object() {
                 let mut stops = Vec::new();
@@ -353,11 +551,26 @@ impl VectorEngine {
             if let Some(v) = params["stroke_cap"].as_str() { obj.stroke_cap = v.to_string(); }
             if let Some(v) = params["stroke_join"].as_str() { obj.stroke_join = v.to_string(); }
             if let Some(arr) = params["stroke_dash"].as_array() { obj.stroke_dash = arr.iter().filter_map(|v| v.as_f64()).collect(); }
+            if let Some(v) = params["stroke_dash_offset"].as_f64() { obj.stroke_dash_offset = v; }
+            if let Some(v) = params["stroke_dash_align"].as_bool() { obj.stroke_dash_align = v; }
+            if let Some(v) = params["stroke_align"].as_str() { obj.stroke_align = v.to_string(); }
             if let Some(v) = params["name"].as_str() { obj.name = v.to_string(); }
             if let Some(v) = params["locked"].as_bool() { obj.locked = v; }
             if let Some(v) = params["sides"].as_u64() { obj.sides = v as u32; }
             if let Some(v) = params["inner_radius"].as_f64() { obj.inner_radius = v; }
-            if let Some(v) = params["corner_radius"].as_f64() { obj.corner_radius = v; }
+            if let Some(v) = params["corner_radius"].as_f64() {
+                obj.corner_radius = v;
+                if let Some(c) = obj.corners.as_mut() { c.radius = v.max(0.0); }
+            }
+            if let Some(v) = params.get("corners") { obj.corners = serde_json::from_value(v.clone()).ok(); }
+            if let Some(arr) = params["corner_radii"].as_array().filter(|_| obj.shape_type == ShapeType::Rectangle) {
+                // Top-left, top-right, bottom-right, bottom-left
+                let radius = obj.corner_radius.max(0.0);
+                let corners = obj.corners.get_or_insert_with(|| LiveCorners { radius, ..Default::default() });
+                for (node, r) in arr.iter().take(4).enumerate() {
+                    if let Some(r) = r.as_f64() { corners.set(0, node, r.max(0.0), None); }
+                }
+            }
             if let Some(v) = params["intelligent_type"].as_str() { obj.intelligent_type = v.to_string(); }
             if let Some(arr) = params["intelligent_params"].as_array() {
                 obj.intelligent_params = arr.iter().filter_map(|v| v.as_f64()).collect();
@@ -389,7 +602,22 @@ impl VectorEngine {
             if let Some(v) = params["shadow_offset_y"].as_f64() { obj.shadow_offset_y = v; }
             if let Some(v) = params["is_mask"].as_bool() { obj.is_mask = v; }
             if let Some(v) = params["mask_id"].as_u64() { obj.mask_id = Some(v as u32); }
+            if let Some(v) = params.get("warp") { obj.warp = serde_json::from_value(v.clone()).ok(); }
+            if let Some(v) = params.get("start_marker") { obj.start_marker = serde_json::from_value(v.clone()).ok(); }
+            if let Some(v) = params.get("end_marker") { obj.end_marker = serde_json::from_value(v.clone()).ok(); }
+            if let Some(v) = params.get("width_profile") { obj.width_profile = serde_json::from_value::<WidthProfile>(v.clone()).ok().map(|p| WidthProfile::new(p.stops)); }
+            if let Some(v) = params.get("appearance") { obj.appearance = serde_json::from_value(v.clone()).unwrap_or_default(); }
             if let Some(style_val) = params.get("layer_style") { if let Ok(style) = serde_json::from_value::<LayerStyle>(style_val.clone()) { obj.layer_style = style; } }
+            if let Some(v) = params["area_text"].as_bool() { obj.area_text = v; }
+            if let Some(v) = params.get("text_runs") { obj.text_runs = serde_json::from_value(v.clone()).unwrap_or_default(); }
+            if let Some(v) = params.get("paragraphs") { obj.paragraphs = serde_json::from_value(v.clone()).unwrap_or_default(); }
+            if let (Some(tp), Some(changes)) = (obj.text_path.as_mut(), params["text_path"].as_object()) {
+                // Only the placement changes here; the path comes from the linked object
+                if let Some(v) = changes.get("start_offset").and_then(|v| v.as_f64()) { tp.start_offset = v; }
+                if let Some(v) = changes.get("align").and_then(|v| serde_json::from_value(v.clone()).ok()) { tp.align = v; }
+                if let Some(v) = changes.get("flip").and_then(|v| v.as_bool()) { tp.flip = v; }
+            }
+            if obj.shape_type == ShapeType::Text && TEXT_LAYOUT_KEYS.iter().any(|k| params.get(k).is_some()) { fit_text_box(obj, &self.fonts); }
             true
         } else { false }
     }
@@ -401,6 +629,98 @@ impl VectorEngine {
         self.objects.len() < initial_len
     }
 
+    pub(crate) fn vectorize_centerline(&mut self, id: u32, params: &serde_json::Value) -> String {
+        let obj_info = self.objects.iter().find(|o| o.id == id).and_then(|o| o.raw_image.as_ref().map(|raw| (o.x, o.y, o.width, o.height, o.name.clone(), raw.clone())));
+        let Some((ox, oy, ow, oh, oname, bytes)) = obj_info else { return "{ \"error\": \"Object not found or no raw image data\" }".to_string() };
+        let Ok(img) = image::load_from_memory(&bytes) else { return "{ \"error\": \"Failed to load image\" }".to_string() };
+        let rgba = img.to_rgba8(); let (width, height) = rgba.dimensions();
+        let threshold = params["threshold"].as_f64().unwrap_or(128.0) as u8;
+        let traced = trace_centerlines(&Bitmap::from_rgba(&rgba, threshold), &CenterlineOptions::from_params(params));
+
+        let (sx, sy) = (ow / width as f64, oh / height as f64);
+        let stroke = params["stroke"].as_str().unwrap_or("#000000").to_string();
+        let stroke_width = params["stroke_width"].as_f64().unwrap_or(traced.stroke_width * (sx + sy) / 2.0);
+        let mut children = Vec::new();
+        for mut bez in traced.strokes {
+            bez.apply_affine(Affine::scale_non_uniform(sx, sy));
+            let bbox = bez.bounding_box();
+            bez.apply_affine(Affine::translate((-bbox.x0, -bbox.y0)));
+            let child_id = self.next_id; self.next_id += 1;
+            let mut child = self.create_default_object(child_id, ShapeType::Path, bbox.x0, bbox.y0, bbox.width().max(1.0), bbox.height().max(1.0));
+            child.fill = "transparent".to_string();
+            child.stroke = stroke.clone();
+            child.stroke_width = stroke_width;
+            child.stroke_cap = "round".to_string();
+            child.stroke_join = "round".to_string();
+            child.path_data = bez.to_svg();
+            child.name = format!("Stroke {}", children.len() + 1);
+            children.push(child);
+        }
+        if children.is_empty() { return "{ \"error\": \"No path generated\" }".to_string(); }
+
+        let group_id = self.next_id; self.next_id += 1;
+        let mut group = self.create_default_object(group_id, ShapeType::Group, ox, oy, ow, oh);
+        group.name = format!("Centerlines {}", oname);
+        group.children = Some(children);
+        self.objects.push(group);
+        format!("{{\"success\": true, \"id\": {}}}", group_id)
+    }
+
+    pub(crate) fn vectorize_color(&mut self, id: u32, params: &serde_json::Value) -> String {
+        let obj_info = self.objects.iter().find(|o| o.id == id).and_then(|o| o.raw_image.as_ref().map(|raw| (o.x, o.y, o.width, o.height, o.name.clone(), raw.clone())));
+        let Some((ox, oy, ow, oh, oname, bytes)) = obj_info else { return "{ \"error\": \"Object not found or no raw image data\" }".to_string() };
+        let Ok(img) = image::load_from_memory(&bytes) else { return "{ \"error\": \"Failed to load image\" }".to_string() };
+        let rgba = img.to_rgba8(); let (width, height) = rgba.dimensions();
+
+        let colors = params["colors"].as_u64().unwrap_or(6) as usize;
+        let palette: Vec<_> = params["palette"].as_array().map(|a| a.iter().filter_map(|c| c.as_str().and_then(parse_hex)).collect()).unwrap_or_default();
+        let quantized = if palette.is_empty() {
+            quantize(rgba.as_raw(), width, height, QuantizeMethod::parse(params["method"].as_str().unwrap_or("kmeans")), colors)
+        } else { map_to_palette(rgba.as_raw(), width, height, palette) };
+
+        // "min_area" is the speckle size in pixels below which regions are dropped
+        let mut options = TraceOptions::from_params(params);
+        if let Some(v) = params["min_area"].as_u64() { options.turdsize = v as u32; }
+        let stacked = params["stacked"].as_bool().unwrap_or(true);
+
+        // Most common colour goes to the bottom. When stacking, each layer also covers every
+        // region above it so neighbouring shapes overlap instead of leaving hairline gaps.
+        let coverage = quantized.coverage();
+        let mut order: Vec<usize> = (0..quantized.palette.len()).filter(|&i| coverage[i] > 0).collect();
+        order.sort_by(|a, b| coverage[*b].cmp(&coverage[*a]));
+        let mut rank = vec![usize::MAX; quantized.palette.len()];
+        for (r, &i) in order.iter().enumerate() { rank[i] = r; }
+
+        let tracer = Tracer::with_options(width, height, options);
+        let scale = Affine::scale_non_uniform(ow / width as f64, oh / height as f64);
+        let mut children = Vec::new();
+        for (r, &ci) in order.iter().enumerate() {
+            let mask = quantized.indices.iter().map(|idx| match idx {
+                Some(i) if stacked => rank[*i] >= r,
+                Some(i) => *i == ci,
+                None => false,
+            }).collect();
+            let mut bez = tracer.trace_bitmap(&Bitmap::from_mask(width, height, mask));
+            if bez.is_empty() { continue; }
+            bez.apply_affine(scale);
+            let child_id = self.next_id; self.next_id += 1;
+            let mut child = self.create_default_object(child_id, ShapeType::Path, 0.0, 0.0, ow, oh);
+            child.fill = to_hex(quantized.palette[ci]);
+            child.fill_rule = "evenodd".to_string();
+            child.path_data = bez.to_svg();
+            child.name = format!("Color {}", child.fill);
+            children.push(child);
+        }
+        if children.is_empty() { return "{ \"error\": \"No path generated\" }".to_string(); }
+
+        let group_id = self.next_id; self.next_id += 1;
+        let mut group = self.create_default_object(group_id, ShapeType::Group, ox, oy, ow, oh);
+        group.name = format!("Traced {}", oname);
+        group.children = Some(children);
+        self.objects.push(group);
+        format!("{{\"success\": true, \"id\": {}}}", group_id)
+    }
+
     pub fn register_brush(&mut self, brush_json: &str) -> u32 {
         if let Ok(mut brush) = serde_json::from_str::<Brush>(brush_json) {
             let id = self.brush_engine.brushes.iter().map(|b| b.id).max().unwrap_or(0) + 1;
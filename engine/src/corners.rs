@@ -0,0 +1,226 @@
+use wasm_bindgen::prelude::*;
+use serde::{Serialize, Deserialize};
+use kurbo::{BezPath, CubicBez, Line, ParamCurve, ParamCurveArclen, PathSeg, Point, Vec2};
+use crate::engine::VectorEngine;
+use crate::geometry::shape_path;
+use crate::measure::tangent;
+use crate::objects::VectorObject;
+use crate::path_ops::{contours, contours_to_path};
+use crate::types::ShapeType;
+use crate::width_profile::CORNER_ANGLE;
+
+// Live corners: rounded, chamfered or inverted corners on rectangles,
+// polygons, stars and paths, kept as parameters over the sharp outline until
+// they are expanded. Corners are addressed like path nodes (`subpath` +
+// `node`, node i being the start of segment i); a rectangle's nodes are its
+// top-left, top-right, bottom-right and bottom-left corners.
+
+const ACCURACY: f64 = 1e-3;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CornerStyle {
+    #[default]
+    Round,
+    Chamfer,
+    Inverted, // A concave arc around the corner point
+}
+
+/// A corner with a radius of its own.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CornerNode {
+    #[serde(default)]
+    pub subpath: usize,
+    pub node: usize,
+    pub radius: f64,
+    #[serde(default)]
+    pub style: Option<CornerStyle>, // `None` for the object's style
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct LiveCorners {
+    #[serde(default)]
+    pub style: CornerStyle,
+    #[serde(default)]
+    pub radius: f64, // Every corner without a radius of its own
+    #[serde(default)]
+    pub nodes: Vec<CornerNode>,
+}
+
+impl LiveCorners {
+    fn at(&self, subpath: usize, node: usize) -> (CornerStyle, f64) {
+        match self.nodes.iter().rev().find(|n| n.subpath == subpath && n.node == node) {
+            Some(n) => (n.style.unwrap_or(self.style), n.radius),
+            None => (self.style, self.radius),
+        }
+    }
+
+    /// Sets the radius of one corner, replacing any earlier one.
+    pub fn set(&mut self, subpath: usize, node: usize, radius: f64, style: Option<CornerStyle>) {
+        self.nodes.retain(|n| n.subpath != subpath || n.node != node);
+        self.nodes.push(CornerNode { subpath, node, radius, style });
+    }
+}
+
+/// Whether live corners apply to the object's outline.
+pub fn has_corners(obj: &VectorObject) -> bool {
+    obj.brush_id == 0 && matches!(obj.shape_type, ShapeType::Rectangle | ShapeType::Polygon | ShapeType::Star | ShapeType::Path)
+}
+
+struct Cut {
+    style: CornerStyle,
+    turn: f64, // Change of direction at the corner, 0 to pi
+    trim: f64, // Length taken off each neighbouring segment
+}
+
+/// The path with its corners cut. The trim on each side is the distance at
+/// which a round of the corner's radius meets the segments, so all styles
+/// share it; trims are shortened so neighbouring corners never overlap.
+/// Smooth nodes and the ends of open subpaths are left alone.
+pub fn round_corners(path: &BezPath, corners: &LiveCorners) -> BezPath {
+    let mut result = contours(path);
+    for (ci, c) in result.iter_mut().enumerate() {
+        let n = c.segs.len();
+        let next = |i: usize| if c.closed { Some((i + 1) % n) } else { (i + 1 < n).then_some(i + 1) };
+        let mut cuts: Vec<Option<Cut>> = (0..n).map(|j| {
+            if !c.closed && j == 0 { return None; }
+            let (style, radius) = corners.at(ci, j);
+            if radius <= 0.0 { return None; }
+            let (u, v) = (tangent(&c.segs[(j + n - 1) % n], 1.0), tangent(&c.segs[j], 0.0));
+            let turn = u.cross(v).atan2(u.dot(v)).abs();
+            if !(CORNER_ANGLE..std::f64::consts::PI - CORNER_ANGLE).contains(&turn) { return None; }
+            Some(Cut { style, turn, trim: radius * (turn / 2.0).tan() })
+        }).collect();
+        if cuts.iter().all(Option::is_none) { continue; }
+
+        let lengths: Vec<f64> = c.segs.iter().map(|s| s.arclen(ACCURACY)).collect();
+        let trim = |cuts: &[Option<Cut>], j: usize| cuts[j].as_ref().map_or(0.0, |k| k.trim);
+        // A segment too short for both of its corners shrinks both in proportion
+        let scales: Vec<f64> = (0..n).map(|j| {
+            let mut scale = 1.0_f64;
+            let mut fit = |seg: usize, other: Option<usize>| {
+                let total = trim(&cuts, j) + other.map_or(0.0, |o| trim(&cuts, o));
+                if total > lengths[seg] { scale = scale.min(lengths[seg] / total); }
+            };
+            fit(j, next(j));
+            let prev = (j + n - 1) % n;
+            fit(prev, Some(prev));
+            scale
+        }).collect();
+        for (cut, scale) in cuts.iter_mut().zip(&scales) {
+            if let Some(k) = cut { k.trim *= scale; }
+        }
+
+        // Parameter range of each segment left after trimming
+        let ranges: Vec<(f64, f64)> = (0..n).map(|i| {
+            let seg = &c.segs[i];
+            let start = trim(&cuts, i);
+            let end = next(i).map_or(0.0, |j| trim(&cuts, j));
+            let t0 = if start > 0.0 { seg.inv_arclen(start, ACCURACY) } else { 0.0 };
+            let t1 = if end > 0.0 { seg.inv_arclen((lengths[i] - end).max(0.0), ACCURACY) } else { 1.0 };
+            (t0, t1.max(t0))
+        }).collect();
+
+        let mut segs = Vec::new();
+        for i in 0..n {
+            let (t0, t1) = ranges[i];
+            if t1 - t0 > 1e-9 { segs.push(c.segs[i].subsegment(t0..t1)); }
+            let Some(j) = next(i) else { continue };
+            let Some(cut) = &cuts[j] else { continue };
+            let (a, b) = (c.segs[i].eval(t1), c.segs[j].eval(ranges[j].0));
+            let (ta, tb) = (tangent(&c.segs[i], t1), tangent(&c.segs[j], ranges[j].0));
+            segs.push(corner(cut, a, ta, b, tb, c.segs[j].eval(0.0)));
+        }
+        c.segs = segs;
+    }
+    contours_to_path(&result)
+}
+
+/// The piece replacing a corner at `apex`, from `a` on the incoming segment
+/// to `b` on the outgoing one.
+fn corner(cut: &Cut, a: Point, ta: Vec2, b: Point, tb: Vec2, apex: Point) -> PathSeg {
+    match cut.style {
+        CornerStyle::Chamfer => PathSeg::Line(Line::new(a, b)),
+        CornerStyle::Round => {
+            let radius = cut.trim / (cut.turn / 2.0).tan();
+            let handle = 4.0 / 3.0 * (cut.turn / 4.0).tan() * radius;
+            PathSeg::Cubic(CubicBez::new(a, a + ta * handle, b - tb * handle, b))
+        }
+        CornerStyle::Inverted => {
+            let (ra, rb) = (a - apex, b - apex);
+            let sweep = ra.cross(rb).atan2(ra.dot(rb)).abs();
+            let k = 4.0 / 3.0 * (sweep / 4.0).tan();
+            // Directions around the apex, heading from a towards b
+            let chord = b - a;
+            let along = |r: Vec2| {
+                let perp = Vec2::new(-r.y, r.x);
+                if perp.dot(chord) < 0.0 { -perp } else { perp }
+            };
+            PathSeg::Cubic(CubicBez::new(a, a + along(ra) * k, b - along(rb) * k, b))
+        }
+    }
+}
+
+/// The object as a plain path with its corners cut, keeping its box and
+/// transform, or `None` when it has no live corners.
+pub fn expand_corners(obj: &VectorObject) -> Option<VectorObject> {
+    if obj.corners.is_none() || !has_corners(obj) { return None; }
+    let path = shape_path(obj)?;
+    let mut out = obj.clone();
+    out.path_data = path.to_svg();
+    out.shape_type = ShapeType::Path;
+    out.corners = None;
+    Some(out)
+}
+
+/// Copies of the objects with their live corners expanded, for exporters.
+pub fn expand_all_corners(objects: &[VectorObject]) -> Vec<VectorObject> {
+    objects.iter().map(|obj| {
+        if let Some(expanded) = expand_corners(obj) { return expanded; }
+        let mut out = obj.clone();
+        if let Some(children) = out.children.as_mut() { *children = expand_all_corners(children); }
+        out
+    }).collect()
+}
+
+#[wasm_bindgen]
+impl VectorEngine {
+    /// Rounds corners of the objects: `radius` and `style` for every corner,
+    /// and `nodes` for corners with radii of their own, merged into any
+    /// already set. A rectangle starts from its corner radius.
+    pub(crate) fn set_live_corners(&mut self, ids: &[u32], params: &serde_json::Value) -> String {
+        let style = match params.get("style").map(|v| serde_json::from_value::<CornerStyle>(v.clone())).transpose() {
+            Ok(s) => s,
+            Err(e) => return serde_json::json!({ "error": format!("Invalid corner style: {}", e) }).to_string(),
+        };
+        let nodes = match params.get("nodes").map(|v| serde_json::from_value::<Vec<CornerNode>>(v.clone())).transpose() {
+            Ok(n) => n.unwrap_or_default(),
+            Err(e) => return serde_json::json!({ "error": format!("Invalid corner: {}", e) }).to_string(),
+        };
+        let mut changed = Vec::new();
+        for obj in self.objects.iter_mut().filter(|o| ids.contains(&o.id) && has_corners(o)) {
+            let seed = if obj.shape_type == ShapeType::Rectangle { obj.corner_radius } else { 0.0 };
+            let corners = obj.corners.get_or_insert_with(|| LiveCorners { radius: seed, ..Default::default() });
+            if let Some(s) = style { corners.style = s; }
+            if let Some(r) = params["radius"].as_f64() { corners.radius = r.max(0.0); }
+            for n in &nodes { corners.set(n.subpath, n.node, n.radius.max(0.0), n.style); }
+            if corners.radius <= 0.0 && corners.nodes.iter().all(|n| n.radius <= 0.0) { obj.corners = None; }
+            changed.push(obj.id);
+        }
+        if changed.is_empty() { return "{ \"error\": \"No shapes with corners to round\" }".to_string(); }
+        serde_json::json!({ "success": true, "ids": changed }).to_string()
+    }
+
+    /// Bakes live corners into the objects' path data, in the same box so a
+    /// warp or envelope on the object stays as it was.
+    pub(crate) fn expand_live_corners(&mut self, ids: &[u32]) -> String {
+        let mut changed = Vec::new();
+        for obj in self.objects.iter_mut().filter(|o| ids.contains(&o.id)) {
+            let Some(expanded) = expand_corners(obj) else { continue };
+            *obj = expanded;
+            changed.push(obj.id);
+        }
+        if changed.is_empty() { return "{ \"error\": \"No live corners to expand\" }".to_string(); }
+        serde_json::json!({ "success": true, "ids": changed }).to_string()
+    }
+}
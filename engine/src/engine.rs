@@ -2,6 +2,9 @@ use wasm_bindgen::prelude::*;
 use crate::types::{Artboard, Guide};
 use crate::objects::VectorObject;
 use crate::brush::BrushEngine;
+use crate::text::{FontRegistry, TextOutline};
+use crate::text_layout::migrate_text_align;
+use crate::warp::WarpedObject;
 use web_sys::HtmlImageElement;
 use std::collections::HashMap;
 
@@ -30,6 +33,9 @@ pub struct VectorEngine {
     pub(crate) redo_stack: Vec<EngineState>,
     pub(crate) brush_engine: BrushEngine,
     pub(crate) brush_image_map: HashMap<String, HtmlImageElement>,
+    pub(crate) fonts: FontRegistry,
+    pub(crate) warped_objects: HashMap<u32, WarpedObject>,
+    pub(crate) text_outlines: HashMap<u32, TextOutline>,
 }
 
 #[wasm_bindgen]
@@ -57,6 +63,9 @@ impl VectorEngine {
             redo_stack: Vec::new(),
             brush_engine: BrushEngine::new(),
             brush_image_map: HashMap::new(),
+            fonts: FontRegistry::default(),
+            warped_objects: HashMap::new(),
+            text_outlines: HashMap::new(),
         }
     }
 
@@ -93,6 +102,7 @@ impl VectorEngine {
             self.selected_ids = prev_state.selected_ids;
             self.artboard = prev_state.artboard;
             self.clip_to_artboard = prev_state.clip_to_artboard;
+            self.refresh_render_caches();
             true
         } else {
             false
@@ -116,6 +126,7 @@ impl VectorEngine {
             self.selected_ids = next_state.selected_ids;
             self.artboard = next_state.artboard;
             self.clip_to_artboard = next_state.clip_to_artboard;
+            self.refresh_render_caches();
             true
         } else {
             false
@@ -146,8 +157,12 @@ impl VectorEngine {
     }
 
     pub fn set_objects(&mut self, objects_json: &str) {
-        if let Ok(objs) = serde_json::from_str::<Vec<VectorObject>>(objects_json) {
+        if let Ok(mut objs) = serde_json::from_str::<Vec<VectorObject>>(objects_json) {
+            let saved: Vec<serde_json::Value> = serde_json::from_str(objects_json).unwrap_or_default();
+            for (obj, saved) in objs.iter_mut().zip(&saved) { migrate_text_align(obj, saved); }
             self.objects = objs;
+            self.sync_text_paths();
         }
+        self.refresh_render_caches();
     }
 }
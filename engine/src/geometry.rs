@@ -0,0 +1,197 @@
+use wasm_bindgen::prelude::*;
+use kurbo::{Affine, BezPath, Ellipse, Point, Rect, RoundedRect, Shape};
+use crate::corners::{has_corners, round_corners};
+use crate::engine::VectorEngine;
+use crate::objects::VectorObject;
+use crate::text::{text_to_path, FontRegistry};
+use crate::types::ShapeType;
+
+// Shared outline geometry for every object type. Paths are built in the
+// object's local space (origin at the top-left of its box, before rotation),
+// matching what the canvas renderer draws.
+
+/// Local-to-parent transform: rotation happens about the centre of the box.
+pub fn object_transform(obj: &VectorObject) -> Affine {
+    Affine::translate((obj.x + obj.width / 2.0, obj.y + obj.height / 2.0))
+        * Affine::rotate(obj.rotation)
+        * Affine::translate((-obj.width / 2.0, -obj.height / 2.0))
+}
+
+/// Outline of a parametric shape or path, with any live corners cut; `None`
+/// for text, groups, images and other objects without intrinsic vector
+/// geometry.
+pub fn shape_path(obj: &VectorObject) -> Option<BezPath> {
+    let path = sharp_path(obj)?;
+    match &obj.corners {
+        Some(corners) if has_corners(obj) => Some(round_corners(&path, corners)),
+        _ => Some(path),
+    }
+}
+
+fn sharp_path(obj: &VectorObject) -> Option<BezPath> {
+    let (w, h) = (obj.width, obj.height);
+    match obj.shape_type {
+        ShapeType::Rectangle => {
+            // Live corners replace the single corner radius
+            let r = if obj.corners.is_some() { 0.0 } else { obj.corner_radius.min(w / 2.0).min(h / 2.0).max(0.0) };
+            if r > 0.0 { Some(RoundedRect::new(0.0, 0.0, w, h, r).to_path(0.1)) } else { Some(Rect::new(0.0, 0.0, w, h).to_path(0.1)) }
+        }
+        ShapeType::Circle | ShapeType::Ellipse => {
+            let mut path = Ellipse::new((w / 2.0, h / 2.0), (w / 2.0, h / 2.0), 0.0).to_path(0.1);
+            path.close_path();
+            Some(path)
+        }
+        ShapeType::Polygon => Some(polygon_path(w / 2.0, h / 2.0, w / 2.0, obj.sides)),
+        ShapeType::Star => Some(star_path(w / 2.0, h / 2.0, w / 2.0, obj.inner_radius * (w / 2.0), obj.sides)),
+        ShapeType::Intelligent => {
+            let shape = crate::intelligent_shapes::get_shape_by_id(&obj.intelligent_type)?;
+            BezPath::from_svg(&shape.generate_path(w, h, &obj.intelligent_params)).ok()
+        }
+        ShapeType::Path => BezPath::from_svg(&obj.path_data).ok(),
+        _ => None,
+    }
+}
+
+/// Outline of any object in local space, including text glyphs, the combined
+/// outlines of a group's children and any warp effect.
+pub fn object_path(obj: &VectorObject, fonts: &FontRegistry) -> Result<BezPath, String> {
+    let path = base_path(obj, fonts)?;
+    match &obj.warp {
+        Some(effect) if obj.brush_id == 0 => Ok(effect.apply(&path, obj.width, obj.height)),
+        _ => Ok(path),
+    }
+}
+
+fn base_path(obj: &VectorObject, fonts: &FontRegistry) -> Result<BezPath, String> {
+    match obj.shape_type {
+        ShapeType::Text => text_to_path(obj, fonts),
+        ShapeType::Group => {
+            let mut combined = BezPath::new();
+            for child in obj.children.iter().flatten() {
+                if !child.visible { continue; }
+                if let Ok(mut p) = object_path(child, fonts) {
+                    p.apply_affine(object_transform(child));
+                    combined.extend(p);
+                }
+            }
+            Ok(combined)
+        }
+        _ => shape_path(obj).ok_or_else(|| format!("{:?} objects have no vector outline", obj.shape_type)),
+    }
+}
+
+/// Outline of any object in its parent's coordinate space.
+pub fn world_path(obj: &VectorObject, fonts: &FontRegistry) -> Result<BezPath, String> {
+    let mut path = object_path(obj, fonts)?;
+    path.apply_affine(object_transform(obj));
+    Ok(path)
+}
+
+/// Stores a local-space path on the object as a plain Path, shrinking the box to
+/// the path's bounds. The box is moved so the geometry stays put on the canvas,
+/// rotation included.
+pub fn set_local_path(obj: &mut VectorObject, mut path: BezPath) {
+    let bbox = path.bounding_box();
+    let (bw, bh) = (bbox.width(), bbox.height());
+    let old_centre = Point::new(obj.x + obj.width / 2.0, obj.y + obj.height / 2.0);
+    let offset = Affine::rotate(obj.rotation) * Point::new(bbox.x0 - obj.width / 2.0 + bw / 2.0, bbox.y0 - obj.height / 2.0 + bh / 2.0);
+    let centre = old_centre + offset.to_vec2();
+    path.apply_affine(Affine::translate((-bbox.x0, -bbox.y0)));
+    obj.x = centre.x - bw / 2.0;
+    obj.y = centre.y - bh / 2.0;
+    obj.width = bw;
+    obj.height = bh;
+    obj.path_data = path.to_svg();
+    obj.shape_type = ShapeType::Path;
+    obj.intelligent_type.clear();
+    obj.intelligent_params.clear();
+    obj.text_content.clear();
+    obj.envelope = None;
+    obj.warp = None;
+    obj.corners = None;
+    obj.text_path = None;
+    obj.text_runs.clear();
+    obj.paragraphs.clear();
+}
+
+pub fn polygon_path(cx: f64, cy: f64, r: f64, sides: u32) -> BezPath {
+    let mut path = BezPath::new();
+    for i in 0..sides {
+        let angle = i as f64 * 2.0 * std::f64::consts::PI / sides as f64;
+        let p = Point::new(cx + r * angle.cos(), cy + r * angle.sin());
+        if i == 0 { path.move_to(p); } else { path.line_to(p); }
+    }
+    path.close_path();
+    path
+}
+
+pub fn star_path(cx: f64, cy: f64, r_outer: f64, r_inner: f64, points: u32) -> BezPath {
+    let mut path = BezPath::new();
+    for i in 0..(points * 2) {
+        let r = if i % 2 == 0 { r_outer } else { r_inner };
+        let angle = (i as f64 * std::f64::consts::PI / points as f64) - (std::f64::consts::PI / 2.0);
+        let p = Point::new(cx + r * angle.cos(), cy + r * angle.sin());
+        if i == 0 { path.move_to(p); } else { path.line_to(p); }
+    }
+    path.close_path();
+    path
+}
+
+/// Replaces an object's parametric geometry with an equivalent path, keeping its
+/// box, rotation and styling so it renders identically. Groups are converted
+/// child by child.
+fn bake_to_path(obj: &mut VectorObject, fonts: &FontRegistry) -> Result<bool, String> {
+    match obj.shape_type {
+        ShapeType::Path if obj.warp.is_none() => Ok(false),
+        ShapeType::Group => {
+            // A warp on the group is pushed down into the children first
+            let mut changed = false;
+            if let Some(expanded) = crate::warp::expand_warp(obj, fonts) { *obj = expanded; changed = true; }
+            for child in obj.children.iter_mut().flatten() {
+                // Images and other raster children are left as they are
+                match bake_to_path(child, fonts) {
+                    Ok(c) => changed |= c,
+                    Err(e) if child.shape_type == ShapeType::Text => return Err(e),
+                    Err(_) => {}
+                }
+            }
+            Ok(changed)
+        }
+        _ => {
+            let path = object_path(obj, fonts)?;
+            obj.path_data = path.to_svg();
+            obj.shape_type = ShapeType::Path;
+            obj.intelligent_type.clear();
+            obj.intelligent_params.clear();
+            obj.text_content.clear();
+            obj.warp = None;
+            obj.corners = None;
+            obj.text_path = None;
+            obj.text_runs.clear();
+            obj.paragraphs.clear();
+            Ok(true)
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl VectorEngine {
+    pub(crate) fn convert_to_path(&mut self, ids: &[u32]) -> String {
+        let mut converted = Vec::new();
+        let mut error = None;
+        for &id in ids {
+            let Some(idx) = self.objects.iter().position(|o| o.id == id) else { continue };
+            let mut obj = self.objects[idx].clone();
+            match bake_to_path(&mut obj, &self.fonts) {
+                Ok(true) => { self.objects[idx] = obj; converted.push(id); }
+                Ok(false) => {}
+                Err(e) => { error.get_or_insert(e); }
+            }
+        }
+        if converted.is_empty() {
+            let msg = error.unwrap_or_else(|| "Nothing to convert".to_string());
+            return serde_json::json!({ "error": msg }).to_string();
+        }
+        serde_json::json!({ "success": true, "ids": converted }).to_string()
+    }
+}
@@ -7,6 +7,40 @@ use crate::ai::{Ai, AiParser};
 use image::{RgbaImage, DynamicImage, ImageOutputFormat};
 use std::io::Cursor;
 use base64::{Engine as _, engine::general_purpose};
+use serde::Deserialize;
+
+/// Options shared by the vector exporters.
+#[derive(Deserialize, Default)]
+pub struct ExportOptions {
+    #[serde(default)]
+    pub outline_text: bool, // Glyph outlines instead of live text, so viewers don't need the fonts
+    #[serde(default)]
+    pub precision: Option<u32>, // Decimal places kept in SVG coordinates; all of them when unset
+    #[serde(default)]
+    pub minify_paths: bool, // Shortest SVG path data, relative or absolute command by command
+    #[serde(default)]
+    pub merge_gradients: bool, // Identical SVG gradients share one definition
+    #[serde(default)]
+    pub strip_hidden: bool, // Leaves hidden objects out of SVG, with whatever else draws nothing
+    #[serde(default)]
+    pub semantic: bool, // SVG with names as ids or classes, colours as CSS custom properties and labelled groups
+}
+
+impl VectorEngine {
+    /// The objects reduced to what the vector exporters understand: warps,
+    /// live corners, appearance stacks, brush strokes and expanded strokes
+    /// baked in. SVG keeps path markers and text on a path as elements of its
+    /// own, and gets masks as paths for its clip paths; other outputs get
+    /// them as geometry.
+    pub(crate) fn export_objects(&self, options: &ExportOptions, svg: bool) -> Vec<VectorObject> {
+        let objects = if svg { crate::svg::bake_masks(&self.objects, &self.fonts) } else { self.objects.clone() };
+        let objects = if options.outline_text || !svg { crate::text::outline_texts(&objects, &self.fonts, options.outline_text) } else { objects };
+        let objects = crate::brush::outline_brushes(&objects, &self.brush_engine);
+        let objects = crate::warp::expand_warps(&objects, &self.fonts);
+        let objects = crate::corners::expand_all_corners(&objects);
+        crate::stroke::expand_strokes(&crate::appearance::expand_appearances(&objects), svg)
+    }
+}
 
 #[wasm_bindgen]
 impl VectorEngine {
@@ -151,7 +185,13 @@ impl VectorEngine {
         psd.to_bytes().unwrap_or_default()
     }
 
-    pub fn export_ai(&self) -> Vec<u8> { Ai::export(self.artboard.width, self.artboard.height, &self.objects) }
+    pub fn export_ai(&self) -> Vec<u8> { self.export_ai_with_options("{}") }
+
+    /// AI export with `ExportOptions` as JSON.
+    pub fn export_ai_with_options(&self, options: &str) -> Vec<u8> {
+        let options: ExportOptions = serde_json::from_str(options).unwrap_or_default();
+        Ai::export(self.artboard.width, self.artboard.height, &self.export_objects(&options, false), &self.fonts)
+    }
 
     fn import_ai(&mut self, data: &[u8]) -> String {
         let mut parser = AiParser::new(data);
@@ -170,7 +210,7 @@ impl VectorEngine {
             id, shape_type, name: format!("{:?} {}", shape_type, id), x, y, width, height, rotation: 0.0, fill: "#000000".to_string(), stroke: "transparent".to_string(), stroke_width: 0.0, opacity: 1.0, visible: true, locked: false, blend_mode: "source-over".to_string(), stroke_cap: "butt".to_string(), stroke_join: "miter".to_string(), stroke_dash: Vec::new(), layer_style: crate::types::LayerStyle::default(), mask_id: None, is_mask: false, sides: 4, inner_radius: 0.0, corner_radius: 0.0, path_data: String::new(), 
             intelligent_type: String::new(),
             intelligent_params: Vec::new(),
-            brush_id: 0, stroke_points: Vec::new(), text_content: String::new(), font_family: "Inter, sans-serif".to_string(), font_size: 24.0, font_weight: "normal".to_string(), text_align: "left".to_string(), kerning: 0.0, leading: 1.2, tracking: 0.0, shadow_color: "transparent".to_string(), shadow_blur: 0.0, shadow_offset_x: 0.0, shadow_offset_y: 0.0, sx: 0.0, sy: 0.0, sw: width.max(1.0), sh: height.max(1.0), brightness: 1.0, contrast: 1.0, saturate: 1.0, hue_rotate: 0.0, blur: 0.0, grayscale: 0.0, sepia: 0.0, invert: 0.0, raw_image: None, raw_rgba: None, raw_rgba_width: 0, raw_rgba_height: 0, image: None, fill_gradient: None, stroke_gradient: None, children: None,
+            brush_id: 0, stroke_points: Vec::new(), text_content: String::new(), font_family: "Inter, sans-serif".to_string(), font_size: 24.0, font_weight: "normal".to_string(), text_align: "left".to_string(), kerning: 0.0, leading: 1.2, tracking: 0.0, shadow_color: "transparent".to_string(), shadow_blur: 0.0, shadow_offset_x: 0.0, shadow_offset_y: 0.0, sx: 0.0, sy: 0.0, sw: width.max(1.0), sh: height.max(1.0), brightness: 1.0, contrast: 1.0, saturate: 1.0, hue_rotate: 0.0, blur: 0.0, grayscale: 0.0, sepia: 0.0, invert: 0.0, raw_image: None, raw_rgba: None, raw_rgba_width: 0, raw_rgba_height: 0, image: None, fill_gradient: None, fill_rule: "nonzero".to_string(), stroke_gradient: None, children: None, blend: None, envelope: None, warp: None, width_profile: None, start_marker: None, end_marker: None, stroke_dash_offset: 0.0, stroke_dash_align: false, stroke_align: String::new(), appearance: Vec::new(), corners: None, area_text: false, text_path: None, text_runs: Vec::new(), paragraphs: Vec::new(),
         }
     }
 }
@@ -0,0 +1,210 @@
+use wasm_bindgen::prelude::*;
+use kurbo::{BezPath, Line, ParamCurve, PathSeg, Point, Shape};
+use crate::engine::VectorEngine;
+use crate::geometry::{object_transform, set_local_path, world_path};
+use crate::path_ops::{close_contour, contours, contours_to_path, nearest_on_contours, Contour};
+
+// Knife: cuts filled shapes along a polyline into separate closed pieces.
+// The knife is split into chords, the stretches between consecutive crossings
+// with a piece's outline that run through its interior. A chord joining two
+// points of the same contour splits the piece in two; a chord joining two
+// different contours (outer edge to a hole) first merges them into a single
+// keyhole contour, which a later chord can then split.
+
+const EPSILON: f64 = 1e-7;
+const MAX_CUTS: usize = 1000;
+
+struct Hit {
+    knife_t: f64, // Segment index plus parameter along the knife polyline
+    contour: usize,
+    seg: usize,
+    t: f64,
+    point: Point,
+}
+
+fn knife_point(knife: &[Point], kt: f64) -> Point {
+    let i = (kt.floor() as usize).min(knife.len() - 2);
+    Line::new(knife[i], knife[i + 1]).eval(kt - i as f64)
+}
+
+/// Knife polyline between two knife parameters, as line segments.
+fn chord(knife: &[Point], from: f64, to: f64) -> Vec<PathSeg> {
+    let mut pts = vec![knife_point(knife, from)];
+    let first = from.floor() as usize + 1;
+    let last = to.ceil() as usize;
+    for p in knife.iter().take(last).skip(first) { pts.push(*p); }
+    pts.push(knife_point(knife, to));
+    pts.dedup_by(|a, b| a.distance(*b) <= EPSILON);
+    pts.windows(2).map(|w| PathSeg::Line(Line::new(w[0], w[1]))).collect()
+}
+
+fn reversed(segs: &[PathSeg]) -> Vec<PathSeg> {
+    segs.iter().rev().map(|s| s.reverse()).collect()
+}
+
+fn hits(knife: &[Point], piece: &[Contour]) -> Vec<Hit> {
+    let mut hits = Vec::new();
+    for (k, w) in knife.windows(2).enumerate() {
+        let line = Line::new(w[0], w[1]);
+        for (ci, c) in piece.iter().enumerate() {
+            if !c.closed { continue; }
+            for (si, seg) in c.segs.iter().enumerate() {
+                // Earlier chords lie on the knife itself and never count as crossings
+                if let PathSeg::Line(l) = seg {
+                    let d = line.p1 - line.p0;
+                    let off = |p: Point| d.cross(p - line.p0).abs() / d.hypot().max(EPSILON);
+                    if off(l.p0) <= 1e-6 && off(l.p1) <= 1e-6 { continue; }
+                }
+                for hit in seg.intersect_line(line) {
+                    let t = hit.segment_t.clamp(0.0, 1.0);
+                    hits.push(Hit { knife_t: k as f64 + hit.line_t, contour: ci, seg: si, t, point: seg.eval(t) });
+                }
+            }
+        }
+    }
+    hits.sort_by(|a, b| a.knife_t.partial_cmp(&b.knife_t).unwrap_or(std::cmp::Ordering::Equal));
+    // A crossing exactly at a node shows up on both neighbouring segments
+    hits.dedup_by(|a, b| a.point.distance(b.point) <= 1e-6);
+    hits
+}
+
+fn filled(path: &BezPath, p: Point, evenodd: bool) -> bool {
+    let w = path.winding(p);
+    if evenodd { w % 2 != 0 } else { w != 0 }
+}
+
+fn area(c: &Contour) -> f64 {
+    contours_to_path(std::slice::from_ref(c)).area()
+}
+
+fn inside(piece: &[Contour], p: Point, evenodd: bool) -> bool {
+    filled(&contours_to_path(piece), p, evenodd)
+}
+
+/// Applies one chord to a piece, returning the resulting pieces.
+fn cut_piece(piece: &[Contour], a: &Hit, b: &Hit, chord: Vec<PathSeg>, evenodd: bool) -> Vec<Vec<Contour>> {
+    let opened_a = piece[a.contour].split(a.seg, a.t).remove(0);
+    if a.contour != b.contour {
+        let mut opened_b = piece[b.contour].split(b.seg, b.t).remove(0);
+        // The keyhole only stays simple when the hole runs against the outer edge
+        if area(&piece[a.contour]).signum() == area(&piece[b.contour]).signum() { opened_b.reverse(); }
+        let mut segs = opened_a.segs;
+        segs.extend(chord.iter().cloned());
+        segs.extend(opened_b.segs);
+        segs.extend(reversed(&chord));
+        let mut merged: Vec<Contour> = piece.iter().enumerate().filter(|(i, _)| *i != a.contour && *i != b.contour).map(|(_, c)| c.clone()).collect();
+        merged.insert(0, Contour { segs, closed: true });
+        return vec![merged];
+    }
+
+    let opened = [opened_a];
+    let Some((_, si, t, _)) = nearest_on_contours(&opened, b.point) else { return vec![piece.to_vec()] };
+    let halves = opened[0].split(si, t);
+    if halves.len() < 2 { return vec![piece.to_vec()]; }
+    let mut first = halves[0].clone();
+    first.segs.extend(reversed(&chord));
+    first.closed = true;
+    let mut second = halves[1].clone();
+    second.segs.extend(chord);
+    second.closed = true;
+
+    let mut pieces = vec![vec![first], vec![second]];
+    for (i, c) in piece.iter().enumerate() {
+        if i == a.contour { continue; }
+        let probe = c.start();
+        let target = if inside(&pieces[1][..1], probe, evenodd) { 1 } else { 0 };
+        pieces[target].push(c.clone());
+    }
+    pieces
+}
+
+/// Cuts a world-space region along the knife, returning the pieces or `None`
+/// when the knife doesn't pass through it.
+pub fn knife_cut(region: Vec<Contour>, knife: &[Point], evenodd: bool) -> Option<Vec<Vec<Contour>>> {
+    if knife.len() < 2 { return None; }
+    // Interior tests run against the original region: a point on an earlier
+    // chord is on the boundary of the pieces, where winding is ambiguous
+    let whole = contours_to_path(&region);
+    let mut pieces = vec![region];
+    let mut used: Vec<(f64, f64)> = Vec::new();
+    for _ in 0..MAX_CUTS {
+        let mut next = None;
+        'search: for (pi, piece) in pieces.iter().enumerate() {
+            let hits = hits(knife, piece);
+            for pair in hits.windows(2) {
+                let (a, b) = (&pair[0], &pair[1]);
+                let mid_t = (a.knife_t + b.knife_t) / 2.0;
+                if b.knife_t - a.knife_t <= EPSILON || used.iter().any(|(s, e)| mid_t > *s && mid_t < *e) { continue; }
+                if !filled(&whole, knife_point(knife, mid_t), evenodd) { continue; }
+                let chord = chord(knife, a.knife_t, b.knife_t);
+                if chord.is_empty() { continue; }
+                used.push((a.knife_t, b.knife_t));
+                next = Some((pi, cut_piece(piece, a, b, chord, evenodd)));
+                break 'search;
+            }
+        }
+        let Some((pi, replacement)) = next else { break };
+        pieces.splice(pi..=pi, replacement);
+    }
+    if used.is_empty() { None } else { Some(pieces) }
+}
+
+#[wasm_bindgen]
+impl VectorEngine {
+    /// Cuts the given objects along a world-space polyline. Every cut object is
+    /// replaced by one Path per resulting piece, keeping its style.
+    pub(crate) fn knife(&mut self, ids: &[u32], knife: &[Point]) -> String {
+        let mut created = Vec::new();
+        for &id in ids {
+            let Some(idx) = self.objects.iter().position(|o| o.id == id) else { continue };
+            let obj = self.objects[idx].clone();
+            if obj.brush_id > 0 || obj.shape_type == crate::types::ShapeType::Group { continue; }
+            let Ok(path) = world_path(&obj, &self.fonts) else { continue };
+            let evenodd = obj.fill_rule == "evenodd";
+            let mut all = contours(&path);
+            // Fills close every subpath implicitly, so a filled shape is cut as closed regions
+            let filled = !matches!(obj.fill.as_str(), "" | "none" | "transparent") || obj.fill_gradient.is_some();
+            if filled { for c in all.iter_mut() { close_contour(c, false); } }
+            let (closed, open): (Vec<Contour>, Vec<Contour>) = all.into_iter().partition(|c| c.closed);
+            let Some(mut pieces) = knife_cut(closed, knife, evenodd) else { continue };
+            if let Some(first) = pieces.first_mut() { first.extend(open); }
+
+            let to_local = object_transform(&obj).inverse();
+            let mut replacement = Vec::new();
+            for (n, piece) in pieces.into_iter().enumerate() {
+                let mut part = obj.clone();
+                if n > 0 { part.id = self.next_id; self.next_id += 1; part.name = format!("{} {}", obj.name, n + 1); }
+                let mut local = contours_to_path(&piece);
+                local.apply_affine(to_local);
+                set_local_path(&mut part, local);
+                created.push(part.id);
+                replacement.push(part);
+            }
+            self.objects.splice(idx..=idx, replacement);
+        }
+        if created.is_empty() { return "{ \"error\": \"Knife didn't cross any shape\" }".to_string(); }
+        self.selected_ids = created.clone();
+        serde_json::json!({ "success": true, "ids": created }).to_string()
+    }
+
+    /// Scissors: cuts the outline of a path at a world-space point, which has
+    /// to be within `tolerance` of it. Without an id the topmost selected or
+    /// visible object whose outline passes that close is cut.
+    pub(crate) fn scissors(&mut self, id: Option<u32>, point: Point, tolerance: f64) -> String {
+        let under = |cid: &u32| {
+            self.objects.iter().find(|o| o.id == *cid)
+                .and_then(|o| world_path(o, &self.fonts).ok())
+                .and_then(|p| nearest_on_contours(&contours(&p), point))
+                .is_some_and(|(_, _, _, d2)| d2.sqrt() <= tolerance)
+        };
+        let id = match id {
+            Some(id) => Some(id).filter(under),
+            None => {
+                let candidates: Vec<u32> = if self.selected_ids.is_empty() { self.objects.iter().rev().filter(|o| o.visible && !o.locked).map(|o| o.id).collect() } else { self.selected_ids.clone() };
+                candidates.into_iter().find(under)
+            }
+        };
+        let Some(id) = id else { return "{ \"error\": \"No path under the scissors\" }".to_string() };
+        self.split_path(id, &serde_json::json!({ "x": point.x, "y": point.y }))
+    }
+}
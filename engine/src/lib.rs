@@ -8,13 +8,33 @@ pub mod render;
 pub mod commands;
 pub mod io;
 pub mod svg;
+pub mod svg_import;
+pub mod svg_optimize;
 pub mod image_ops;
 pub mod psd;
 pub mod ai;
 pub mod tracer;
+pub mod quantize;
+pub mod centerline;
+pub mod geometry;
+pub mod text;
+pub mod text_layout;
+pub mod text_path;
+pub mod rich_text;
+pub mod pdf_text;
+pub mod path_ops;
+pub mod knife;
+pub mod blend;
 pub mod brush;
 pub mod boolean;
 pub mod warp;
+pub mod raster_warp;
+pub mod measure;
+pub mod width_profile;
+pub mod markers;
+pub mod stroke;
+pub mod appearance;
+pub mod corners;
 pub mod intelligent_shapes;
 pub mod smart_backgrounds;
 
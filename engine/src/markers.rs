@@ -0,0 +1,198 @@
+use wasm_bindgen::prelude::*;
+use serde::{Serialize, Deserialize};
+use kurbo::{Affine, BezPath, Circle, ParamCurve, ParamCurveArclen, PathSeg, Rect, Shape};
+use crate::engine::VectorEngine;
+use crate::geometry::shape_path;
+use crate::measure::tangent;
+use crate::objects::VectorObject;
+use crate::path_ops::{contours, contours_to_path, Contour};
+use crate::stroke::has_stroke;
+use crate::types::ShapeType;
+use crate::width_profile::{outline, WidthProfile};
+
+// Arrowheads and other line-end markers. Marker shapes are drawn in units of
+// the stroke width with the tip at the origin pointing along +x, and the path
+// is shortened under the marker so a butt end never pokes through its tip.
+
+const ACCURACY: f64 = 1e-3;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MarkerKind {
+    Arrow,
+    OpenArrow,
+    Circle,
+    Square,
+    Diamond,
+    Bar,
+    Custom,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MarkerAlign {
+    #[default]
+    Tip,    // Tip on the end point, path shortened to meet it
+    Center, // Centre of the marker on the end point
+    Extend, // Whole marker beyond the end point, path untouched
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Marker {
+    pub kind: MarkerKind,
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+    #[serde(default)]
+    pub align: MarkerAlign,
+    #[serde(default)]
+    pub path: String, // Custom shape, in stroke widths
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+impl Marker {
+    /// The shape in stroke widths, its length behind the tip and how far the
+    /// path can be cut back under it.
+    fn shape(&self) -> (BezPath, f64, f64) {
+        match self.kind {
+            MarkerKind::Arrow => (polygon(&[(0.0, 0.0), (-3.0, 1.5), (-3.0, -1.5)]), 3.0, 1.5),
+            MarkerKind::OpenArrow => {
+                let mut chevron = BezPath::new();
+                chevron.move_to((-2.5, 2.0));
+                chevron.line_to((0.0, 0.0));
+                chevron.line_to((-2.5, -2.0));
+                (outline(&chevron, 1.0, &WidthProfile::default(), "butt", "miter"), 2.5, 0.5)
+            }
+            MarkerKind::Circle => (Circle::new((-1.5, 0.0), 1.5).to_path(0.01), 3.0, 1.5),
+            MarkerKind::Square => (Rect::new(-3.0, -1.5, 0.0, 1.5).to_path(0.01), 3.0, 1.5),
+            MarkerKind::Diamond => (polygon(&[(0.0, 0.0), (-2.0, 1.5), (-4.0, 0.0), (-2.0, -1.5)]), 4.0, 2.0),
+            MarkerKind::Bar => (Rect::new(-1.0, -2.0, 0.0, 2.0).to_path(0.01), 1.0, 0.5),
+            MarkerKind::Custom => {
+                let path = BezPath::from_svg(&self.path).unwrap_or_default();
+                let length = if path.elements().is_empty() { 0.0 } else { (-path.bounding_box().x0).max(0.0) };
+                (path, length, 0.0)
+            }
+        }
+    }
+
+    /// The scaled shape placed for the alignment, in stroke widths, and the
+    /// length to cut from the path.
+    fn placed(&self) -> (BezPath, f64) {
+        let (mut shape, length, trim) = self.shape();
+        let (shift, trim) = match self.align {
+            MarkerAlign::Tip => (0.0, trim),
+            MarkerAlign::Center => (length / 2.0, (trim - length / 2.0).max(0.0)),
+            MarkerAlign::Extend => (length, 0.0),
+        };
+        shape.apply_affine(Affine::scale(self.scale) * Affine::translate((shift, 0.0)));
+        (shape, trim * self.scale)
+    }
+}
+
+fn polygon(points: &[(f64, f64)]) -> BezPath {
+    let mut path = BezPath::new();
+    for (i, &p) in points.iter().enumerate() {
+        if i == 0 { path.move_to(p); } else { path.line_to(p); }
+    }
+    path.close_path();
+    path
+}
+
+/// Cuts `length` off the end of an open contour.
+fn trim_end(c: &mut Contour, mut length: f64) {
+    while length > 0.0 {
+        let Some(seg) = c.segs.last().copied() else { return };
+        let len = seg.arclen(ACCURACY);
+        if len <= length {
+            c.segs.pop();
+            length -= len;
+            continue;
+        }
+        let t = seg.inv_arclen(len - length, ACCURACY);
+        *c.segs.last_mut().unwrap() = seg.subsegment(0.0..t);
+        return;
+    }
+}
+
+/// Where the markers sit: the end points of the first and last subpaths when
+/// they're open, as segments running out to them.
+fn ends(cs: &[Contour]) -> (Option<PathSeg>, Option<PathSeg>) {
+    let start = cs.first().filter(|c| !c.closed).and_then(|c| c.segs.first()).map(|s| s.reverse());
+    let end = cs.last().filter(|c| !c.closed).and_then(|c| c.segs.last()).copied();
+    (start, end)
+}
+
+/// The object's outline with the ends cut back under its markers, plus each
+/// marker's shape placed in local space.
+pub fn place_markers(obj: &VectorObject) -> Option<(BezPath, Option<BezPath>, Option<BezPath>)> {
+    if obj.start_marker.is_none() && obj.end_marker.is_none() { return None; }
+    if !has_stroke(obj) { return None; }
+    let path = shape_path(obj)?;
+    let mut cs = contours(&path);
+    let (start_seg, end_seg) = ends(&cs);
+    let place = |marker: &Option<Marker>, seg: Option<PathSeg>| -> (Option<BezPath>, f64) {
+        let (Some(m), Some(seg)) = (marker, seg) else { return (None, 0.0) };
+        let (mut shape, trim) = m.placed();
+        let t = tangent(&seg, 1.0);
+        shape.apply_affine(Affine::translate(seg.eval(1.0).to_vec2()) * Affine::rotate(t.atan2()) * Affine::scale(obj.stroke_width));
+        (Some(shape), trim * obj.stroke_width)
+    };
+    let (start, start_trim) = place(&obj.start_marker, start_seg);
+    let (end, end_trim) = place(&obj.end_marker, end_seg);
+    if start.is_none() && end.is_none() { return None; }
+    if let Some(c) = cs.first_mut().filter(|_| start.is_some()) {
+        c.reverse();
+        trim_end(c, start_trim);
+        c.reverse();
+    }
+    if let Some(c) = cs.last_mut().filter(|_| end.is_some()) { trim_end(c, end_trim); }
+    Some((contours_to_path(&cs), start, end))
+}
+
+/// SVG `<marker>` definitions for a path, returning the shortened path data
+/// and the attributes referencing them. Marker content is in stroke widths.
+pub fn svg_markers(obj: &VectorObject, defs: &mut Vec<String>) -> Option<(String, String)> {
+    let (body, start, end) = place_markers(obj)?;
+    let mut attrs = Vec::new();
+    for (marker, placed, label) in [(&obj.start_marker, start.is_some(), "start"), (&obj.end_marker, end.is_some(), "end")] {
+        let Some(m) = marker.as_ref().filter(|_| placed) else { continue };
+        let (mut shape, trim) = m.placed();
+        // Drawn at the shortened end, oriented along the path; the start marker
+        // faces backwards
+        let flip = if label == "start" { Affine::rotate(std::f64::consts::PI) } else { Affine::IDENTITY };
+        shape.apply_affine(flip * Affine::translate((trim, 0.0)));
+        let id = format!("marker_{}_{}", label, obj.id);
+        defs.push(format!(r##"<marker id="{}" markerUnits="strokeWidth" orient="auto" overflow="visible" markerWidth="1" markerHeight="1"><path d="{}" fill="{}" /></marker>"##, id, shape.to_svg(), obj.stroke));
+        attrs.push(format!(r##"marker-{}="url(#{})""##, label, id));
+    }
+    Some((body.to_svg(), attrs.join(" ")))
+}
+
+#[wasm_bindgen]
+impl VectorEngine {
+    /// Sets or clears the start and end markers. Each of `start` and `end` is
+    /// a marker object, `null` to remove it, or absent to leave it alone.
+    pub(crate) fn set_markers(&mut self, ids: &[u32], params: &serde_json::Value) -> String {
+        let parse = |key: &str| -> Result<Option<Option<Marker>>, String> {
+            match params.get(key) {
+                None => Ok(None),
+                Some(v) if v.is_null() => Ok(Some(None)),
+                Some(v) => serde_json::from_value(v.clone()).map(|m| Some(Some(m))).map_err(|e| format!("Invalid {} marker: {}", key, e)),
+            }
+        };
+        let (start, end) = match (parse("start"), parse("end")) {
+            (Ok(s), Ok(e)) => (s, e),
+            (Err(e), _) | (_, Err(e)) => return serde_json::json!({ "error": e }).to_string(),
+        };
+        let mut changed = Vec::new();
+        for obj in self.objects.iter_mut().filter(|o| ids.contains(&o.id) && o.shape_type == ShapeType::Path && o.brush_id == 0) {
+            if let Some(m) = &start { obj.start_marker = m.clone(); }
+            if let Some(m) = &end { obj.end_marker = m.clone(); }
+            changed.push(obj.id);
+        }
+        if changed.is_empty() { return "{ \"error\": \"Markers can only be set on paths\" }".to_string(); }
+        serde_json::json!({ "success": true, "ids": changed }).to_string()
+    }
+}
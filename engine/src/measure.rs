@@ -0,0 +1,266 @@
+use wasm_bindgen::prelude::*;
+use kurbo::{BezPath, ParamCurve, ParamCurveArclen, ParamCurveDeriv, ParamCurveNearest, PathSeg, Point, Shape, Vec2};
+use crate::engine::VectorEngine;
+use crate::geometry::world_path;
+use crate::path_ops::contours;
+
+// Path measurement for scripts: length, area, centroid, sampling by arc length
+// or curve parameter, nearest point and intersections. Everything is reported
+// in world coordinates, with segments numbered per subpath the same way the
+// path editing commands number them.
+
+const ACCURACY: f64 = 1e-4;
+const MAX_SUBDIVISIONS: usize = 20_000;
+
+struct MeasuredSeg {
+    seg: PathSeg,
+    subpath: usize,
+    index: usize,
+    start: f64, // Arc length from the start of the path to this segment
+    length: f64,
+}
+
+pub struct PathMeasure {
+    segs: Vec<MeasuredSeg>,
+    closed: Vec<bool>,
+    total: f64,
+}
+
+pub struct Sample {
+    pub point: Point,
+    pub tangent: Vec2,
+    pub length: f64,
+    pub subpath: usize,
+    pub segment: usize,
+    pub t: f64,
+}
+
+impl Sample {
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "x": self.point.x, "y": self.point.y,
+            "tangent": { "x": self.tangent.x, "y": self.tangent.y },
+            "angle": self.tangent.y.atan2(self.tangent.x),
+            "length": self.length, "subpath": self.subpath, "segment": self.segment, "t": self.t,
+        })
+    }
+}
+
+fn deriv(seg: &PathSeg, t: f64) -> Vec2 {
+    match seg {
+        PathSeg::Line(l) => l.p1 - l.p0,
+        PathSeg::Quad(q) => q.deriv().eval(t).to_vec2(),
+        PathSeg::Cubic(c) => c.deriv().eval(t).to_vec2(),
+    }
+}
+
+/// Unit tangent, looking slightly inside the segment where a control point
+/// coincides with an end point and the derivative vanishes.
+pub(crate) fn tangent(seg: &PathSeg, t: f64) -> Vec2 {
+    let mut d = deriv(seg, t);
+    if d.hypot() < 1e-12 { d = deriv(seg, if t < 0.5 { t + 1e-3 } else { t - 1e-3 }); }
+    if d.hypot() < 1e-12 { d = seg.eval(1.0) - seg.eval(0.0); }
+    if d.hypot() < 1e-12 { Vec2::new(1.0, 0.0) } else { d.normalize() }
+}
+
+impl PathMeasure {
+    pub fn new(path: &BezPath) -> Self {
+        let mut segs = Vec::new();
+        let mut closed = Vec::new();
+        let mut total = 0.0;
+        for (ci, c) in contours(path).into_iter().enumerate() {
+            closed.push(c.closed);
+            for (si, seg) in c.segs.into_iter().enumerate() {
+                let length = seg.arclen(ACCURACY);
+                segs.push(MeasuredSeg { seg, subpath: ci, index: si, start: total, length });
+                total += length;
+            }
+        }
+        PathMeasure { segs, closed, total }
+    }
+
+    pub fn length(&self) -> f64 {
+        self.total
+    }
+
+    fn sample(&self, m: &MeasuredSeg, t: f64) -> Sample {
+        let length = m.start + m.seg.subsegment(0.0..t).arclen(ACCURACY);
+        Sample { point: m.seg.eval(t), tangent: tangent(&m.seg, t), length, subpath: m.subpath, segment: m.index, t }
+    }
+
+    /// Point at an arc length from the start, clamped to the path.
+    pub fn at_length(&self, distance: f64) -> Option<Sample> {
+        let distance = distance.clamp(0.0, self.total);
+        let m = self.segs.iter().find(|m| distance <= m.start + m.length).or(self.segs.last())?;
+        let t = if m.length > 0.0 { m.seg.inv_arclen((distance - m.start).min(m.length), ACCURACY) } else { 0.0 };
+        Some(Sample { length: distance, ..self.sample(m, t) })
+    }
+
+    /// Point at parameter `t` of a segment.
+    pub fn at_param(&self, subpath: usize, segment: usize, t: f64) -> Option<Sample> {
+        let m = self.segs.iter().find(|m| m.subpath == subpath && m.index == segment)?;
+        Some(self.sample(m, t.clamp(0.0, 1.0)))
+    }
+
+    pub fn nearest(&self, p: Point) -> Option<(Sample, f64)> {
+        let (m, n) = self.segs.iter()
+            .map(|m| (m, m.seg.nearest(p, ACCURACY)))
+            .min_by(|a, b| a.1.distance_sq.total_cmp(&b.1.distance_sq))?;
+        Some((self.sample(m, n.t), n.distance_sq.sqrt()))
+    }
+
+    pub fn subpaths(&self) -> Vec<(f64, bool)> {
+        self.closed.iter().enumerate().map(|(i, &closed)| {
+            (self.segs.iter().filter(|m| m.subpath == i).map(|m| m.length).sum(), closed)
+        }).collect()
+    }
+}
+
+/// Centroid of the filled area, or of the outline itself when the path
+/// encloses no area (open paths, lines).
+pub fn centroid(path: &BezPath, measure: &PathMeasure) -> Option<Point> {
+    let (mut area, mut cx, mut cy) = (0.0, 0.0, 0.0);
+    let mut first = Point::ZERO;
+    let mut prev = Point::ZERO;
+    let mut edge = |a: Point, b: Point| {
+        let cross = a.x * b.y - b.x * a.y;
+        area += cross;
+        cx += (a.x + b.x) * cross;
+        cy += (a.y + b.y) * cross;
+    };
+    kurbo::flatten(path, ACCURACY, |el| match el {
+        kurbo::PathEl::MoveTo(p) => { first = p; prev = p; }
+        kurbo::PathEl::LineTo(p) => { edge(prev, p); prev = p; }
+        kurbo::PathEl::ClosePath => { edge(prev, first); prev = first; }
+        _ => {}
+    });
+    if area.abs() > 1e-9 {
+        return Some(Point::new(cx / (3.0 * area), cy / (3.0 * area)));
+    }
+    if measure.total <= 0.0 { return measure.segs.first().map(|m| m.seg.eval(0.0)); }
+    let sum = measure.segs.iter().fold(Vec2::ZERO, |acc, m| acc + m.seg.eval(0.5).to_vec2() * m.length);
+    Some((sum / measure.total).to_point())
+}
+
+/// Parameters of the crossings between two segments, by recursive subdivision
+/// (exact for lines).
+fn seg_intersections(a: &PathSeg, b: &PathSeg, out: &mut Vec<(f64, f64)>) {
+    if let PathSeg::Line(l) = b {
+        out.extend(a.intersect_line(*l).into_iter().map(|h| (h.segment_t, h.line_t)));
+        return;
+    }
+    if let PathSeg::Line(l) = a {
+        out.extend(b.intersect_line(*l).into_iter().map(|h| (h.line_t, h.segment_t)));
+        return;
+    }
+    let mut budget = MAX_SUBDIVISIONS;
+    let mut stack = vec![(0.0, 1.0, 0.0, 1.0)];
+    while let Some((a0, a1, b0, b1)) = stack.pop() {
+        if budget == 0 { break; }
+        budget -= 1;
+        let (sa, sb) = (a.subsegment(a0..a1), b.subsegment(b0..b1));
+        let (ra, rb) = (sa.bounding_box(), sb.bounding_box());
+        if ra.x0 > rb.x1 || rb.x0 > ra.x1 || ra.y0 > rb.y1 || rb.y0 > ra.y1 { continue; }
+        if ra.width().max(ra.height()) < 1e-7 && rb.width().max(rb.height()) < 1e-7 {
+            out.push(((a0 + a1) / 2.0, (b0 + b1) / 2.0));
+            continue;
+        }
+        if ra.width() + ra.height() >= rb.width() + rb.height() {
+            let am = (a0 + a1) / 2.0;
+            stack.push((a0, am, b0, b1));
+            stack.push((am, a1, b0, b1));
+        } else {
+            let bm = (b0 + b1) / 2.0;
+            stack.push((a0, a1, b0, bm));
+            stack.push((a0, a1, bm, b1));
+        }
+    }
+}
+
+/// Crossing points between two paths, each with its position on both.
+pub fn intersections(a: &PathMeasure, b: &PathMeasure) -> Vec<(Point, Sample, Sample)> {
+    let mut found: Vec<(Point, Sample, Sample)> = Vec::new();
+    for ma in &a.segs {
+        for mb in &b.segs {
+            let mut hits = Vec::new();
+            seg_intersections(&ma.seg, &mb.seg, &mut hits);
+            for (ta, tb) in hits {
+                let p = ma.seg.eval(ta);
+                // Hits on shared end points and subdivision leftovers come in clusters
+                if found.iter().any(|(q, _, _)| q.distance(p) < 1e-6) { continue; }
+                found.push((p, a.sample(ma, ta), b.sample(mb, tb)));
+            }
+        }
+    }
+    found.sort_by(|x, y| x.1.length.total_cmp(&y.1.length));
+    found
+}
+
+#[wasm_bindgen]
+impl VectorEngine {
+    fn measured(&self, id: u32) -> Result<(BezPath, PathMeasure), String> {
+        let obj = self.objects.iter().find(|o| o.id == id).ok_or("Object not found")?;
+        let path = world_path(obj, &self.fonts)?;
+        let measure = PathMeasure::new(&path);
+        Ok((path, measure))
+    }
+
+    /// Length, signed area, centroid, bounds and per-subpath lengths of an object's outline.
+    pub(crate) fn measure_path(&self, id: u32) -> String {
+        match self.measured(id) {
+            Ok((path, measure)) => {
+                let b = path.bounding_box();
+                let c = centroid(&path, &measure);
+                let subpaths: Vec<_> = measure.subpaths().into_iter().map(|(length, closed)| serde_json::json!({ "length": length, "closed": closed })).collect();
+                serde_json::json!({
+                    "length": measure.length(),
+                    "area": path.area(),
+                    "centroid": c.map(|p| serde_json::json!({ "x": p.x, "y": p.y })),
+                    "bounds": { "x": b.x0, "y": b.y0, "width": b.width(), "height": b.height() },
+                    "subpaths": subpaths,
+                }).to_string()
+            }
+            Err(e) => serde_json::json!({ "error": e }).to_string(),
+        }
+    }
+
+    /// Point and tangent at an arc length (`length`, or `fraction` of the whole
+    /// path) or at a curve parameter (`subpath`, `segment`, `t`).
+    pub(crate) fn point_at(&self, id: u32, params: &serde_json::Value) -> String {
+        let (_, measure) = match self.measured(id) { Ok(m) => m, Err(e) => return serde_json::json!({ "error": e }).to_string() };
+        let sample = if let Some(t) = params["t"].as_f64() {
+            let subpath = params["subpath"].as_u64().unwrap_or(0) as usize;
+            let segment = params["segment"].as_u64().unwrap_or(0) as usize;
+            measure.at_param(subpath, segment, t)
+        } else if let Some(f) = params["fraction"].as_f64() {
+            measure.at_length(f * measure.length())
+        } else {
+            measure.at_length(params["length"].as_f64().unwrap_or(0.0))
+        };
+        match sample {
+            Some(s) => s.to_json().to_string(),
+            None => "{ \"error\": \"Point not found on path\" }".to_string(),
+        }
+    }
+
+    pub(crate) fn nearest_point(&self, id: u32, point: Point) -> String {
+        let (_, measure) = match self.measured(id) { Ok(m) => m, Err(e) => return serde_json::json!({ "error": e }).to_string() };
+        match measure.nearest(point) {
+            Some((s, distance)) => {
+                let mut json = s.to_json();
+                json["distance"] = serde_json::json!(distance);
+                json.to_string()
+            }
+            None => "{ \"error\": \"Path is empty\" }".to_string(),
+        }
+    }
+
+    pub(crate) fn path_intersections(&self, a: u32, b: u32) -> String {
+        let result = self.measured(a).and_then(|ma| Ok((ma, self.measured(b)?)));
+        let ((_, ma), (_, mb)) = match result { Ok(m) => m, Err(e) => return serde_json::json!({ "error": e }).to_string() };
+        let points: Vec<_> = intersections(&ma, &mb).into_iter().map(|(p, sa, sb)| serde_json::json!({
+            "x": p.x, "y": p.y, "a": sa.to_json(), "b": sb.to_json(),
+        })).collect();
+        serde_json::json!({ "points": points }).to_string()
+    }
+}
@@ -1,8 +1,22 @@
 use serde::{Serialize, Deserialize};
 use wasm_bindgen::prelude::*;
-use crate::types::{ShapeType, Gradient, LayerStyle};
+use crate::types::{ShapeType, Gradient, LayerStyle, BlendSpec};
 use crate::brush::StrokePoint;
+use crate::warp::{Envelope, WarpEffect};
+use crate::width_profile::WidthProfile;
+use crate::markers::Marker;
+use crate::appearance::AppearanceLayer;
+use crate::corners::LiveCorners;
+use crate::rich_text::{ParagraphStyle, TextRun};
+use crate::geometry::{polygon_path, star_path};
+use crate::io::ExportOptions;
+use crate::smart_backgrounds::SvgSurface;
+use crate::svg::{escape_xml, svg_blend_mode, svg_effects_filter, svg_elements, svg_gradient};
+use crate::text::FontRegistry;
+use crate::text_path::{PathTextAlign, TextOnPath};
 use base64::{Engine as _, engine::general_purpose};
+use kurbo::PathEl;
+use std::io::Cursor;
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct VectorObject {
@@ -17,6 +31,8 @@ pub struct VectorObject {
     pub fill: String,
     #[serde(skip)]
     pub fill_gradient: Option<Gradient>,
+    #[serde(default)]
+    pub fill_rule: String, // "nonzero" (default) or "evenodd"
     pub stroke: String,
     #[serde(skip)]
     pub stroke_gradient: Option<Gradient>,
@@ -80,6 +96,37 @@ pub struct VectorObject {
     pub image: Option<JsValue>,
     // Grouping
     pub children: Option<Vec<VectorObject>>,
+    // Set on blend groups so the steps can be regenerated
+    #[serde(default)]
+    pub blend: Option<BlendSpec>,
+    #[serde(default)]
+    pub envelope: Option<Box<Envelope>>,
+    #[serde(default)]
+    pub warp: Option<WarpEffect>,
+    #[serde(default)]
+    pub width_profile: Option<WidthProfile>,
+    #[serde(default)]
+    pub start_marker: Option<Marker>,
+    #[serde(default)]
+    pub end_marker: Option<Marker>,
+    #[serde(default)]
+    pub stroke_dash_offset: f64,
+    #[serde(default)]
+    pub stroke_dash_align: bool, // Stretch dashes to sit on corners and path ends
+    #[serde(default)]
+    pub stroke_align: String, // "center" (default), "inside" or "outside"; closed subpaths only
+    #[serde(default)]
+    pub appearance: Vec<AppearanceLayer>, // Replaces fill and stroke when not empty
+    #[serde(default)]
+    pub corners: Option<LiveCorners>,
+    #[serde(default)]
+    pub area_text: bool, // Wraps at the box width instead of sizing the box to the text
+    #[serde(default)]
+    pub text_path: Option<TextOnPath>,
+    #[serde(default)]
+    pub text_runs: Vec<TextRun>, // Character styles over ranges of `text_content`
+    #[serde(default)]
+    pub paragraphs: Vec<ParagraphStyle>, // By paragraph; missing ones are unstyled
 }
 
 impl VectorObject {
@@ -129,10 +176,16 @@ impl VectorObject {
         (min_x, min_y, max_x, max_y)
     }
 
-    pub fn to_svg_element(&self, defs: &mut Vec<String>) -> String {
-        if !self.visible { return String::new(); }
+    /// Font attributes of a text element, spacing included.
+    fn svg_font_attrs(&self) -> String {
+        let mut attrs = format!(r##"font-family="{}" font-size="{}" font-weight="{}" xml:space="preserve""##,
+            escape_xml(&self.font_family), self.font_size, escape_xml(&self.font_weight));
+        let spacing = (self.tracking + self.kerning) / 1000.0 * self.font_size;
+        if spacing != 0.0 { attrs.push_str(&format!(r##" letter-spacing="{}""##, spacing)); }
+        attrs
+    }
 
-        let mut attrs = Vec::new();
+    pub fn to_svg_element(&self, defs: &mut Vec<String>, options: &ExportOptions, fonts: &FontRegistry) -> String {
         let transform = format!("translate({} {}) rotate({}) translate({} {})",
             self.x + self.width / 2.0, 
             self.y + self.height / 2.0,
@@ -141,71 +194,69 @@ impl VectorObject {
             -self.height / 2.0
         );
 
-        attrs.push(format!(r##"transform="{}"##, transform));
-        
+        // A mask only clips the objects that use it, hidden or not. Export has
+        // already turned it into a path.
+        if self.is_mask && self.shape_type != ShapeType::SmartBackground {
+            let rule = if self.fill_rule == "evenodd" { r##" clip-rule="evenodd""## } else { "" };
+            defs.push(format!(r##"<clipPath id="clip-{}"><path d="{}" transform="{}"{} /></clipPath>"##,
+                self.id, escape_xml(&self.path_data), transform, rule));
+            return String::new();
+        }
+        if !self.visible && options.strip_hidden { return String::new(); }
+
+        let mut attrs = Vec::new();
+        attrs.push(format!(r##"transform="{}""##, transform));
+        if !self.visible { attrs.push(r##"display="none""##.to_string()); }
+        // Turned into an id or class once the whole document is known
+        if options.semantic && !self.name.is_empty() { attrs.push(format!(r##"data-name="{}""##, escape_xml(&self.name))); }
+
+        // Opacity and blending apply to the clipped result when there's a mask
+        let mut compositing = Vec::new();
         if self.opacity < 1.0 {
-            attrs.push(format!(r##"opacity="{}"##, self.opacity));
+            compositing.push(format!(r##"opacity="{}""##, self.opacity));
         }
-        
-        if self.blend_mode != "source-over" {
-            attrs.push(format!(r##"style="mix-blend-mode: {}"##, self.blend_mode));
+        let mut style = Vec::new();
+        if let Some(mode) = svg_blend_mode(&self.blend_mode) {
+            style.push(format!("mix-blend-mode: {}", mode));
+        }
+        if !style.is_empty() {
+            compositing.push(format!(r##"style="{}""##, style.join("; ")));
+        }
+        if self.mask_id.is_none() { attrs.append(&mut compositing); }
+
+        if let Some(filter) = svg_effects_filter(self, defs) {
+            attrs.push(format!(r##"filter="url(#{})""##, filter));
         }
 
         // Fill
         if let Some(grad) = &self.fill_gradient {
             let grad_id = format!("grad_fill_{}", self.id);
-            let mut grad_svg = if grad.is_radial {
-                format!(r##"<radialGradient id="{}" cx="{}" cy="{}" r="{}" fx="{}" fy="{}" gradientUnits="userSpaceOnUse">"##,
-                    grad_id, grad.x1, grad.y1, grad.r2, grad.x1, grad.y1)
-            } else {
-                format!(r##"<linearGradient id="{}" x1="{}" y1="{}" x2="{}" y2="{}" gradientUnits="userSpaceOnUse">"##,
-                    grad_id, grad.x1, grad.y1, grad.x2, grad.y2)
-            };
-            for stop in &grad.stops {
-                grad_svg.push_str(&format!(r##"<stop offset="{}" stop-color="{}" />"##, stop.offset, stop.color));
-            }
-            if grad.is_radial {
-                grad_svg.push_str("</radialGradient>");
-            } else {
-                grad_svg.push_str("</linearGradient>");
-            }
-            defs.push(grad_svg);
-            attrs.push(format!(r##"fill="url(#{})"##, grad_id));
+            defs.push(svg_gradient(&grad_id, grad));
+            attrs.push(format!(r##"fill="url(#{})""##, grad_id));
         } else {
-            let fill = if self.fill == "transparent" { "none".to_string() } else if self.fill.is_empty() { "none".to_string() } else { self.fill.clone() };
-            attrs.push(format!(r##"fill="{}"##, fill));
+            let fill = if self.fill == "transparent" { "none".to_string() } else if self.fill.is_empty() { "none".to_string() } else { escape_xml(&self.fill) };
+            attrs.push(format!(r##"fill="{}""##, fill));
+        }
+        if self.fill_rule == "evenodd" {
+            attrs.push(r##"fill-rule="evenodd""##.to_string());
         }
 
         // Stroke
         if self.stroke_width > 0.0 && self.stroke != "transparent" && !self.stroke.is_empty() {
             if let Some(grad) = &self.stroke_gradient {
                 let grad_id = format!("grad_stroke_{}", self.id);
-                let mut grad_svg = if grad.is_radial {
-                    format!(r##"<radialGradient id="{}" cx="{}" cy="{}" r="{}" fx="{}" fy="{}" gradientUnits="userSpaceOnUse">"##,
-                        grad_id, grad.x1, grad.y1, grad.r2, grad.x1, grad.y1)
-                } else {
-                    format!(r##"<linearGradient id="{}" x1="{}" y1="{}" x2="{}" y2="{}" gradientUnits="userSpaceOnUse">"##,
-                        grad_id, grad.x1, grad.y1, grad.x2, grad.y2)
-                };
-                for stop in &grad.stops {
-                    grad_svg.push_str(&format!(r##"<stop offset="{}" stop-color="{}" />"##, stop.offset, stop.color));
-                }
-                if grad.is_radial {
-                    grad_svg.push_str("</radialGradient>");
-                } else {
-                    grad_svg.push_str("</linearGradient>");
-                }
-                defs.push(grad_svg);
-                attrs.push(format!(r##"stroke="url(#{})"##, grad_id));
+                defs.push(svg_gradient(&grad_id, grad));
+                attrs.push(format!(r##"stroke="url(#{})""##, grad_id));
             } else {
-                attrs.push(format!(r##"stroke="{}"##, self.stroke));
+                attrs.push(format!(r##"stroke="{}""##, escape_xml(&self.stroke)));
             }
-            attrs.push(format!(r##"stroke-width="{}"##, self.stroke_width));
-            attrs.push(format!(r##"stroke-linecap="{}"##, self.stroke_cap));
-            attrs.push(format!(r##"stroke-linejoin="{}"##, self.stroke_join));
+            attrs.push(format!(r##"stroke-width="{}""##, self.stroke_width));
+            attrs.push(format!(r##"stroke-linecap="{}""##, escape_xml(&self.stroke_cap)));
+            attrs.push(format!(r##"stroke-linejoin="{}""##, escape_xml(&self.stroke_join)));
             if !self.stroke_dash.is_empty() {
                 let dash = self.stroke_dash.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(" ");
-                attrs.push(format!(r##"stroke-dasharray="{}"##, dash));
+                attrs.push(format!(r##"stroke-dasharray="{}""##, dash));
+                if self.stroke_dash_offset != 0.0 { attrs.push(format!(r##"stroke-dashoffset="{}""##, self.stroke_dash_offset)); }
             }
         } else {
             attrs.push(r##"stroke="none""##.to_string());
@@ -213,7 +264,9 @@ impl VectorObject {
 
         let attr_str = attrs.join(" ");
 
-        match self.shape_type {
+        // Text on a path exports as <textPath> along its guide
+        let path_guide = self.text_path.as_ref().and_then(|tp| Some((tp, tp.guide()?)));
+        let element = match self.shape_type {
             ShapeType::Rectangle => {
                 if self.corner_radius > 0.0 {
                     format!(r##"<rect width="{}" height="{}" rx="{}" ry="{}" {} />"##, 
@@ -228,34 +281,22 @@ impl VectorObject {
                     self.width / 2.0, self.height / 2.0, self.width / 2.0, self.height / 2.0, attr_str)
             }
             ShapeType::Path => {
-                format!(r##"<path d="{}" {} />"##, self.path_data, attr_str)
-            }
-            ShapeType::Polygon => {
-                let mut points = Vec::new();
-                let cx = self.width / 2.0;
-                let cy = self.height / 2.0;
-                let r = self.width / 2.0;
-                for i in 0..self.sides {
-                    let angle = (i as f64 * 2.0 * std::f64::consts::PI / self.sides as f64) - (std::f64::consts::PI / 2.0);
-                    let x = cx + r * angle.cos();
-                    let y = cy + r * angle.sin();
-                    points.push(format!("{},{}", x, y));
+                match crate::markers::svg_markers(self, defs) {
+                    Some((d, markers)) => format!(r##"<path d="{}" {} {} />"##, d, attr_str, markers),
+                    None => format!(r##"<path d="{}" {} />"##, escape_xml(&self.path_data), attr_str),
                 }
-                format!(r##"<polygon points="{}" {} />"##, points.join(" "), attr_str)
             }
-            ShapeType::Star => {
-                let mut points = Vec::new();
-                let cx = self.width / 2.0;
-                let cy = self.height / 2.0;
-                let r_outer = self.width / 2.0;
-                let r_inner = self.inner_radius * (self.width / 2.0);
-                for i in 0..(self.sides * 2) {
-                    let r = if i % 2 == 0 { r_outer } else { r_inner };
-                    let angle = (i as f64 * std::f64::consts::PI / self.sides as f64) - (std::f64::consts::PI / 2.0);
-                    let x = cx + r * angle.cos();
-                    let y = cy + r * angle.sin();
-                    points.push(format!("{},{}", x, y));
-                }
+            ShapeType::Polygon | ShapeType::Star => {
+                // The same vertices the canvas draws
+                let outline = if self.shape_type == ShapeType::Polygon {
+                    polygon_path(self.width / 2.0, self.height / 2.0, self.width / 2.0, self.sides)
+                } else {
+                    star_path(self.width / 2.0, self.height / 2.0, self.width / 2.0, self.inner_radius * (self.width / 2.0), self.sides)
+                };
+                let points: Vec<String> = outline.elements().iter().filter_map(|el| match el {
+                    PathEl::MoveTo(p) | PathEl::LineTo(p) => Some(format!("{},{}", p.x, p.y)),
+                    _ => None,
+                }).collect();
                 format!(r##"<polygon points="{}" {} />"##, points.join(" "), attr_str)
             }
             ShapeType::Intelligent => {
@@ -266,31 +307,71 @@ impl VectorObject {
                     String::new()
                 }
             }
+            ShapeType::Text if path_guide.is_some() => {
+                let (tp, guide) = path_guide.unwrap();
+                let length = crate::measure::PathMeasure::new(&guide).length();
+                defs.push(format!(r##"<path id="text-path-{}" d="{}" />"##, self.id, guide.to_svg()));
+                let (anchor, offset) = match self.text_align.as_str() {
+                    "center" => ("middle", tp.start_offset + length / 2.0),
+                    "right" | "end" => ("end", tp.start_offset + length),
+                    // A single line on a path has no width to justify to
+                    "left" | "justify" => ("start", tp.start_offset),
+                    _ => ("start", tp.start_offset),
+                };
+                let baseline = match tp.align {
+                    PathTextAlign::Baseline => "alphabetic",
+                    PathTextAlign::Center => "central",
+                    PathTextAlign::Ascender => "text-before-edge",
+                };
+                format!(r##"<text {} text-anchor="{}" dominant-baseline="{}" {}><textPath href="#text-path-{}" startOffset="{}">{}</textPath></text>"##,
+                    self.svg_font_attrs(), anchor, baseline, attr_str, self.id, offset, crate::rich_text::svg_spans(self, 0..self.text_content.len()))
+            }
             ShapeType::Text => {
-                format!(r##"<text x="0" y="{}" font-family="{}" font-size="{}" font-weight="{}" text-anchor="{}" {}>{}</text>"##,
-                    self.font_size, self.font_family, self.font_size, self.font_weight, 
-                    if self.text_align == "left" { "start" } else if self.text_align == "right" { "end" } else { "middle" },
-                    attr_str, self.text_content)
+                format!(r##"<text {} {}>{}</text>"##, self.svg_font_attrs(), attr_str, crate::rich_text::svg_text_lines(self, fonts))
             }
             ShapeType::Group => {
-                let mut inner = String::new();
-                if let Some(children) = &self.children {
-                    for child in children {
-                        inner.push_str(&child.to_svg_element(defs));
-                    }
+                let inner = self.children.as_deref().map(|children| svg_elements(children, defs, options, fonts)).unwrap_or_default();
+                if options.semantic && !self.name.is_empty() {
+                    let name = escape_xml(&self.name);
+                    format!(r##"<g role="group" aria-label="{}" {}><title>{}</title>{}</g>"##, name, attr_str, name, inner)
+                } else {
+                    format!(r##"<g {}>{}</g>"##, attr_str, inner)
                 }
-                format!(r##"<g {}>{}</g>"##, attr_str, inner)
             }
             ShapeType::Image => {
                 if let Some(raw_image) = &self.raw_image {
-                    let b64 = general_purpose::STANDARD.encode(raw_image);
-                    format!(r##"<image width="{}" height="{}" href="data:image/png;base64,{}" {} />"##,
-                        self.width, self.height, b64, attr_str)
+                    let reader = image::io::Reader::new(Cursor::new(raw_image)).with_guessed_format().ok();
+                    let mime = reader.as_ref().and_then(|r| r.format()).map_or("image/png", |f| f.to_mime_type());
+                    let href = format!("data:{};base64,{}", mime, general_purpose::STANDARD.encode(raw_image));
+                    let size = reader.and_then(|r| r.into_dimensions().ok()).map(|(w, h)| (w as f64, h as f64));
+                    match size {
+                        // Only the source rectangle is drawn, stretched over the box
+                        Some((w, h)) if self.sw > 0.0 && self.sh > 0.0 && (self.sx, self.sy, self.sw, self.sh) != (0.0, 0.0, w, h) => {
+                            format!(r##"<g {}><svg width="{}" height="{}" viewBox="{} {} {} {}" preserveAspectRatio="none"><image width="{}" height="{}" href="{}" /></svg></g>"##,
+                                attr_str, self.width, self.height, self.sx, self.sy, self.sw, self.sh, w, h, href)
+                        }
+                        _ => format!(r##"<image width="{}" height="{}" preserveAspectRatio="none" href="{}" {} />"##,
+                            self.width, self.height, href, attr_str),
+                    }
                 } else {
                     format!(r##"<rect width="{}" height="{}" fill="#ccc" {} />"##, self.width, self.height, attr_str)
                 }
             }
+            ShapeType::SmartBackground => {
+                let Some(background) = crate::smart_backgrounds::get_background_by_id(&self.intelligent_type) else { return String::new() };
+                let mut surface = SvgSurface::new(format!("background-{}", self.id), defs);
+                background.render(&mut surface, self.width, self.height, &self.intelligent_params);
+                format!(r##"<g {}>{}</g>"##, attr_str, surface.body)
+            }
             _ => String::new()
+        };
+        match self.mask_id {
+            Some(mask) if !element.is_empty() => {
+                let mut wrapper = vec![format!(r##"clip-path="url(#clip-{})""##, mask)];
+                wrapper.append(&mut compositing);
+                format!(r##"<g {}>{}</g>"##, wrapper.join(" "), element)
+            }
+            _ => element,
         }
     }
 }
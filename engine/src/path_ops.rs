@@ -0,0 +1,378 @@
+use wasm_bindgen::prelude::*;
+use kurbo::{BezPath, CubicBez, Line, ParamCurve, ParamCurveNearest, PathEl, PathSeg, Point, QuadBez};
+use crate::engine::VectorEngine;
+use crate::geometry::{object_path, object_transform, set_local_path};
+use crate::objects::VectorObject;
+use crate::types::ShapeType;
+
+// Structural path editing: split, join, reverse, open/close, break apart and
+// compound paths. Paths are handled as lists of contours made of explicit
+// segments so the implicit closing line of a closed subpath is never lost.
+
+const EPSILON: f64 = 1e-6;
+
+#[derive(Clone, Debug)]
+pub struct Contour {
+    pub segs: Vec<PathSeg>,
+    pub closed: bool,
+}
+
+impl Contour {
+    pub fn start(&self) -> Point {
+        self.segs.first().map(seg_start).unwrap_or_default()
+    }
+
+    pub fn end(&self) -> Point {
+        self.segs.last().map(seg_end).unwrap_or_default()
+    }
+
+    pub fn reverse(&mut self) {
+        self.segs.reverse();
+        for s in self.segs.iter_mut() { *s = s.reverse(); }
+    }
+
+    /// Splits at parameter `t` of segment `index`. A closed contour opens up at
+    /// that point; an open one falls into two pieces.
+    pub fn split(&self, index: usize, t: f64) -> Vec<Contour> {
+        let seg = self.segs[index];
+        let head = (t > EPSILON).then(|| seg.subsegment(0.0..t));
+        let tail = (t < 1.0 - EPSILON).then(|| seg.subsegment(t..1.0));
+        if self.closed {
+            let mut segs: Vec<PathSeg> = tail.into_iter().collect();
+            segs.extend_from_slice(&self.segs[index + 1..]);
+            segs.extend_from_slice(&self.segs[..index]);
+            segs.extend(head);
+            return vec![Contour { segs, closed: false }];
+        }
+        let mut first: Vec<PathSeg> = self.segs[..index].to_vec();
+        first.extend(head);
+        let mut second: Vec<PathSeg> = tail.into_iter().collect();
+        second.extend_from_slice(&self.segs[index + 1..]);
+        [first, second].into_iter().filter(|s| !s.is_empty()).map(|segs| Contour { segs, closed: false }).collect()
+    }
+}
+
+pub fn seg_start(seg: &PathSeg) -> Point {
+    match seg { PathSeg::Line(l) => l.p0, PathSeg::Quad(q) => q.p0, PathSeg::Cubic(c) => c.p0 }
+}
+
+pub fn seg_end(seg: &PathSeg) -> Point {
+    match seg { PathSeg::Line(l) => l.p1, PathSeg::Quad(q) => q.p2, PathSeg::Cubic(c) => c.p3 }
+}
+
+/// Moves a segment's start point, dragging the neighbouring control point along.
+fn move_seg_start(seg: &mut PathSeg, p: Point) {
+    let delta = p - seg_start(seg);
+    match seg {
+        PathSeg::Line(l) => l.p0 = p,
+        PathSeg::Quad(q) => q.p0 = p,
+        PathSeg::Cubic(c) => { c.p0 = p; c.p1 += delta; }
+    }
+}
+
+fn move_seg_end(seg: &mut PathSeg, p: Point) {
+    let delta = p - seg_end(seg);
+    match seg {
+        PathSeg::Line(l) => l.p1 = p,
+        PathSeg::Quad(q) => q.p2 = p,
+        PathSeg::Cubic(c) => { c.p3 = p; c.p2 += delta; }
+    }
+}
+
+pub fn contours(path: &BezPath) -> Vec<Contour> {
+    let mut result = Vec::new();
+    let mut segs = Vec::new();
+    let (mut start, mut current) = (Point::ZERO, Point::ZERO);
+    let mut flush = |segs: &mut Vec<PathSeg>, closed: bool| {
+        if !segs.is_empty() { result.push(Contour { segs: std::mem::take(segs), closed }); }
+    };
+    for el in path.elements() {
+        match *el {
+            PathEl::MoveTo(p) => { flush(&mut segs, false); start = p; current = p; }
+            PathEl::LineTo(p) => { segs.push(PathSeg::Line(Line::new(current, p))); current = p; }
+            PathEl::QuadTo(p1, p2) => { segs.push(PathSeg::Quad(QuadBez::new(current, p1, p2))); current = p2; }
+            PathEl::CurveTo(p1, p2, p3) => { segs.push(PathSeg::Cubic(CubicBez::new(current, p1, p2, p3))); current = p3; }
+            PathEl::ClosePath => {
+                if current.distance(start) > EPSILON { segs.push(PathSeg::Line(Line::new(current, start))); }
+                flush(&mut segs, true);
+                current = start;
+            }
+        }
+    }
+    flush(&mut segs, false);
+    result
+}
+
+pub fn contours_to_path(contours: &[Contour]) -> BezPath {
+    let mut path = BezPath::new();
+    for c in contours {
+        let Some(first) = c.segs.first() else { continue };
+        path.move_to(seg_start(first));
+        // The closing line is implied by ClosePath, so a trailing straight line
+        // back to the start is dropped to keep round trips stable.
+        let mut segs = &c.segs[..];
+        if c.closed && segs.len() > 1 {
+            if let Some(PathSeg::Line(l)) = segs.last() {
+                if l.p1.distance(seg_start(first)) <= EPSILON { segs = &segs[..segs.len() - 1]; }
+            }
+        }
+        for seg in segs {
+            match seg {
+                PathSeg::Line(l) => path.line_to(l.p1),
+                PathSeg::Quad(q) => path.quad_to(q.p1, q.p2),
+                PathSeg::Cubic(cb) => path.curve_to(cb.p1, cb.p2, cb.p3),
+            }
+        }
+        if c.closed { path.close_path(); }
+    }
+    path
+}
+
+/// Finds the segment and parameter on any contour closest to `p`.
+pub fn nearest_on_contours(contours: &[Contour], p: Point) -> Option<(usize, usize, f64, f64)> {
+    let mut best: Option<(usize, usize, f64, f64)> = None;
+    for (ci, c) in contours.iter().enumerate() {
+        for (si, seg) in c.segs.iter().enumerate() {
+            let n = seg.nearest(p, 1e-6);
+            if best.is_none_or(|b| n.distance_sq < b.3) { best = Some((ci, si, n.t, n.distance_sq)); }
+        }
+    }
+    best
+}
+
+/// Joins two open contours at their closest pair of end points, either by
+/// bridging the gap with a line or by merging both ends at their midpoint.
+pub fn join_contours(mut a: Contour, mut b: Contour, average: bool) -> Contour {
+    let options = [
+        (a.end().distance(b.start()), false, false),
+        (a.end().distance(b.end()), false, true),
+        (a.start().distance(b.start()), true, false),
+        (a.start().distance(b.end()), true, true),
+    ];
+    let (_, rev_a, rev_b) = options.iter().cloned().fold((f64::INFINITY, false, false), |best, o| if o.0 < best.0 { o } else { best });
+    if rev_a { a.reverse(); }
+    if rev_b { b.reverse(); }
+    let (pa, pb) = (a.end(), b.start());
+    if average || pa.distance(pb) <= EPSILON {
+        let mid = pa.midpoint(pb);
+        if let Some(last) = a.segs.last_mut() { move_seg_end(last, mid); }
+        if let Some(first) = b.segs.first_mut() { move_seg_start(first, mid); }
+    } else {
+        a.segs.push(PathSeg::Line(Line::new(pa, pb)));
+    }
+    a.segs.extend(b.segs);
+    a
+}
+
+/// Closes an open contour onto itself, optionally averaging the two ends.
+pub fn close_contour(c: &mut Contour, average: bool) {
+    if c.closed || c.segs.is_empty() { return; }
+    let (start, end) = (c.start(), c.end());
+    if average && start.distance(end) > EPSILON {
+        let mid = start.midpoint(end);
+        if let Some(first) = c.segs.first_mut() { move_seg_start(first, mid); }
+        if let Some(last) = c.segs.last_mut() { move_seg_end(last, mid); }
+    } else if start.distance(end) > EPSILON {
+        c.segs.push(PathSeg::Line(Line::new(end, start)));
+    }
+    c.closed = true;
+}
+
+fn subpath_filter(params: &serde_json::Value) -> impl Fn(usize) -> bool {
+    let only = params["subpath"].as_u64().map(|v| v as usize);
+    move |i| only.is_none_or(|s| s == i)
+}
+
+#[wasm_bindgen]
+impl VectorEngine {
+    /// Local-space contours of an object that can be edited as a path.
+    fn editable_contours(&self, obj: &VectorObject) -> Result<Vec<Contour>, String> {
+        if obj.brush_id > 0 { return Err("Brush strokes can't be edited as paths".to_string()); }
+        if obj.shape_type == ShapeType::Group { return Err("Groups can't be edited as a single path".to_string()); }
+        Ok(contours(&object_path(obj, &self.fonts)?))
+    }
+
+    fn object_index(&self, id: u32) -> Result<usize, String> {
+        self.objects.iter().position(|o| o.id == id).ok_or_else(|| "Object not found".to_string())
+    }
+
+    /// Applies a per-contour edit to an object in place.
+    pub(crate) fn edit_contours(&mut self, id: u32, edit: impl FnOnce(&mut Vec<Contour>) -> Result<(), String>) -> String {
+        let result = self.object_index(id).and_then(|idx| {
+            let mut cs = self.editable_contours(&self.objects[idx])?;
+            edit(&mut cs)?;
+            set_local_path(&mut self.objects[idx], contours_to_path(&cs));
+            Ok(())
+        });
+        match result {
+            Ok(()) => format!("{{\"success\": true, \"id\": {}}}", id),
+            Err(e) => serde_json::json!({ "error": e }).to_string(),
+        }
+    }
+
+    pub(crate) fn reverse_path(&mut self, id: u32, params: &serde_json::Value) -> String {
+        let wanted = subpath_filter(params);
+        self.edit_contours(id, |cs| {
+            for (i, c) in cs.iter_mut().enumerate() { if wanted(i) { c.reverse(); } }
+            Ok(())
+        })
+    }
+
+    pub(crate) fn set_path_closed(&mut self, id: u32, closed: bool, params: &serde_json::Value) -> String {
+        let wanted = subpath_filter(params);
+        let average = params["mode"].as_str() == Some("average");
+        self.edit_contours(id, |cs| {
+            for (i, c) in cs.iter_mut().enumerate() {
+                if !wanted(i) { continue; }
+                if closed { close_contour(c, average); } else { c.closed = false; }
+            }
+            Ok(())
+        })
+    }
+
+    /// Splits a path at a world-space point or at a node (`subpath` + `node`).
+    /// Open pieces beyond the first become new objects with the same style.
+    pub(crate) fn split_path(&mut self, id: u32, params: &serde_json::Value) -> String {
+        let result = self.object_index(id).and_then(|idx| {
+            let obj = &self.objects[idx];
+            let mut cs = self.editable_contours(obj)?;
+            let (ci, si, t) = if let Some(node) = params["node"].as_u64() {
+                let ci = params["subpath"].as_u64().unwrap_or(0) as usize;
+                let c = cs.get(ci).ok_or("Subpath not found")?;
+                let node = node as usize;
+                if c.closed { (ci, node % c.segs.len(), 0.0) }
+                else if node > 0 && node < c.segs.len() { (ci, node, 0.0) }
+                else { return Err("Can't split an open path at its end point".to_string()); }
+            } else {
+                let world = Point::new(params["x"].as_f64().unwrap_or(0.0), params["y"].as_f64().unwrap_or(0.0));
+                let local = object_transform(obj).inverse() * world;
+                let (ci, si, t, _) = nearest_on_contours(&cs, local).ok_or("Path is empty")?;
+                (ci, si, t)
+            };
+            let mut pieces = cs[ci].split(si, t);
+            if pieces.is_empty() { return Err("Nothing to split".to_string()); }
+            // An open path cut at one of its ends comes back whole
+            if !cs[ci].closed && pieces.len() < 2 { return Err("Can't split an open path at its end point".to_string()); }
+            let extra = pieces.split_off(1);
+            cs.splice(ci..=ci, pieces);
+
+            let mut ids = vec![id];
+            let template = self.objects[idx].clone();
+            set_local_path(&mut self.objects[idx], contours_to_path(&cs));
+            for (n, piece) in extra.into_iter().enumerate() {
+                let mut obj = template.clone();
+                obj.id = self.next_id; self.next_id += 1;
+                set_local_path(&mut obj, contours_to_path(&[piece]));
+                ids.push(obj.id);
+                self.objects.insert(idx + 1 + n, obj);
+            }
+            Ok(ids)
+        });
+        match result {
+            Ok(ids) => serde_json::json!({ "success": true, "ids": ids }).to_string(),
+            Err(e) => serde_json::json!({ "error": e }).to_string(),
+        }
+    }
+
+    /// Joins the nearest open ends of two paths; the second path is merged into
+    /// the first. With a single id the path's own open ends are joined.
+    pub(crate) fn join_paths(&mut self, ids: &[u32], average: bool) -> String {
+        let result = (|| {
+            let &first = ids.first().ok_or("No path given")?;
+            if ids.len() > 2 { return Err("Join takes one or two paths".to_string()); }
+            if ids.len() == 2 && ids[1] == first { return Err("Can't join a path to itself".to_string()); }
+            if ids.len() == 1 {
+                let idx = self.object_index(first)?;
+                let mut cs = self.editable_contours(&self.objects[idx])?;
+                let c = cs.iter_mut().find(|c| !c.closed).ok_or("Path has no open ends")?;
+                close_contour(c, average);
+                set_local_path(&mut self.objects[idx], contours_to_path(&cs));
+                return Ok(first);
+            }
+            let (ia, ib) = (self.object_index(first)?, self.object_index(ids[1])?);
+            let ta = object_transform(&self.objects[ia]);
+            // Work in the first object's local space
+            let to_a = ta.inverse() * object_transform(&self.objects[ib]);
+            let mut ca = self.editable_contours(&self.objects[ia])?;
+            let cb: Vec<Contour> = contours(&{ let mut p = contours_to_path(&self.editable_contours(&self.objects[ib])?); p.apply_affine(to_a); p });
+
+            // Pick the pair of open contours whose ends are closest together
+            let mut best: Option<(usize, usize, f64)> = None;
+            for (i, a) in ca.iter().enumerate().filter(|(_, c)| !c.closed) {
+                for (j, b) in cb.iter().enumerate().filter(|(_, c)| !c.closed) {
+                    let d = [a.start().distance(b.start()), a.start().distance(b.end()), a.end().distance(b.start()), a.end().distance(b.end())]
+                        .into_iter().fold(f64::INFINITY, f64::min);
+                    if best.is_none_or(|x| d < x.2) { best = Some((i, j, d)); }
+                }
+            }
+            let (i, j, _) = best.ok_or("Both paths need an open end")?;
+            ca[i] = join_contours(ca[i].clone(), cb[j].clone(), average);
+            ca.extend(cb.into_iter().enumerate().filter(|(k, _)| *k != j).map(|(_, c)| c));
+            set_local_path(&mut self.objects[ia], contours_to_path(&ca));
+            let second = ids[1];
+            self.objects.retain(|o| o.id != second);
+            self.selected_ids.retain(|&s| s != second);
+            Ok::<u32, String>(first)
+        })();
+        match result {
+            Ok(id) => format!("{{\"success\": true, \"id\": {}}}", id),
+            Err(e) => serde_json::json!({ "error": e }).to_string(),
+        }
+    }
+
+    /// Splits every subpath of a compound path into its own Path object.
+    pub(crate) fn break_apart(&mut self, id: u32) -> String {
+        let result = self.object_index(id).and_then(|idx| {
+            let template = self.objects[idx].clone();
+            let cs = self.editable_contours(&template)?;
+            if cs.len() < 2 { return Err("Path has a single subpath".to_string()); }
+            let mut ids = Vec::new();
+            let mut pieces = Vec::new();
+            for (n, c) in cs.into_iter().enumerate() {
+                let mut obj = template.clone();
+                if n > 0 { obj.id = self.next_id; self.next_id += 1; obj.name = format!("{} {}", template.name, n + 1); }
+                set_local_path(&mut obj, contours_to_path(&[c]));
+                ids.push(obj.id);
+                pieces.push(obj);
+            }
+            self.objects.splice(idx..=idx, pieces);
+            Ok(ids)
+        });
+        match result {
+            Ok(ids) => serde_json::json!({ "success": true, "ids": ids }).to_string(),
+            Err(e) => serde_json::json!({ "error": e }).to_string(),
+        }
+    }
+
+    /// Combines several objects into one even-odd filled path that takes the
+    /// style of the backmost object and sits where the frontmost one was.
+    pub(crate) fn make_compound_path(&mut self, ids: &[u32]) -> String {
+        let mut indices: Vec<usize> = ids.iter().filter_map(|id| self.objects.iter().position(|o| o.id == *id)).collect();
+        indices.sort_unstable();
+        indices.dedup();
+        if indices.len() < 2 { return "{ \"error\": \"At least 2 objects required\" }".to_string(); }
+
+        let base = self.objects[indices[0]].clone();
+        let to_base = object_transform(&base).inverse();
+        let mut combined = BezPath::new();
+        for &idx in &indices {
+            match self.editable_contours(&self.objects[idx]) {
+                Ok(cs) => {
+                    let mut p = contours_to_path(&cs);
+                    p.apply_affine(to_base * object_transform(&self.objects[idx]));
+                    combined.extend(p);
+                }
+                Err(e) => return serde_json::json!({ "error": e }).to_string(),
+            }
+        }
+        let mut compound = base;
+        compound.fill_rule = "evenodd".to_string();
+        set_local_path(&mut compound, combined);
+        let front = indices[indices.len() - 1];
+        let new_id = compound.id;
+        self.objects.insert(front + 1, compound);
+        for &idx in indices.iter().rev() { self.objects.remove(idx); }
+        self.selected_ids = vec![new_id];
+        format!("{{\"success\": true, \"id\": {}}}", new_id)
+    }
+}
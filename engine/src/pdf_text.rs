@@ -0,0 +1,323 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use kurbo::{Affine, Shape};
+use ttf_parser::{Face, GlyphId, Tag};
+use crate::ai::parse_hex_color;
+use crate::objects::VectorObject;
+use crate::text::{FontFace, FontRegistry};
+use crate::text_layout::{layout_text, OBLIQUE_SKEW};
+
+// Text for AI/PDF export. The fonts text uses are embedded as Type0 fonts
+// with Identity-H encoding, so the layout's glyph ids go straight into the
+// content stream, and a ToUnicode map keeps the text searchable. TrueType
+// fonts are subset to the glyphs used; CFF-based fonts are embedded whole.
+
+struct EmbeddedFont<'a> {
+    font: &'a FontFace,
+    glyphs: BTreeMap<u16, String>, // Glyph id to the text it shows
+}
+
+#[derive(Default)]
+pub struct PdfFonts<'a> {
+    fonts: Vec<EmbeddedFont<'a>>,
+}
+
+impl<'a> PdfFonts<'a> {
+    fn index(&mut self, font: &'a FontFace) -> usize {
+        if let Some(i) = self.fonts.iter().position(|f| std::ptr::eq(f.font, font)) { return i; }
+        self.fonts.push(EmbeddedFont { font, glyphs: BTreeMap::new() });
+        self.fonts.len() - 1
+    }
+
+    /// Page resource name of an embedded font; F1 is the Helvetica fallback.
+    fn resource_name(index: usize) -> String {
+        format!("F{}", index + 2)
+    }
+
+    /// Content stream operators drawing a text object laid out with its
+    /// registered fonts, with the page's y axis flipped at `height`. `None`
+    /// without a registered font.
+    pub fn text_ops(&mut self, obj: &VectorObject, fonts: &'a FontRegistry, height: f64) -> Option<Vec<u8>> {
+        let layout = layout_text(obj, fonts).ok()?;
+        let mut used = Vec::with_capacity(layout.styles.len());
+        for style in &layout.styles {
+            let font = fonts.resolve(&style.font_family, &style.font_weight, style.italic)?;
+            let face = Face::parse(&font.data, 0).ok()?;
+            let skew = if style.italic && !font.italic { OBLIQUE_SKEW } else { 0.0 };
+            used.push((self.index(font), face, skew));
+        }
+        let rgb = |color: &str| {
+            let (r, g, b) = parse_hex_color(color);
+            format!("{} {} {}", r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0)
+        };
+        let fill = |f: &Option<String>| rgb(f.as_deref().unwrap_or(&obj.fill));
+        let stroke = obj.stroke_width > 0.0 && obj.stroke != "transparent" && !obj.stroke.is_empty();
+
+        let mut ops = format!("q\n{}", object_matrix(obj, height));
+        if stroke { ops.push_str(&format!("{} RG\n{} w\n", rgb(&obj.stroke), obj.stroke_width)); }
+        for underline in layout.underlines(fonts).ok()? {
+            let r = underline.path.bounding_box();
+            ops.push_str(&format!("{} rg\n{} {} {} {} re\n{}\n", fill(&underline.fill), r.x0, r.y0, r.width(), r.height(), if stroke { "B" } else { "f" }));
+        }
+        ops.push_str(&format!("BT\n{} Tr\n", if stroke { 2 } else { 0 }));
+        for line in &layout.lines {
+            // A glyph shows the text from its cluster to the next one
+            let mut clusters: Vec<usize> = line.glyphs.iter().map(|g| g.cluster).collect();
+            clusters.sort_unstable();
+            clusters.dedup();
+            let text_of = |c: usize| {
+                let end = clusters.iter().copied().find(|&n| n > c).unwrap_or(line.end).max(c);
+                obj.text_content[c..end].to_string()
+            };
+            // Glyphs of a style on one baseline share a TJ; its numbers move
+            // each glyph from where the font's advance leaves it to where the
+            // layout put it
+            let mut i = 0;
+            while i < line.glyphs.len() {
+                let first = &line.glyphs[i];
+                let (font, face, skew) = &used[first.style];
+                let style = &layout.styles[first.style];
+                let size = style.font_size;
+                let upem = face.units_per_em() as f64;
+                ops.push_str(&format!("/{} {} Tf\n{} rg\n1 0 {} -1 {} {} Tm\n[", Self::resource_name(*font), size, fill(&style.fill), skew, first.x, first.y));
+                let mut j = i;
+                loop {
+                    let g = &line.glyphs[j];
+                    self.fonts[*font].glyphs.entry(g.id).or_insert_with(|| text_of(g.cluster));
+                    ops.push_str(&format!("<{:04X}>", g.id));
+                    match line.glyphs.get(j + 1) {
+                        Some(next) if next.style == g.style && next.y == g.y && size > 0.0 => {
+                            let width = face.glyph_hor_advance(GlyphId(g.id)).unwrap_or(0) as f64 * 1000.0 / upem;
+                            let adjust = ((width - (next.x - g.x) * 1000.0 / size) * 1000.0).round() / 1000.0;
+                            if adjust.abs() > 1e-3 { ops.push_str(&format!(" {} ", adjust)); }
+                            j += 1;
+                        }
+                        _ => break,
+                    }
+                }
+                ops.push_str("] TJ\n");
+                i = j + 1;
+            }
+        }
+        ops.push_str("ET\nQ\n");
+        Some(ops.into_bytes())
+    }
+
+    /// Font resource entries for the page, for fonts written from `first_id`.
+    pub fn resources(&self, first_id: usize) -> String {
+        (0..self.fonts.len()).map(|i| format!("/{} {} 0 R ", Self::resource_name(i), first_id + i * 5)).collect()
+    }
+
+    /// The PDF objects of the embedded fonts, five per font, numbered from
+    /// `first_id`.
+    pub fn objects(&self, first_id: usize) -> Vec<Vec<u8>> {
+        let mut objects = Vec::new();
+        for (i, embedded) in self.fonts.iter().enumerate() {
+            let id = first_id + i * 5;
+            let Ok(face) = Face::parse(&embedded.font.data, 0) else { continue };
+            let scale = 1000.0 / face.units_per_em() as f64;
+            let glyphs: BTreeSet<u16> = embedded.glyphs.keys().copied().collect();
+            let name = format!("{}+{}", subset_tag(i, &glyphs), postscript_name(&face, &embedded.font.family));
+            let (cid_type, file_key, file) = match subset_truetype(&embedded.font.data, &glyphs) {
+                Some(subset) => ("CIDFontType2", "FontFile2", subset),
+                None => ("CIDFontType0", "FontFile3", embedded.font.data.clone()),
+            };
+
+            objects.push(format!("<< /Type /Font /Subtype /Type0 /BaseFont /{} /Encoding /Identity-H /DescendantFonts [{} 0 R] /ToUnicode {} 0 R >>\n", name, id + 1, id + 4).into_bytes());
+
+            let widths: String = glyphs.iter().map(|&g| format!("{} [{}] ", g, (face.glyph_hor_advance(GlyphId(g)).unwrap_or(0) as f64 * scale).round())).collect();
+            objects.push(format!("<< /Type /Font /Subtype /{} /BaseFont /{} /CIDSystemInfo << /Registry (Adobe) /Ordering (Identity) /Supplement 0 >> /FontDescriptor {} 0 R /DW 1000 /W [{}] /CIDToGIDMap /Identity >>\n", cid_type, name, id + 2, widths).into_bytes());
+
+            let bbox = face.global_bounding_box();
+            let flags = 4 | if face.is_monospaced() { 1 } else { 0 } | if face.is_italic() { 64 } else { 0 };
+            let ascent = face.ascender() as f64 * scale;
+            objects.push(format!("<< /Type /FontDescriptor /FontName /{} /Flags {} /FontBBox [{} {} {} {}] /ItalicAngle {} /Ascent {} /Descent {} /CapHeight {} /StemV 80 /{} {} 0 R >>\n",
+                name, flags, (bbox.x_min as f64 * scale).round(), (bbox.y_min as f64 * scale).round(), (bbox.x_max as f64 * scale).round(), (bbox.y_max as f64 * scale).round(),
+                face.italic_angle(), ascent.round(), (face.descender() as f64 * scale).round(), face.capital_height().map_or(ascent, |h| h as f64 * scale).round(), file_key, id + 3).into_bytes());
+
+            let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(&file).unwrap();
+            let compressed = encoder.finish().unwrap();
+            let subtype = if file_key == "FontFile3" { " /Subtype /OpenType" } else { "" };
+            let mut stream = format!("<< /Length {} /Length1 {} /Filter /FlateDecode{} >>\nstream\n", compressed.len(), file.len(), subtype).into_bytes();
+            stream.extend_from_slice(&compressed);
+            stream.extend_from_slice(b"\nendstream\n");
+            objects.push(stream);
+
+            let cmap = to_unicode_cmap(&embedded.glyphs);
+            objects.push(format!("<< /Length {} >>\nstream\n{}\nendstream\n", cmap.len(), cmap).into_bytes());
+        }
+        objects
+    }
+}
+
+/// `cm` operator mapping a text object's local space, y down, to the page.
+fn object_matrix(obj: &VectorObject, height: f64) -> String {
+    let m = (Affine::new([1.0, 0.0, 0.0, -1.0, 0.0, height])
+        * Affine::translate((obj.x + obj.width / 2.0, obj.y + obj.height / 2.0))
+        * Affine::rotate(obj.rotation)
+        * Affine::translate((-obj.width / 2.0, -obj.height / 2.0))).as_coeffs();
+    format!("{} {} {} {} {} {} cm\n", m[0], m[1], m[2], m[3], m[4], m[5])
+}
+
+/// Text without a registered font, in Helvetica: one line per paragraph at
+/// the object's leading, left aligned. Characters outside WinAnsi show as
+/// `?`.
+pub fn fallback_text_ops(obj: &VectorObject, height: f64) -> Vec<u8> {
+    let (r, g, b) = parse_hex_color(&obj.fill);
+    let font = if crate::text::parse_weight(&obj.font_weight) >= 600 { "F1B" } else { "F1" };
+    let mut ops = format!("q\n{}BT\n/{} {} Tf\n{} {} {} rg\n{} Tc\n",
+        object_matrix(obj, height), font, obj.font_size, r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, obj.tracking / 1000.0 * obj.font_size).into_bytes();
+    for (i, line) in obj.text_content.split('\n').enumerate() {
+        ops.extend_from_slice(format!("1 0 0 -1 0 {} Tm\n(", obj.font_size + i as f64 * obj.font_size * obj.leading).as_bytes());
+        for c in line.trim_end_matches('\r').chars() {
+            match c {
+                '(' | ')' | '\\' => { ops.push(b'\\'); ops.push(c as u8); }
+                c if (c as u32) < 0x20 => ops.push(b' '),
+                c if (c as u32) <= 0xFF => ops.push(c as u32 as u8),
+                _ => ops.push(b'?'),
+            }
+        }
+        ops.extend_from_slice(b") Tj\n");
+    }
+    ops.extend_from_slice(b"ET\nQ\n");
+    ops
+}
+
+fn postscript_name(face: &Face, family: &str) -> String {
+    let name = face.names().into_iter()
+        .find(|n| n.name_id == ttf_parser::name_id::POST_SCRIPT_NAME)
+        .and_then(|n| n.to_string())
+        .unwrap_or_else(|| family.to_string());
+    // PDF names can't hold delimiters or spaces
+    name.chars().filter(|c| c.is_ascii_graphic() && !"()<>[]{}/%#".contains(*c)).collect()
+}
+
+/// Six capital letters naming a subset, from the font and its glyphs.
+fn subset_tag(index: usize, glyphs: &BTreeSet<u16>) -> String {
+    let mut hash: u32 = 2166136261 ^ index as u32;
+    for g in glyphs { hash = (hash ^ *g as u32).wrapping_mul(16777619); }
+    (0..6).map(|k| (b'A' + ((hash >> (k * 5)) % 26) as u8) as char).collect()
+}
+
+fn to_unicode_cmap(glyphs: &BTreeMap<u16, String>) -> String {
+    let mut cmap = String::from("/CIDInit /ProcSet findresource begin\n12 dict begin\nbegincmap\n/CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n/CMapName /Adobe-Identity-UCS def\n/CMapType 2 def\n1 begincodespacerange\n<0000> <FFFF>\nendcodespacerange\n");
+    // .notdef stands in for any missing character, so it maps to none
+    let entries: Vec<_> = glyphs.iter().filter(|(gid, text)| **gid != 0 && !text.is_empty()).collect();
+    // bfchar blocks hold at most 100 entries
+    for block in entries.chunks(100) {
+        cmap.push_str(&format!("{} beginbfchar\n", block.len()));
+        for (gid, text) in block {
+            let utf16: String = text.encode_utf16().map(|u| format!("{:04X}", u)).collect();
+            cmap.push_str(&format!("<{:04X}> <{}>\n", gid, utf16));
+        }
+        cmap.push_str("endbfchar\n");
+    }
+    cmap.push_str("endcmap\nCMapName currentdict /CMap defineresource pop\nend\nend");
+    cmap
+}
+
+/// Glyph ids a composite glyph is built from.
+fn components(glyph: &[u8]) -> Vec<u16> {
+    let mut out = Vec::new();
+    if glyph.len() < 10 || i16::from_be_bytes([glyph[0], glyph[1]]) >= 0 { return out; }
+    let mut pos = 10;
+    while pos + 4 <= glyph.len() {
+        let flags = u16::from_be_bytes([glyph[pos], glyph[pos + 1]]);
+        out.push(u16::from_be_bytes([glyph[pos + 2], glyph[pos + 3]]));
+        // Arguments are words or bytes, then an optional scale, x/y scale or 2x2 matrix
+        pos += 4 + if flags & 0x1 != 0 { 4 } else { 2 };
+        pos += if flags & 0x8 != 0 { 2 } else if flags & 0x40 != 0 { 4 } else if flags & 0x80 != 0 { 8 } else { 0 };
+        if flags & 0x20 == 0 { break; }
+    }
+    out
+}
+
+/// A copy of a TrueType font with only the outlines of `glyphs` (and the
+/// glyphs they're built from) left in. Glyph ids don't change, so the subset
+/// works with an identity CIDToGIDMap. `None` for fonts without `glyf`.
+fn subset_truetype(data: &[u8], glyphs: &BTreeSet<u16>) -> Option<Vec<u8>> {
+    let raw = ttf_parser::RawFace::parse(data, 0).ok()?;
+    let table = |tag: &[u8; 4]| raw.table(Tag::from_bytes(tag));
+    let (head, loca, glyf) = (table(b"head")?, table(b"loca")?, table(b"glyf")?);
+    if head.len() < 54 { return None; }
+    let long = i16::from_be_bytes([head[50], head[51]]) != 0;
+    let count = (if long { loca.len() / 4 } else { loca.len() / 2 }).checked_sub(1)?;
+    let offset = |i: usize| if long {
+        u32::from_be_bytes([loca[4 * i], loca[4 * i + 1], loca[4 * i + 2], loca[4 * i + 3]]) as usize
+    } else {
+        u16::from_be_bytes([loca[2 * i], loca[2 * i + 1]]) as usize * 2
+    };
+    let glyph = |g: usize| glyf.get(offset(g)..offset(g + 1)).unwrap_or(&[]);
+
+    let mut keep: BTreeSet<u16> = glyphs.iter().copied().filter(|&g| (g as usize) < count).collect();
+    keep.insert(0);
+    let mut pending: Vec<u16> = keep.iter().copied().collect();
+    while let Some(g) = pending.pop() {
+        for c in components(glyph(g as usize)) {
+            if (c as usize) < count && keep.insert(c) { pending.push(c); }
+        }
+    }
+    let mut new_glyf = Vec::new();
+    let mut new_loca = Vec::with_capacity((count + 1) * 4);
+    for g in 0..count {
+        new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+        if keep.contains(&(g as u16)) {
+            new_glyf.extend_from_slice(glyph(g));
+            while new_glyf.len() % 4 != 0 { new_glyf.push(0); }
+        }
+    }
+    new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+    let mut new_head = head.to_vec();
+    new_head[8..12].fill(0);
+    new_head[50..52].copy_from_slice(&1i16.to_be_bytes());
+
+    let mut tables = Vec::new();
+    for tag in [b"OS/2", b"cvt ", b"fpgm", b"glyf", b"head", b"hhea", b"hmtx", b"loca", b"maxp", b"name", b"prep"] {
+        let data = match tag {
+            b"glyf" => new_glyf.clone(),
+            b"loca" => new_loca.clone(),
+            b"head" => new_head.clone(),
+            _ => match table(tag) { Some(t) => t.to_vec(), None => continue },
+        };
+        tables.push((*tag, data));
+    }
+    Some(write_sfnt(&tables))
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0u8; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+/// A TrueType file from tables sorted by tag.
+fn write_sfnt(tables: &[([u8; 4], Vec<u8>)]) -> Vec<u8> {
+    let count = tables.len() as u16;
+    let selector = 15 - count.leading_zeros() as u16;
+    let range = (1u16 << selector) * 16;
+    let mut out = Vec::new();
+    out.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+    for v in [count, range, selector, count * 16 - range] { out.extend_from_slice(&v.to_be_bytes()); }
+    let mut body = Vec::new();
+    let mut head_at = None;
+    for (tag, data) in tables {
+        let at = 12 + 16 * tables.len() + body.len();
+        if tag == b"head" { head_at = Some(at); }
+        out.extend_from_slice(tag);
+        out.extend_from_slice(&checksum(data).to_be_bytes());
+        out.extend_from_slice(&(at as u32).to_be_bytes());
+        out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        body.extend_from_slice(data);
+        while body.len() % 4 != 0 { body.push(0); }
+    }
+    out.extend_from_slice(&body);
+    // The head table's adjustment makes the whole file sum to a fixed value
+    if let Some(at) = head_at {
+        let adjust = 0xB1B0_AFBAu32.wrapping_sub(checksum(&out));
+        out[at + 8..at + 12].copy_from_slice(&adjust.to_be_bytes());
+    }
+    out
+}
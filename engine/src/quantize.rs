@@ -0,0 +1,208 @@
+use std::collections::HashSet;
+
+// Colour quantization used by the colour tracing mode of `vectorize`.
+
+pub type Rgb = [u8; 3];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum QuantizeMethod {
+    MedianCut,
+    KMeans,
+    Grayscale,
+}
+
+impl QuantizeMethod {
+    pub fn parse(name: &str) -> Self {
+        match name {
+            "median_cut" => QuantizeMethod::MedianCut,
+            "grayscale" => QuantizeMethod::Grayscale,
+            _ => QuantizeMethod::KMeans,
+        }
+    }
+}
+
+/// Result of mapping an image onto a palette. `indices` holds one entry per
+/// pixel; fully transparent pixels map to `None`.
+pub struct QuantizedImage {
+    pub width: u32,
+    pub height: u32,
+    pub palette: Vec<Rgb>,
+    pub indices: Vec<Option<usize>>,
+}
+
+impl QuantizedImage {
+    pub fn coverage(&self) -> Vec<usize> {
+        let mut counts = vec![0usize; self.palette.len()];
+        for idx in self.indices.iter().flatten() {
+            counts[*idx] += 1;
+        }
+        counts
+    }
+}
+
+const MAX_SAMPLES: usize = 100_000;
+const KMEANS_ITERATIONS: usize = 12;
+
+pub fn quantize(rgba: &[u8], width: u32, height: u32, method: QuantizeMethod, colors: usize) -> QuantizedImage {
+    let colors = colors.clamp(1, 256);
+    let samples = sample_pixels(rgba);
+    let palette = match method {
+        QuantizeMethod::MedianCut => median_cut(&samples, colors),
+        QuantizeMethod::KMeans => {
+            let seeds = median_cut(&samples, colors);
+            kmeans(&samples, seeds, KMEANS_ITERATIONS)
+        }
+        QuantizeMethod::Grayscale => return quantize_gray(rgba, width, height, colors),
+    };
+    map_to_palette(rgba, width, height, palette)
+}
+
+pub fn map_to_palette(rgba: &[u8], width: u32, height: u32, palette: Vec<Rgb>) -> QuantizedImage {
+    let indices = rgba
+        .chunks_exact(4)
+        .map(|p| if p[3] < 128 || palette.is_empty() { None } else { Some(nearest(&palette, [p[0], p[1], p[2]])) })
+        .collect();
+    QuantizedImage { width, height, palette, indices }
+}
+
+/// Evenly spaced gray levels, assigned by luma rather than RGB distance.
+fn quantize_gray(rgba: &[u8], width: u32, height: u32, levels: usize) -> QuantizedImage {
+    let levels = levels.max(2);
+    let palette: Vec<Rgb> = (0..levels)
+        .map(|i| {
+            let v = (i as f64 * 255.0 / (levels - 1) as f64).round() as u8;
+            [v, v, v]
+        })
+        .collect();
+    let indices = rgba
+        .chunks_exact(4)
+        .map(|p| {
+            if p[3] < 128 { return None; }
+            let l = luma([p[0], p[1], p[2]]);
+            Some(((l / 255.0) * (levels - 1) as f64).round() as usize)
+        })
+        .collect();
+    QuantizedImage { width, height, palette, indices }
+}
+
+pub fn parse_hex(hex: &str) -> Option<Rgb> {
+    let hex = hex.trim().trim_start_matches('#');
+    match hex.len() {
+        6 => u32::from_str_radix(hex, 16).ok().map(|c| [(c >> 16) as u8, (c >> 8) as u8, c as u8]),
+        3 => u16::from_str_radix(hex, 16).ok().map(|c| {
+            let r = ((c >> 8) & 0xf) as u8;
+            let g = ((c >> 4) & 0xf) as u8;
+            let b = (c & 0xf) as u8;
+            [r | (r << 4), g | (g << 4), b | (b << 4)]
+        }),
+        _ => None,
+    }
+}
+
+pub fn to_hex(c: Rgb) -> String {
+    format!("#{:02x}{:02x}{:02x}", c[0], c[1], c[2])
+}
+
+pub fn luma(c: Rgb) -> f64 {
+    0.299 * c[0] as f64 + 0.587 * c[1] as f64 + 0.114 * c[2] as f64
+}
+
+fn sample_pixels(rgba: &[u8]) -> Vec<Rgb> {
+    let total = rgba.len() / 4;
+    let step = (total / MAX_SAMPLES).max(1);
+    rgba.chunks_exact(4)
+        .step_by(step)
+        .filter(|p| p[3] >= 128)
+        .map(|p| [p[0], p[1], p[2]])
+        .collect()
+}
+
+fn dist2(a: Rgb, b: Rgb) -> u32 {
+    let dr = a[0] as i32 - b[0] as i32;
+    let dg = a[1] as i32 - b[1] as i32;
+    let db = a[2] as i32 - b[2] as i32;
+    (dr * dr + dg * dg + db * db) as u32
+}
+
+fn nearest(palette: &[Rgb], c: Rgb) -> usize {
+    let mut best = 0;
+    let mut best_d = u32::MAX;
+    for (i, p) in palette.iter().enumerate() {
+        let d = dist2(*p, c);
+        if d < best_d {
+            best_d = d;
+            best = i;
+        }
+    }
+    best
+}
+
+fn mean(pixels: &[Rgb]) -> Rgb {
+    if pixels.is_empty() { return [0, 0, 0]; }
+    let mut sum = [0u64; 3];
+    for p in pixels {
+        for ch in 0..3 { sum[ch] += p[ch] as u64; }
+    }
+    let n = pixels.len() as u64;
+    [(sum[0] / n) as u8, (sum[1] / n) as u8, (sum[2] / n) as u8]
+}
+
+/// Repeatedly splits the box with the widest channel range at its median.
+pub fn median_cut(pixels: &[Rgb], colors: usize) -> Vec<Rgb> {
+    if pixels.is_empty() { return Vec::new(); }
+    let mut boxes: Vec<Vec<Rgb>> = vec![pixels.to_vec()];
+    while boxes.len() < colors {
+        let mut best: Option<(usize, usize, u8)> = None;
+        for (i, b) in boxes.iter().enumerate() {
+            if b.len() < 2 { continue; }
+            for ch in 0..3 {
+                let min = b.iter().map(|p| p[ch]).min().unwrap_or(0);
+                let max = b.iter().map(|p| p[ch]).max().unwrap_or(0);
+                let range = max - min;
+                if range > 0 && best.is_none_or(|(_, _, r)| range > r) {
+                    best = Some((i, ch, range));
+                }
+            }
+        }
+        let Some((i, ch, _)) = best else { break };
+        let mut b = boxes.swap_remove(i);
+        b.sort_by_key(|p| p[ch]);
+        // Split between distinct values so a dominant colour isn't divided into two boxes
+        let median = b[b.len() / 2][ch];
+        let mut cut = b.partition_point(|p| p[ch] <= median);
+        if cut == b.len() { cut = b.partition_point(|p| p[ch] < median); }
+        let upper = b.split_off(cut);
+        boxes.push(b);
+        boxes.push(upper);
+    }
+    boxes.iter().map(|b| mean(b)).collect()
+}
+
+/// Lloyd's algorithm starting from the given centroids.
+pub fn kmeans(pixels: &[Rgb], mut centroids: Vec<Rgb>, iterations: usize) -> Vec<Rgb> {
+    if pixels.is_empty() || centroids.is_empty() { return centroids; }
+    for _ in 0..iterations {
+        let mut sums = vec![[0u64; 4]; centroids.len()];
+        for p in pixels {
+            let s = &mut sums[nearest(&centroids, *p)];
+            s[0] += p[0] as u64;
+            s[1] += p[1] as u64;
+            s[2] += p[2] as u64;
+            s[3] += 1;
+        }
+        let mut changed = false;
+        for (c, s) in centroids.iter_mut().zip(sums.iter()) {
+            if s[3] == 0 { continue; }
+            let next = [(s[0] / s[3]) as u8, (s[1] / s[3]) as u8, (s[2] / s[3]) as u8];
+            if next != *c {
+                *c = next;
+                changed = true;
+            }
+        }
+        if !changed { break; }
+    }
+    // Centroids can converge on the same colour anywhere in the list
+    let mut seen = HashSet::new();
+    centroids.retain(|c| seen.insert(*c));
+    centroids
+}
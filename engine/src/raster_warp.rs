@@ -0,0 +1,175 @@
+use kurbo::{Affine, Point, Rect};
+use crate::geometry::object_transform;
+use crate::objects::VectorObject;
+use crate::warp::WarpMesh;
+
+// Mesh warping for raster images. The image is cut into a grid of small
+// quads whose corners are pushed through the mesh; each warped triangle is
+// then scan converted at several samples per output pixel, looking up the
+// source with bilinear or bicubic interpolation. Samples are averaged with
+// premultiplied alpha, so edges come out antialiased and everything outside
+// the warped image stays transparent.
+
+const SUPERSAMPLE: usize = 2;
+const MAX_PIXELS: f64 = 8_000_000.0;
+const GRID_CELL: f64 = 8.0; // Source pixels per grid cell
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Interpolation {
+    Bilinear,
+    Bicubic,
+}
+
+impl Interpolation {
+    pub fn parse(name: &str) -> Self {
+        if name == "bicubic" { Interpolation::Bicubic } else { Interpolation::Bilinear }
+    }
+}
+
+pub struct WarpedImage {
+    pub rgba: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    pub bounds: Rect, // World-space area covered by the pixels
+}
+
+struct Source<'a> {
+    rgba: &'a [u8],
+    width: usize,
+    height: usize,
+}
+
+impl Source<'_> {
+    /// Premultiplied pixel, with coordinates clamped to the edge.
+    fn texel(&self, x: i64, y: i64) -> [f32; 4] {
+        let x = x.clamp(0, self.width as i64 - 1) as usize;
+        let y = y.clamp(0, self.height as i64 - 1) as usize;
+        let i = (y * self.width + x) * 4;
+        let a = self.rgba[i + 3] as f32 / 255.0;
+        [self.rgba[i] as f32 * a, self.rgba[i + 1] as f32 * a, self.rgba[i + 2] as f32 * a, self.rgba[i + 3] as f32]
+    }
+
+    /// Samples at a position in pixel units, where pixel centres sit at +0.5.
+    fn sample(&self, x: f64, y: f64, interpolation: Interpolation) -> [f32; 4] {
+        let (fx, fy) = (x - 0.5, y - 0.5);
+        let (x0, y0) = (fx.floor() as i64, fy.floor() as i64);
+        let (tx, ty) = ((fx - x0 as f64) as f32, (fy - y0 as f64) as f32);
+        let mut out = [0.0f32; 4];
+        match interpolation {
+            Interpolation::Bilinear => {
+                let weights = [(0, 0, (1.0 - tx) * (1.0 - ty)), (1, 0, tx * (1.0 - ty)), (0, 1, (1.0 - tx) * ty), (1, 1, tx * ty)];
+                for (dx, dy, w) in weights {
+                    let t = self.texel(x0 + dx, y0 + dy);
+                    for c in 0..4 { out[c] += t[c] * w; }
+                }
+            }
+            Interpolation::Bicubic => {
+                let (wx, wy) = (catmull_rom(tx), catmull_rom(ty));
+                for (j, wyj) in wy.iter().enumerate() {
+                    for (i, wxi) in wx.iter().enumerate() {
+                        let t = self.texel(x0 + i as i64 - 1, y0 + j as i64 - 1);
+                        for c in 0..4 { out[c] += t[c] * wxi * wyj; }
+                    }
+                }
+                // Catmull-Rom overshoots; keep the colour inside the alpha
+                let alpha = out[3].clamp(0.0, 255.0);
+                out = [out[0].clamp(0.0, alpha), out[1].clamp(0.0, alpha), out[2].clamp(0.0, alpha), alpha];
+            }
+        }
+        out
+    }
+}
+
+fn catmull_rom(t: f32) -> [f32; 4] {
+    let (t2, t3) = (t * t, t * t * t);
+    [
+        0.5 * (-t3 + 2.0 * t2 - t),
+        0.5 * (3.0 * t3 - 5.0 * t2 + 2.0),
+        0.5 * (-3.0 * t3 + 4.0 * t2 + t),
+        0.5 * (t3 - t2),
+    ]
+}
+
+/// Warps the pixels shown by an image object. `rgba` holds the full source
+/// image; the object displays its `sx, sy, sw, sh` crop stretched over its box.
+pub fn warp_image(obj: &VectorObject, rgba: &[u8], width: u32, height: u32, mesh: &WarpMesh, interpolation: Interpolation) -> Option<WarpedImage> {
+    if width == 0 || height == 0 || rgba.len() < (width * height * 4) as usize || obj.width <= 0.0 || obj.height <= 0.0 { return None; }
+    let source = Source { rgba, width: width as usize, height: height as usize };
+    let (crop_x, crop_y) = (obj.sx, obj.sy);
+    let (crop_w, crop_h) = if obj.sw > 0.0 && obj.sh > 0.0 { (obj.sw, obj.sh) } else { (width as f64, height as f64) };
+
+    // Grid over the object's box, carrying world positions after the warp and
+    // the matching source pixel positions
+    let cols = ((crop_w / GRID_CELL).ceil() as usize).clamp(4, 256);
+    let rows = ((crop_h / GRID_CELL).ceil() as usize).clamp(4, 256);
+    let to_world = object_transform(obj);
+    let mut warped = Vec::with_capacity((rows + 1) * (cols + 1));
+    let mut src = Vec::with_capacity((rows + 1) * (cols + 1));
+    for r in 0..=rows {
+        for c in 0..=cols {
+            let (u, v) = (c as f64 / cols as f64, r as f64 / rows as f64);
+            warped.push(mesh.warp_point(to_world * Point::new(u * obj.width, v * obj.height)));
+            src.push(Point::new(crop_x + u * crop_w, crop_y + v * crop_h));
+        }
+    }
+    let bounds = warped.iter().fold(Rect::from_points(warped[0], warped[0]), |r, p| r.union_pt(*p));
+    if bounds.width() <= 0.0 || bounds.height() <= 0.0 { return None; }
+
+    // Keep roughly the source's pixel density, within the size limit
+    let mut density = (crop_w / obj.width).max(crop_h / obj.height);
+    let area = bounds.width() * bounds.height() * density * density;
+    if area > MAX_PIXELS { density *= (MAX_PIXELS / area).sqrt(); }
+    let out_w = (bounds.width() * density).ceil().max(1.0) as usize;
+    let out_h = (bounds.height() * density).ceil().max(1.0) as usize;
+    let to_pixels = Affine::scale_non_uniform(out_w as f64 / bounds.width(), out_h as f64 / bounds.height()) * Affine::translate((-bounds.x0, -bounds.y0));
+    let warped: Vec<Point> = warped.into_iter().map(|p| to_pixels * p).collect();
+
+    let ss = SUPERSAMPLE;
+    let (sub_w, sub_h) = (out_w * ss, out_h * ss);
+    let mut filled = vec![false; sub_w * sub_h];
+    let mut accum = vec![[0.0f32; 4]; out_w * out_h];
+    let mut raster = |tri: [usize; 3]| {
+        let (a, b, c) = (warped[tri[0]], warped[tri[1]], warped[tri[2]]);
+        let det = (b.x - a.x) * (c.y - a.y) - (c.x - a.x) * (b.y - a.y);
+        if det.abs() < 1e-12 { return; }
+        let min_x = ((a.x.min(b.x).min(c.x) * ss as f64).floor().max(0.0)) as usize;
+        let max_x = ((a.x.max(b.x).max(c.x) * ss as f64).ceil() as usize).min(sub_w);
+        let min_y = ((a.y.min(b.y).min(c.y) * ss as f64).floor().max(0.0)) as usize;
+        let max_y = ((a.y.max(b.y).max(c.y) * ss as f64).ceil() as usize).min(sub_h);
+        for sy in min_y..max_y {
+            for sx in min_x..max_x {
+                let idx = sy * sub_w + sx;
+                // A sample already claimed by another triangle (or by a fold in
+                // the mesh) is not painted twice
+                if filled[idx] { continue; }
+                let p = Point::new((sx as f64 + 0.5) / ss as f64, (sy as f64 + 0.5) / ss as f64);
+                let w1 = ((p.x - a.x) * (c.y - a.y) - (c.x - a.x) * (p.y - a.y)) / det;
+                let w2 = ((b.x - a.x) * (p.y - a.y) - (p.x - a.x) * (b.y - a.y)) / det;
+                let w0 = 1.0 - w1 - w2;
+                if w0 < -1e-9 || w1 < -1e-9 || w2 < -1e-9 { continue; }
+                filled[idx] = true;
+                let s = (src[tri[0]].to_vec2() * w0 + src[tri[1]].to_vec2() * w1 + src[tri[2]].to_vec2() * w2).to_point();
+                let colour = source.sample(s.x, s.y, interpolation);
+                let acc = &mut accum[(sy / ss) * out_w + sx / ss];
+                for ch in 0..4 { acc[ch] += colour[ch]; }
+            }
+        }
+    };
+    for r in 0..rows {
+        for c in 0..cols {
+            let i = r * (cols + 1) + c;
+            let (tl, tr, bl, br) = (i, i + 1, i + cols + 1, i + cols + 2);
+            raster([tl, tr, br]);
+            raster([tl, br, bl]);
+        }
+    }
+
+    let samples = (ss * ss) as f32;
+    let mut out = vec![0u8; out_w * out_h * 4];
+    for (px, acc) in out.chunks_exact_mut(4).zip(&accum) {
+        if acc[3] <= 0.0 { continue; }
+        for ch in 0..3 { px[ch] = (acc[ch] / acc[3] * 255.0).round().clamp(0.0, 255.0) as u8; }
+        px[3] = (acc[3] / samples).round().clamp(0.0, 255.0) as u8;
+    }
+    Some(WarpedImage { rgba: out, width: out_w as u32, height: out_h as u32, bounds })
+}
@@ -3,7 +3,11 @@ use crate::engine::VectorEngine;
 use crate::objects::VectorObject;
 use crate::types::{ShapeType, EffectType};
 use kurbo::BezPath;
-use web_sys::{CanvasRenderingContext2d, Path2d};
+use web_sys::{CanvasRenderingContext2d, CanvasWindingRule, Path2d};
+
+fn find_object(objects: &[VectorObject], id: u32) -> Option<&VectorObject> {
+    objects.iter().find_map(|o| if o.id == id { Some(o) } else { find_object(o.children.as_deref().unwrap_or_default(), id) })
+}
 
 #[wasm_bindgen]
 impl VectorEngine {
@@ -133,12 +137,37 @@ impl VectorEngine {
         }
     }
 
+    /// Rebuilds what the canvas keeps for objects changed since the last
+    /// refresh, so drawing a frame doesn't redo expensive geometry.
+    pub(crate) fn refresh_render_caches(&mut self) {
+        self.refresh_warps();
+        self.refresh_text_outlines();
+    }
+
     fn render_object(&self, ctx: &CanvasRenderingContext2d, obj: &VectorObject) {
         if !obj.visible { return; }
+        if obj.warp.is_some() {
+            let fresh;
+            let warped = match self.warped_objects.get(&obj.id) {
+                Some(w) if w.is_current(obj) => w,
+                _ => { fresh = crate::warp::WarpedObject::new(obj, &self.fonts); &fresh }
+            };
+            if let Some(expanded) = &warped.object { return self.render_object(ctx, expanded); }
+        }
+        if let Some(expanded) = crate::corners::expand_corners(obj) { return self.render_object(ctx, &expanded); }
+        if let Some(parts) = crate::appearance::expand_appearance(obj) {
+            for part in &parts { self.render_object(ctx, part); }
+            return;
+        }
+        if let Some(parts) = crate::stroke::expand_stroke(obj, false) {
+            for part in &parts { self.render_object(ctx, part); }
+            return;
+        }
         if obj.is_mask && obj.shape_type != ShapeType::SmartBackground { return; }
         ctx.save();
         if let Some(mask_id) = obj.mask_id {
-            if let Some(mask_obj) = self.objects.iter().find(|o| o.id == mask_id) {
+            // Masks sit beside what they clip, so inside a group they're among its children
+            if let Some(mask_obj) = find_object(&self.objects, mask_id) {
                 ctx.save();
                 ctx.translate(mask_obj.x + mask_obj.width / 2.0, mask_obj.y + mask_obj.height / 2.0).unwrap();
                 ctx.rotate(mask_obj.rotation).unwrap();
@@ -211,6 +240,7 @@ impl VectorEngine {
                  let dash_array = js_sys::Array::new();
                  for &d in &obj.stroke_dash { dash_array.push(&JsValue::from_f64(d)); }
                  let _ = ctx.set_line_dash(&dash_array);
+                 ctx.set_line_dash_offset(obj.stroke_dash_offset);
             } else { let _ = ctx.set_line_dash(&js_sys::Array::new()); }
 
             match obj.shape_type {
@@ -285,7 +315,8 @@ impl VectorEngine {
                                  }
                              } else {
                                  if let Ok(p) = Path2d::new_with_path_string(&obj.path_data) {
-                                     ctx.fill_with_path_2d(&p);
+                                     if obj.fill_rule == "evenodd" { ctx.fill_with_path_2d_and_winding(&p, CanvasWindingRule::Evenodd); }
+                                     else { ctx.fill_with_path_2d(&p); }
                                      if obj.stroke_width > 0.0 { ctx.stroke_with_path(&p); }
                                  }
                              }
@@ -293,10 +324,36 @@ impl VectorEngine {
                     }
                 }
                 ShapeType::Text => {
-                    ctx.set_font(&format!("{} {}px {}", obj.font_weight, obj.font_size, obj.font_family));
-                    ctx.set_text_align(&obj.text_align);
-                    let _ = ctx.fill_text(&obj.text_content, 0.0, obj.font_size);
-                    if obj.stroke_width > 0.0 { let _ = ctx.stroke_text(&obj.text_content, 0.0, obj.font_size); }
+                    let fresh;
+                    let outline = match self.text_outlines.get(&obj.id) {
+                        Some(o) if o.is_current(obj) => o,
+                        _ => { fresh = crate::text::TextOutline::new(obj, &self.fonts); &fresh }
+                    };
+                    if let Some(pieces) = &outline.pieces {
+                        // Runs with their own colour come after the object's fill
+                        for (path, fill) in pieces {
+                            if let Some(fill) = fill { ctx.set_fill_style_str(fill); }
+                            if let Ok(p) = Path2d::new_with_path_string(path) {
+                                ctx.fill_with_path_2d(&p);
+                                if obj.stroke_width > 0.0 { ctx.stroke_with_path(&p); }
+                            }
+                        }
+                    } else {
+                        // No registered font: the browser draws each line, wrapped like SVG export wraps it
+                        ctx.set_font(&format!("{} {}px {}", obj.font_weight, obj.font_size, obj.font_family));
+                        let (align, x) = match obj.text_align.as_str() {
+                            "center" => ("center", obj.width / 2.0),
+                            "right" => ("right", obj.width),
+                            _ => ("left", 0.0),
+                        };
+                        ctx.set_text_align(align);
+                        let lines = crate::text_layout::fallback_lines(obj).into_iter().flatten();
+                        for (i, line) in lines.map(|r| &obj.text_content[r]).enumerate() {
+                            let baseline = obj.font_size + i as f64 * obj.font_size * obj.leading;
+                            let _ = ctx.fill_text(line, x, baseline);
+                            if obj.stroke_width > 0.0 { let _ = ctx.stroke_text(line, x, baseline); }
+                        }
+                    }
                 }
                 _ => {}
             }
@@ -365,7 +422,7 @@ impl VectorEngine {
 
     fn render_smart_background(&self, ctx: &CanvasRenderingContext2d, obj: &VectorObject) {
         if let Some(bg_def) = crate::smart_backgrounds::get_background_by_id(&obj.intelligent_type) {
-            bg_def.render(ctx, obj.width, obj.height, &obj.intelligent_params);
+            bg_def.render(&mut ctx.clone(), obj.width, obj.height, &obj.intelligent_params);
         }
     }
 }
@@ -0,0 +1,272 @@
+use wasm_bindgen::prelude::*;
+use std::ops::Range;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use crate::engine::VectorEngine;
+use crate::objects::VectorObject;
+use crate::svg::escape_xml;
+use crate::text::FontRegistry;
+use crate::text_layout::{fallback_lines, fit_text_box, layout_text};
+use crate::types::ShapeType;
+
+// Rich text: runs override the object's character style over ranges of its
+// text, and paragraph styles set alignment, indents and spacing per
+// paragraph. Ranges count characters (Unicode scalar values). Runs are kept
+// sorted and apart, and unstyled text has no run.
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct TextStyle {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font_family: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font_size: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font_weight: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub italic: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fill: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub underline: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub baseline_shift: Option<f64>, // Raised by this much; negative lowers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tracking: Option<f64>, // Thousandths of an em
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TextRun {
+    pub start: usize,
+    pub end: usize,
+    #[serde(flatten)]
+    pub style: TextStyle,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ParagraphStyle {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub align: Option<String>, // Overrides `text_align`
+    #[serde(default)]
+    pub left_indent: f64,
+    #[serde(default)]
+    pub right_indent: f64,
+    #[serde(default)]
+    pub first_line_indent: f64, // On the paragraph's start side
+    #[serde(default)]
+    pub space_before: f64,
+    #[serde(default)]
+    pub space_after: f64,
+}
+
+/// A run's style filled in from the object.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ResolvedStyle {
+    pub font_family: String,
+    pub font_weight: String,
+    pub italic: bool,
+    pub font_size: f64,
+    pub fill: Option<String>, // None draws with the object's fill
+    pub underline: bool,
+    pub baseline_shift: f64,
+    pub tracking: f64,
+}
+
+impl ResolvedStyle {
+    pub fn new(obj: &VectorObject, style: &TextStyle) -> Self {
+        ResolvedStyle {
+            font_family: style.font_family.clone().unwrap_or_else(|| obj.font_family.clone()),
+            font_weight: style.font_weight.clone().unwrap_or_else(|| obj.font_weight.clone()),
+            italic: style.italic.unwrap_or(false),
+            font_size: style.font_size.unwrap_or(obj.font_size),
+            fill: style.fill.clone(),
+            underline: style.underline.unwrap_or(false),
+            baseline_shift: style.baseline_shift.unwrap_or(0.0),
+            tracking: style.tracking.unwrap_or(obj.tracking),
+        }
+    }
+}
+
+/// The style of each character of the text.
+pub fn char_styles(obj: &VectorObject) -> Vec<TextStyle> {
+    let mut styles = vec![TextStyle::default(); obj.text_content.chars().count()];
+    for run in &obj.text_runs {
+        let end = run.end.min(styles.len());
+        for s in styles.iter_mut().take(end).skip(run.start) { *s = run.style.clone(); }
+    }
+    styles
+}
+
+/// Runs for per-character styles, joining neighbours with the same style.
+pub(crate) fn to_runs(styles: &[TextStyle]) -> Vec<TextRun> {
+    let mut runs: Vec<TextRun> = Vec::new();
+    for (i, style) in styles.iter().enumerate() {
+        if style == &TextStyle::default() { continue; }
+        match runs.last_mut() {
+            Some(last) if last.end == i && &last.style == style => last.end = i + 1,
+            _ => runs.push(TextRun { start: i, end: i + 1, style: style.clone() }),
+        }
+    }
+    runs
+}
+
+/// Byte ranges of the text with their styles, in text order.
+pub fn style_spans(obj: &VectorObject) -> Vec<(Range<usize>, TextStyle)> {
+    let mut spans: Vec<(Range<usize>, TextStyle)> = Vec::new();
+    for ((pos, c), style) in obj.text_content.char_indices().zip(char_styles(obj)) {
+        match spans.last_mut() {
+            Some((range, last)) if *last == style => range.end = pos + c.len_utf8(),
+            _ => spans.push((pos..pos + c.len_utf8(), style)),
+        }
+    }
+    spans
+}
+
+pub fn paragraph_style(obj: &VectorObject, index: usize) -> ParagraphStyle {
+    obj.paragraphs.get(index).cloned().unwrap_or_default()
+}
+
+/// Applies a JSON patch to a style: keys set a value, and null clears it.
+fn patch<T: Serialize + DeserializeOwned>(value: &T, changes: &serde_json::Map<String, serde_json::Value>) -> Result<T, String> {
+    let mut merged = serde_json::to_value(value).map_err(|e| e.to_string())?;
+    let Some(map) = merged.as_object_mut() else { return Err("Invalid style".to_string()) };
+    for (key, v) in changes {
+        if v.is_null() { map.remove(key); } else { map.insert(key.clone(), v.clone()); }
+    }
+    serde_json::from_value(merged).map_err(|e| e.to_string())
+}
+
+/// Character range from `start`/`end` params, the whole text by default.
+fn char_range(obj: &VectorObject, params: &serde_json::Value) -> Range<usize> {
+    let len = obj.text_content.chars().count();
+    let start = (params["start"].as_u64().unwrap_or(0) as usize).min(len);
+    let end = (params["end"].as_u64().map_or(len, |v| v as usize)).clamp(start, len);
+    start..end
+}
+
+/// SVG `<tspan>`s for a byte range of the text, one per styled span.
+pub fn svg_spans(obj: &VectorObject, range: Range<usize>) -> String {
+    let mut out = String::new();
+    for (span, style) in style_spans(obj) {
+        let (start, end) = (span.start.max(range.start), span.end.min(range.end));
+        if start >= end { continue; }
+        let text = escape_xml(&obj.text_content[start..end]);
+        if style == TextStyle::default() { out.push_str(&text); continue; }
+        let resolved = ResolvedStyle::new(obj, &style);
+        let mut attrs = Vec::new();
+        if let Some(v) = &style.font_family { attrs.push(format!(r#"font-family="{}""#, escape_xml(v))); }
+        if let Some(v) = style.font_size { attrs.push(format!(r#"font-size="{}""#, v)); }
+        if let Some(v) = &style.font_weight { attrs.push(format!(r#"font-weight="{}""#, escape_xml(v))); }
+        if let Some(v) = style.italic { attrs.push(format!(r#"font-style="{}""#, if v { "italic" } else { "normal" })); }
+        if let Some(v) = &style.fill { attrs.push(format!(r#"fill="{}""#, escape_xml(v))); }
+        if let Some(v) = style.underline { attrs.push(format!(r#"text-decoration="{}""#, if v { "underline" } else { "none" })); }
+        if let Some(v) = style.baseline_shift { attrs.push(format!(r#"baseline-shift="{}""#, v)); }
+        if style.tracking.is_some() || (style.font_size.is_some() && resolved.tracking + obj.kerning != 0.0) { attrs.push(format!(r#"letter-spacing="{}""#, (resolved.tracking + obj.kerning) / 1000.0 * resolved.font_size)); }
+        out.push_str(&format!("<tspan {}>{}</tspan>", attrs.join(" "), text));
+    }
+    out
+}
+
+/// SVG `<tspan>` lines of text, placed like the canvas draws them: from the
+/// layout with a registered font, otherwise one line per paragraph, with
+/// area text wrapped as in `fallback_lines`.
+pub fn svg_text_lines(obj: &VectorObject, fonts: &FontRegistry) -> String {
+    if let Ok(layout) = layout_text(obj, fonts) {
+        return layout.lines.iter().map(|line| {
+            format!(r#"<tspan x="{}" y="{}">{}</tspan>"#, line.x, line.baseline, svg_spans(obj, line.start..line.end))
+        }).collect();
+    }
+    let spans = style_spans(obj);
+    let mut out = String::new();
+    let (mut baseline, mut space_after) = (0.0, 0.0);
+    for (i, lines) in fallback_lines(obj).into_iter().enumerate() {
+        let pstyle = paragraph_style(obj, i);
+        for (k, range) in lines.into_iter().enumerate() {
+            let size = spans.iter().filter(|(r, _)| r.start < range.end && r.end > range.start)
+                .map(|(_, s)| s.font_size.unwrap_or(obj.font_size)).reduce(f64::max).unwrap_or(obj.font_size);
+            baseline = match (i, k) {
+                (0, 0) => size,
+                (_, 0) => baseline + size * obj.leading + space_after + pstyle.space_before,
+                _ => baseline + size * obj.leading,
+            };
+            let indent = if k == 0 { pstyle.first_line_indent } else { 0.0 };
+            let (anchor, x) = match pstyle.align.as_deref().unwrap_or(&obj.text_align) {
+                "center" => ("middle", (pstyle.left_indent + obj.width - pstyle.right_indent) / 2.0),
+                "right" | "end" => ("end", obj.width - pstyle.right_indent),
+                _ => ("start", pstyle.left_indent + indent),
+            };
+            out.push_str(&format!(r#"<tspan x="{}" y="{}" text-anchor="{}">{}</tspan>"#, x, baseline, anchor, svg_spans(obj, range)));
+        }
+        space_after = pstyle.space_after;
+    }
+    out
+}
+
+#[wasm_bindgen]
+impl VectorEngine {
+    /// Styles characters `start..end` of a text object. Style keys given set
+    /// that attribute and null resets it to the object's own.
+    pub(crate) fn set_text_style(&mut self, id: u32, params: &serde_json::Value) -> String {
+        let Some(obj) = self.objects.iter_mut().find(|o| o.id == id && o.shape_type == ShapeType::Text) else { return "{ \"error\": \"Text not found\" }".to_string() };
+        let Some(changes) = params["style"].as_object() else { return "{ \"error\": \"No style given\" }".to_string() };
+        let range = char_range(obj, params);
+        let mut styles = char_styles(obj);
+        for style in &mut styles[range] {
+            match patch(style, changes) {
+                Ok(s) => *style = s,
+                Err(e) => return serde_json::json!({ "error": format!("Invalid text style: {}", e) }).to_string(),
+            }
+        }
+        obj.text_runs = to_runs(&styles);
+        fit_text_box(obj, &self.fonts);
+        "{ \"success\": true }".to_string()
+    }
+
+    /// Sets the style of the paragraphs touching characters `start..end`.
+    pub(crate) fn set_paragraph_style(&mut self, id: u32, params: &serde_json::Value) -> String {
+        let Some(obj) = self.objects.iter_mut().find(|o| o.id == id && o.shape_type == ShapeType::Text) else { return "{ \"error\": \"Text not found\" }".to_string() };
+        let Some(changes) = params["style"].as_object() else { return "{ \"error\": \"No style given\" }".to_string() };
+        let range = char_range(obj, params);
+        let breaks = |n: usize| obj.text_content.chars().take(n).filter(|c| *c == '\n').count();
+        let (first, last) = (breaks(range.start), breaks(range.end));
+        let count = obj.text_content.matches('\n').count() + 1;
+        let mut paragraphs: Vec<ParagraphStyle> = (0..count).map(|i| paragraph_style(obj, i)).collect();
+        for para in &mut paragraphs[first..=last] {
+            match patch(para, changes) {
+                Ok(p) => *para = p,
+                Err(e) => return serde_json::json!({ "error": format!("Invalid paragraph style: {}", e) }).to_string(),
+            }
+        }
+        while paragraphs.last() == Some(&ParagraphStyle::default()) { paragraphs.pop(); }
+        obj.paragraphs = paragraphs;
+        fit_text_box(obj, &self.fonts);
+        "{ \"success\": true }".to_string()
+    }
+
+    /// Replaces characters `start..end` with `text`. Inserted text takes the
+    /// style of the character before it, and new paragraphs the style of the
+    /// one they split from.
+    pub(crate) fn replace_text(&mut self, id: u32, params: &serde_json::Value) -> String {
+        let Some(obj) = self.objects.iter_mut().find(|o| o.id == id && o.shape_type == ShapeType::Text) else { return "{ \"error\": \"Text not found\" }".to_string() };
+        let text = params["text"].as_str().unwrap_or("");
+        let range = char_range(obj, params);
+        let chars: Vec<char> = obj.text_content.chars().collect();
+        let mut styles = char_styles(obj);
+        let inherit = styles.get(range.start.saturating_sub(1)).cloned().unwrap_or_default();
+        styles.splice(range.clone(), std::iter::repeat_n(inherit, text.chars().count()));
+        if !obj.paragraphs.is_empty() {
+            let para = chars[..range.start].iter().filter(|c| **c == '\n').count();
+            let removed = chars[range.clone()].iter().filter(|c| **c == '\n').count();
+            obj.paragraphs.resize(chars.iter().filter(|c| **c == '\n').count() + 1, ParagraphStyle::default());
+            let split = obj.paragraphs[para].clone();
+            obj.paragraphs.splice(para + 1..para + 1 + removed, std::iter::repeat_n(split, text.matches('\n').count()));
+            while obj.paragraphs.last() == Some(&ParagraphStyle::default()) { obj.paragraphs.pop(); }
+        }
+        let mut content: String = chars[..range.start].iter().collect();
+        content.push_str(text);
+        content.extend(&chars[range.end..]);
+        obj.text_content = content;
+        obj.text_runs = to_runs(&styles);
+        fit_text_box(obj, &self.fonts);
+        "{ \"success\": true }".to_string()
+    }
+}
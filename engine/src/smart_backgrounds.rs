@@ -1,5 +1,8 @@
 use serde::{Serialize, Deserialize};
+use kurbo::{Arc, BezPath, Point, Vec2};
 use web_sys::CanvasRenderingContext2d;
+use crate::svg::{escape_xml, svg_blend_mode, svg_gradient};
+use crate::types::{Gradient, GradientStop};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "lowercase")]
@@ -30,7 +33,134 @@ pub struct SmartBackgroundMetadata {
 
 pub trait SmartBackground {
     fn get_metadata(&self) -> SmartBackgroundMetadata;
-    fn render(&self, ctx: &CanvasRenderingContext2d, width: f64, height: f64, params: &[f64]);
+    fn render(&self, ctx: &mut dyn Surface, width: f64, height: f64, params: &[f64]);
+}
+
+/// The drawing calls backgrounds make, so the same code paints the canvas
+/// and writes SVG.
+pub trait Surface {
+    fn set_fill(&mut self, color: &str);
+    fn set_fill_gradient(&mut self, gradient: &Gradient);
+    fn set_stroke(&mut self, color: &str);
+    fn set_line_width(&mut self, width: f64);
+    fn set_composite(&mut self, op: &str);
+    fn fill_rect(&mut self, x: f64, y: f64, w: f64, h: f64);
+    fn begin_path(&mut self);
+    fn move_to(&mut self, x: f64, y: f64);
+    fn line_to(&mut self, x: f64, y: f64);
+    fn bezier_curve_to(&mut self, c1x: f64, c1y: f64, c2x: f64, c2y: f64, x: f64, y: f64);
+    fn arc(&mut self, x: f64, y: f64, r: f64, start: f64, end: f64);
+    fn close_path(&mut self);
+    fn fill(&mut self);
+    fn stroke(&mut self);
+}
+
+fn gradient_stops(stops: &[(f64, &str)]) -> Vec<GradientStop> {
+    stops.iter().map(|&(offset, color)| GradientStop { offset, color: color.to_string() }).collect()
+}
+
+pub fn linear_gradient(x1: f64, y1: f64, x2: f64, y2: f64, stops: &[(f64, &str)]) -> Gradient {
+    Gradient { is_radial: false, x1, y1, x2, y2, r1: 0.0, r2: 0.0, stops: gradient_stops(stops) }
+}
+
+pub fn radial_gradient(x1: f64, y1: f64, r1: f64, x2: f64, y2: f64, r2: f64, stops: &[(f64, &str)]) -> Gradient {
+    Gradient { is_radial: true, x1, y1, x2, y2, r1, r2, stops: gradient_stops(stops) }
+}
+
+impl Surface for CanvasRenderingContext2d {
+    fn set_fill(&mut self, color: &str) { self.set_fill_style_str(color); }
+    fn set_fill_gradient(&mut self, g: &Gradient) {
+        let gradient = if g.is_radial {
+            match self.create_radial_gradient(g.x1, g.y1, g.r1, g.x2, g.y2, g.r2) { Ok(gradient) => gradient, Err(_) => return }
+        } else {
+            self.create_linear_gradient(g.x1, g.y1, g.x2, g.y2)
+        };
+        for stop in &g.stops { let _ = gradient.add_color_stop(stop.offset as f32, &stop.color); }
+        self.set_fill_style_canvas_gradient(&gradient);
+    }
+    fn set_stroke(&mut self, color: &str) { self.set_stroke_style_str(color); }
+    fn set_line_width(&mut self, width: f64) { CanvasRenderingContext2d::set_line_width(self, width); }
+    fn set_composite(&mut self, op: &str) { self.set_global_composite_operation(op).unwrap_or(()); }
+    fn fill_rect(&mut self, x: f64, y: f64, w: f64, h: f64) { CanvasRenderingContext2d::fill_rect(self, x, y, w, h); }
+    fn begin_path(&mut self) { CanvasRenderingContext2d::begin_path(self); }
+    fn move_to(&mut self, x: f64, y: f64) { CanvasRenderingContext2d::move_to(self, x, y); }
+    fn line_to(&mut self, x: f64, y: f64) { CanvasRenderingContext2d::line_to(self, x, y); }
+    fn bezier_curve_to(&mut self, c1x: f64, c1y: f64, c2x: f64, c2y: f64, x: f64, y: f64) {
+        CanvasRenderingContext2d::bezier_curve_to(self, c1x, c1y, c2x, c2y, x, y);
+    }
+    fn arc(&mut self, x: f64, y: f64, r: f64, start: f64, end: f64) { let _ = CanvasRenderingContext2d::arc(self, x, y, r, start, end); }
+    fn close_path(&mut self) { CanvasRenderingContext2d::close_path(self); }
+    fn fill(&mut self) { CanvasRenderingContext2d::fill(self); }
+    fn stroke(&mut self) { CanvasRenderingContext2d::stroke(self); }
+}
+
+/// Records a background as SVG elements in its own box, gradients going to
+/// `defs` under ids starting with `prefix`.
+pub struct SvgSurface<'a> {
+    pub body: String,
+    defs: &'a mut Vec<String>,
+    prefix: String,
+    gradients: usize,
+    fill: String,
+    stroke: String,
+    line_width: f64,
+    blend: Option<&'static str>,
+    path: BezPath,
+}
+
+impl<'a> SvgSurface<'a> {
+    pub fn new(prefix: String, defs: &'a mut Vec<String>) -> Self {
+        SvgSurface {
+            body: String::new(), defs, prefix, gradients: 0,
+            fill: "#000000".to_string(), stroke: "#000000".to_string(), line_width: 1.0, blend: None, path: BezPath::new(),
+        }
+    }
+
+    fn blend_attr(&self) -> String {
+        self.blend.map(|mode| format!(r##" style="mix-blend-mode: {}""##, mode)).unwrap_or_default()
+    }
+}
+
+impl Surface for SvgSurface<'_> {
+    fn set_fill(&mut self, color: &str) { self.fill = escape_xml(color); }
+    fn set_fill_gradient(&mut self, gradient: &Gradient) {
+        let id = format!("{}-{}", self.prefix, self.gradients);
+        self.gradients += 1;
+        self.defs.push(svg_gradient(&id, gradient));
+        self.fill = format!("url(#{})", id);
+    }
+    fn set_stroke(&mut self, color: &str) { self.stroke = escape_xml(color); }
+    fn set_line_width(&mut self, width: f64) { self.line_width = width; }
+    fn set_composite(&mut self, op: &str) { self.blend = svg_blend_mode(op); }
+    fn fill_rect(&mut self, x: f64, y: f64, w: f64, h: f64) {
+        let rect = format!(r##"<rect x="{}" y="{}" width="{}" height="{}" fill="{}" stroke="none"{} />"##, x, y, w, h, self.fill, self.blend_attr());
+        self.body.push_str(&rect);
+    }
+    fn begin_path(&mut self) { self.path = BezPath::new(); }
+    fn move_to(&mut self, x: f64, y: f64) { self.path.move_to((x, y)); }
+    fn line_to(&mut self, x: f64, y: f64) {
+        if self.path.elements().is_empty() { self.path.move_to((x, y)); } else { self.path.line_to((x, y)); }
+    }
+    fn bezier_curve_to(&mut self, c1x: f64, c1y: f64, c2x: f64, c2y: f64, x: f64, y: f64) {
+        if self.path.elements().is_empty() { self.path.move_to((c1x, c1y)); }
+        self.path.curve_to((c1x, c1y), (c2x, c2y), (x, y));
+    }
+    fn arc(&mut self, x: f64, y: f64, r: f64, start: f64, end: f64) {
+        let sweep = (end - start).min(std::f64::consts::TAU);
+        let arc = Arc { center: Point::new(x, y), radii: Vec2::new(r, r), start_angle: start, sweep_angle: sweep, x_rotation: 0.0 };
+        let from = Point::new(x + r * start.cos(), y + r * start.sin());
+        self.line_to(from.x, from.y);
+        for el in arc.append_iter(0.1) { self.path.push(el); }
+    }
+    fn close_path(&mut self) { self.path.close_path(); }
+    fn fill(&mut self) {
+        let path = format!(r##"<path d="{}" fill="{}" stroke="none"{} />"##, self.path.to_svg(), self.fill, self.blend_attr());
+        self.body.push_str(&path);
+    }
+    fn stroke(&mut self) {
+        let path = format!(r##"<path d="{}" fill="none" stroke="{}" stroke-width="{}"{} />"##, self.path.to_svg(), self.stroke, self.line_width, self.blend_attr());
+        self.body.push_str(&path);
+    }
 }
 
 pub mod stars;
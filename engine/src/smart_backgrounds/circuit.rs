@@ -1,5 +1,4 @@
 use super::*;
-use wasm_bindgen::JsValue;
 
 pub struct CircuitBackground;
 
@@ -19,7 +18,7 @@ impl SmartBackground for CircuitBackground {
         }
     }
 
-    fn render(&self, ctx: &CanvasRenderingContext2d, w: f64, h: f64, params: &[f64]) {
+    fn render(&self, ctx: &mut dyn Surface, w: f64, h: f64, params: &[f64]) {
         let grid = params.get(0).cloned().unwrap_or(40.0);
         let density = params.get(1).cloned().unwrap_or(0.5);
         let color_val = params.get(2).cloned().unwrap_or(0x00FF88 as f64) as u32;
@@ -30,7 +29,7 @@ impl SmartBackground for CircuitBackground {
         let g = ((color_val >> 8) & 0xFF) as f64;
         let b = (color_val & 0xFF) as f64;
 
-        ctx.set_fill_style(&JsValue::from_str("#0a0a0f"));
+        ctx.set_fill("#0a0a0f");
         ctx.fill_rect(0.0, 0.0, w, h);
 
         let mut seed = 44444.0;
@@ -39,7 +38,7 @@ impl SmartBackground for CircuitBackground {
             seed / 2147483648.0
         };
 
-        ctx.set_stroke_style(&JsValue::from_str(&format!("rgba({}, {}, {}, 0.4)", r, g, b)));
+        ctx.set_stroke(&format!("rgba({}, {}, {}, 0.4)", r, g, b));
         ctx.set_line_width(line_width);
 
         let cols = (w / grid) as i32 + 1;
@@ -69,9 +68,9 @@ impl SmartBackground for CircuitBackground {
                 ctx.stroke();
 
                 if show_nodes && rand() > 0.7 {
-                    ctx.set_fill_style(&JsValue::from_str(&format!("rgb({}, {}, {})", r, g, b)));
+                    ctx.set_fill(&format!("rgb({}, {}, {})", r, g, b));
                     ctx.begin_path();
-                    let _ = ctx.arc(x, y, line_width * 1.5, 0.0, std::f64::consts::PI * 2.0);
+                    ctx.arc(x, y, line_width * 1.5, 0.0, std::f64::consts::PI * 2.0);
                     ctx.fill();
                 }
             }
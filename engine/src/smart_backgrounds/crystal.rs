@@ -1,5 +1,4 @@
 use super::*;
-use wasm_bindgen::JsValue;
 
 pub struct CrystalBackground;
 
@@ -19,7 +18,7 @@ impl SmartBackground for CrystalBackground {
         }
     }
 
-    fn render(&self, ctx: &CanvasRenderingContext2d, w: f64, h: f64, params: &[f64]) {
+    fn render(&self, ctx: &mut dyn Surface, w: f64, h: f64, params: &[f64]) {
         let size = params.get(0).cloned().unwrap_or(80.0);
         let hue_var = params.get(1).cloned().unwrap_or(20.0);
         let color_val = params.get(2).cloned().unwrap_or(0x4488FF as f64) as u32;
@@ -30,7 +29,7 @@ impl SmartBackground for CrystalBackground {
         let g_base = ((color_val >> 8) & 0xFF) as f64;
         let b_base = (color_val & 0xFF) as f64;
 
-        ctx.set_fill_style(&JsValue::from_str("#111"));
+        ctx.set_fill("#111");
         ctx.fill_rect(0.0, 0.0, w, h);
 
         let mut seed = 55555.0;
@@ -70,13 +69,13 @@ impl SmartBackground for CrystalBackground {
 }
 
 impl CrystalBackground {
-    fn draw_tri(&self, ctx: &CanvasRenderingContext2d, p1: (f64, f64), p2: (f64, f64), p3: (f64, f64), r: f64, g: f64, b: f64, var: f64, rand: &mut dyn FnMut() -> f64, wire: bool) {
+    fn draw_tri(&self, ctx: &mut dyn Surface, p1: (f64, f64), p2: (f64, f64), p3: (f64, f64), r: f64, g: f64, b: f64, var: f64, rand: &mut dyn FnMut() -> f64, wire: bool) {
         let v = (rand() - 0.5) * var;
         let dr = (r + v).clamp(0.0, 255.0);
         let dg = (g + v).clamp(0.0, 255.0);
         let db = (b + v).clamp(0.0, 255.0);
 
-        ctx.set_fill_style(&JsValue::from_str(&format!("rgb({}, {}, {})", dr, dg, db)));
+        ctx.set_fill(&format!("rgb({}, {}, {})", dr, dg, db));
         ctx.begin_path();
         ctx.move_to(p1.0, p1.1);
         ctx.line_to(p2.0, p2.1);
@@ -85,7 +84,7 @@ impl CrystalBackground {
         ctx.fill();
 
         if wire {
-            ctx.set_stroke_style(&JsValue::from_str("rgba(255, 255, 255, 0.1)"));
+            ctx.set_stroke("rgba(255, 255, 255, 0.1)");
             ctx.set_line_width(0.5);
             ctx.stroke();
         }
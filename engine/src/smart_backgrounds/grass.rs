@@ -1,7 +1,5 @@
 use super::*;
 
-use wasm_bindgen::JsValue;
-
 pub struct GrassBackground;
 
 impl SmartBackground for GrassBackground {
@@ -18,20 +16,17 @@ impl SmartBackground for GrassBackground {
         }
     }
 
-    fn render(&self, ctx: &CanvasRenderingContext2d, w: f64, h: f64, params: &[f64]) {
+    fn render(&self, ctx: &mut dyn Surface, w: f64, h: f64, params: &[f64]) {
         let hill_h_ratio = params.get(0).cloned().unwrap_or(0.3);
         let density = params.get(1).cloned().unwrap_or(100.0) as i32;
 
         // Sky
-        let sky_grad = ctx.create_linear_gradient(0.0, 0.0, 0.0, h);
-        let _ = sky_grad.add_color_stop(0.0, "#87CEEB");
-        let _ = sky_grad.add_color_stop(1.0, "#E0F6FF");
-        ctx.set_fill_style(&sky_grad.into());
+        ctx.set_fill_gradient(&linear_gradient(0.0, 0.0, 0.0, h, &[(0.0, "#87CEEB"), (1.0, "#E0F6FF")]));
         ctx.fill_rect(0.0, 0.0, w, h);
 
         // Hill
         let hill_y = h * (1.0 - hill_h_ratio);
-        ctx.set_fill_style(&JsValue::from_str("#2d5a27"));
+        ctx.set_fill("#2d5a27");
         ctx.begin_path();
         ctx.move_to(0.0, h);
         ctx.line_to(0.0, hill_y);
@@ -47,7 +42,7 @@ impl SmartBackground for GrassBackground {
             seed / 2147483648.0
         };
 
-        ctx.set_stroke_style(&JsValue::from_str("#3e7b36"));
+        ctx.set_stroke("#3e7b36");
         ctx.set_line_width(2.0);
         for _ in 0..density {
             let x = rand() * w;
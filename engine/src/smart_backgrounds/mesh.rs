@@ -1,5 +1,4 @@
 use super::*;
-use wasm_bindgen::JsValue;
 
 pub struct MeshBackground;
 
@@ -19,7 +18,7 @@ impl SmartBackground for MeshBackground {
         }
     }
 
-    fn render(&self, ctx: &CanvasRenderingContext2d, w: f64, h: f64, params: &[f64]) {
+    fn render(&self, ctx: &mut dyn Surface, w: f64, h: f64, params: &[f64]) {
         let count = params.get(0).cloned().unwrap_or(6.0) as i32;
         let c1 = params.get(1).cloned().unwrap_or(0xFF88CC as f64) as u32;
         let c2 = params.get(2).cloned().unwrap_or(0x88CCFF as f64) as u32;
@@ -32,7 +31,7 @@ impl SmartBackground for MeshBackground {
             ((c3 >> 16) & 0xFF, (c3 >> 8) & 0xFF, c3 & 0xFF),
         ];
 
-        ctx.set_fill_style(&JsValue::from_str("#fff"));
+        ctx.set_fill("#fff");
         ctx.fill_rect(0.0, 0.0, w, h);
 
         let mut seed = 77777.0;
@@ -47,14 +46,11 @@ impl SmartBackground for MeshBackground {
             let radius = (w.max(h)) * 0.8 * blur;
             let color = colors[i as usize % 3];
 
-            let grad = ctx.create_radial_gradient(x, y, 0.0, x, y, radius).unwrap();
-            let _ = grad.add_color_stop(0.0, &format!("rgba({}, {}, {}, 0.8)", color.0, color.1, color.2));
-            let _ = grad.add_color_stop(1.0, "rgba(255, 255, 255, 0)");
-
-            ctx.set_fill_style(&grad.into());
-            ctx.set_global_composite_operation("multiply").unwrap_or(());
+            let inner = format!("rgba({}, {}, {}, 0.8)", color.0, color.1, color.2);
+            ctx.set_fill_gradient(&radial_gradient(x, y, 0.0, x, y, radius, &[(0.0, &inner), (1.0, "rgba(255, 255, 255, 0)")]));
+            ctx.set_composite("multiply");
             ctx.fill_rect(0.0, 0.0, w, h);
         }
-        ctx.set_global_composite_operation("source-over").unwrap_or(());
+        ctx.set_composite("source-over");
     }
 }
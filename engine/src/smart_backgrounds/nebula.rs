@@ -1,5 +1,4 @@
 use super::*;
-use wasm_bindgen::JsValue;
 use std::f64::consts::PI;
 
 pub struct NebulaBackground;
@@ -20,7 +19,7 @@ impl SmartBackground for NebulaBackground {
         }
     }
 
-    fn render(&self, ctx: &CanvasRenderingContext2d, w: f64, h: f64, params: &[f64]) {
+    fn render(&self, ctx: &mut dyn Surface, w: f64, h: f64, params: &[f64]) {
         let count = params.get(0).cloned().unwrap_or(20.0) as i32;
         let c1 = params.get(1).cloned().unwrap_or(0xFF00FF as f64) as u32;
         let c2 = params.get(2).cloned().unwrap_or(0x00FFFF as f64) as u32;
@@ -36,7 +35,7 @@ impl SmartBackground for NebulaBackground {
         let b2 = (c2 & 0xFF) as f64;
 
         // Background
-        ctx.set_fill_style(&JsValue::from_str("#020005"));
+        ctx.set_fill("#020005");
         ctx.fill_rect(0.0, 0.0, w, h);
 
         let mut seed = 98765.0;
@@ -56,12 +55,9 @@ impl SmartBackground for NebulaBackground {
             let g = g1 * mix + g2 * (1.0 - mix);
             let b = b1 * mix + b2 * (1.0 - mix);
 
-            let grad = ctx.create_radial_gradient(x, y, 0.0, x, y, radius).unwrap();
-            let _ = grad.add_color_stop(0.0, &format!("rgba({}, {}, {}, {})", r, g, b, glow * 0.3));
-            let _ = grad.add_color_stop(0.5, &format!("rgba({}, {}, {}, {})", r, g, b, glow * 0.1));
-            let _ = grad.add_color_stop(1.0, "rgba(0, 0, 0, 0)");
-
-            ctx.set_fill_style(&grad.into());
+            let core = format!("rgba({}, {}, {}, {})", r, g, b, glow * 0.3);
+            let haze = format!("rgba({}, {}, {}, {})", r, g, b, glow * 0.1);
+            ctx.set_fill_gradient(&radial_gradient(x, y, 0.0, x, y, radius, &[(0.0, &core), (0.5, &haze), (1.0, "rgba(0, 0, 0, 0)")]));
             ctx.fill_rect(x - radius, y - radius, radius * 2.0, radius * 2.0);
         }
 
@@ -71,9 +67,9 @@ impl SmartBackground for NebulaBackground {
             let y = rand() * h;
             let sz = rand() * 1.2;
             let op = rand();
-            ctx.set_fill_style(&JsValue::from_str(&format!("rgba(255, 255, 255, {})", op)));
+            ctx.set_fill(&format!("rgba(255, 255, 255, {})", op));
             ctx.begin_path();
-            let _ = ctx.arc(x, y, sz, 0.0, PI * 2.0);
+            ctx.arc(x, y, sz, 0.0, PI * 2.0);
             ctx.fill();
         }
     }
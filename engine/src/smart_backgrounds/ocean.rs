@@ -1,5 +1,4 @@
 use super::*;
-use wasm_bindgen::JsValue;
 
 pub struct OceanBackground;
 
@@ -19,7 +18,7 @@ impl SmartBackground for OceanBackground {
         }
     }
 
-    fn render(&self, ctx: &CanvasRenderingContext2d, w: f64, h: f64, params: &[f64]) {
+    fn render(&self, ctx: &mut dyn Surface, w: f64, h: f64, params: &[f64]) {
         let layers = params.get(0).cloned().unwrap_or(5.0) as i32;
         let amp = params.get(1).cloned().unwrap_or(50.0);
         let freq = params.get(2).cloned().unwrap_or(0.01);
@@ -31,7 +30,7 @@ impl SmartBackground for OceanBackground {
         let b = (base_color & 0xFF) as f64;
 
         // Background sky/upper ocean
-        ctx.set_fill_style(&JsValue::from_str(&format!("rgb({}, {}, {})", r * 0.5, g * 0.5, b * 0.5)));
+        ctx.set_fill(&format!("rgb({}, {}, {})", r * 0.5, g * 0.5, b * 0.5));
         ctx.fill_rect(0.0, 0.0, w, h);
 
         for i in 0..layers {
@@ -42,7 +41,7 @@ impl SmartBackground for OceanBackground {
             let layer_g = g * (0.6 + ratio * 0.4);
             let layer_b = b * (0.6 + ratio * 0.4);
 
-            ctx.set_fill_style(&JsValue::from_str(&format!("rgb({}, {}, {})", layer_r, layer_g, layer_b)));
+            ctx.set_fill(&format!("rgb({}, {}, {})", layer_r, layer_g, layer_b));
             
             ctx.begin_path();
             ctx.move_to(0.0, h);
@@ -61,7 +60,7 @@ impl SmartBackground for OceanBackground {
             ctx.fill();
 
             if show_foam && i > 0 {
-                ctx.set_stroke_style(&JsValue::from_str(&format!("rgba(255, 255, 255, {})", 0.1 + ratio * 0.2)));
+                ctx.set_stroke(&format!("rgba(255, 255, 255, {})", 0.1 + ratio * 0.2));
                 ctx.set_line_width(2.0);
                 ctx.begin_path();
                 for s in 0..=steps {
@@ -1,8 +1,6 @@
 use super::*;
 use std::f64::consts::PI;
 
-use wasm_bindgen::JsValue;
-
 pub struct StarsBackground;
 
 impl SmartBackground for StarsBackground {
@@ -19,12 +17,12 @@ impl SmartBackground for StarsBackground {
         }
     }
 
-    fn render(&self, ctx: &CanvasRenderingContext2d, w: f64, h: f64, params: &[f64]) {
+    fn render(&self, ctx: &mut dyn Surface, w: f64, h: f64, params: &[f64]) {
         let count = params.get(0).cloned().unwrap_or(200.0) as i32;
         let brightness = params.get(1).cloned().unwrap_or(0.8);
         
         // Background
-        ctx.set_fill_style(&JsValue::from_str("#050510"));
+        ctx.set_fill("#050510");
         ctx.fill_rect(0.0, 0.0, w, h);
 
         // Simple pseudo-random stars based on fixed seed
@@ -40,9 +38,9 @@ impl SmartBackground for StarsBackground {
             let size = rand() * 1.5;
             let op = rand() * brightness;
 
-            ctx.set_fill_style(&JsValue::from_str(&format!("rgba(255, 255, 255, {})", op)));
+            ctx.set_fill(&format!("rgba(255, 255, 255, {})", op));
             ctx.begin_path();
-            let _ = ctx.arc(x, y, size, 0.0, PI * 2.0);
+            ctx.arc(x, y, size, 0.0, PI * 2.0);
             ctx.fill();
         }
     }
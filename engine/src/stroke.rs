@@ -0,0 +1,321 @@
+use wasm_bindgen::prelude::*;
+use kurbo::{BezPath, ParamCurve, ParamCurveArclen, PathSeg, Point, Shape};
+use crate::engine::VectorEngine;
+use crate::geometry::{set_local_path, shape_path};
+use crate::markers::place_markers;
+use crate::measure::tangent;
+use crate::objects::VectorObject;
+use crate::path_ops::{contours, contours_to_path, Contour};
+use crate::types::ShapeType;
+use crate::width_profile::{offset_path, outline, WidthProfile, CORNER_ANGLE};
+
+// Stroke settings canvas and the exporters can't draw natively: width
+// profiles, inside and outside alignment, dashes fitted to corners, and
+// markers where the output has none of its own. Such a stroke is expanded
+// into plain parts: the fill, the stroke along an adjusted path (or filled
+// as an outline), then the markers.
+
+const ACCURACY: f64 = 1e-3;
+const MAX_DASHES: usize = 10_000; // Per subpath
+/// Id bits of the expanded parts, so they never clash with the object's own
+/// id in exported gradient references.
+const OUTLINE_ID_FLAG: u32 = 1 << 31;
+const START_ID_FLAG: u32 = 1 << 30;
+const END_ID_FLAG: u32 = 1 << 29;
+
+pub(crate) fn paints(colour: &str) -> bool {
+    !colour.is_empty() && colour != "transparent" && colour != "none"
+}
+
+pub(crate) fn has_stroke(obj: &VectorObject) -> bool {
+    obj.brush_id == 0 && obj.stroke_width > 0.0 && (paints(&obj.stroke) || obj.stroke_gradient.is_some())
+}
+
+/// The dash pattern as drawn: an odd count repeats once, as on canvas and in SVG.
+fn dash_pattern(dash: &[f64]) -> Option<Vec<f64>> {
+    if dash.is_empty() || dash.iter().any(|d| !d.is_finite() || *d < 0.0) || dash.iter().sum::<f64>() <= 0.0 { return None; }
+    let mut pattern = dash.to_vec();
+    if pattern.len() % 2 == 1 { pattern.extend_from_slice(dash); }
+    Some(pattern)
+}
+
+/// Adds the dashes between pattern positions `from` and `to` as arc length
+/// intervals, starting at `at` on the subpath and stretched by `scale`.
+/// Touching dashes merge.
+fn walk(pattern: &[f64], from: f64, to: f64, at: f64, scale: f64, out: &mut Vec<(f64, f64)>) {
+    let mut pos = 0.0;
+    for (i, &len) in pattern.iter().cycle().enumerate() {
+        if pos > to || out.len() > MAX_DASHES { break; }
+        if i % 2 == 0 {
+            let (x0, x1) = (pos.max(from), (pos + len).min(to));
+            if x1 >= x0 {
+                let (u0, u1) = (at + (x0 - from) * scale, at + (x1 - from) * scale);
+                match out.last_mut() {
+                    Some(last) if u0 - last.1 < 1e-9 => last.1 = u1,
+                    _ => out.push((u0, u1)),
+                }
+            }
+        }
+        pos += len;
+    }
+}
+
+/// A subpath's segments with their start and length along it.
+fn measure(segs: &[PathSeg]) -> (Vec<(PathSeg, f64, f64)>, f64) {
+    let mut total = 0.0;
+    let measured = segs.iter().map(|&seg| {
+        let len = seg.arclen(ACCURACY);
+        total += len;
+        (seg, total - len, len)
+    }).collect();
+    (measured, total)
+}
+
+fn point_at(segs: &[(PathSeg, f64, f64)], u: f64) -> Point {
+    let Some(&(seg, start, len)) = segs.iter().find(|(_, s, l)| u <= s + l).or(segs.last()) else { return Point::ZERO };
+    seg.eval(if len > 0.0 { seg.inv_arclen((u - start).clamp(0.0, len), ACCURACY) } else { 0.0 })
+}
+
+/// Draws the subpath between two arc lengths, continuing the current
+/// subpath unless `begin` is set.
+fn stretch(out: &mut BezPath, segs: &[(PathSeg, f64, f64)], from: f64, to: f64, begin: bool) {
+    if begin {
+        let p = point_at(segs, from);
+        out.move_to(p);
+        // Zero-length dashes still get their caps
+        if to - from <= 1e-9 { out.line_to(p); return; }
+    }
+    for &(seg, start, len) in segs {
+        if start + len <= from || start >= to || len <= 0.0 { continue; }
+        let t0 = if from > start { seg.inv_arclen(from - start, ACCURACY) } else { 0.0 };
+        let t1 = if to < start + len { seg.inv_arclen(to - start, ACCURACY) } else { 1.0 };
+        match seg.subsegment(t0..t1) {
+            PathSeg::Line(l) => out.line_to(l.p1),
+            PathSeg::Quad(q) => out.quad_to(q.p1, q.p2),
+            PathSeg::Cubic(c) => out.curve_to(c.p1, c.p2, c.p3),
+        }
+    }
+}
+
+/// The dashes themselves as open subpaths, to be stroked solid. With `fit`
+/// the pattern is stretched between corners so every corner sits in the
+/// middle of a dash and open ends finish on whole dashes; otherwise it runs
+/// from `offset` the way canvas draws it.
+fn dashes(path: &BezPath, pattern: &[f64], offset: f64, fit: bool) -> BezPath {
+    let period: f64 = pattern.iter().sum();
+    let first = pattern[0];
+    let turns = |a: &PathSeg, b: &PathSeg| {
+        let (ta, tb) = (tangent(a, 1.0), tangent(b, 0.0));
+        ta.cross(tb).atan2(ta.dot(tb)).abs() > CORNER_ANGLE
+    };
+    let mut out = BezPath::new();
+    for contour in contours(path) {
+        let mut segs: Vec<PathSeg> = contour.segs.into_iter().filter(|s| s.arclen(ACCURACY) > 1e-9).collect();
+        if segs.is_empty() { continue; }
+        let n = segs.len();
+        // A closed subpath starts over from one of its corners
+        if fit && contour.closed {
+            if let Some(k) = (0..n).find(|&k| turns(&segs[(k + n - 1) % n], &segs[k])) { segs.rotate_left(k); }
+        }
+        let (measured, total) = measure(&segs);
+        let mut on = Vec::new();
+        if fit {
+            let mut breaks = vec![0.0];
+            breaks.extend((1..n).filter(|&k| turns(&segs[k - 1], &segs[k])).map(|k| measured[k].1));
+            breaks.push(total);
+            let pieces = breaks.len() - 1;
+            for k in 0..pieces {
+                let (u0, u1) = (breaks[k], breaks[k + 1]);
+                // Pattern positions where the piece starts and finishes: an open
+                // end on a dash's end, a corner half way along a dash
+                let a = if !contour.closed && k == 0 { 0.0 } else { first / 2.0 };
+                let b = if !contour.closed && k == pieces - 1 { first } else { first / 2.0 };
+                let mut count = ((u1 - u0 - (b - a)) / period).round().max(0.0);
+                if count == 0.0 && b <= a { count = 1.0; }
+                let span = count * period + b - a;
+                walk(pattern, a, a + span, u0, (u1 - u0) / span, &mut on);
+            }
+        } else {
+            let from = offset.rem_euclid(period);
+            walk(pattern, from, from + total, 0.0, 1.0, &mut on);
+        }
+        if on.len() > MAX_DASHES {
+            // Too fine to cut up; left solid
+            out.extend(contours_to_path(&[Contour { segs, closed: contour.closed }]).elements().iter().copied());
+            continue;
+        }
+        // Fitted dashes on a closed subpath meet across its start
+        let wrap = fit && contour.closed && on.len() > 1 && on[0].0 <= 1e-9 && on[on.len() - 1].1 >= total - 1e-9;
+        if fit && contour.closed && on.len() == 1 && on[0].1 - on[0].0 >= total - 1e-9 {
+            stretch(&mut out, &measured, 0.0, total, true);
+            out.close_path();
+            continue;
+        }
+        let head = if wrap { Some(on.remove(0)) } else { None };
+        for &(from, to) in &on { stretch(&mut out, &measured, from, to.min(total), true); }
+        if let Some((_, to)) = head { stretch(&mut out, &measured, 0.0, to, false); }
+    }
+    out
+}
+
+/// The closed subpaths grown outwards by `distance`, or shrunk when it's
+/// negative.
+pub(crate) fn grow(path: &BezPath, distance: f64, join_style: &str) -> BezPath {
+    // The fill lies to the left of every edge when the largest subpath has
+    // positive area
+    let area = contours(path).into_iter().filter(|c| c.closed)
+        .map(|c| contours_to_path(&[c]).area())
+        .max_by(|a, b| a.abs().total_cmp(&b.abs()))
+        .unwrap_or(0.0);
+    let outward = if area >= 0.0 { -1.0 } else { 1.0 };
+    offset_path(path, outward * distance, join_style)
+}
+
+/// The path moved half the stroke width into or out of the filled area, so
+/// a centred stroke along it lies inside or outside the shape.
+fn aligned_path(path: &BezPath, obj: &VectorObject) -> BezPath {
+    let half = obj.stroke_width / 2.0;
+    grow(path, if obj.stroke_align == "inside" { -half } else { half }, &obj.stroke_join)
+}
+
+/// A copy with nothing left to expand.
+fn settled(obj: &VectorObject) -> VectorObject {
+    let mut part = obj.clone();
+    part.width_profile = None;
+    part.start_marker = None;
+    part.end_marker = None;
+    part.stroke_align.clear();
+    part.stroke_dash_align = false;
+    part
+}
+
+fn path_part(obj: &VectorObject, path: &BezPath, flag: u32, label: &str) -> VectorObject {
+    let mut part = settled(obj);
+    part.id = obj.id | flag;
+    part.name = format!("{} {}", obj.name, label);
+    part.shape_type = ShapeType::Path;
+    part.path_data = path.to_svg();
+    part.corners = None;
+    part.intelligent_type.clear();
+    part.intelligent_params.clear();
+    part
+}
+
+/// A shape filled with the stroke's paint: an outline or a marker.
+fn stroke_fill_part(obj: &VectorObject, path: &BezPath, flag: u32, label: &str) -> VectorObject {
+    let mut part = path_part(obj, path, flag, label);
+    part.fill = obj.stroke.clone();
+    part.fill_gradient = obj.stroke_gradient.clone();
+    part.fill_rule = "nonzero".to_string();
+    part.stroke = "transparent".to_string();
+    part.stroke_gradient = None;
+    part.stroke_width = 0.0;
+    part.stroke_dash.clear();
+    part
+}
+
+/// The object split into plain parts, or `None` when its stroke can be drawn
+/// as it is. With `keep_markers`, markers on paths are left to outputs that
+/// have markers of their own, unless something else about the stroke is
+/// expanded anyway. Dashes on a profiled stroke are cut first, each dash
+/// taking the whole profile.
+pub fn expand_stroke(obj: &VectorObject, keep_markers: bool) -> Option<Vec<VectorObject>> {
+    if !has_stroke(obj) { return None; }
+    let align = matches!(obj.stroke_align.as_str(), "inside" | "outside");
+    let pattern = dash_pattern(&obj.stroke_dash);
+    let fit = obj.stroke_dash_align && pattern.is_some();
+    let has_markers = obj.start_marker.is_some() || obj.end_marker.is_some();
+    let plain = obj.width_profile.is_none() && !align && !fit;
+    if plain && (!has_markers || (keep_markers && obj.shape_type == ShapeType::Path)) { return None; }
+    let mut path = shape_path(obj)?;
+    let (mut start, mut end) = (None, None);
+    if let Some((body, s, e)) = place_markers(obj) { path = body; start = s; end = e; }
+    if align { path = aligned_path(&path, obj); }
+    let cut = fit || obj.width_profile.is_some();
+    if let Some(p) = pattern.as_ref().filter(|_| cut) { path = dashes(&path, p, obj.stroke_dash_offset, fit); }
+
+    let mut parts = Vec::new();
+    if paints(&obj.fill) || obj.fill_gradient.is_some() {
+        let mut fill = settled(obj);
+        fill.stroke = "transparent".to_string();
+        fill.stroke_gradient = None;
+        fill.stroke_width = 0.0;
+        parts.push(fill);
+    }
+    match &obj.width_profile {
+        Some(profile) => {
+            let shape = outline(&path, obj.stroke_width, profile, &obj.stroke_cap, &obj.stroke_join);
+            parts.push(stroke_fill_part(obj, &shape, OUTLINE_ID_FLAG, "Stroke"));
+        }
+        None => {
+            let mut stroke = path_part(obj, &path, OUTLINE_ID_FLAG, "Stroke");
+            stroke.fill = "transparent".to_string();
+            stroke.fill_gradient = None;
+            if cut && pattern.is_some() {
+                stroke.stroke_dash.clear();
+                stroke.stroke_dash_offset = 0.0;
+            }
+            parts.push(stroke);
+        }
+    }
+    for (shape, flag, label) in [(start, START_ID_FLAG, "Start Marker"), (end, END_ID_FLAG, "End Marker")] {
+        if let Some(shape) = shape { parts.push(stroke_fill_part(obj, &shape, flag, label)); }
+    }
+    Some(parts)
+}
+
+/// Copies of the objects with their strokes expanded, for exporters.
+pub fn expand_strokes(objects: &[VectorObject], keep_markers: bool) -> Vec<VectorObject> {
+    objects.iter().flat_map(|obj| {
+        if let Some(parts) = expand_stroke(obj, keep_markers) { return parts; }
+        let mut out = obj.clone();
+        if let Some(children) = out.children.as_mut() { *children = expand_strokes(children, keep_markers); }
+        vec![out]
+    }).collect()
+}
+
+#[wasm_bindgen]
+impl VectorEngine {
+    /// Replaces strokes with filled outlines, markers and dashes included. An
+    /// object that also has a fill becomes a group of its fill and outlines.
+    pub(crate) fn outline_stroke(&mut self, ids: &[u32]) -> String {
+        let mut converted = Vec::new();
+        for &id in ids {
+            let Some(idx) = self.objects.iter().position(|o| o.id == id) else { continue };
+            let obj = self.objects[idx].clone();
+            if !has_stroke(&obj) || shape_path(&obj).is_none() { continue; }
+            let mut profiled = obj.clone();
+            profiled.width_profile.get_or_insert_with(WidthProfile::default);
+            let Some(mut parts) = expand_stroke(&profiled, false) else { continue };
+            for part in parts.iter_mut() { part.id = self.next_id; self.next_id += 1; }
+            let result = if parts.len() == 1 {
+                let mut part = parts.remove(0);
+                part.id = obj.id;
+                part.name = obj.name.clone();
+                part
+            } else {
+                // Parts share the object's box; in the group they sit at its origin
+                let mut group = self.create_default_object(obj.id, ShapeType::Group, obj.x, obj.y, obj.width, obj.height);
+                group.name = obj.name.clone();
+                group.rotation = obj.rotation;
+                group.fill = "transparent".to_string();
+                group.mask_id = obj.mask_id;
+                group.visible = obj.visible;
+                group.locked = obj.locked;
+                for part in parts.iter_mut() {
+                    part.x = 0.0; part.y = 0.0; part.rotation = 0.0;
+                    part.mask_id = None;
+                    if part.shape_type == ShapeType::Path {
+                        if let Ok(path) = BezPath::from_svg(&part.path_data) { set_local_path(part, path); }
+                    }
+                }
+                group.children = Some(parts);
+                group
+            };
+            self.objects[idx] = result;
+            converted.push(id);
+        }
+        if converted.is_empty() { return "{ \"error\": \"Nothing to outline\" }".to_string(); }
+        serde_json::json!({ "success": true, "ids": converted }).to_string()
+    }
+}
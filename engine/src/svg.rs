@@ -1,103 +1,231 @@
 use wasm_bindgen::prelude::*;
+use std::collections::HashSet;
+use kurbo::{Rect, Shape};
 use crate::engine::VectorEngine;
-use crate::types::{ShapeType, Artboard};
+use crate::geometry::object_path;
+use crate::io::ExportOptions;
 use crate::objects::VectorObject;
-use kurbo::{BezPath, Point, Shape, Affine};
+use crate::stroke::paints;
+use crate::text::FontRegistry;
+use crate::types::{EffectType, Gradient, ShapeType};
 
-#[wasm_bindgen]
-impl VectorEngine {
-    pub fn export_svg(&self) -> String {
-        let mut defs = Vec::new();
-        let mut body = String::new();
-        for obj in &self.objects { body.push_str(&obj.to_svg_element(&mut defs)); }
-        let defs_str = if defs.is_empty() { String::new() } else { format!("<defs>{}</defs>", defs.join("")) };
-        format!(
-            r##"<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\"><rect width=\"100%\" height=\"100%\" fill=\"{}\" />{}{}</svg>"##,
-            self.artboard.width, self.artboard.height, self.artboard.width, self.artboard.height,
-            self.artboard.background, defs_str, body
-        )
+/// Escapes text for SVG content and attribute values.
+pub fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&apos;")
+}
+
+/// A gradient definition in user space. Canvas radial gradients run from the
+/// circle at (x1, y1, r1) to the one at (x2, y2, r2), i.e. focus to centre.
+pub fn svg_gradient(id: &str, grad: &Gradient) -> String {
+    let mut svg = if grad.is_radial {
+        format!(r##"<radialGradient id="{}" cx="{}" cy="{}" r="{}" fx="{}" fy="{}" fr="{}" gradientUnits="userSpaceOnUse">"##,
+            id, grad.x2, grad.y2, grad.r2, grad.x1, grad.y1, grad.r1)
+    } else {
+        format!(r##"<linearGradient id="{}" x1="{}" y1="{}" x2="{}" y2="{}" gradientUnits="userSpaceOnUse">"##,
+            id, grad.x1, grad.y1, grad.x2, grad.y2)
+    };
+    for stop in &grad.stops {
+        svg.push_str(&format!(r##"<stop offset="{}" stop-color="{}" />"##, stop.offset, escape_xml(&stop.color)));
     }
+    svg.push_str(if grad.is_radial { "</radialGradient>" } else { "</linearGradient>" });
+    svg
+}
+
+/// The CSS `mix-blend-mode` for a canvas composite operation, `None` for
+/// normal painting and for operations CSS has no blend mode for.
+pub fn svg_blend_mode(op: &str) -> Option<&'static str> {
+    const MODES: [&str; 15] = ["multiply", "screen", "overlay", "darken", "lighten", "color-dodge", "color-burn", "hard-light",
+        "soft-light", "difference", "exclusion", "hue", "saturation", "color", "luminosity"];
+    match op {
+        "lighter" | "plus-lighter" => Some("plus-lighter"),
+        _ => MODES.iter().find(|&&m| m == op).copied(),
+    }
+}
 
-    pub fn import_svg(&mut self, data: &[u8]) -> String {
-        let svg_str = String::from_utf8_lossy(data);
-        let doc = match roxmltree::Document::parse(&svg_str) {
-            Ok(d) => d,
-            Err(e) => return format!("{{\"error\": \"Failed to parse SVG: {:?}\"}}", e),
-        };
-        let root = doc.root_element();
-        let mut width = root.attribute("width").and_then(|s| s.parse::<f64>().ok()).unwrap_or(800.0);
-        let mut height = root.attribute("height").and_then(|s| s.parse::<f64>().ok()).unwrap_or(600.0);
-        if let Some(viewbox) = root.attribute("viewBox") {
-            let parts: Vec<f64> = viewbox.split_whitespace().filter_map(|s| s.parse::<f64>().ok()).collect();
-            if parts.len() == 4 { width = parts[2]; height = parts[3]; }
+/// Shadows and glows as one filter in the object's own space, returning its
+/// id; `None` when nothing is cast. Blur radii are canvas shadow blurs.
+pub fn svg_effects_filter(obj: &VectorObject, defs: &mut Vec<String>) -> Option<String> {
+    // Kind, colour, opacity, blur, offset and spread of each cast
+    let mut casts = Vec::new();
+    for e in obj.layer_style.effects.iter().filter(|e| e.enabled) {
+        match e.effect_type {
+            EffectType::DropShadow | EffectType::InnerShadow => casts.push((e.effect_type, e.color.as_str(), e.opacity, e.blur, e.x, e.y, e.spread)),
+            EffectType::OuterGlow | EffectType::InnerGlow => casts.push((e.effect_type, e.color.as_str(), e.opacity, e.blur, 0.0, 0.0, e.spread)),
+            _ => {}
         }
-        let mut objects = Vec::new();
-        let mut next_id = self.next_id;
-        self.parse_svg_node(root, &mut objects, &mut next_id);
-        self.next_id = next_id;
-        let result = serde_json::json!({ "width": width, "height": height, "objects": objects });
-        result.to_string()
     }
+    // The canvas draws a shape's own shadow, but not a group's
+    let offset = obj.shadow_offset_x != 0.0 || obj.shadow_offset_y != 0.0;
+    if obj.shape_type != ShapeType::Group && paints(&obj.shadow_color) && (obj.shadow_blur > 0.0 || offset) {
+        casts.push((EffectType::DropShadow, obj.shadow_color.as_str(), 1.0, obj.shadow_blur, obj.shadow_offset_x, obj.shadow_offset_y, 0.0));
+    }
+    if casts.is_empty() { return None; }
 
-    fn parse_svg_node(&self, node: roxmltree::Node, objects: &mut Vec<VectorObject>, next_id: &mut u32) {
-        for child in node.children() {
-            if !child.is_element() { continue; }
-            match child.tag_name().name() {
-                "rect" => {
-                    let x = child.attribute("x").and_then(|s| s.parse::<f64>().ok()).unwrap_or(0.0);
-                    let y = child.attribute("y").and_then(|s| s.parse::<f64>().ok()).unwrap_or(0.0);
-                    let w = child.attribute("width").and_then(|s| s.parse::<f64>().ok()).unwrap_or(0.0);
-                    let h = child.attribute("height").and_then(|s| s.parse::<f64>().ok()).unwrap_or(0.0);
-                    let mut obj = self.create_default_object(*next_id, ShapeType::Rectangle, x, y, w, h);
-                    obj.corner_radius = child.attribute("rx").and_then(|s| s.parse::<f64>().ok()).unwrap_or(0.0);
-                    obj.name = format!("Rectangle {}", *next_id);
-                    self.apply_svg_styles(child, &mut obj); objects.push(obj); *next_id += 1;
-                }
-                "circle" | "ellipse" => {
-                    let cx = child.attribute("cx").and_then(|s| s.parse::<f64>().ok()).unwrap_or(0.0);
-                    let cy = child.attribute("cy").and_then(|s| s.parse::<f64>().ok()).unwrap_or(0.0);
-                    let (rx, ry) = if child.tag_name().name() == "circle" {
-                        let r = child.attribute("r").and_then(|s| s.parse::<f64>().ok()).unwrap_or(0.0); (r, r)
-                    } else {
-                        (child.attribute("rx").and_then(|s| s.parse::<f64>().ok()).unwrap_or(0.0), child.attribute("ry").and_then(|s| s.parse::<f64>().ok()).unwrap_or(0.0))
-                    };
-                    let mut obj = self.create_default_object(*next_id, ShapeType::Circle, cx - rx, cy - ry, rx * 2.0, ry * 2.0);
-                    obj.name = format!("{} {}", if child.tag_name().name() == "circle" { "Circle" } else { "Ellipse" }, *next_id);
-                    self.apply_svg_styles(child, &mut obj); objects.push(obj); *next_id += 1;
-                }
-                "path" => {
-                    let d = child.attribute("d").unwrap_or("").to_string();
-                    if let Ok(bez) = BezPath::from_svg(&d) {
-                        let bbox = bez.bounding_box();
-                        let mut obj = self.create_default_object(*next_id, ShapeType::Path, bbox.x0, bbox.y0, bbox.width(), bbox.height());
-                        obj.name = format!("Path {}", *next_id);
-                        let mut normalized = bez.clone(); normalized.apply_affine(Affine::translate((-bbox.x0, -bbox.y0)));
-                        obj.path_data = normalized.to_svg();
-                        self.apply_svg_styles(child, &mut obj); objects.push(obj); *next_id += 1;
-                    }
-                }
-                "g" => { self.parse_svg_node(child, objects, next_id); }
-                _ => { if child.tag_name().name() != "defs" && child.tag_name().name() != "style" { self.parse_svg_node(child, objects, next_id); } }
-            }
+    let mut primitives = String::new();
+    let (mut behind, mut above) = (String::new(), String::new());
+    let mut pad = obj.stroke_width.max(0.0);
+    for (i, &(kind, color, opacity, blur, dx, dy, spread)) in casts.iter().enumerate() {
+        let deviation = blur.max(0.0) / 2.0;
+        let spread = spread.max(0.0);
+        pad = pad.max(obj.stroke_width.max(0.0) + dx.abs().max(dy.abs()) + spread + deviation * 3.0);
+        let inner = matches!(kind, EffectType::InnerShadow | EffectType::InnerGlow);
+        let mut source = "SourceAlpha".to_string();
+        if inner {
+            // What's outside the shape, cast back over its inner edges
+            primitives.push_str(&format!(r##"<feComponentTransfer in="SourceAlpha" result="fx{i}-outside"><feFuncA type="table" tableValues="1 0" /></feComponentTransfer>"##));
+            source = format!("fx{i}-outside");
+        }
+        if spread > 0.0 {
+            primitives.push_str(&format!(r##"<feMorphology in="{}" operator="dilate" radius="{}" result="fx{i}-spread" />"##, source, spread));
+            source = format!("fx{i}-spread");
+        }
+        primitives.push_str(&format!(r##"<feOffset in="{}" dx="{}" dy="{}" result="fx{i}-offset" />"##, source, dx, dy));
+        primitives.push_str(&format!(r##"<feGaussianBlur in="fx{i}-offset" stdDeviation="{}" result="fx{i}-blur" />"##, deviation));
+        primitives.push_str(&format!(r##"<feFlood flood-color="{}" flood-opacity="{}" />"##, escape_xml(color), opacity));
+        primitives.push_str(&format!(r##"<feComposite in2="fx{i}-blur" operator="in" result="fx{i}" />"##));
+        if inner {
+            primitives.push_str(&format!(r##"<feComposite in="fx{i}" in2="SourceAlpha" operator="in" result="fx{i}" />"##));
+            above.push_str(&format!(r##"<feMergeNode in="fx{i}" />"##));
+        } else {
+            behind.push_str(&format!(r##"<feMergeNode in="fx{i}" />"##));
         }
     }
+    let id = format!("effects-{}", obj.id);
+    defs.push(format!(
+        r##"<filter id="{}" filterUnits="userSpaceOnUse" x="{}" y="{}" width="{}" height="{}" color-interpolation-filters="sRGB">{}<feMerge>{}<feMergeNode in="SourceGraphic" />{}</feMerge></filter>"##,
+        id, -pad, -pad, obj.width + pad * 2.0, obj.height + pad * 2.0, primitives, behind, above
+    ));
+    Some(id)
+}
 
-    fn apply_svg_styles(&self, node: roxmltree::Node, obj: &mut VectorObject) {
-        let mut fill_val = node.attribute("fill");
-        let mut stroke_val = node.attribute("stroke");
-        let mut stroke_width_val = node.attribute("stroke-width");
-        let mut opacity_val = node.attribute("opacity");
-        if let Some(style) = node.attribute("style") {
-            for part in style.split(';') {
-                let kv: Vec<&str> = part.split(':').collect();
-                if kv.len() == 2 { 
-                    match kv[0].trim() { "fill" => fill_val = Some(kv[1].trim()), "stroke" => stroke_val = Some(kv[1].trim()), "stroke-width" => stroke_width_val = Some(kv[1].trim()), "opacity" => opacity_val = Some(kv[1].trim()), _ => {} }
-                }
-            }
+/// An adjustment layer's settings as a filter, in the order the canvas
+/// applies them; `None` when they leave colours as they are.
+fn svg_adjustment_filter(obj: &VectorObject, defs: &mut Vec<String>) -> Option<String> {
+    let linear = |slope: f64, intercept: f64| {
+        let func = format!(r##"type="linear" slope="{}" intercept="{}""##, slope, intercept);
+        format!(r##"<feComponentTransfer><feFuncR {func} /><feFuncG {func} /><feFuncB {func} /></feComponentTransfer>"##)
+    };
+    let mut primitives = String::new();
+    if obj.brightness != 1.0 { primitives.push_str(&linear(obj.brightness, 0.0)); }
+    if obj.contrast != 1.0 { primitives.push_str(&linear(obj.contrast, 0.5 - 0.5 * obj.contrast)); }
+    if obj.saturate != 1.0 {
+        primitives.push_str(&format!(r##"<feColorMatrix type="saturate" values="{}" />"##, obj.saturate));
+    }
+    if obj.hue_rotate != 0.0 {
+        primitives.push_str(&format!(r##"<feColorMatrix type="hueRotate" values="{}" />"##, obj.hue_rotate));
+    }
+    if obj.blur > 0.0 { primitives.push_str(&format!(r##"<feGaussianBlur stdDeviation="{}" />"##, obj.blur)); }
+    if obj.grayscale > 0.0 {
+        primitives.push_str(&format!(r##"<feColorMatrix type="saturate" values="{}" />"##, 1.0 - obj.grayscale.min(1.0)));
+    }
+    if obj.sepia > 0.0 {
+        let k = 1.0 - obj.sepia.min(1.0);
+        let m = [
+            0.393 + 0.607 * k, 0.769 - 0.769 * k, 0.189 - 0.189 * k,
+            0.349 - 0.349 * k, 0.686 + 0.314 * k, 0.168 - 0.168 * k,
+            0.272 - 0.272 * k, 0.534 - 0.534 * k, 0.131 + 0.869 * k,
+        ];
+        primitives.push_str(&format!(r##"<feColorMatrix type="matrix" values="{} {} {} 0 0 {} {} {} 0 0 {} {} {} 0 0 0 0 0 1 0" />"##,
+            m[0], m[1], m[2], m[3], m[4], m[5], m[6], m[7], m[8]));
+    }
+    if obj.invert > 0.0 {
+        let i = obj.invert.min(1.0);
+        let func = format!(r##"type="table" tableValues="{} {}""##, i, 1.0 - i);
+        primitives.push_str(&format!(r##"<feComponentTransfer><feFuncR {func} /><feFuncG {func} /><feFuncB {func} /></feComponentTransfer>"##));
+    }
+    if primitives.is_empty() { return None; }
+    let id = format!("adjust-{}", obj.id);
+    defs.push(format!(r##"<filter id="{}" color-interpolation-filters="sRGB">{}</filter>"##, id, primitives));
+    Some(id)
+}
+
+/// Elements for a list of siblings. As on the canvas, an adjustment filters
+/// everything after it in the list, up to the next adjustment.
+pub fn svg_elements(objects: &[VectorObject], defs: &mut Vec<String>, options: &ExportOptions, fonts: &FontRegistry) -> String {
+    let mut out = String::new();
+    let mut adjusted: Option<(String, String)> = None;
+    let close = |out: &mut String, adjusted: Option<(String, String)>| {
+        if let Some((filter, inner)) = adjusted {
+            if !inner.is_empty() { out.push_str(&format!(r##"<g filter="url(#{})">{}</g>"##, filter, inner)); }
+        }
+    };
+    for obj in objects {
+        if obj.shape_type == ShapeType::Adjustment && obj.visible {
+            close(&mut out, adjusted.take());
+            adjusted = svg_adjustment_filter(obj, defs).map(|filter| (filter, String::new()));
+            continue;
+        }
+        let element = obj.to_svg_element(defs, options, fonts);
+        match adjusted.as_mut() {
+            Some((_, inner)) => inner.push_str(&element),
+            None => out.push_str(&element),
+        }
+    }
+    close(&mut out, adjusted);
+    out
+}
+
+fn collect_masks(objects: &[VectorObject], ids: &mut HashSet<u32>) {
+    for obj in objects {
+        if obj.is_mask && obj.shape_type != ShapeType::SmartBackground { ids.insert(obj.id); }
+        if let Some(children) = &obj.children { collect_masks(children, ids); }
+    }
+}
+
+fn bake_mask_list(objects: &[VectorObject], masks: &HashSet<u32>, fonts: &FontRegistry) -> Vec<VectorObject> {
+    objects.iter().map(|obj| {
+        let mut out = obj.clone();
+        if out.mask_id.is_some_and(|id| !masks.contains(&id)) { out.mask_id = None; }
+        if masks.contains(&obj.id) {
+            // Objects without an outline clip to their box, as on the canvas
+            let path = object_path(obj, fonts).unwrap_or_else(|_| Rect::new(0.0, 0.0, obj.width, obj.height).to_path(0.1));
+            out.shape_type = ShapeType::Path;
+            out.path_data = path.to_svg();
+            out.children = None;
+            out.brush_id = 0;
+            out.stroke_width = 0.0;
+            out.appearance.clear();
+            out.layer_style.effects.clear();
+            out.warp = None;
+            out.corners = None;
+            out.width_profile = None;
+            out.start_marker = None;
+            out.end_marker = None;
+            out.text_path = None;
+            out.text_content.clear();
+            out.text_runs.clear();
+            out.paragraphs.clear();
+        } else if let Some(children) = &obj.children {
+            out.children = Some(bake_mask_list(children, masks, fonts));
         }
-        if let Some(fill) = fill_val { if fill != "none" { obj.fill = fill.to_string(); } else { obj.fill = "transparent".to_string(); } }
-        if let Some(stroke) = stroke_val { if stroke != "none" { obj.stroke = stroke.to_string(); } else { obj.stroke = "transparent".to_string(); } }
-        if let Some(sw) = stroke_width_val { obj.stroke_width = sw.parse::<f64>().unwrap_or(obj.stroke_width); }
-        if let Some(op) = opacity_val { obj.opacity = op.parse::<f64>().unwrap_or(obj.opacity); }
+        out
+    }).collect()
+}
+
+/// Copies of the objects with every mask turned into a path of its outline,
+/// ready to become a `<clipPath>`. References to masks that no longer exist
+/// are dropped, as the canvas ignores them.
+pub(crate) fn bake_masks(objects: &[VectorObject], fonts: &FontRegistry) -> Vec<VectorObject> {
+    let mut masks = HashSet::new();
+    collect_masks(objects, &mut masks);
+    bake_mask_list(objects, &masks, fonts)
+}
+
+#[wasm_bindgen]
+impl VectorEngine {
+    pub fn export_svg(&self) -> String { self.export_svg_with_options("{}") }
+
+    /// SVG export with `ExportOptions` as JSON.
+    pub fn export_svg_with_options(&self, options: &str) -> String {
+        let options: ExportOptions = serde_json::from_str(options).unwrap_or_default();
+        let mut defs = Vec::new();
+        let body = svg_elements(&self.export_objects(&options, true), &mut defs, &options, &self.fonts);
+        let defs_str = if defs.is_empty() { String::new() } else { format!("<defs>{}</defs>", defs.join("")) };
+        let svg = format!(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}"><rect width="100%" height="100%" fill="{}" />{}{}</svg>"##,
+            self.artboard.width, self.artboard.height, self.artboard.width, self.artboard.height,
+            escape_xml(&self.artboard.background), defs_str, body
+        );
+        crate::svg_optimize::optimize(&svg, &options)
     }
 }
@@ -0,0 +1,957 @@
+use wasm_bindgen::prelude::*;
+use std::collections::HashMap;
+use std::io::Cursor;
+use base64::{Engine as _, engine::general_purpose};
+use kurbo::{Affine, BezPath, Ellipse, Point, Rect, RoundedRect, Shape};
+use roxmltree::{Node, NodeId};
+use crate::blend::parse_rgba;
+use crate::engine::VectorEngine;
+use crate::geometry::{object_transform, set_local_path, world_path};
+use crate::objects::VectorObject;
+use crate::rich_text::{to_runs, TextStyle};
+use crate::text_layout::fit_text_box;
+use crate::types::{Gradient, GradientStop, ShapeType};
+
+// SVG import. Each element's full transform is baked into its object: shapes
+// keep their type while the transform only moves, rotates and scales them,
+// and become paths otherwise. Styles cascade from presentation attributes,
+// `<style>` sheets and inline styles. `<use>`, gradients, clip paths and
+// masks are resolved through their ids; masks are taken as clips by their
+// shapes. Groups are boxes around their children, which sit in the group's
+// space.
+
+const SVG_NS: &str = "http://www.w3.org/2000/svg";
+const XLINK_NS: &str = "http://www.w3.org/1999/xlink";
+const XML_NS: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// Properties that can be set as attributes as well as in styles.
+const PROPERTIES: &[&str] = &[
+    "fill", "fill-opacity", "fill-rule", "stroke", "stroke-width", "stroke-opacity", "stroke-linecap", "stroke-linejoin",
+    "stroke-dasharray", "stroke-dashoffset", "opacity", "color", "display", "visibility", "font-family", "font-size",
+    "font-weight", "font-style", "text-anchor", "letter-spacing", "text-decoration", "baseline-shift", "clip-path",
+    "mask", "mix-blend-mode", "stop-color", "stop-opacity",
+];
+
+/// Properties children take from their parent unless they set their own.
+/// Decorations and shifts are included so nested spans keep them.
+const INHERITED: &[&str] = &[
+    "fill", "fill-opacity", "fill-rule", "stroke", "stroke-width", "stroke-opacity", "stroke-linecap", "stroke-linejoin",
+    "stroke-dasharray", "stroke-dashoffset", "color", "visibility", "font-family", "font-size", "font-weight",
+    "font-style", "text-anchor", "letter-spacing", "text-decoration", "baseline-shift",
+];
+
+/// Elements that are only drawn through a reference, if at all.
+const NOT_DRAWN: &[&str] = &[
+    "defs", "symbol", "clipPath", "mask", "linearGradient", "radialGradient", "pattern", "marker", "style", "title",
+    "desc", "metadata", "filter", "script", "foreignObject",
+];
+
+type Props = HashMap<String, String>;
+
+#[derive(Clone)]
+struct Scope {
+    ctm: Affine, // User space to the document
+    props: Props,
+    viewport: (f64, f64), // What percentages are of
+}
+
+#[derive(Clone, Copy)]
+enum Axis { X, Y, Other }
+
+fn font_size(props: &Props) -> f64 {
+    props.get("font-size").and_then(|v| v.parse().ok()).unwrap_or(16.0)
+}
+
+/// A CSS length in px.
+fn to_px(value: &str, em: f64, percent_of: f64) -> Option<f64> {
+    let value = value.trim();
+    let units = [
+        ("px", 1.0), ("pt", 4.0 / 3.0), ("pc", 16.0), ("mm", 96.0 / 25.4), ("cm", 96.0 / 2.54), ("in", 96.0),
+        ("em", em), ("ex", em / 2.0), ("%", percent_of / 100.0),
+    ];
+    let (number, scale) = units.iter().find_map(|(unit, scale)| value.strip_suffix(unit).map(|n| (n, *scale))).unwrap_or((value, 1.0));
+    number.trim().parse::<f64>().ok().map(|n| n * scale)
+}
+
+/// A length in user units; percentages are of the viewport.
+fn length(value: &str, axis: Axis, scope: &Scope) -> Option<f64> {
+    let (w, h) = scope.viewport;
+    let percent_of = match axis { Axis::X => w, Axis::Y => h, Axis::Other => w.hypot(h) / std::f64::consts::SQRT_2 };
+    to_px(value, font_size(&scope.props), percent_of)
+}
+
+fn attr_length(node: Node, name: &str, axis: Axis, scope: &Scope) -> Option<f64> {
+    length(node.attribute(name)?, axis, scope)
+}
+
+/// The first length of a coordinate list attribute such as `x` or `dy`.
+fn first_length(node: Node, name: &str, axis: Axis, scope: &Scope) -> Option<f64> {
+    length(node.attribute(name)?.split(|c: char| c == ',' || c.is_whitespace()).find(|s| !s.is_empty())?, axis, scope)
+}
+
+/// A number or percentage, e.g. an opacity or gradient stop offset.
+fn fraction(value: &str) -> Option<f64> {
+    let value = value.trim();
+    match value.strip_suffix('%') {
+        Some(p) => p.trim().parse::<f64>().ok().map(|p| p / 100.0),
+        None => value.parse().ok(),
+    }
+}
+
+/// The numbers in a list such as `points` or `viewBox`, however they are
+/// separated.
+fn numbers(s: &str) -> Vec<f64> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        if matches!(bytes[i], b'+' | b'-') { i += 1; }
+        let (mut dot, mut digits) = (false, false);
+        while i < bytes.len() && (bytes[i].is_ascii_digit() || (bytes[i] == b'.' && !dot)) {
+            if bytes[i] == b'.' { dot = true; } else { digits = true; }
+            i += 1;
+        }
+        if !digits { i = start + 1; continue; }
+        if i < bytes.len() && matches!(bytes[i], b'e' | b'E') {
+            let mut j = i + 1;
+            if j < bytes.len() && matches!(bytes[j], b'+' | b'-') { j += 1; }
+            if j < bytes.len() && bytes[j].is_ascii_digit() {
+                i = j;
+                while i < bytes.len() && bytes[i].is_ascii_digit() { i += 1; }
+            }
+        }
+        if let Ok(v) = s[start..i].parse() { out.push(v); }
+    }
+    out
+}
+
+fn parse_transform(s: &str) -> Affine {
+    let mut m = Affine::IDENTITY;
+    for part in s.split(')') {
+        let Some((name, args)) = part.split_once('(') else { continue };
+        let t = match (name.trim_matches(|c: char| c == ',' || c.is_whitespace()), numbers(args).as_slice()) {
+            ("matrix", &[a, b, c, d, e, f]) => Affine::new([a, b, c, d, e, f]),
+            ("translate", &[x]) => Affine::translate((x, 0.0)),
+            ("translate", &[x, y]) => Affine::translate((x, y)),
+            ("scale", &[s]) => Affine::scale(s),
+            ("scale", &[x, y]) => Affine::scale_non_uniform(x, y),
+            ("rotate", &[a]) => Affine::rotate(a.to_radians()),
+            ("rotate", &[a, x, y]) => Affine::translate((x, y)) * Affine::rotate(a.to_radians()) * Affine::translate((-x, -y)),
+            ("skewX", &[a]) => Affine::new([1.0, 0.0, a.to_radians().tan(), 1.0, 0.0, 0.0]),
+            ("skewY", &[a]) => Affine::new([1.0, a.to_radians().tan(), 0.0, 1.0, 0.0, 0.0]),
+            _ => Affine::IDENTITY,
+        };
+        m *= t;
+    }
+    m
+}
+
+fn view_box(node: Node) -> Option<Rect> {
+    match numbers(node.attribute("viewBox")?)[..] {
+        [x, y, w, h] if w > 0.0 && h > 0.0 => Some(Rect::new(x, y, x + w, y + h)),
+        _ => None,
+    }
+}
+
+/// Maps a viewBox onto a viewport as `preserveAspectRatio` says.
+fn view_box_transform(view_box: Rect, aspect: Option<&str>, width: f64, height: f64) -> Affine {
+    let mut parts = aspect.unwrap_or("").split_whitespace().filter(|p| *p != "defer");
+    let align = parts.next().unwrap_or("xMidYMid");
+    let slice = parts.next() == Some("slice");
+    let (mut sx, mut sy) = (width / view_box.width(), height / view_box.height());
+    if align != "none" {
+        let s = if slice { sx.max(sy) } else { sx.min(sy) };
+        (sx, sy) = (s, s);
+    }
+    let (ax, ay) = if align.len() == 8 { (&align[1..4], &align[5..8]) } else { ("Mid", "Mid") };
+    let offset = |a: &str, room: f64| match a { "Mid" => room / 2.0, "Max" => room, _ => 0.0 };
+    Affine::translate((offset(ax, width - view_box.width() * sx), offset(ay, height - view_box.height() * sy)))
+        * Affine::scale_non_uniform(sx, sy)
+        * Affine::translate((-view_box.x0, -view_box.y0))
+}
+
+/// Starts a new viewport at `origin` for an `<svg>` or `<symbol>`.
+fn enter_viewport(scope: &mut Scope, node: Node, origin: Point, size: (f64, f64)) {
+    scope.ctm *= Affine::translate(origin.to_vec2());
+    if let Some(vb) = view_box(node) {
+        scope.ctm *= view_box_transform(vb, node.attribute("preserveAspectRatio"), size.0, size.1);
+        scope.viewport = (vb.width(), vb.height());
+    } else {
+        scope.viewport = size;
+    }
+}
+
+/// Scale along each axis and rotation of a transform that neither skews nor
+/// mirrors.
+fn decompose(m: Affine) -> Option<(f64, f64, f64)> {
+    let [a, b, c, d, _, _] = m.as_coeffs();
+    let (sx, sy) = (a.hypot(b), c.hypot(d));
+    if sx == 0.0 || sy == 0.0 || (a * c + b * d).abs() > 1e-9 * sx * sy || m.determinant() <= 0.0 { return None; }
+    Some((sx, sy, b.atan2(a)))
+}
+
+/// How much a transform scales lengths such as stroke widths.
+fn mean_scale(m: Affine) -> f64 {
+    m.determinant().abs().sqrt()
+}
+
+/// Puts a user-space box through a transform that only scales and rotates.
+fn place_box(obj: &mut VectorObject, rect: Rect, ctm: Affine, (sx, sy, angle): (f64, f64, f64)) {
+    let centre = ctm * rect.center();
+    obj.width = rect.width() * sx;
+    obj.height = rect.height() * sy;
+    obj.x = centre.x - obj.width / 2.0;
+    obj.y = centre.y - obj.height / 2.0;
+    obj.rotation = angle;
+}
+
+/// A colour with its alpha scaled. Named colours can't be, so they stay
+/// opaque.
+fn with_alpha(color: &str, alpha: f64) -> String {
+    if alpha >= 1.0 { return color.to_string(); }
+    match parse_rgba(color) {
+        Ok(Some([r, g, b, a])) => format!("rgba({}, {}, {}, {:.3})", r.round() as u8, g.round() as u8, b.round() as u8, a * alpha.max(0.0)),
+        Ok(None) => "transparent".to_string(),
+        Err(()) => color.to_string(),
+    }
+}
+
+#[derive(Clone, Default)]
+struct Compound {
+    tag: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+    child: bool, // Of the compound before it, rather than any descendant
+}
+
+impl Compound {
+    fn parse(s: &str) -> Option<Compound> {
+        if s.contains(|c: char| "[]:+~()".contains(c)) { return None; }
+        let next = |s: &str| s.find(['.', '#']).unwrap_or(s.len());
+        let mut compound = Compound::default();
+        let end = next(s);
+        if end > 0 && &s[..end] != "*" { compound.tag = Some(s[..end].to_string()); }
+        let mut rest = &s[end..];
+        while let Some(kind) = rest.chars().next() {
+            let body = &rest[1..];
+            let end = next(body);
+            if end == 0 { return None; }
+            if kind == '.' { compound.classes.push(body[..end].to_string()); } else { compound.id = Some(body[..end].to_string()); }
+            rest = &body[end..];
+        }
+        Some(compound)
+    }
+
+    fn matches(&self, node: Node) -> bool {
+        self.tag.as_ref().is_none_or(|t| node.tag_name().name() == t)
+            && self.id.as_ref().is_none_or(|id| node.attribute("id") == Some(id.as_str()))
+            && self.classes.iter().all(|c| node.attribute("class").is_some_and(|cls| cls.split_whitespace().any(|x| x == c)))
+    }
+}
+
+struct CssRule {
+    selector: Vec<Compound>,
+    specificity: (usize, usize, usize),
+    important: bool,
+    declarations: Vec<(String, String)>,
+}
+
+fn parse_selector(s: &str) -> Option<Vec<Compound>> {
+    let mut parts = Vec::new();
+    let mut child = false;
+    for token in s.replace('>', " > ").split_whitespace() {
+        if token == ">" { child = true; continue; }
+        let mut compound = Compound::parse(token)?;
+        compound.child = std::mem::take(&mut child);
+        parts.push(compound);
+    }
+    (!parts.is_empty()).then_some(parts)
+}
+
+fn selector_matches(selector: &[Compound], node: Node) -> bool {
+    let Some((last, rest)) = selector.split_last() else { return false };
+    if !last.matches(node) { return false; }
+    if rest.is_empty() { return true; }
+    if last.child { return node.parent_element().is_some_and(|p| selector_matches(rest, p)); }
+    node.ancestors().skip(1).filter(|a| a.is_element()).any(|a| selector_matches(rest, a))
+}
+
+/// `name: value` pairs, each marked if `!important`.
+fn parse_declarations(s: &str) -> Vec<(String, String, bool)> {
+    s.split(';').filter_map(|d| {
+        let (key, value) = d.split_once(':')?;
+        let value = value.trim();
+        let (value, important) = match value.strip_suffix("!important") { Some(v) => (v.trim_end(), true), None => (value, false) };
+        Some((key.trim().to_ascii_lowercase(), value.to_string(), important))
+    }).collect()
+}
+
+/// Rules from a stylesheet. Selectors that need more than tags, classes, ids
+/// and descendant or child combinators are dropped; `@media` blocks always
+/// apply.
+fn parse_css(css: &str, rules: &mut Vec<CssRule>) {
+    let mut css = css.to_string();
+    while let Some(start) = css.find("/*") {
+        let end = css[start..].find("*/").map_or(css.len(), |e| start + e + 2);
+        css.replace_range(start..end, "");
+    }
+    let mut rest = css.as_str();
+    while let Some(open) = rest.find('{') {
+        let mut depth = 0;
+        let close = rest[open..].char_indices().find_map(|(i, c)| {
+            match c { '{' => depth += 1, '}' => depth -= 1, _ => {} }
+            (depth == 0).then_some(open + i)
+        }).unwrap_or(rest.len());
+        let prelude = rest[..open].rsplit(';').next().unwrap_or("").trim();
+        let body = &rest[open + 1..close];
+        rest = rest.get(close + 1..).unwrap_or("");
+        if prelude.starts_with("@media") { parse_css(body, rules); }
+        if prelude.starts_with('@') { continue; }
+        let declarations = parse_declarations(body);
+        for selector in prelude.split(',').filter_map(|s| parse_selector(s.trim())) {
+            let count = |f: fn(&Compound) -> usize| selector.iter().map(f).sum::<usize>();
+            let specificity = (count(|c| c.id.is_some() as usize), count(|c| c.classes.len()), count(|c| c.tag.is_some() as usize));
+            for important in [false, true] {
+                let declarations: Vec<_> = declarations.iter().filter(|d| d.2 == important).map(|d| (d.0.clone(), d.1.clone())).collect();
+                if !declarations.is_empty() { rules.push(CssRule { selector: selector.clone(), specificity, important, declarations }); }
+            }
+        }
+    }
+}
+
+/// A font size in px from a `font-size` value, given the parent's.
+fn computed_font_size(value: &str, parent: f64) -> Option<f64> {
+    match value {
+        "xx-small" => Some(9.0), "x-small" => Some(10.0), "small" => Some(13.0), "medium" => Some(16.0),
+        "large" => Some(18.0), "x-large" => Some(24.0), "xx-large" => Some(32.0),
+        "smaller" => Some(parent / 1.2), "larger" => Some(parent * 1.2),
+        v => to_px(v, parent, parent),
+    }
+}
+
+enum Geometry {
+    Rect(Rect, f64, f64), // With its corner radii
+    Ellipse(Point, f64, f64),
+    Path(BezPath),
+}
+
+impl Geometry {
+    fn path(&self) -> BezPath {
+        match *self {
+            Geometry::Rect(r, rx, ry) if rx > 0.0 && ry > 0.0 => {
+                // Rounded as if the corners were circular, then squashed
+                let k = ry / rx;
+                let mut path = RoundedRect::new(0.0, 0.0, r.width(), r.height() / k, rx).to_path(0.1);
+                path.apply_affine(Affine::translate(r.origin().to_vec2()) * Affine::scale_non_uniform(1.0, k));
+                path
+            }
+            Geometry::Rect(r, ..) => r.to_path(0.1),
+            Geometry::Ellipse(c, rx, ry) => {
+                let mut path = Ellipse::new(c, (rx, ry), 0.0).to_path(0.1);
+                path.close_path();
+                path
+            }
+            Geometry::Path(ref path) => path.clone(),
+        }
+    }
+
+    fn bbox(&self) -> Rect {
+        match *self {
+            Geometry::Rect(r, ..) => r,
+            Geometry::Ellipse(c, rx, ry) => Rect::new(c.x - rx, c.y - ry, c.x + rx, c.y + ry),
+            Geometry::Path(ref path) => path.bounding_box(),
+        }
+    }
+}
+
+enum Paint {
+    None,
+    Color(String),
+    Gradient(Gradient),
+}
+
+/// Text gathered from a `<text>` and its spans, a style per character.
+struct TextLines {
+    content: String,
+    styles: Vec<TextStyle>,
+    x: Option<f64>,
+    baselines: Vec<f64>,
+    anchor: Option<String>, // From the first span that sets its own
+    preserve: bool,
+}
+
+/// How a span's styling differs from its text element's. Styles the text
+/// object has no setting of its own for are given wherever they are set.
+fn text_style(span: &Props, text: &Props, scope: &Scope) -> TextStyle {
+    let changed = |name: &str| span.get(name).filter(|v| Some(*v) != text.get(name)).map(String::as_str);
+    let set = |name: &str| span.get(name).map(String::as_str);
+    let size = font_size(text);
+    let span_size = changed("font-size").and_then(|v| v.parse().ok());
+    let em = span_size.unwrap_or(size);
+    let tracking = text.get("letter-spacing").and_then(|v| length(v, Axis::Other, scope)).map_or(0.0, |v| v / size * 1000.0);
+    TextStyle {
+        font_family: changed("font-family").map(str::to_string),
+        font_size: span_size,
+        font_weight: changed("font-weight").map(str::to_string),
+        italic: set("font-style").map(|v| v == "italic" || v == "oblique").filter(|i| *i),
+        fill: changed("fill").filter(|v| *v != "none" && *v != "currentColor" && !v.starts_with("url(")).map(str::to_string),
+        underline: set("text-decoration").map(|v| v.contains("underline")).filter(|u| *u),
+        baseline_shift: set("baseline-shift").and_then(|v| match v {
+            "super" => Some(em / 3.0),
+            "sub" => Some(-em / 5.0),
+            "baseline" => None,
+            v => length(v, Axis::Other, scope),
+        }),
+        // Spacing is a length, so it's in thousandths of the span's own em
+        tracking: span.get("letter-spacing").and_then(|v| length(v, Axis::Other, scope)).map(|v| v / em * 1000.0).filter(|t| (t - tracking).abs() > 1e-6),
+    }
+}
+
+struct SvgImporter<'a, 'input> {
+    engine: &'a VectorEngine,
+    ids: HashMap<&'a str, Node<'a, 'input>>,
+    rules: Vec<CssRule>, // By precedence
+    next_id: u32,
+    images: HashMap<u32, String>, // Data URLs for the host to load
+    expanding: Vec<NodeId>, // References being drawn, to stop cycles
+}
+
+impl<'a, 'input> SvgImporter<'a, 'input> {
+    fn new(engine: &'a VectorEngine, doc: &'a roxmltree::Document<'input>) -> Self {
+        let mut ids = HashMap::new();
+        let mut rules = Vec::new();
+        for node in doc.descendants().filter(|n| n.is_element()) {
+            if let Some(id) = node.attribute("id") { ids.entry(id).or_insert(node); }
+            if node.tag_name().name() == "style" {
+                parse_css(&node.children().filter_map(|c| c.text()).collect::<String>(), &mut rules);
+            }
+        }
+        rules.sort_by_key(|r| (r.important, r.specificity));
+        SvgImporter { engine, ids, rules, next_id: engine.next_id, images: HashMap::new(), expanding: Vec::new() }
+    }
+
+    fn take_id(&mut self) -> u32 {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    /// The element's properties: its parent's inherited ones overridden by
+    /// its attributes, matching rules and inline style in order of
+    /// precedence. Font sizes are resolved to px.
+    fn cascade(&self, node: Node, parent: &Props) -> Props {
+        let mut props: Props = parent.iter().filter(|(k, _)| INHERITED.contains(&k.as_str())).map(|(k, v)| (k.clone(), v.clone())).collect();
+        let inline = node.attribute("style").map(parse_declarations).unwrap_or_default();
+        let rules = |important: bool| self.rules.iter()
+            .filter(move |r| r.important == important && selector_matches(&r.selector, node))
+            .flat_map(|r| r.declarations.iter().cloned());
+        let declared = PROPERTIES.iter().filter_map(|p| node.attribute(*p).map(|v| (p.to_string(), v.trim().to_string())))
+            .chain(rules(false))
+            .chain(inline.iter().filter(|d| !d.2).map(|d| (d.0.clone(), d.1.clone())))
+            .chain(rules(true))
+            .chain(inline.iter().filter(|d| d.2).map(|d| (d.0.clone(), d.1.clone())));
+        for (key, value) in declared {
+            if value == "inherit" {
+                match parent.get(&key) { Some(v) => props.insert(key, v.clone()), None => props.remove(&key) };
+            } else if key == "font-size" {
+                if let Some(size) = computed_font_size(&value, font_size(parent)) { props.insert(key, size.to_string()); }
+            } else {
+                props.insert(key, value);
+            }
+        }
+        props
+    }
+
+    fn href(&self, node: Node) -> Option<Node<'a, 'input>> {
+        let link = node.attribute((XLINK_NS, "href")).or_else(|| node.attribute("href"))?;
+        self.ids.get(link.trim().strip_prefix('#')?).copied()
+    }
+
+    /// The element a `url(#id)` value points to.
+    fn url(&self, value: &str) -> Option<Node<'a, 'input>> {
+        let inner = value.trim().strip_prefix("url(")?.split(')').next()?;
+        self.ids.get(inner.trim().trim_matches(|c| c == '"' || c == '\'').strip_prefix('#')?).copied()
+    }
+
+    fn import(&mut self, root: Node<'a, 'input>) -> (f64, f64, Vec<VectorObject>) {
+        let vb = view_box(root);
+        let mut scope = Scope { ctm: Affine::IDENTITY, props: Props::new(), viewport: vb.map_or((800.0, 600.0), |v| (v.width(), v.height())) };
+        let width = attr_length(root, "width", Axis::X, &scope).unwrap_or(scope.viewport.0);
+        let height = attr_length(root, "height", Axis::Y, &scope).unwrap_or(scope.viewport.1);
+        scope.props = self.cascade(root, &Props::new());
+        enter_viewport(&mut scope, root, Point::ZERO, (width, height));
+        let mut objects = Vec::new();
+        self.children(root, &scope, &mut objects);
+        (width, height, objects)
+    }
+
+    fn children(&mut self, node: Node<'a, 'input>, scope: &Scope, out: &mut Vec<VectorObject>) {
+        for child in node.children().filter(|c| c.is_element() && !NOT_DRAWN.contains(&c.tag_name().name())) {
+            self.element(child, scope, out);
+        }
+    }
+
+    /// Adds the objects an element draws to `out`: its own, preceded by the
+    /// mask it's clipped by.
+    fn element(&mut self, node: Node<'a, 'input>, parent: &Scope, out: &mut Vec<VectorObject>) {
+        if node.tag_name().namespace().is_some_and(|ns| ns != SVG_NS) { return; }
+        let props = self.cascade(node, &parent.props);
+        if props.get("display").map(String::as_str) == Some("none") { return; }
+        let mut scope = Scope { ctm: parent.ctm * node.attribute("transform").map_or(Affine::IDENTITY, parse_transform), props, viewport: parent.viewport };
+        let mut bbox = None;
+        let obj = match node.tag_name().name() {
+            "g" | "a" | "switch" => self.group(node, &scope),
+            "svg" => {
+                let len = |name, axis, default| attr_length(node, name, axis, &scope).unwrap_or(default);
+                let origin = Point::new(len("x", Axis::X, 0.0), len("y", Axis::Y, 0.0));
+                let size = (len("width", Axis::X, scope.viewport.0), len("height", Axis::Y, scope.viewport.1));
+                enter_viewport(&mut scope, node, origin, size);
+                self.group(node, &scope)
+            }
+            "use" => self.use_element(node, &scope),
+            "text" => self.text(node, &scope),
+            "image" => self.image(node, &scope),
+            _ => self.geometry(node, &scope).map(|geometry| {
+                bbox = Some(geometry.bbox());
+                self.shape(node, geometry, &scope)
+            }),
+        };
+        let Some(mut obj) = obj else { return };
+        if let Some(id) = node.attribute("id") { obj.name = id.to_string(); }
+        if let Some(opacity) = scope.props.get("opacity").and_then(|v| fraction(v)) { obj.opacity *= opacity.clamp(0.0, 1.0); }
+        if let Some(mode) = scope.props.get("mix-blend-mode") { obj.blend_mode = if mode == "normal" { "source-over".to_string() } else { mode.clone() }; }
+        if matches!(scope.props.get("visibility").map(String::as_str), Some("hidden" | "collapse")) { obj.visible = false; }
+        if let Some(mask) = self.clip(&scope, &obj, bbox) {
+            obj.mask_id = Some(mask.id);
+            out.push(mask);
+        }
+        out.push(obj);
+    }
+
+    fn group(&mut self, node: Node<'a, 'input>, scope: &Scope) -> Option<VectorObject> {
+        let mut children = Vec::new();
+        self.children(node, scope, &mut children);
+        self.group_object(children)
+    }
+
+    /// A group around the objects, which move into its space.
+    fn group_object(&mut self, mut children: Vec<VectorObject>) -> Option<VectorObject> {
+        let bounds = children.iter().map(|c| {
+            let (x0, y0, x1, y1) = c.get_world_bounds();
+            Rect::new(x0, y0, x1, y1)
+        }).reduce(|a, b| a.union(b))?;
+        for child in &mut children {
+            child.x -= bounds.x0;
+            child.y -= bounds.y0;
+        }
+        let id = self.take_id();
+        let mut group = self.engine.create_default_object(id, ShapeType::Group, bounds.x0, bounds.y0, bounds.width(), bounds.height());
+        group.name = format!("Group {}", id);
+        group.fill = "transparent".to_string();
+        group.children = Some(children);
+        Some(group)
+    }
+
+    /// A `<use>`: the element it references drawn at its `x` and `y`, or a
+    /// symbol's contents fitted to its `width` and `height`.
+    fn use_element(&mut self, node: Node<'a, 'input>, scope: &Scope) -> Option<VectorObject> {
+        let target = self.href(node)?;
+        if self.expanding.contains(&target.id()) { return None; }
+        let len = |n: Node, name, axis| attr_length(n, name, axis, scope);
+        let mut inner = scope.clone();
+        inner.ctm *= Affine::translate((len(node, "x", Axis::X).unwrap_or(0.0), len(node, "y", Axis::Y).unwrap_or(0.0)));
+        self.expanding.push(target.id());
+        let mut children = Vec::new();
+        if matches!(target.tag_name().name(), "symbol" | "svg") {
+            let size = |name, axis, default| len(node, name, axis).or_else(|| len(target, name, axis)).unwrap_or(default);
+            let size = (size("width", Axis::X, scope.viewport.0), size("height", Axis::Y, scope.viewport.1));
+            inner.props = self.cascade(target, &inner.props);
+            enter_viewport(&mut inner, target, Point::ZERO, size);
+            self.children(target, &inner, &mut children);
+        } else {
+            self.element(target, &inner, &mut children);
+        }
+        self.expanding.pop();
+        // A single element stands for the use itself
+        if children.len() == 1 && children[0].mask_id.is_none() { return children.pop(); }
+        self.group_object(children)
+    }
+
+    fn geometry(&self, node: Node, scope: &Scope) -> Option<Geometry> {
+        let len = |name, axis| attr_length(node, name, axis, scope);
+        let radii = |rx: Option<f64>, ry: Option<f64>| match (rx, ry) {
+            (Some(rx), Some(ry)) => (rx, ry),
+            (Some(r), None) | (None, Some(r)) => (r, r),
+            (None, None) => (0.0, 0.0),
+        };
+        match node.tag_name().name() {
+            "rect" => {
+                let (w, h) = (len("width", Axis::X)?, len("height", Axis::Y)?);
+                if w <= 0.0 || h <= 0.0 { return None; }
+                let (x, y) = (len("x", Axis::X).unwrap_or(0.0), len("y", Axis::Y).unwrap_or(0.0));
+                let (rx, ry) = radii(len("rx", Axis::X), len("ry", Axis::Y));
+                Some(Geometry::Rect(Rect::new(x, y, x + w, y + h), rx.clamp(0.0, w / 2.0), ry.clamp(0.0, h / 2.0)))
+            }
+            "circle" | "ellipse" => {
+                let centre = Point::new(len("cx", Axis::X).unwrap_or(0.0), len("cy", Axis::Y).unwrap_or(0.0));
+                let (rx, ry) = match len("r", Axis::Other) {
+                    Some(r) if node.tag_name().name() == "circle" => (r, r),
+                    _ => radii(len("rx", Axis::X), len("ry", Axis::Y)),
+                };
+                (rx > 0.0 && ry > 0.0).then_some(Geometry::Ellipse(centre, rx, ry))
+            }
+            "line" => {
+                let mut path = BezPath::new();
+                path.move_to((len("x1", Axis::X).unwrap_or(0.0), len("y1", Axis::Y).unwrap_or(0.0)));
+                path.line_to((len("x2", Axis::X).unwrap_or(0.0), len("y2", Axis::Y).unwrap_or(0.0)));
+                Some(Geometry::Path(path))
+            }
+            "polyline" | "polygon" => {
+                let points: Vec<Point> = numbers(node.attribute("points")?).chunks_exact(2).map(|p| Point::new(p[0], p[1])).collect();
+                if points.len() < 2 { return None; }
+                let mut path = BezPath::new();
+                path.move_to(points[0]);
+                for &p in &points[1..] { path.line_to(p); }
+                if node.tag_name().name() == "polygon" { path.close_path(); }
+                Some(Geometry::Path(path))
+            }
+            "path" => BezPath::from_svg(node.attribute("d")?).ok().filter(|p| !p.elements().is_empty()).map(Geometry::Path),
+            _ => None,
+        }
+    }
+
+    fn shape(&mut self, node: Node, geometry: Geometry, scope: &Scope) -> VectorObject {
+        let id = self.take_id();
+        let mut obj = match (&geometry, decompose(scope.ctm)) {
+            (&Geometry::Rect(rect, rx, ry), Some(parts)) if (rx * parts.0 - ry * parts.1).abs() < 1e-6 => {
+                let mut obj = self.engine.create_default_object(id, ShapeType::Rectangle, 0.0, 0.0, 0.0, 0.0);
+                place_box(&mut obj, rect, scope.ctm, parts);
+                obj.corner_radius = rx * parts.0;
+                obj
+            }
+            (&Geometry::Ellipse(..), Some(parts)) => {
+                let mut obj = self.engine.create_default_object(id, ShapeType::Circle, 0.0, 0.0, 0.0, 0.0);
+                place_box(&mut obj, geometry.bbox(), scope.ctm, parts);
+                obj
+            }
+            _ => {
+                let mut obj = self.engine.create_default_object(id, ShapeType::Path, 0.0, 0.0, 0.0, 0.0);
+                set_local_path(&mut obj, scope.ctm * geometry.path());
+                obj
+            }
+        };
+        let kind = match node.tag_name().name() {
+            "rect" => "Rectangle", "circle" => "Circle", "ellipse" => "Ellipse", "line" => "Line",
+            "polyline" => "Polyline", "polygon" => "Polygon", _ => "Path",
+        };
+        obj.name = format!("{} {}", kind, id);
+        self.paint(&mut obj, scope, geometry.bbox());
+        obj
+    }
+
+    /// Fill and stroke, with gradients moved into the object's space.
+    fn paint(&self, obj: &mut VectorObject, scope: &Scope, bbox: Rect) {
+        let props = &scope.props;
+        let to_local = object_transform(obj).inverse() * scope.ctm;
+        let alpha = |name: &str| props.get(name).and_then(|v| fraction(v)).map_or(1.0, |a| a.clamp(0.0, 1.0));
+        let prop = |name: &str, default: &'static str| props.get(name).map_or(default, String::as_str);
+        match self.resolve_paint(prop("fill", "black"), alpha("fill-opacity"), scope, to_local, bbox) {
+            Paint::None => obj.fill = "transparent".to_string(),
+            Paint::Color(color) => obj.fill = color,
+            Paint::Gradient(gradient) => {
+                obj.fill = gradient.stops[0].color.clone();
+                obj.fill_gradient = Some(gradient);
+            }
+        }
+        if prop("fill-rule", "nonzero") == "evenodd" { obj.fill_rule = "evenodd".to_string(); }
+
+        let stroke = self.resolve_paint(prop("stroke", "none"), alpha("stroke-opacity"), scope, to_local, bbox);
+        if matches!(stroke, Paint::None) { return; }
+        let scale = mean_scale(scope.ctm);
+        obj.stroke_width = length(prop("stroke-width", "1"), Axis::Other, scope).unwrap_or(1.0) * scale;
+        match stroke {
+            Paint::Color(color) => obj.stroke = color,
+            Paint::Gradient(gradient) => {
+                obj.stroke = gradient.stops[0].color.clone();
+                obj.stroke_gradient = Some(gradient);
+            }
+            Paint::None => {}
+        }
+        obj.stroke_cap = match prop("stroke-linecap", "butt") { c @ ("round" | "square") => c, _ => "butt" }.to_string();
+        obj.stroke_join = match prop("stroke-linejoin", "miter") { j @ ("round" | "bevel") => j, _ => "miter" }.to_string();
+        let dashes: Vec<f64> = prop("stroke-dasharray", "none").split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|s| !s.is_empty())
+            .filter_map(|s| length(s, Axis::Other, scope).map(|d| d * scale))
+            .collect();
+        if dashes.iter().any(|d| *d > 0.0) { obj.stroke_dash = dashes; }
+        obj.stroke_dash_offset = length(prop("stroke-dashoffset", "0"), Axis::Other, scope).unwrap_or(0.0) * scale;
+    }
+
+    fn resolve_paint(&self, value: &str, alpha: f64, scope: &Scope, to_local: Affine, bbox: Rect) -> Paint {
+        let value = value.trim();
+        if value.starts_with("url(") {
+            if let Some(paint) = self.url(value).and_then(|node| self.gradient(node, alpha, scope, to_local, bbox)) { return paint; }
+            // Patterns and missing references give the fallback colour
+            let fallback = value.split_once(')').map_or("", |(_, f)| f.trim());
+            return if fallback.is_empty() { Paint::None } else { self.resolve_paint(fallback, alpha, scope, to_local, bbox) };
+        }
+        match value {
+            "none" | "transparent" => Paint::None,
+            "currentColor" => Paint::Color(with_alpha(scope.props.get("color").map_or("black", String::as_str), alpha)),
+            color => Paint::Color(with_alpha(color, alpha)),
+        }
+    }
+
+    /// A linear or radial gradient in the object's space, taking attributes
+    /// and stops it doesn't have from the gradients it links to. `None` for
+    /// anything else.
+    fn gradient(&self, node: Node<'a, 'input>, alpha: f64, scope: &Scope, to_local: Affine, bbox: Rect) -> Option<Paint> {
+        let radial = match node.tag_name().name() { "linearGradient" => false, "radialGradient" => true, _ => return None };
+        let mut chain = vec![node];
+        while let Some(next) = self.href(chain[chain.len() - 1]).filter(|n| n.tag_name().name().ends_with("Gradient") && !chain.contains(n)) {
+            chain.push(next);
+        }
+        let attr = |name: &str| chain.iter().find_map(|n| n.attribute(name));
+        let is_stop = |n: &Node| n.tag_name().name() == "stop";
+        let mut stops: Vec<GradientStop> = Vec::new();
+        if let Some(owner) = chain.iter().find(|n| n.children().any(|c| is_stop(&c))) {
+            for stop in owner.children().filter(is_stop) {
+                let props = self.cascade(stop, &Props::new());
+                let previous = stops.last().map_or(0.0, |s| s.offset);
+                let offset = stop.attribute("offset").and_then(fraction).unwrap_or(0.0).clamp(previous, 1.0);
+                let color = match props.get("stop-color").map(String::as_str) {
+                    Some("currentColor") => scope.props.get("color").map_or("black", String::as_str),
+                    Some(color) => color,
+                    None => "black",
+                };
+                let opacity = props.get("stop-opacity").and_then(|v| fraction(v)).map_or(1.0, |a| a.clamp(0.0, 1.0));
+                stops.push(GradientStop { offset, color: with_alpha(color, opacity * alpha) });
+            }
+        }
+        if stops.len() < 2 { return Some(stops.pop().map_or(Paint::None, |s| Paint::Color(s.color))); }
+
+        let bbox_units = attr("gradientUnits") != Some("userSpaceOnUse");
+        if bbox_units && (bbox.width() <= 0.0 || bbox.height() <= 0.0) { return Some(Paint::None); }
+        let coord = |name: &str, default: &str, axis: Axis| {
+            let value = attr(name).unwrap_or(default);
+            if bbox_units { fraction(value) } else { length(value, axis, scope) }.unwrap_or(0.0)
+        };
+        let units = if bbox_units { Affine::new([bbox.width(), 0.0, 0.0, bbox.height(), bbox.x0, bbox.y0]) } else { Affine::IDENTITY };
+        let m = to_local * units * attr("gradientTransform").map_or(Affine::IDENTITY, parse_transform);
+        let gradient = if radial {
+            // Drawn from the focal circle out to the end circle
+            let centre = m * Point::new(coord("cx", "50%", Axis::X), coord("cy", "50%", Axis::Y));
+            let focus = m * Point::new(coord("fx", attr("cx").unwrap_or("50%"), Axis::X), coord("fy", attr("cy").unwrap_or("50%"), Axis::Y));
+            let scale = mean_scale(m);
+            Gradient {
+                is_radial: true,
+                x1: focus.x, y1: focus.y, x2: centre.x, y2: centre.y,
+                r1: coord("fr", "0", Axis::Other) * scale, r2: coord("r", "50%", Axis::Other) * scale,
+                stops,
+            }
+        } else {
+            let start = m * Point::new(coord("x1", "0%", Axis::X), coord("y1", "0%", Axis::Y));
+            let end = m * Point::new(coord("x2", "100%", Axis::X), coord("y2", "0%", Axis::Y));
+            Gradient { is_radial: false, x1: start.x, y1: start.y, x2: end.x, y2: end.y, r1: 0.0, r2: 0.0, stops }
+        };
+        Some(Paint::Gradient(gradient))
+    }
+
+    /// The mask object for an element's `clip-path` or `mask`, in the space
+    /// the element is drawn in. `bbox` is its user-space bounds, or `None` to
+    /// take them from the object's bounds mapped back into user space.
+    fn clip(&mut self, scope: &Scope, obj: &VectorObject, bbox: Option<Rect>) -> Option<VectorObject> {
+        let reference = scope.props.get("clip-path").or_else(|| scope.props.get("mask"))?;
+        let node = self.url(reference)?;
+        let units = match node.tag_name().name() {
+            "clipPath" => node.attribute("clipPathUnits"),
+            "mask" => node.attribute("maskContentUnits"),
+            _ => return None,
+        };
+        if self.expanding.contains(&node.id()) { return None; }
+        let mut ctm = scope.ctm * node.attribute("transform").map_or(Affine::IDENTITY, parse_transform);
+        if units == Some("objectBoundingBox") {
+            let bbox = bbox.unwrap_or_else(|| {
+                let (x0, y0, x1, y1) = obj.get_world_bounds();
+                scope.ctm.inverse().transform_rect_bbox(Rect::new(x0, y0, x1, y1))
+            });
+            ctm *= Affine::new([bbox.width(), 0.0, 0.0, bbox.height(), bbox.x0, bbox.y0]);
+        }
+        let inner = Scope { ctm, props: self.cascade(node, &Props::new()), viewport: scope.viewport };
+        self.expanding.push(node.id());
+        let mut shapes = Vec::new();
+        self.children(node, &inner, &mut shapes);
+        self.expanding.pop();
+        let mut path = BezPath::new();
+        for shape in shapes.iter().filter(|s| !s.is_mask) {
+            if let Ok(p) = world_path(shape, &self.engine.fonts) { path.extend(p); }
+        }
+        if path.elements().is_empty() { return None; }
+        let id = self.take_id();
+        let mut mask = self.engine.create_default_object(id, ShapeType::Path, 0.0, 0.0, 0.0, 0.0);
+        set_local_path(&mut mask, path);
+        mask.name = node.attribute("id").map_or_else(|| format!("Clip {}", id), str::to_string);
+        mask.is_mask = true;
+        Some(mask)
+    }
+
+    /// An `<image>` with a data URL, fitted to its box as
+    /// `preserveAspectRatio` says.
+    fn image(&mut self, node: Node, scope: &Scope) -> Option<VectorObject> {
+        let href = node.attribute((XLINK_NS, "href")).or_else(|| node.attribute("href"))?.trim();
+        let (meta, data) = href.strip_prefix("data:")?.split_once(',')?;
+        let bytes = meta.ends_with(";base64").then(|| general_purpose::STANDARD.decode(data.split_whitespace().collect::<String>()).ok()).flatten();
+        let pixels = bytes.as_ref()
+            .and_then(|b| image::io::Reader::new(Cursor::new(b)).with_guessed_format().ok()?.into_dimensions().ok())
+            .map(|(w, h)| Rect::new(0.0, 0.0, w as f64, h as f64));
+        let len = |name, axis| attr_length(node, name, axis, scope);
+        let (x, y) = (len("x", Axis::X).unwrap_or(0.0), len("y", Axis::Y).unwrap_or(0.0));
+        let w = len("width", Axis::X).or(pixels.map(|p| p.width()))?;
+        let h = len("height", Axis::Y).or(pixels.map(|p| p.height()))?;
+        if w <= 0.0 || h <= 0.0 { return None; }
+        let viewport = Rect::new(x, y, x + w, y + h);
+        // Without the image's size the host fills in the source rect when it loads
+        let (shown, source) = match pixels {
+            Some(pixels) => {
+                let fit = Affine::translate((x, y)) * view_box_transform(pixels, node.attribute("preserveAspectRatio"), w, h);
+                let shown = fit.transform_rect_bbox(pixels).intersect(viewport);
+                (shown, fit.inverse().transform_rect_bbox(shown))
+            }
+            None => (viewport, Rect::ZERO),
+        };
+        let id = self.take_id();
+        let mut obj = self.engine.create_default_object(id, ShapeType::Image, 0.0, 0.0, 0.0, 0.0);
+        match decompose(scope.ctm) {
+            Some(parts) => place_box(&mut obj, shown, scope.ctm, parts),
+            None => place_box(&mut obj, scope.ctm.transform_rect_bbox(shown), Affine::IDENTITY, (1.0, 1.0, 0.0)),
+        }
+        (obj.sx, obj.sy, obj.sw, obj.sh) = (source.x0, source.y0, source.width(), source.height());
+        obj.name = format!("Image {}", id);
+        obj.raw_image = bytes;
+        self.images.insert(id, href.to_string());
+        Some(obj)
+    }
+
+    /// A text object from a `<text>` element. A `<tspan>` placed with its
+    /// own `y` or `dy` starts a new line, and text styled differently from
+    /// the element becomes a run.
+    fn text(&mut self, node: Node, scope: &Scope) -> Option<VectorObject> {
+        let size = font_size(&scope.props);
+        let mut lines = TextLines {
+            content: String::new(),
+            styles: Vec::new(),
+            x: first_length(node, "x", Axis::X, scope),
+            baselines: vec![first_length(node, "y", Axis::Y, scope).unwrap_or(0.0) + first_length(node, "dy", Axis::Y, scope).unwrap_or(0.0)],
+            anchor: None,
+            preserve: node.ancestors().any(|n| n.attribute((XML_NS, "space")) == Some("preserve")),
+        };
+        self.collect_text(node, scope, &scope.props, &mut lines);
+        while lines.content.ends_with(' ') && !lines.preserve { lines.content.pop(); lines.styles.pop(); }
+        if lines.content.trim().is_empty() { return None; }
+
+        let id = self.take_id();
+        let mut obj = self.engine.create_default_object(id, ShapeType::Text, 0.0, 0.0, 0.0, 0.0);
+        obj.name = format!("Text {}", id);
+        let props = &scope.props;
+        if let Some(v) = props.get("font-family") { obj.font_family = v.clone(); }
+        if let Some(v) = props.get("font-weight") { obj.font_weight = v.clone(); }
+        let anchor = props.get("text-anchor").or(lines.anchor.as_ref()).map(String::as_str);
+        obj.text_align = match anchor { Some("middle") => "center", Some("end") => "right", _ => "left" }.to_string();
+        obj.tracking = props.get("letter-spacing").and_then(|v| length(v, Axis::Other, scope)).map_or(0.0, |v| v / size * 1000.0);
+        if let [first, second, ..] = lines.baselines[..] { if second > first { obj.leading = (second - first) / size; } }
+        obj.text_runs = to_runs(&lines.styles);
+        // Sizes scale with the transform, which otherwise only turns the text
+        let scale = mean_scale(scope.ctm);
+        obj.font_size = size * scale;
+        for run in &mut obj.text_runs {
+            run.style.font_size = run.style.font_size.map(|s| s * scale);
+            run.style.baseline_shift = run.style.baseline_shift.map(|s| s * scale);
+        }
+        // Sized roughly until a registered font fits it, keeping the anchor
+        let line_count = lines.content.split('\n').count();
+        obj.width = lines.content.split('\n').map(|l| l.chars().count()).max().unwrap_or(0) as f64 * obj.font_size * 0.6;
+        obj.height = obj.font_size * (1.0 + (line_count - 1) as f64 * obj.leading);
+        obj.text_content = lines.content;
+        let [a, b, ..] = scope.ctm.as_coeffs();
+        obj.rotation = b.atan2(a);
+        let offset = match obj.text_align.as_str() { "center" => obj.width / 2.0, "right" => obj.width, _ => 0.0 };
+        let top = scope.ctm * Point::new(lines.x.unwrap_or(0.0), lines.baselines[0] - size);
+        let centre = top + (Affine::rotate(obj.rotation) * Point::new(obj.width / 2.0 - offset, obj.height / 2.0)).to_vec2();
+        obj.x = centre.x - obj.width / 2.0;
+        obj.y = centre.y - obj.height / 2.0;
+        fit_text_box(&mut obj, &self.engine.fonts);
+        // Painted as if the text's box, at its unscaled size, were its user space
+        let user_box = Rect::from_origin_size((0.0, 0.0), (obj.width / scale, obj.height / scale));
+        let box_space = Scope { ctm: object_transform(&obj) * Affine::scale(scale), ..scope.clone() };
+        self.paint(&mut obj, &box_space, user_box);
+        Some(obj)
+    }
+
+    fn collect_text(&self, node: Node, span: &Scope, text: &Props, lines: &mut TextLines) {
+        let line_empty = |content: &str| content.is_empty() || content.ends_with('\n');
+        for child in node.children() {
+            if let Some(t) = child.text().filter(|_| child.is_text()) {
+                let style = text_style(&span.props, text, span);
+                for c in t.chars() {
+                    let c = if lines.preserve { c } else if c == '\n' || c == '\r' { continue } else if c.is_whitespace() { ' ' } else { c };
+                    if c == ' ' && !lines.preserve && (line_empty(&lines.content) || lines.content.ends_with(' ')) { continue; }
+                    lines.content.push(c);
+                    lines.styles.push(style.clone());
+                }
+                continue;
+            }
+            if !matches!(child.tag_name().name(), "tspan" | "a" | "textPath") { continue; }
+            let scope = Scope { props: self.cascade(child, &span.props), ..span.clone() };
+            if scope.props.get("display").map(String::as_str) == Some("none") { continue; }
+            let (y, dy) = (first_length(child, "y", Axis::Y, &scope), first_length(child, "dy", Axis::Y, &scope).filter(|v| *v != 0.0));
+            if y.is_some() || dy.is_some() {
+                let line_y = y.unwrap_or(lines.baselines[lines.baselines.len() - 1]) + dy.unwrap_or(0.0);
+                if line_empty(&lines.content) {
+                    let last = lines.baselines.len() - 1;
+                    lines.baselines[last] = line_y;
+                } else {
+                    // Spaces before a line break don't show
+                    while lines.content.ends_with(' ') && !lines.preserve { lines.content.pop(); lines.styles.pop(); }
+                    lines.content.push('\n');
+                    lines.styles.push(lines.styles.last().cloned().unwrap_or_default());
+                    lines.baselines.push(line_y);
+                }
+            }
+            if lines.x.is_none() { lines.x = first_length(child, "x", Axis::X, &scope); }
+            if lines.anchor.is_none() && scope.props.get("text-anchor") != text.get("text-anchor") {
+                lines.anchor = scope.props.get("text-anchor").cloned();
+            }
+            self.collect_text(child, &scope, text, lines);
+        }
+    }
+}
+
+/// Adds `image_data_url` to the image objects in serialized objects.
+fn attach_image_urls(objects: &mut serde_json::Value, images: &HashMap<u32, String>) {
+    for obj in objects.as_array_mut().into_iter().flatten() {
+        if let Some(url) = obj["id"].as_u64().and_then(|id| images.get(&(id as u32))) {
+            obj["image_data_url"] = serde_json::Value::String(url.clone());
+        }
+        attach_image_urls(&mut obj["children"], images);
+    }
+}
+
+#[wasm_bindgen]
+impl VectorEngine {
+    pub fn import_svg(&mut self, data: &[u8]) -> String {
+        let svg_str = String::from_utf8_lossy(data);
+        let options = roxmltree::ParsingOptions { allow_dtd: true, ..Default::default() };
+        let doc = match roxmltree::Document::parse_with_options(&svg_str, options) {
+            Ok(d) => d,
+            Err(e) => return format!("{{\"error\": \"Failed to parse SVG: {:?}\"}}", e),
+        };
+        let mut importer = SvgImporter::new(self, &doc);
+        let (width, height, objects) = importer.import(doc.root_element());
+        let (next_id, images) = (importer.next_id, importer.images);
+        self.next_id = next_id;
+        let mut json = serde_json::to_value(&objects).unwrap_or_default();
+        attach_image_urls(&mut json, &images);
+        self.objects.extend(objects);
+        serde_json::json!({ "width": width, "height": height, "objects": json }).to_string()
+    }
+}
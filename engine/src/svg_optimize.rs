@@ -0,0 +1,563 @@
+use std::collections::{HashMap, HashSet};
+use roxmltree::{Document, Node};
+use crate::io::ExportOptions;
+use crate::svg::escape_xml;
+
+// Clean-up of exported SVG, run over the finished document so it covers every
+// element however it was written: hidden and empty elements dropped, duplicate
+// gradients shared, names turned into ids or classes, colours into CSS custom
+// properties, and numbers and path data shortened as the file is written out.
+
+const SVG_NS: &str = "http://www.w3.org/2000/svg";
+const XML_NS: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// Attributes made of numbers, rounded to the export precision.
+const NUMERIC: &[&str] = &[
+    "x", "y", "width", "height", "rx", "ry", "cx", "cy", "r", "fx", "fy", "fr", "x1", "y1", "x2", "y2", "dx", "dy",
+    "points", "transform", "viewBox", "stroke-width", "stroke-dasharray", "stroke-dashoffset", "offset", "startOffset",
+    "font-size", "letter-spacing", "stdDeviation", "radius", "slope", "intercept", "values", "tableValues",
+];
+
+/// Attributes taking a colour, which semantic output turns into custom properties.
+const PAINTS: &[&str] = &["fill", "stroke", "stop-color", "flood-color"];
+
+/// Elements painted with their fill and stroke alone.
+const SHAPES: &[&str] = &["rect", "circle", "ellipse", "line", "polyline", "polygon", "path"];
+
+enum Item {
+    Element(Element),
+    Text(String),
+}
+
+struct Element {
+    name: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<Item>,
+}
+
+impl Element {
+    fn read(node: Node) -> Element {
+        let attrs = node.attributes().map(|a| {
+            let name = if a.namespace() == Some(XML_NS) { format!("xml:{}", a.name()) } else { a.name().to_string() };
+            (name, a.value().to_string())
+        }).collect();
+        let children = node.children().filter_map(|child| {
+            if child.is_element() { Some(Item::Element(Element::read(child))) }
+            else { child.text().map(|t| Item::Text(t.to_string())) }
+        }).collect();
+        Element { name: node.tag_name().name().to_string(), attrs, children }
+    }
+
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+    }
+
+    fn remove_attr(&mut self, name: &str) -> Option<String> {
+        let i = self.attrs.iter().position(|(k, _)| k == name)?;
+        Some(self.attrs.remove(i).1)
+    }
+
+    fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|c| match c { Item::Element(e) => Some(e), Item::Text(_) => None })
+    }
+
+    fn elements_mut(&mut self) -> impl Iterator<Item = &mut Element> {
+        self.children.iter_mut().filter_map(|c| match c { Item::Element(e) => Some(e), Item::Text(_) => None })
+    }
+
+    fn visit(&self, f: &mut impl FnMut(&Element)) {
+        f(self);
+        for child in self.elements() { child.visit(f); }
+    }
+
+    fn visit_mut(&mut self, f: &mut impl FnMut(&mut Element)) {
+        f(self);
+        for child in self.elements_mut() { child.visit_mut(f); }
+    }
+
+    fn defs_mut(&mut self) -> Option<&mut Element> {
+        self.elements_mut().find(|e| e.name == "defs")
+    }
+
+    fn write(&self, options: &ExportOptions, out: &mut String) {
+        out.push('<');
+        out.push_str(&self.name);
+        for (name, value) in &self.attrs {
+            let value = match name.as_str() {
+                "d" => path_data(value, options),
+                n if NUMERIC.contains(&n) => round_numbers(value, options.precision),
+                _ => value.clone(),
+            };
+            out.push_str(&format!(r##" {}="{}""##, name, escape_xml(&value)));
+        }
+        if self.children.is_empty() {
+            out.push_str(" />");
+            return;
+        }
+        out.push('>');
+        for child in &self.children {
+            match child {
+                Item::Element(e) => e.write(options, out),
+                Item::Text(t) => out.push_str(&escape_xml(t)),
+            }
+        }
+        out.push_str(&format!("</{}>", self.name));
+    }
+}
+
+/// The SVG with `options`' clean-ups applied, or as it is when there are none.
+pub fn optimize(svg: &str, options: &ExportOptions) -> String {
+    let wanted = options.precision.is_some() || options.minify_paths || options.merge_gradients || options.strip_hidden || options.semantic;
+    if !wanted { return svg.to_string(); }
+    let Ok(doc) = Document::parse(svg) else { return svg.to_string() };
+    let mut root = Element::read(doc.root_element());
+    root.attrs.insert(0, ("xmlns".to_string(), SVG_NS.to_string()));
+
+    if options.merge_gradients { merge_gradients(&mut root, options); }
+    if options.strip_hidden {
+        strip_hidden(&mut root, "#000000", "none");
+        strip_unused_defs(&mut root);
+    }
+    if options.semantic {
+        name_elements(&mut root);
+        colour_properties(&mut root);
+    }
+    let mut out = String::new();
+    root.write(options, &mut out);
+    out
+}
+
+// Numbers
+
+fn round(v: f64, precision: Option<u32>) -> f64 {
+    match precision {
+        Some(p) => { let f = 10f64.powi(p as i32); (v * f).round() / f }
+        None => v,
+    }
+}
+
+fn format_number(v: f64, precision: Option<u32>) -> String {
+    let v = round(v, precision);
+    if v == 0.0 { "0".to_string() } else { v.to_string() }
+}
+
+/// Length of the number starting at `i`, if one does.
+fn number_at(s: &[u8], i: usize) -> Option<usize> {
+    let mut j = i;
+    if j < s.len() && (s[j] == b'-' || s[j] == b'+') { j += 1; }
+    let digits = |j: &mut usize| { let from = *j; while *j < s.len() && s[*j].is_ascii_digit() { *j += 1; } *j > from };
+    let mut any = digits(&mut j);
+    if j < s.len() && s[j] == b'.' {
+        j += 1;
+        any |= digits(&mut j);
+    }
+    if !any { return None; }
+    if j < s.len() && (s[j] == b'e' || s[j] == b'E') {
+        let mut k = j + 1;
+        if k < s.len() && (s[k] == b'-' || s[k] == b'+') { k += 1; }
+        if digits(&mut k) { j = k; }
+    }
+    Some(j - i)
+}
+
+/// Every number in a value rounded, everything else left alone.
+fn round_numbers(value: &str, precision: Option<u32>) -> String {
+    if precision.is_none() { return value.to_string(); }
+    let s = value.as_bytes();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < s.len() {
+        match number_at(s, i) {
+            Some(len) => {
+                let text = &value[i..i + len];
+                out.push_str(&text.parse::<f64>().map(|v| format_number(v, precision)).unwrap_or_else(|_| text.to_string()));
+                i += len;
+            }
+            None => {
+                let ch = value[i..].chars().next().unwrap();
+                out.push(ch);
+                i += ch.len_utf8();
+            }
+        }
+    }
+    out
+}
+
+// Path data
+
+type P = (f64, f64);
+
+/// A path command in absolute coordinates.
+enum Seg {
+    Move(P),
+    Line(P),
+    Cubic(P, P, P),
+    Quad(P, P),
+    Arc([f64; 5], P), // Radii, rotation and the two flags
+    Close,
+}
+
+struct PathReader<'a> {
+    s: &'a [u8],
+    i: usize,
+}
+
+impl PathReader<'_> {
+    fn skip(&mut self) {
+        while self.i < self.s.len() && (self.s[self.i].is_ascii_whitespace() || self.s[self.i] == b',') { self.i += 1; }
+    }
+
+    fn at_number(&mut self) -> bool {
+        self.skip();
+        number_at(self.s, self.i).is_some()
+    }
+
+    fn number(&mut self) -> Option<f64> {
+        self.skip();
+        let len = number_at(self.s, self.i)?;
+        let v = std::str::from_utf8(&self.s[self.i..self.i + len]).ok()?.parse().ok()?;
+        self.i += len;
+        Some(v)
+    }
+
+    fn point(&mut self, relative: bool, cur: P) -> Option<P> {
+        let (x, y) = (self.number()?, self.number()?);
+        Some(if relative { (cur.0 + x, cur.1 + y) } else { (x, y) })
+    }
+
+    /// Arc flags may be written without separators, as in `a1 1 0 01 5 5`.
+    fn flag(&mut self) -> Option<f64> {
+        self.skip();
+        let v = match self.s.get(self.i)? { b'0' => 0.0, b'1' => 1.0, _ => return None };
+        self.i += 1;
+        Some(v)
+    }
+}
+
+fn reflect(about: P, p: P) -> P {
+    (2.0 * about.0 - p.0, 2.0 * about.1 - p.1)
+}
+
+fn parse_path(d: &str) -> Option<Vec<Seg>> {
+    let mut r = PathReader { s: d.as_bytes(), i: 0 };
+    let mut segs = Vec::new();
+    let (mut cur, mut start) = ((0.0, 0.0), (0.0, 0.0));
+    loop {
+        r.skip();
+        let Some(&cmd) = r.s.get(r.i) else { break };
+        if !cmd.is_ascii_alphabetic() { return None; }
+        r.i += 1;
+        let rel = cmd.is_ascii_lowercase();
+        for n in 0.. {
+            if n > 0 && (cmd.eq_ignore_ascii_case(&b'z') || !r.at_number()) { break; }
+            let seg = match cmd.to_ascii_uppercase() {
+                // Pairs after the first of a move are lines
+                b'M' if n > 0 => Seg::Line(r.point(rel, cur)?),
+                b'M' => { let p = r.point(rel, cur)?; start = p; Seg::Move(p) }
+                b'L' => Seg::Line(r.point(rel, cur)?),
+                b'H' => { let x = r.number()?; Seg::Line((if rel { cur.0 + x } else { x }, cur.1)) }
+                b'V' => { let y = r.number()?; Seg::Line((cur.0, if rel { cur.1 + y } else { y })) }
+                b'C' => Seg::Cubic(r.point(rel, cur)?, r.point(rel, cur)?, r.point(rel, cur)?),
+                b'S' => {
+                    let c1 = match segs.last() { Some(Seg::Cubic(_, c2, _)) => reflect(cur, *c2), _ => cur };
+                    Seg::Cubic(c1, r.point(rel, cur)?, r.point(rel, cur)?)
+                }
+                b'Q' => Seg::Quad(r.point(rel, cur)?, r.point(rel, cur)?),
+                b'T' => {
+                    let c = match segs.last() { Some(Seg::Quad(c, _)) => reflect(cur, *c), _ => cur };
+                    Seg::Quad(c, r.point(rel, cur)?)
+                }
+                b'A' => {
+                    let (rx, ry, rot) = (r.number()?, r.number()?, r.number()?);
+                    let (large, sweep) = (r.flag()?, r.flag()?);
+                    Seg::Arc([rx, ry, rot, large, sweep], r.point(rel, cur)?)
+                }
+                b'Z' => Seg::Close,
+                _ => return None,
+            };
+            cur = match &seg {
+                Seg::Move(p) | Seg::Line(p) | Seg::Cubic(_, _, p) | Seg::Quad(_, p) | Seg::Arc(_, p) => *p,
+                Seg::Close => start,
+            };
+            segs.push(seg);
+        }
+    }
+    Some(segs)
+}
+
+/// Numbers joined with the fewest separators: none before a minus sign, or
+/// before a fraction that follows one.
+fn join_numbers(out: &mut String, last: &mut Option<String>, nums: &[f64], precision: Option<u32>) {
+    for &v in nums {
+        let mut s = format_number(v, precision);
+        if let Some(rest) = s.strip_prefix("0.") { s = format!(".{}", rest); }
+        else if let Some(rest) = s.strip_prefix("-0.") { s = format!("-.{}", rest); }
+        if let Some(prev) = last.as_deref() {
+            if !(s.starts_with('-') || (s.starts_with('.') && prev.contains('.'))) { out.push(' '); }
+        }
+        out.push_str(&s);
+        *last = Some(s);
+    }
+}
+
+/// Path data in its shortest form: each command relative or absolute,
+/// whichever is shorter, lines as `H`/`V` where they can be, mirrored control
+/// points as `S`/`T`, and repeated command letters left out.
+fn write_path(segs: &[Seg], precision: Option<u32>) -> String {
+    let rp = |p: P| (round(p.0, precision), round(p.1, precision));
+    let near = |a: P, b: P| (a.0 - b.0).abs() < 1e-9 && (a.1 - b.1).abs() < 1e-9;
+    let mut out = String::new();
+    let mut last_cmd = ' ';
+    let mut last_num: Option<String> = None;
+    let (mut cur, mut start) = ((0.0, 0.0), (0.0, 0.0));
+    let mut prev_ctrl: Option<(char, P)> = None;
+    for seg in segs {
+        let (abs, rel): ((char, Vec<f64>), (char, Vec<f64>));
+        let mut ctrl = None;
+        let end = match seg {
+            Seg::Move(p) => {
+                let p = rp(*p);
+                abs = ('M', vec![p.0, p.1]);
+                rel = ('m', vec![p.0 - cur.0, p.1 - cur.1]);
+                start = p;
+                p
+            }
+            Seg::Line(p) => {
+                let p = rp(*p);
+                if p.1 == cur.1 { abs = ('H', vec![p.0]); rel = ('h', vec![p.0 - cur.0]); }
+                else if p.0 == cur.0 { abs = ('V', vec![p.1]); rel = ('v', vec![p.1 - cur.1]); }
+                else { abs = ('L', vec![p.0, p.1]); rel = ('l', vec![p.0 - cur.0, p.1 - cur.1]); }
+                p
+            }
+            Seg::Cubic(c1, c2, p) => {
+                let (c1, c2, p) = (rp(*c1), rp(*c2), rp(*p));
+                ctrl = Some(('C', c2));
+                match prev_ctrl {
+                    Some(('C', prev)) if near(reflect(cur, prev), c1) => {
+                        abs = ('S', vec![c2.0, c2.1, p.0, p.1]);
+                        rel = ('s', vec![c2.0 - cur.0, c2.1 - cur.1, p.0 - cur.0, p.1 - cur.1]);
+                    }
+                    _ => {
+                        abs = ('C', vec![c1.0, c1.1, c2.0, c2.1, p.0, p.1]);
+                        rel = ('c', vec![c1.0 - cur.0, c1.1 - cur.1, c2.0 - cur.0, c2.1 - cur.1, p.0 - cur.0, p.1 - cur.1]);
+                    }
+                }
+                p
+            }
+            Seg::Quad(c, p) => {
+                let (c, p) = (rp(*c), rp(*p));
+                ctrl = Some(('Q', c));
+                match prev_ctrl {
+                    Some(('Q', prev)) if near(reflect(cur, prev), c) => {
+                        abs = ('T', vec![p.0, p.1]);
+                        rel = ('t', vec![p.0 - cur.0, p.1 - cur.1]);
+                    }
+                    _ => {
+                        abs = ('Q', vec![c.0, c.1, p.0, p.1]);
+                        rel = ('q', vec![c.0 - cur.0, c.1 - cur.1, p.0 - cur.0, p.1 - cur.1]);
+                    }
+                }
+                p
+            }
+            Seg::Arc(params, p) => {
+                let p = rp(*p);
+                let mut a = params.to_vec();
+                a.extend([p.0, p.1]);
+                let mut r = params.to_vec();
+                r.extend([p.0 - cur.0, p.1 - cur.1]);
+                abs = ('A', a);
+                rel = ('a', r);
+                p
+            }
+            Seg::Close => {
+                out.push('z');
+                last_cmd = 'z';
+                last_num = None;
+                cur = start;
+                prev_ctrl = None;
+                continue;
+            }
+        };
+        // A letter is implied when it repeats, or when lines follow a move
+        let implied = match last_cmd { 'M' => 'L', 'm' => 'l', c => c };
+        let render = |(cmd, nums): &(char, Vec<f64>)| {
+            let mut s = String::new();
+            let mut last = if *cmd == implied { last_num.clone() } else { None };
+            if *cmd != implied { s.push(*cmd); }
+            join_numbers(&mut s, &mut last, nums, precision);
+            (s, last)
+        };
+        let (a, r) = (render(&abs), render(&rel));
+        let ((text, num), cmd) = if r.0.len() < a.0.len() { (r, rel.0) } else { (a, abs.0) };
+        out.push_str(&text);
+        last_num = num;
+        last_cmd = cmd;
+        cur = end;
+        prev_ctrl = ctrl;
+    }
+    out
+}
+
+fn path_data(d: &str, options: &ExportOptions) -> String {
+    if options.minify_paths {
+        if let Some(segs) = parse_path(d) { return write_path(&segs, options.precision); }
+    }
+    round_numbers(d, options.precision)
+}
+
+// Passes over the document
+
+/// Ids named by `url(#id)` or `#id` references anywhere in the element's tree.
+fn references(root: &Element) -> HashSet<String> {
+    let mut ids = HashSet::new();
+    root.visit(&mut |e| {
+        for (_, value) in &e.attrs {
+            if let Some(id) = value.strip_prefix('#') { ids.insert(id.to_string()); }
+            for part in value.split("url(#").skip(1) {
+                if let Some(id) = part.split(')').next() { ids.insert(id.to_string()); }
+            }
+        }
+    });
+    ids
+}
+
+fn merge_gradients(root: &mut Element, options: &ExportOptions) {
+    let Some(defs) = root.defs_mut() else { return };
+    let mut seen: HashMap<String, String> = HashMap::new();
+    let mut renamed: HashMap<String, String> = HashMap::new();
+    defs.children.retain(|child| {
+        let Item::Element(e) = child else { return true };
+        if !e.name.ends_with("Gradient") { return true; }
+        let Some(id) = e.attr("id") else { return true };
+        // Compared as they'll be written, so rounding can make more of them equal
+        let mut key = String::new();
+        Element { name: e.name.clone(), attrs: e.attrs.iter().filter(|(k, _)| k != "id").cloned().collect(), children: Vec::new() }.write(options, &mut key);
+        for stop in e.elements() { stop.write(options, &mut key); }
+        match seen.get(&key) {
+            Some(kept) => { renamed.insert(id.to_string(), kept.clone()); false }
+            None => { seen.insert(key, id.to_string()); true }
+        }
+    });
+    if renamed.is_empty() { return; }
+    root.visit_mut(&mut |e| {
+        for (_, value) in e.attrs.iter_mut() {
+            if let Some(kept) = value.strip_prefix('#').and_then(|id| renamed.get(id)) { *value = format!("#{}", kept); }
+            if value.contains("url(#") {
+                for (old, kept) in &renamed { *value = value.replace(&format!("url(#{})", old), &format!("url(#{})", kept)); }
+            }
+        }
+    });
+}
+
+fn paints(value: Option<&str>) -> bool {
+    !matches!(value.map(str::trim), Some("none") | Some("transparent"))
+}
+
+/// Whether an element draws anything, given the fill and stroke it inherits.
+fn draws(e: &Element, fill: &str, stroke: &str) -> bool {
+    let zero = |name: &str| e.attr(name).and_then(|v| v.trim().parse::<f64>().ok()) == Some(0.0);
+    if e.attr("display") == Some("none") || e.attr("visibility") == Some("hidden") || zero("opacity") { return false; }
+    match e.name.as_str() {
+        "rect" | "image" if zero("width") || zero("height") => false,
+        "ellipse" if zero("rx") || zero("ry") => false,
+        "circle" if zero("r") => false,
+        "path" if e.attr("d").is_none_or(|d| d.trim().is_empty()) => false,
+        "polygon" | "polyline" if e.attr("points").is_none_or(|p| p.trim().is_empty()) => false,
+        name if SHAPES.contains(&name) => {
+            let stroked = paints(Some(e.attr("stroke").unwrap_or(stroke))) && !zero("stroke-width");
+            paints(Some(e.attr("fill").unwrap_or(fill))) || stroked || e.attr("filter").is_some()
+        }
+        // A group draws if anything in it does
+        "g" => e.elements().any(|c| c.name != "title"),
+        _ => true,
+    }
+}
+
+/// Drops elements that draw nothing: hidden, fully transparent, empty or
+/// unpainted, and groups left with nothing in them.
+fn strip_hidden(e: &mut Element, fill: &str, stroke: &str) {
+    if matches!(e.name.as_str(), "defs" | "text" | "clipPath" | "mask" | "marker" | "pattern" | "symbol") { return; }
+    let fill = e.attr("fill").unwrap_or(fill).to_string();
+    let stroke = e.attr("stroke").unwrap_or(stroke).to_string();
+    for child in e.elements_mut() { strip_hidden(child, &fill, &stroke); }
+    e.children.retain(|child| match child {
+        Item::Element(c) => draws(c, &fill, &stroke),
+        Item::Text(_) => true,
+    });
+}
+
+/// Drops definitions nothing refers to, and `<defs>` left empty.
+fn strip_unused_defs(root: &mut Element) {
+    loop {
+        let used = references(root);
+        let Some(defs) = root.defs_mut() else { return };
+        let before = defs.children.len();
+        defs.children.retain(|child| match child {
+            Item::Element(e) => e.attr("id").is_some_and(|id| used.contains(id)),
+            Item::Text(_) => false,
+        });
+        if defs.children.is_empty() {
+            root.children.retain(|c| !matches!(c, Item::Element(e) if e.name == "defs"));
+            return;
+        }
+        if defs.children.len() == before { return; }
+    }
+}
+
+fn slug(name: &str) -> String {
+    let mut out = String::new();
+    for ch in name.chars().flat_map(char::to_lowercase) {
+        if ch.is_alphanumeric() { out.push(ch); }
+        else if !out.is_empty() && !out.ends_with('-') { out.push('-'); }
+    }
+    let out = out.trim_end_matches('-');
+    // Ids can't start with a digit
+    if out.starts_with(|c: char| c.is_ascii_digit()) { format!("_{}", out) } else { out.to_string() }
+}
+
+/// Object names become ids when only one object has them and classes when
+/// several share them, so the same parts can be styled together.
+fn name_elements(root: &mut Element) {
+    let mut taken = HashSet::new();
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    root.visit(&mut |e| {
+        if let Some(id) = e.attr("id") { taken.insert(id.to_string()); }
+        if let Some(name) = e.attr("data-name") { *counts.entry(slug(name)).or_default() += 1; }
+    });
+    root.visit_mut(&mut |e| {
+        let Some(name) = e.remove_attr("data-name") else { return };
+        let slug = slug(&name);
+        if slug.is_empty() { return; }
+        if counts[&slug] == 1 && !taken.contains(&slug) {
+            e.attrs.insert(0, ("id".to_string(), slug));
+        } else {
+            match e.attrs.iter_mut().find(|(k, _)| k == "class") {
+                Some((_, class)) => { class.push(' '); class.push_str(&slug); }
+                None => e.attrs.insert(0, ("class".to_string(), slug)),
+            }
+        }
+    });
+}
+
+/// Colours move into styles as `var(--color-N, colour)`, numbered by first
+/// use, so a page can recolour the artwork by setting the properties.
+fn colour_properties(root: &mut Element) {
+    let mut vars: HashMap<String, usize> = HashMap::new();
+    root.visit_mut(&mut |e| {
+        let mut declarations = Vec::new();
+        for &property in PAINTS {
+            let Some(value) = e.attr(property) else { continue };
+            let colour = value.trim();
+            if matches!(colour, "none" | "transparent" | "currentColor" | "inherit") || colour.starts_with("url(") { continue; }
+            let next = vars.len() + 1;
+            let n = *vars.entry(colour.to_lowercase()).or_insert(next);
+            declarations.push(format!("{}: var(--color-{}, {})", property, n, colour));
+            e.remove_attr(property);
+        }
+        if declarations.is_empty() { return; }
+        match e.attrs.iter_mut().find(|(k, _)| k == "style") {
+            Some((_, style)) => { declarations.push(style.clone()); *style = declarations.join("; "); }
+            None => e.attrs.push(("style".to_string(), declarations.join("; "))),
+        }
+    });
+}
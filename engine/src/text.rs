@@ -0,0 +1,284 @@
+use wasm_bindgen::prelude::*;
+use std::collections::HashMap;
+use kurbo::{BezPath, Point};
+use ttf_parser::{Face, OutlineBuilder};
+use crate::engine::VectorEngine;
+use crate::objects::VectorObject;
+use crate::text_layout::{fit_text_box, layout_text};
+use crate::text_path::path_text_glyphs;
+use crate::types::{LayerStyle, ShapeType};
+
+// Fonts are supplied by the host as raw TTF/OTF bytes. Text in a registered
+// font is laid out and drawn by the engine; without one the canvas falls back
+// to the browser's fonts.
+
+pub struct FontFace {
+    pub family: String,
+    pub weight: u16,
+    pub italic: bool,
+    pub data: Vec<u8>,
+}
+
+#[derive(Default)]
+pub struct FontRegistry {
+    faces: Vec<FontFace>,
+}
+
+impl FontRegistry {
+    pub fn register(&mut self, family: &str, weight: u16, data: Vec<u8>) -> Result<(), String> {
+        // Italic faces come from the font itself
+        let italic = Face::parse(&data, 0).map_err(|e| e.to_string())?.is_italic();
+        let family = family.trim().to_string();
+        self.faces.retain(|f| !(f.family.eq_ignore_ascii_case(&family) && f.weight == weight && f.italic == italic));
+        self.faces.push(FontFace { family, weight, italic, data });
+        Ok(())
+    }
+
+    /// Picks a face for a CSS style family list, or `None` when none of the
+    /// families are registered so the caller can fall back to the browser's
+    /// fonts. A family without an italic face gives its upright one.
+    pub fn resolve(&self, family_list: &str, weight: &str, italic: bool) -> Option<&FontFace> {
+        let weight = parse_weight(weight);
+        for family in family_list.split(',') {
+            let family = family.trim().trim_matches(|c| c == '"' || c == '\'');
+            let best = self.faces.iter()
+                .filter(|f| f.family.eq_ignore_ascii_case(family))
+                .min_by_key(|f| (f.italic != italic, (f.weight as i32 - weight as i32).abs()));
+            if best.is_some() { return best; }
+        }
+        None
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.faces.is_empty()
+    }
+}
+
+pub fn parse_weight(weight: &str) -> u16 {
+    match weight {
+        "normal" | "" => 400,
+        "bold" => 700,
+        "lighter" => 300,
+        "bolder" => 800,
+        w => w.parse().unwrap_or(400),
+    }
+}
+
+pub(crate) struct GlyphPath<'a> {
+    pub path: &'a mut BezPath,
+    pub scale: f64,
+    pub skew: f64, // Slants oblique glyphs
+    pub origin: Point,
+}
+
+impl GlyphPath<'_> {
+    fn pt(&self, x: f32, y: f32) -> Point {
+        Point::new(self.origin.x + (x as f64 + y as f64 * self.skew) * self.scale, self.origin.y - y as f64 * self.scale)
+    }
+}
+
+impl OutlineBuilder for GlyphPath<'_> {
+    fn move_to(&mut self, x: f32, y: f32) { let p = self.pt(x, y); self.path.move_to(p); }
+    fn line_to(&mut self, x: f32, y: f32) { let p = self.pt(x, y); self.path.line_to(p); }
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) { let (p1, p) = (self.pt(x1, y1), self.pt(x, y)); self.path.quad_to(p1, p); }
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) { let (p1, p2, p) = (self.pt(x1, y1), self.pt(x2, y2), self.pt(x, y)); self.path.curve_to(p1, p2, p); }
+    fn close(&mut self) { self.path.close_path(); }
+}
+
+/// Outlines a text object in its local space, laid out the same way the canvas
+/// draws it.
+pub fn text_to_path(obj: &VectorObject, fonts: &FontRegistry) -> Result<BezPath, String> {
+    let mut path = BezPath::new();
+    for (piece, _) in outline_pieces(obj, fonts, "compound")? { path.extend(piece); }
+    Ok(path)
+}
+
+/// Glyph outlines of a text object, each with the fill of its text or None
+/// for the object's own: one path per fill, or per line or glyph as `split`
+/// asks. Text on a path is a single line. Compound pieces put the object's
+/// fill first.
+pub fn outline_pieces(obj: &VectorObject, fonts: &FontRegistry, split: &str) -> Result<Vec<(BezPath, Option<String>)>, String> {
+    let shapes: Vec<(usize, BezPath, Option<String>)> = if let Some(tp) = &obj.text_path {
+        path_text_glyphs(obj, fonts, tp)?.into_iter().map(|(path, fill)| (0, path, fill)).collect()
+    } else {
+        layout_text(obj, fonts)?.shapes(fonts)?.into_iter().map(|s| (s.line, s.path, s.fill)).collect()
+    };
+    if split == "glyphs" { return Ok(shapes.into_iter().map(|(_, path, fill)| (path, fill)).collect()); }
+    let mut pieces: Vec<(usize, BezPath, Option<String>)> = Vec::new();
+    for (line, path, fill) in shapes {
+        let line = if split == "lines" { line } else { 0 };
+        match pieces.iter_mut().find(|p| p.0 == line && p.2 == fill) {
+            Some(piece) => piece.1.extend(path),
+            None => pieces.push((line, path, fill)),
+        }
+    }
+    if split != "lines" {
+        if pieces.is_empty() { pieces.push((0, BezPath::new(), None)); }
+        pieces.sort_by_key(|p| p.2.is_some());
+    }
+    Ok(pieces.into_iter().map(|(_, path, fill)| (path, fill)).collect())
+}
+
+/// Glyph outlines the canvas draws for a text object, as path data with the
+/// fill of each piece, and the layout inputs they were built from. `None`
+/// without a registered font, for the browser to draw the text instead.
+pub(crate) struct TextOutline {
+    key: String,
+    pub pieces: Option<Vec<(String, Option<String>)>>,
+}
+
+/// Everything that changes how a text object's glyphs are laid out.
+fn outline_key(obj: &VectorObject) -> String {
+    serde_json::json!([
+        obj.text_content, obj.font_family, obj.font_size, obj.font_weight, obj.text_align, obj.kerning, obj.leading,
+        obj.tracking, obj.width, obj.height, obj.area_text, obj.text_path, obj.text_runs, obj.paragraphs,
+    ]).to_string()
+}
+
+impl TextOutline {
+    pub fn new(obj: &VectorObject, fonts: &FontRegistry) -> Self {
+        let pieces = outline_pieces(obj, fonts, "compound").ok()
+            .map(|pieces| pieces.into_iter().map(|(path, fill)| (path.to_svg(), fill)).collect());
+        TextOutline { key: outline_key(obj), pieces }
+    }
+
+    /// Whether the outline still matches the object, which it may not when
+    /// objects were changed outside a command.
+    pub fn is_current(&self, obj: &VectorObject) -> bool {
+        self.key == outline_key(obj)
+    }
+}
+
+fn collect_outlines(objects: &[VectorObject], fonts: &FontRegistry, old: &mut HashMap<u32, TextOutline>, out: &mut HashMap<u32, TextOutline>) {
+    for obj in objects {
+        if obj.shape_type == ShapeType::Text {
+            let outline = match old.remove(&obj.id) {
+                Some(o) if o.is_current(obj) => o,
+                _ => TextOutline::new(obj, fonts),
+            };
+            out.insert(obj.id, outline);
+        }
+        if let Some(children) = &obj.children { collect_outlines(children, fonts, old, out); }
+    }
+}
+
+/// The text as plain paths in the same box: one compound path, or with
+/// `split` set to "lines" or "glyphs", a group of one path per line or glyph.
+/// Text with coloured runs gives a group of one path per fill even when
+/// compound.
+pub fn outline_text_split(obj: &VectorObject, fonts: &FontRegistry, split: &str) -> Result<VectorObject, String> {
+    let pieces = outline_pieces(obj, fonts, split)?;
+    let as_path = |path: &BezPath, fill: &Option<String>| {
+        let mut out = obj.clone();
+        out.shape_type = ShapeType::Path;
+        out.path_data = path.to_svg();
+        out.fill_rule = "nonzero".to_string();
+        if let Some(fill) = fill { out.fill = fill.clone(); out.fill_gradient = None; }
+        out.text_content.clear();
+        out.text_path = None;
+        out.text_runs.clear();
+        out.paragraphs.clear();
+        out
+    };
+    if !matches!(split, "lines" | "glyphs") && pieces.len() < 2 {
+        let (path, fill) = pieces.into_iter().next().unwrap_or_default();
+        return Ok(as_path(&path, &fill));
+    }
+    if pieces.is_empty() { return Err("Text has no visible glyphs".to_string()); }
+    // Children sit in the group's space, which is the text's box. Compositing,
+    // effects, masks and warps stay on the group so they apply once.
+    let mut group = as_path(&BezPath::new(), &None);
+    group.shape_type = ShapeType::Group;
+    group.path_data.clear();
+    group.children = Some(pieces.iter().map(|(path, fill)| {
+        let mut child = as_path(path, fill);
+        child.x = 0.0;
+        child.y = 0.0;
+        child.rotation = 0.0;
+        child.opacity = 1.0;
+        child.blend_mode = "source-over".to_string();
+        child.layer_style = LayerStyle::default();
+        child.shadow_color = "transparent".to_string();
+        child.shadow_blur = 0.0;
+        child.shadow_offset_x = 0.0;
+        child.shadow_offset_y = 0.0;
+        child.mask_id = None;
+        child.warp = None;
+        child
+    }).collect());
+    Ok(group)
+}
+
+/// The text as a plain path in the same box, or a group of one path per fill
+/// when its runs are coloured. `None` without a registered font.
+pub fn outline_text(obj: &VectorObject, fonts: &FontRegistry) -> Option<VectorObject> {
+    outline_text_split(obj, fonts, "compound").ok()
+}
+
+/// Copies of the objects with their text outlined, for exporters: all of it,
+/// or only text on a path. Text without a registered font is kept as text.
+pub fn outline_texts(objects: &[VectorObject], fonts: &FontRegistry, all: bool) -> Vec<VectorObject> {
+    objects.iter().map(|obj| {
+        if obj.shape_type == ShapeType::Text && (all || obj.text_path.is_some()) {
+            if let Some(outlined) = outline_text(obj, fonts) { return outlined; }
+        }
+        let mut out = obj.clone();
+        if let Some(children) = out.children.as_mut() { *children = outline_texts(children, fonts, all); }
+        out
+    }).collect()
+}
+
+fn fit_texts(objects: &mut [VectorObject], fonts: &FontRegistry) {
+    for obj in objects {
+        if obj.shape_type == ShapeType::Text { fit_text_box(obj, fonts); }
+        if let Some(children) = obj.children.as_mut() { fit_texts(children, fonts); }
+    }
+}
+
+#[wasm_bindgen]
+impl VectorEngine {
+    /// Registers TTF/OTF data for a font family so text can be converted to outlines.
+    pub fn register_font(&mut self, family: &str, weight: &str, data: Vec<u8>) -> bool {
+        if self.fonts.register(family, parse_weight(weight), data).is_err() { return false; }
+        // Point text already on the canvas takes the new font's metrics, and
+        // text is drawn again with it, warped or not
+        fit_texts(&mut self.objects, &self.fonts);
+        self.text_outlines.clear();
+        self.warped_objects.clear();
+        self.refresh_render_caches();
+        true
+    }
+
+    /// Lays out again the text whose content, style or size changed since the
+    /// last refresh.
+    pub(crate) fn refresh_text_outlines(&mut self) {
+        let mut old = std::mem::take(&mut self.text_outlines);
+        collect_outlines(&self.objects, &self.fonts, &mut old, &mut self.text_outlines);
+    }
+
+    /// Replaces text objects with their glyph outlines: one compound path, or
+    /// with `split` set to "lines" or "glyphs", a group of one path per line
+    /// or glyph. Outlines keep the text's id, style and placement.
+    pub(crate) fn create_outlines(&mut self, ids: &[u32], params: &serde_json::Value) -> String {
+        let split = params["split"].as_str().unwrap_or("compound");
+        let mut outlined = Vec::new();
+        let mut error = None;
+        for &id in ids {
+            let Some(idx) = self.objects.iter().position(|o| o.id == id && o.shape_type == ShapeType::Text) else { continue };
+            let name = self.objects[idx].name.clone();
+            let mut out = match outline_text_split(&self.objects[idx], &self.fonts, split) { Ok(o) => o, Err(e) => { error.get_or_insert(e); continue } };
+            out.name = format!("{} Outlines", name);
+            for (i, child) in out.children.iter_mut().flatten().enumerate() {
+                child.name = format!("{} {}", name, i + 1);
+                child.id = self.next_id; self.next_id += 1;
+            }
+            self.objects[idx] = out;
+            outlined.push(id);
+        }
+        if outlined.is_empty() {
+            let msg = error.unwrap_or_else(|| "No text to outline".to_string());
+            return serde_json::json!({ "error": msg }).to_string();
+        }
+        serde_json::json!({ "success": true, "ids": outlined }).to_string()
+    }
+}
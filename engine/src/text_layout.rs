@@ -0,0 +1,458 @@
+use wasm_bindgen::prelude::*;
+use std::ops::Range;
+use serde::Serialize;
+use kurbo::{Affine, BezPath, Point, Shape};
+use rustybuzz::{Direction, UnicodeBuffer};
+use unicode_bidi::ParagraphBidiInfo;
+use unicode_linebreak::{linebreaks, BreakOpportunity};
+use crate::engine::VectorEngine;
+use crate::objects::VectorObject;
+use crate::rich_text::{paragraph_style, style_spans, ParagraphStyle, ResolvedStyle, TextStyle};
+use crate::text::{FontRegistry, GlyphPath};
+use crate::types::ShapeType;
+
+// Text layout: paragraphs are split into bidi runs and shaped with the
+// object's font, then broken into lines at Unicode line break opportunities.
+// Point text sizes its box to the lines; area text wraps at the box width.
+// Positions are in the object's local space, y down, first baseline at
+// `font_size` like the canvas draws it.
+
+/// Object fields that change the layout, and so the size of point text.
+pub(crate) const TEXT_LAYOUT_KEYS: &[&str] = &["text_content", "font_family", "font_size", "font_weight", "text_align", "kerning", "leading", "tracking", "area_text", "text_runs", "paragraphs"];
+
+// Slant of italic text in a family without an italic face
+pub(crate) const OBLIQUE_SKEW: f64 = 0.2;
+
+// Average character advance in ems, for wrapping text without a font
+const FALLBACK_ADVANCE: f64 = 0.55;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct PositionedGlyph {
+    pub id: u16,
+    pub cluster: usize, // Byte offset of the glyph's text in `text_content`
+    pub style: usize, // Index into the layout's styles
+    pub x: f64,
+    pub y: f64,
+    pub advance: f64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct LayoutLine {
+    pub start: usize, // Byte range in `text_content`, without the line break
+    pub end: usize,
+    pub x: f64,
+    pub baseline: f64,
+    pub width: f64,
+    pub ascent: f64, // Of the line's largest font
+    pub descent: f64,
+    pub rtl: bool, // Paragraph direction
+    pub glyphs: Vec<PositionedGlyph>, // Visual order
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct TextLayout {
+    pub lines: Vec<LayoutLine>,
+    pub styles: Vec<ResolvedStyle>, // The object's own style first
+    pub width: f64,
+    pub height: f64,
+    pub ascent: f64, // Of the object's font
+    pub descent: f64,
+    pub overflow: bool, // Area text that doesn't fit its box
+}
+
+/// A glyph or underline outline with the fill of its style.
+pub struct GlyphShape {
+    pub line: usize,
+    pub fill: Option<String>,
+    pub path: BezPath,
+}
+
+/// A style's font, ready for shaping.
+struct ShapingFont<'a> {
+    face: rustybuzz::Face<'a>,
+    scale: f64,
+    spacing: f64,
+    ascent: f64,
+    descent: f64,
+}
+
+impl<'a> ShapingFont<'a> {
+    fn new(style: &ResolvedStyle, kerning: f64, fonts: &'a FontRegistry) -> Result<Self, String> {
+        let font = fonts.resolve(&style.font_family, &style.font_weight, style.italic).ok_or("No font registered for text layout")?;
+        let face = rustybuzz::Face::from_slice(&font.data, 0).ok_or("Invalid font data")?;
+        let scale = style.font_size / face.units_per_em() as f64;
+        Ok(ShapingFont {
+            // Tracking and manual kerning are in thousandths of an em; kerning pairs come from the font
+            spacing: (style.tracking + kerning) / 1000.0 * style.font_size,
+            ascent: face.ascender() as f64 * scale,
+            descent: -face.descender() as f64 * scale,
+            scale,
+            face,
+        })
+    }
+}
+
+/// A style's font, ready for outlining.
+pub(crate) struct OutlineFont<'a> {
+    face: ttf_parser::Face<'a>,
+    scale: f64,
+    skew: f64,
+}
+
+/// Shapes `text` as a single run. Glyph advances include the spacing after
+/// each cluster; clusters are byte offsets into `text`.
+fn shape(font: &ShapingFont, style: usize, text: &str, rtl: bool) -> Vec<PositionedGlyph> {
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(text);
+    buffer.set_direction(if rtl { Direction::RightToLeft } else { Direction::LeftToRight });
+    let shaped = rustybuzz::shape(&font.face, &[], buffer);
+    let (infos, positions) = (shaped.glyph_infos(), shaped.glyph_positions());
+    let mut glyphs = Vec::with_capacity(infos.len());
+    for (i, (info, pos)) in infos.iter().zip(positions).enumerate() {
+        let cluster_end = infos.get(i + 1).is_none_or(|next| next.cluster != info.cluster);
+        glyphs.push(PositionedGlyph {
+            id: info.glyph_id as u16,
+            cluster: info.cluster as usize,
+            style,
+            x: pos.x_offset as f64 * font.scale,
+            y: -pos.y_offset as f64 * font.scale,
+            advance: pos.x_advance as f64 * font.scale + if cluster_end { font.spacing } else { 0.0 },
+        });
+    }
+    glyphs
+}
+
+/// Splits a byte range into runs of one style, in logical order.
+fn style_runs(byte_styles: &[usize], range: Range<usize>) -> Vec<(Range<usize>, usize)> {
+    let mut runs: Vec<(Range<usize>, usize)> = Vec::new();
+    for i in range {
+        match runs.last_mut() {
+            Some((r, style)) if *style == byte_styles[i] => r.end = i + 1,
+            _ => runs.push((i..i + 1, byte_styles[i])),
+        }
+    }
+    runs
+}
+
+/// Runs of equal bidi level, in logical order.
+fn level_runs(bidi: &ParagraphBidiInfo) -> Vec<(Range<usize>, bool)> {
+    let mut starts: Vec<(usize, bool)> = Vec::new();
+    for (i, _) in bidi.text.char_indices() {
+        let rtl = bidi.levels[i].is_rtl();
+        if starts.last().is_none_or(|s| s.1 != rtl) { starts.push((i, rtl)); }
+    }
+    starts.iter().enumerate().map(|(k, &(start, rtl))| {
+        let end = starts.get(k + 1).map_or(bidi.text.len(), |s| s.0);
+        (start..end, rtl)
+    }).collect()
+}
+
+/// Splits a paragraph into lines, line `n` no wider than `limit(n)`,
+/// breaking only where Unicode allows it. A word longer than the limit
+/// overflows its line. `spacing` is the tracking after each byte's cluster,
+/// which doesn't count at the end of a line.
+fn break_lines(text: &str, advances: &[f64], spacing: &[f64], limit: impl Fn(usize) -> Option<f64>) -> Vec<Range<usize>> {
+    if text.is_empty() { return vec![Range { start: 0, end: 0 }]; }
+    let width = |r: Range<usize>| if r.is_empty() { 0.0 } else { advances[r.clone()].iter().sum::<f64>() - spacing[r.end - 1] };
+    let visible = |start: usize, end: usize| start + text[start..end].trim_end().len();
+    let mut lines = Vec::new();
+    let (mut start, mut last_fit) = (0, None);
+    for (pos, opportunity) in linebreaks(text) {
+        if let (Some(limit), Some(fit)) = (limit(lines.len()), last_fit) {
+            if width(start..visible(start, pos)) > limit {
+                lines.push(start..fit);
+                start = fit;
+            }
+        }
+        if opportunity == BreakOpportunity::Mandatory {
+            lines.push(start..pos);
+            start = pos;
+            last_fit = None;
+        } else {
+            last_fit = Some(pos);
+        }
+    }
+    if start < text.len() { lines.push(start..text.len()); }
+    lines
+}
+
+/// Lines of each paragraph of text without a registered font, as byte ranges
+/// of `text_content`. There are no metrics to measure with, so area text
+/// wraps at its box by an average advance per character.
+pub fn fallback_lines(obj: &VectorObject) -> Vec<Vec<Range<usize>>> {
+    let spans = style_spans(obj);
+    let mut offset = 0;
+    obj.text_content.split('\n').enumerate().map(|(p, raw)| {
+        let para = raw.strip_suffix('\r').unwrap_or(raw);
+        let pstyle = paragraph_style(obj, p);
+        let spacing = vec![(obj.tracking + obj.kerning) / 1000.0 * obj.font_size; para.len()];
+        let mut advances = vec![0.0; para.len()];
+        for (i, _) in para.char_indices() {
+            let size = spans.iter().find(|(r, _)| r.contains(&(offset + i))).and_then(|(_, s)| s.font_size).unwrap_or(obj.font_size);
+            advances[i] = size * FALLBACK_ADVANCE + spacing[i];
+        }
+        let limit = |n: usize| obj.area_text.then_some(obj.width - pstyle.left_indent - pstyle.right_indent - if n == 0 { pstyle.first_line_indent } else { 0.0 });
+        let lines = break_lines(para, &advances, &spacing, limit).into_iter()
+            .map(|r| offset + r.start..offset + r.start + para[r].trim_end().len())
+            .collect();
+        offset += raw.len() + 1;
+        lines
+    }).collect()
+}
+
+/// Lays out a text object with its registered fonts.
+pub fn layout_text(obj: &VectorObject, fonts: &FontRegistry) -> Result<TextLayout, String> {
+    // Distinct styles of the text, and the style of each byte
+    let mut styles = vec![ResolvedStyle::new(obj, &TextStyle::default())];
+    let mut byte_styles = vec![0; obj.text_content.len()];
+    for (range, style) in style_spans(obj) {
+        let resolved = ResolvedStyle::new(obj, &style);
+        let index = styles.iter().position(|s| *s == resolved).unwrap_or_else(|| { styles.push(resolved); styles.len() - 1 });
+        byte_styles[range].fill(index);
+    }
+    let shaping: Vec<ShapingFont> = styles.iter().map(|s| ShapingFont::new(s, obj.kerning, fonts)).collect::<Result<_, _>>()?;
+
+    let mut lines = Vec::new();
+    // Paragraph index of each line and whether it starts or ends it
+    let mut line_paragraphs = Vec::new();
+    let mut offset = 0;
+    for (p, raw) in obj.text_content.split('\n').enumerate() {
+        let para = raw.strip_suffix('\r').unwrap_or(raw);
+        let para_styles = &byte_styles[offset..offset + para.len()];
+        let pstyle = paragraph_style(obj, p);
+        let bidi = ParagraphBidiInfo::new(para, None);
+        let rtl = bidi.paragraph_level.is_rtl();
+        // Logical advances per byte for measuring line breaks
+        let mut advances = vec![0.0; para.len()];
+        let spacing: Vec<f64> = para_styles.iter().map(|&s| shaping[s].spacing).collect();
+        for (run, run_rtl) in level_runs(&bidi) {
+            for (part, style) in style_runs(para_styles, run) {
+                for g in shape(&shaping[style], style, &para[part.clone()], run_rtl) { advances[part.start + g.cluster] += g.advance; }
+            }
+        }
+        let limit = |n: usize| obj.area_text.then_some(obj.width - pstyle.left_indent - pstyle.right_indent - if n == 0 { pstyle.first_line_indent } else { 0.0 });
+        let ranges = break_lines(para, &advances, &spacing, limit);
+        let last = ranges.len() - 1;
+        for (k, range) in ranges.into_iter().enumerate() {
+            let end = range.start + para[range.clone()].trim_end().len();
+            let mut glyphs = Vec::new();
+            let mut x = 0.0;
+            if end > range.start {
+                let (levels, runs) = bidi.visual_runs(range.start..end);
+                for run in runs {
+                    let run_rtl = levels[run.start].is_rtl();
+                    let mut parts = style_runs(para_styles, run);
+                    if run_rtl { parts.reverse(); }
+                    for (part, style) in parts {
+                        for mut g in shape(&shaping[style], style, &para[part.clone()], run_rtl) {
+                            g.cluster += offset + part.start;
+                            g.x += x;
+                            g.y -= styles[style].baseline_shift;
+                            x += g.advance;
+                            glyphs.push(g);
+                        }
+                    }
+                }
+            }
+            let width = glyphs.last().map_or(0.0, |g| x - shaping[g.style].spacing);
+            // An empty line has the size of the text it starts at
+            let line_style = byte_styles.get(offset + range.start).or(byte_styles.last()).copied().unwrap_or(0);
+            let used = || glyphs.iter().map(|g| g.style).chain(glyphs.is_empty().then_some(line_style));
+            let ascent = used().map(|s| shaping[s].ascent).fold(0.0, f64::max);
+            let descent = used().map(|s| shaping[s].descent).fold(0.0, f64::max);
+            let size = used().map(|s| styles[s].font_size).fold(0.0, f64::max);
+            lines.push((LayoutLine { start: offset + range.start, end: offset + end, x: 0.0, baseline: 0.0, width, ascent, descent, rtl, glyphs }, size));
+            line_paragraphs.push((pstyle.clone(), k == 0, k == last));
+        }
+        offset += raw.len() + 1;
+    }
+
+    let inset = |line: &LayoutLine, pstyle: &ParagraphStyle, first: bool| {
+        let indent = if first { pstyle.first_line_indent } else { 0.0 };
+        if line.rtl { (pstyle.left_indent, pstyle.right_indent + indent) } else { (pstyle.left_indent + indent, pstyle.right_indent) }
+    };
+    let text_width = lines.iter().zip(&line_paragraphs).map(|((l, _), (ps, first, _))| {
+        let (left, right) = inset(l, ps, *first);
+        left + l.width + right
+    }).fold(0.0, f64::max);
+    let width = if obj.area_text { obj.width } else { text_width };
+    // Each line is `leading` times its largest font below the one before it
+    let mut baseline = 0.0;
+    let mut space_after = 0.0;
+    for (i, ((line, size), (pstyle, first, last))) in lines.iter_mut().zip(&line_paragraphs).enumerate() {
+        baseline = if i == 0 { *size } else { baseline + *size * obj.leading };
+        if *first && i > 0 { baseline += space_after + pstyle.space_before; }
+        space_after = pstyle.space_after;
+        line.baseline = baseline;
+        let (left, right) = inset(line, pstyle, *first);
+        let available = width - left - right;
+        let slack = available - line.width;
+        let align = pstyle.align.as_deref().unwrap_or(&obj.text_align);
+        let justify = align == "justify" && obj.area_text && !last;
+        let at_end = match align {
+            "center" => None,
+            "right" => Some(true),
+            "left" => Some(false),
+            "end" => Some(!line.rtl),
+            _ => Some(line.rtl), // "start" and justified paragraph ends
+        };
+        line.x = left + match at_end { None => slack / 2.0, Some(true) => slack, Some(false) => 0.0 };
+        // Justified lines spread the slack over their spaces
+        if justify && slack > 0.0 {
+            let spaces: Vec<bool> = line.glyphs.iter().map(|g| obj.text_content[g.cluster..].starts_with(' ')).collect();
+            let count = spaces.iter().filter(|s| **s).count();
+            if count > 0 {
+                let extra = slack / count as f64;
+                let mut shift = 0.0;
+                for (g, space) in line.glyphs.iter_mut().zip(&spaces) {
+                    g.x += shift;
+                    if *space { shift += extra; g.advance += extra; }
+                }
+                line.x = left;
+                line.width = available;
+            }
+        }
+        let (lx, baseline) = (line.x, line.baseline);
+        for g in line.glyphs.iter_mut() { g.x += lx; g.y += baseline; }
+    }
+    let lines: Vec<LayoutLine> = lines.into_iter().map(|(l, _)| l).collect();
+    let content_height = lines.last().map_or(0.0, |l| l.baseline + l.descent);
+    let height = if obj.area_text { obj.height } else { content_height };
+    Ok(TextLayout { lines, styles, width, height, ascent: shaping[0].ascent, descent: shaping[0].descent, overflow: obj.area_text && content_height > obj.height })
+}
+
+impl TextLayout {
+    /// Fonts of the layout's styles for outlining. Italic text in a family
+    /// without an italic face is slanted.
+    pub(crate) fn outline_fonts<'a>(&self, fonts: &'a FontRegistry) -> Result<Vec<OutlineFont<'a>>, String> {
+        self.styles.iter().map(|s| {
+            let font = fonts.resolve(&s.font_family, &s.font_weight, s.italic).ok_or("No font registered for text outlines")?;
+            let face = ttf_parser::Face::parse(&font.data, 0).map_err(|e| e.to_string())?;
+            let skew = if s.italic && !font.italic { OBLIQUE_SKEW } else { 0.0 };
+            Ok(OutlineFont { scale: s.font_size / face.units_per_em() as f64, skew, face })
+        }).collect()
+    }
+
+    /// Glyph and underline outlines, line by line in visual order.
+    pub fn shapes(&self, fonts: &FontRegistry) -> Result<Vec<GlyphShape>, String> {
+        let outline_fonts = self.outline_fonts(fonts)?;
+        let mut shapes = Vec::new();
+        for (i, line) in self.lines.iter().enumerate() {
+            for g in &line.glyphs {
+                let mut path = BezPath::new();
+                outline_glyph(&outline_fonts, g, Point::new(g.x, g.y), &mut path);
+                if !path.elements().is_empty() { shapes.push(GlyphShape { line: i, fill: self.styles[g.style].fill.clone(), path }); }
+            }
+        }
+        shapes.extend(self.underline_shapes(&outline_fonts));
+        Ok(shapes)
+    }
+
+    /// Underlines as rectangles, one along each stretch of underlined glyphs
+    /// of a style.
+    pub fn underlines(&self, fonts: &FontRegistry) -> Result<Vec<GlyphShape>, String> {
+        Ok(self.underline_shapes(&self.outline_fonts(fonts)?))
+    }
+
+    fn underline_shapes(&self, outline_fonts: &[OutlineFont]) -> Vec<GlyphShape> {
+        let mut shapes = Vec::new();
+        for (i, line) in self.lines.iter().enumerate() {
+            let mut stretches: Vec<(usize, f64, f64, f64)> = Vec::new();
+            for g in line.glyphs.iter().filter(|g| self.styles[g.style].underline) {
+                let y = line.baseline - self.styles[g.style].baseline_shift;
+                match stretches.last_mut() {
+                    Some((style, _, x1, sy)) if *style == g.style && *sy == y && (*x1 - g.x).abs() < 1e-6 => *x1 = g.x + g.advance,
+                    _ => stretches.push((g.style, g.x, g.x + g.advance, y)),
+                }
+            }
+            for (style, x0, x1, y) in stretches {
+                let font = &outline_fonts[style];
+                let upem = font.face.units_per_em() as f64;
+                let (position, thickness) = font.face.underline_metrics().map_or((-upem / 10.0, upem / 20.0), |m| (m.position as f64, m.thickness as f64));
+                let top = y - position * font.scale;
+                let rect = kurbo::Rect::new(x0, top, x1, top + thickness * font.scale);
+                shapes.push(GlyphShape { line: i, fill: self.styles[style].fill.clone(), path: rect.to_path(0.1) });
+            }
+        }
+        shapes
+    }
+}
+
+/// Appends a glyph's outline with its baseline origin at `origin`.
+pub(crate) fn outline_glyph(fonts: &[OutlineFont], g: &PositionedGlyph, origin: Point, path: &mut BezPath) {
+    let font = &fonts[g.style];
+    let mut builder = GlyphPath { path, scale: font.scale, skew: font.skew, origin };
+    font.face.outline_glyph(ttf_parser::GlyphId(g.id), &mut builder);
+}
+
+/// Text saved before this layout engine had centred and right-aligned lines
+/// drawn about the left edge of its box rather than within its width. Such
+/// objects are told apart by missing the `area_text` field every object has
+/// been saved with since, and are moved so their text stays where it was.
+pub(crate) fn migrate_text_align(obj: &mut VectorObject, saved: &serde_json::Value) {
+    if obj.shape_type == ShapeType::Text && saved.get("area_text").is_none() {
+        let shift = match obj.text_align.as_str() {
+            "center" => obj.width / 2.0,
+            "right" | "end" => obj.width,
+            _ => 0.0,
+        };
+        let offset = Affine::rotate(obj.rotation) * Point::new(-shift, 0.0);
+        obj.x += offset.x;
+        obj.y += offset.y;
+    }
+    if let (Some(children), Some(saved)) = (obj.children.as_mut(), saved["children"].as_array()) {
+        for (child, saved) in children.iter_mut().zip(saved) { migrate_text_align(child, saved); }
+    }
+}
+
+/// Resizes a point text object's box to its laid out text, keeping the
+/// alignment anchor (left edge, centre or right edge of the first line) in
+/// place, rotation included. Area text, text on a path and text without a
+/// registered font are left alone.
+pub fn fit_text_box(obj: &mut VectorObject, fonts: &FontRegistry) {
+    if obj.area_text || obj.text_path.is_some() { return; }
+    let Ok(layout) = layout_text(obj, fonts) else { return };
+    let anchor = |w: f64| match obj.text_align.as_str() {
+        "center" => w / 2.0,
+        "right" => w,
+        _ => 0.0,
+    };
+    let (w, h) = (layout.width, layout.height);
+    let rotate = |x: f64, y: f64| (Affine::rotate(obj.rotation) * Point::new(x, y)).to_vec2();
+    let old = Point::new(obj.x + obj.width / 2.0, obj.y + obj.height / 2.0) + rotate(anchor(obj.width) - obj.width / 2.0, -obj.height / 2.0);
+    let centre = old - rotate(anchor(w) - w / 2.0, -h / 2.0);
+    obj.x = centre.x - w / 2.0;
+    obj.y = centre.y - h / 2.0;
+    obj.width = w;
+    obj.height = h;
+}
+
+#[wasm_bindgen]
+impl VectorEngine {
+    /// Refits a point text box to its text, e.g. after it is created.
+    pub(crate) fn fit_text(&mut self, id: u32) {
+        if let Some(obj) = self.objects.iter_mut().find(|o| o.id == id && o.shape_type == ShapeType::Text) { fit_text_box(obj, &self.fonts); }
+    }
+
+    /// Lines and glyph positions of a text object, for carets and selection.
+    /// Offsets are char indices into `text_content` rather than the byte
+    /// offsets the layout works in.
+    pub(crate) fn text_layout(&self, id: u32) -> String {
+        let Some(obj) = self.objects.iter().find(|o| o.id == id) else { return "{ \"error\": \"Object not found\" }".to_string() };
+        if obj.shape_type != ShapeType::Text { return "{ \"error\": \"Not a text object\" }".to_string(); }
+        match layout_text(obj, &self.fonts) {
+            Ok(mut layout) => {
+                let text = &obj.text_content;
+                let mut char_index = vec![text.chars().count(); text.len() + 1];
+                for (i, (b, c)) in text.char_indices().enumerate() { char_index[b..b + c.len_utf8()].fill(i); }
+                for line in layout.lines.iter_mut() {
+                    line.start = char_index[line.start];
+                    line.end = char_index[line.end];
+                    for g in line.glyphs.iter_mut() { g.cluster = char_index[g.cluster]; }
+                }
+                serde_json::to_string(&layout).unwrap_or_default()
+            }
+            Err(e) => serde_json::json!({ "error": e }).to_string(),
+        }
+    }
+}
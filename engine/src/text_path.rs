@@ -0,0 +1,175 @@
+use wasm_bindgen::prelude::*;
+use serde::{Serialize, Deserialize};
+use kurbo::{Affine, BezPath, Point, Rect, Shape};
+use crate::engine::VectorEngine;
+use crate::geometry::world_path;
+use crate::measure::PathMeasure;
+use crate::objects::VectorObject;
+use crate::path_ops::{contours, contours_to_path};
+use crate::text::FontRegistry;
+use crate::text_layout::{layout_text, outline_glyph};
+use crate::types::ShapeType;
+
+// Type on a path: text laid out as a single line and flowed along the first
+// subpath of a path, each glyph turned to the path's direction at its centre.
+// The text keeps a copy of the path in its own space, refreshed from the
+// linked path object after every command, so it renders and exports on its
+// own and survives the path being deleted.
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PathTextAlign {
+    #[default]
+    Baseline,
+    Center, // Midway between ascender and descender
+    Ascender,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct TextOnPath {
+    #[serde(default)]
+    pub path_id: Option<u32>,
+    #[serde(default)]
+    pub path_data: String, // Local space; the text's box is the path's bounds
+    #[serde(default)]
+    pub start_offset: f64, // Along the path, in its units
+    #[serde(default)]
+    pub align: PathTextAlign,
+    #[serde(default)]
+    pub flip: bool, // Runs the other way, on the other side of the path
+}
+
+impl TextOnPath {
+    /// The subpath the text runs along, in the direction it runs.
+    pub fn guide(&self) -> Option<BezPath> {
+        let mut first = contours(&BezPath::from_svg(&self.path_data).ok()?).into_iter().next()?;
+        if self.flip { first.reverse(); }
+        Some(contours_to_path(&[first]))
+    }
+}
+
+/// Where a line of `width` starts along a guide of `length`: `text_align`
+/// places it at the start, middle or end, shifted by the start offset.
+pub fn start_along(obj: &VectorObject, tp: &TextOnPath, length: f64, width: f64) -> f64 {
+    tp.start_offset + match obj.text_align.as_str() {
+        "center" => (length - width) / 2.0,
+        "right" | "end" => length - width,
+        _ => 0.0,
+    }
+}
+
+/// One outline per visible glyph with its fill, placed along the path.
+/// Glyphs running off the end of an open path are dropped; on a closed path
+/// they wrap around.
+pub fn path_text_glyphs(obj: &VectorObject, fonts: &FontRegistry, tp: &TextOnPath) -> Result<Vec<(BezPath, Option<String>)>, String> {
+    let guide = tp.guide().ok_or("Text path has no geometry")?;
+    let closed = guide.elements().last() == Some(&kurbo::PathEl::ClosePath);
+    let measure = PathMeasure::new(&guide);
+    let length = measure.length();
+    // A single left-aligned line; line breaks become spaces of the same length
+    let mut line = obj.clone();
+    line.text_content = obj.text_content.replace('\n', " ");
+    line.area_text = false;
+    line.text_align = "left".to_string();
+    line.paragraphs.clear();
+    let layout = layout_text(&line, fonts)?;
+    let outline_fonts = layout.outline_fonts(fonts)?;
+    let rise = match tp.align {
+        PathTextAlign::Baseline => 0.0,
+        PathTextAlign::Center => (layout.ascent - layout.descent) / 2.0,
+        PathTextAlign::Ascender => layout.ascent,
+    };
+    let start = start_along(obj, tp, length, layout.width);
+    let mut glyphs = Vec::new();
+    for l in &layout.lines {
+        for g in &l.glyphs {
+            let mut centre = start + g.x + g.advance / 2.0;
+            if closed && length > 0.0 { centre = centre.rem_euclid(length); }
+            if !(0.0..=length).contains(&centre) { continue; }
+            let Some(sample) = measure.at_length(centre) else { continue };
+            let mut path = BezPath::new();
+            outline_glyph(&outline_fonts, g, Point::new(-g.advance / 2.0, rise + g.y - l.baseline), &mut path);
+            if path.elements().is_empty() { continue; }
+            path.apply_affine(Affine::translate(sample.point.to_vec2()) * Affine::rotate(sample.tangent.atan2()));
+            glyphs.push((path, layout.styles[g.style].fill.clone()));
+        }
+    }
+    Ok(glyphs)
+}
+
+/// Moves the text's box onto the world-space path and stores the path in it.
+fn attach(text: &mut VectorObject, path: &BezPath) {
+    let bbox = path.bounding_box();
+    let bbox = Rect::new(bbox.x0, bbox.y0, bbox.x0 + bbox.width().max(1.0), bbox.y0 + bbox.height().max(1.0));
+    text.x = bbox.x0;
+    text.y = bbox.y0;
+    text.width = bbox.width();
+    text.height = bbox.height();
+    text.rotation = 0.0;
+    if let Some(tp) = text.text_path.as_mut() {
+        tp.path_data = (Affine::translate((-bbox.x0, -bbox.y0)) * path.clone()).to_svg();
+    }
+}
+
+#[wasm_bindgen]
+impl VectorEngine {
+    /// Flows text along a path: the text `id` given, or a new text object
+    /// styled from the params. `start_offset`, `align` and `flip` set how it
+    /// sits on the path.
+    pub(crate) fn put_text_on_path(&mut self, path_id: u32, params: &serde_json::Value) -> String {
+        let Some(path_obj) = self.objects.iter().find(|o| o.id == path_id) else { return "{ \"error\": \"Path not found\" }".to_string() };
+        if matches!(path_obj.shape_type, ShapeType::Text | ShapeType::Group | ShapeType::Image) { return "{ \"error\": \"Text can only follow a path or shape\" }".to_string(); }
+        let Ok(path) = world_path(path_obj, &self.fonts) else { return "{ \"error\": \"Path has no geometry\" }".to_string() };
+        let align = match params.get("align").map(|v| serde_json::from_value::<PathTextAlign>(v.clone())).transpose() {
+            Ok(a) => a.unwrap_or_default(),
+            Err(e) => return serde_json::json!({ "error": format!("Invalid text path alignment: {}", e) }).to_string(),
+        };
+        let text_id = match params["id"].as_u64() {
+            Some(id) => {
+                let id = id as u32;
+                if !self.objects.iter().any(|o| o.id == id && o.shape_type == ShapeType::Text) { return "{ \"error\": \"Text not found\" }".to_string(); }
+                id
+            }
+            None => {
+                let id = self.add_object(ShapeType::Text, 0.0, 0.0, 1.0, 1.0, params["fill"].as_str().unwrap_or("#000000"));
+                self.update_object(id, params);
+                id
+            }
+        };
+        let Some(text) = self.objects.iter_mut().find(|o| o.id == text_id) else { return "{ \"error\": \"Text not found\" }".to_string() };
+        text.text_path = Some(TextOnPath {
+            path_id: Some(path_id),
+            path_data: String::new(),
+            start_offset: params["start_offset"].as_f64().unwrap_or(0.0),
+            align,
+            flip: params["flip"].as_bool().unwrap_or(false),
+        });
+        text.area_text = false;
+        attach(text, &path);
+        format!("{{\"success\": true, \"id\": {}}}", text_id)
+    }
+
+    /// Turns text on a path back into point text where it started.
+    pub(crate) fn release_text_path(&mut self, ids: &[u32]) -> String {
+        let mut released = Vec::new();
+        for obj in self.objects.iter_mut().filter(|o| ids.contains(&o.id)) {
+            if obj.text_path.take().is_some() {
+                crate::text_layout::fit_text_box(obj, &self.fonts);
+                released.push(obj.id);
+            }
+        }
+        if released.is_empty() { return "{ \"error\": \"No text on a path to release\" }".to_string(); }
+        serde_json::json!({ "success": true, "ids": released }).to_string()
+    }
+
+    /// Refreshes text on a path from the paths it follows, e.g. after one of
+    /// them was moved or edited.
+    pub(crate) fn sync_text_paths(&mut self) {
+        let updates: Vec<(usize, BezPath)> = self.objects.iter().enumerate().filter_map(|(i, o)| {
+            let path_id = o.text_path.as_ref()?.path_id?;
+            let path_obj = self.objects.iter().find(|p| p.id == path_id)?;
+            Some((i, world_path(path_obj, &self.fonts).ok()?))
+        }).collect();
+        for (i, path) in updates { attach(&mut self.objects[i], &path); }
+    }
+}
@@ -1,135 +1,977 @@
-use image::GrayImage;
-use std::collections::{HashMap, HashSet};
+use image::{GrayImage, RgbaImage};
+use kurbo::{BezPath, Point};
+
+// Bitmap tracer following the potrace pipeline: contours are decomposed on the
+// pixel-corner lattice, fitted with an optimal polygon, smoothed into bezier
+// segments and finally joined into longer curves where the error allows.
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TurnPolicy {
+    Black,
+    White,
+    Left,
+    Right,
+    Minority,
+    Majority,
+}
+
+impl TurnPolicy {
+    pub fn parse(name: &str) -> Self {
+        match name {
+            "black" => TurnPolicy::Black,
+            "white" => TurnPolicy::White,
+            "left" => TurnPolicy::Left,
+            "right" => TurnPolicy::Right,
+            "majority" => TurnPolicy::Majority,
+            _ => TurnPolicy::Minority,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TraceOptions {
+    pub turdsize: u32,          // Contours enclosing this many pixels or fewer are dropped
+    pub turn_policy: TurnPolicy,
+    pub alphamax: f64,          // Corner threshold: 0.0 = all corners, 4/3 = no corners
+    pub opticurve: bool,        // Join adjacent bezier segments where possible
+    pub opttolerance: f64,      // Max error allowed when joining segments
+}
+
+impl Default for TraceOptions {
+    fn default() -> Self {
+        TraceOptions {
+            turdsize: 2,
+            turn_policy: TurnPolicy::Minority,
+            alphamax: 1.0,
+            opticurve: true,
+            opttolerance: 0.2,
+        }
+    }
+}
+
+impl TraceOptions {
+    pub fn from_params(params: &serde_json::Value) -> Self {
+        let defaults = TraceOptions::default();
+        TraceOptions {
+            turdsize: params["turdsize"].as_u64().map(|v| v as u32).unwrap_or(defaults.turdsize),
+            turn_policy: params["turn_policy"].as_str().map(TurnPolicy::parse).unwrap_or(defaults.turn_policy),
+            alphamax: params["alphamax"].as_f64().unwrap_or(defaults.alphamax),
+            opticurve: params["opticurve"].as_bool().unwrap_or(defaults.opticurve),
+            opttolerance: params["opttolerance"].as_f64().unwrap_or(defaults.opttolerance),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Bitmap {
+    pub width: u32,
+    pub height: u32,
+    data: Vec<bool>,
+}
+
+impl Bitmap {
+    pub fn new(width: u32, height: u32) -> Self {
+        Bitmap { width, height, data: vec![false; (width * height) as usize] }
+    }
+
+    pub fn from_mask(width: u32, height: u32, data: Vec<bool>) -> Self {
+        debug_assert_eq!(data.len(), (width * height) as usize);
+        Bitmap { width, height, data }
+    }
+
+    /// Pixels darker than `threshold` become foreground.
+    pub fn from_gray(image: &GrayImage, threshold: u8) -> Self {
+        let (width, height) = image.dimensions();
+        let data = image.pixels().map(|p| p[0] < threshold).collect();
+        Bitmap { width, height, data }
+    }
+
+    /// Like `from_gray`, with transparent pixels composited over white so they count as background.
+    pub fn from_rgba(image: &RgbaImage, threshold: u8) -> Self {
+        let (width, height) = image.dimensions();
+        let data = image.pixels().map(|p| {
+            let a = p[3] as f64 / 255.0;
+            let luma = 0.299 * p[0] as f64 + 0.587 * p[1] as f64 + 0.114 * p[2] as f64;
+            ((luma * a + 255.0 * (1.0 - a)).round() as u8) < threshold
+        }).collect();
+        Bitmap { width, height, data }
+    }
+
+    /// Out-of-range coordinates read as background.
+    pub fn get(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height && self.data[(y as u32 * self.width + x as u32) as usize]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, value: bool) {
+        self.data[(y * self.width + x) as usize] = value;
+    }
+
+    fn flip(&mut self, x: i32, y: i32) {
+        if x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height {
+            let idx = (y as u32 * self.width + x as u32) as usize;
+            self.data[idx] = !self.data[idx];
+        }
+    }
+
+    pub fn data(&self) -> &[bool] {
+        &self.data
+    }
+}
+
+/// A closed traced contour. Outer contours wind opposite to holes, so the
+/// combined path fills correctly with either even-odd or non-zero rules.
+pub struct TracedPath {
+    pub is_outer: bool,
+    pub area: i64,
+    pub curve: BezPath,
+}
 
 pub struct Tracer {
     width: u32,
     height: u32,
+    options: TraceOptions,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct IPoint {
+    x: i64,
+    y: i64,
 }
 
-#[derive(Hash, Eq, PartialEq, Clone, Copy, Debug)]
-struct Point {
-    x: i32, // Fixed point math to avoid float precision issues during joining
-    y: i32,
+#[derive(Clone, Copy, Default)]
+struct Sum {
+    x: f64,
+    y: f64,
+    xy: f64,
+    x2: f64,
+    y2: f64,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Tag {
+    Curve,
+    Corner,
+}
+
+#[derive(Clone)]
+struct Curve {
+    tag: Vec<Tag>,
+    c: Vec<[Point; 3]>,
+    vertex: Vec<Point>,
+    alpha: Vec<f64>,
+}
+
+impl Curve {
+    fn new(n: usize) -> Self {
+        Curve {
+            tag: vec![Tag::Corner; n],
+            c: vec![[Point::ZERO; 3]; n],
+            vertex: vec![Point::ZERO; n],
+            alpha: vec![0.0; n],
+        }
+    }
+}
+
+struct Contour {
+    pt: Vec<IPoint>,
+    is_outer: bool,
+    area: i64,
+    max_x: i64,
+    sums: Vec<Sum>,
+    lon: Vec<usize>,
+    po: Vec<usize>,
+    curve: Curve,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Opti {
+    pen: f64,
+    c: [Point; 2],
+    s: f64,
+    alpha: f64,
 }
 
 impl Tracer {
     pub fn new(width: u32, height: u32) -> Self {
-        Self { width, height }
+        Self { width, height, options: TraceOptions::default() }
+    }
+
+    pub fn with_options(width: u32, height: u32, options: TraceOptions) -> Self {
+        Self { width, height, options }
     }
 
     pub fn trace(&self, image: &GrayImage, threshold: u8) -> String {
-        let mut segments = Vec::new();
-
-        // 1. Generate all segments using Marching Squares
-        for y in 0..self.height - 1 {
-            for x in 0..self.width - 1 {
-                let v00 = image.get_pixel(x, y)[0] < threshold;
-                let v10 = image.get_pixel(x + 1, y)[0] < threshold;
-                let v11 = image.get_pixel(x + 1, y + 1)[0] < threshold;
-                let v01 = image.get_pixel(x, y + 1)[0] < threshold;
-
-                let mut case_index = 0;
-                if v00 { case_index |= 1; }
-                if v10 { case_index |= 2; }
-                if v11 { case_index |= 4; }
-                if v01 { case_index |= 8; }
-
-                if case_index == 0 || case_index == 15 { continue; }
-
-                let cell_segments = match case_index {
-                    1 | 14 => vec![(0, 1, 1, 0)],
-                    2 | 13 => vec![(1, 0, 2, 1)],
-                    4 | 11 => vec![(2, 1, 1, 2)],
-                    8 | 7  => vec![(1, 2, 0, 1)],
-                    3 | 12 => vec![(0, 1, 2, 1)],
-                    6 | 9  => vec![(1, 0, 1, 2)],
-                    5 => vec![(0, 1, 1, 0), (2, 1, 1, 2)],
-                    10 => vec![(1, 0, 2, 1), (0, 1, 1, 2)],
-                    _ => vec![],
+        let mut bm = Bitmap::from_gray(image, threshold);
+        if bm.width != self.width || bm.height != self.height {
+            bm = self.crop_bitmap(&bm);
+        }
+        let path = self.trace_bitmap(&bm);
+        if path.is_empty() { String::new() } else { path.to_svg() }
+    }
+
+    /// Traces every contour of the bitmap into a single compound path.
+    pub fn trace_bitmap(&self, bm: &Bitmap) -> BezPath {
+        let mut result = BezPath::new();
+        for traced in self.trace_contours(bm) {
+            for el in traced.curve.elements() {
+                result.push(*el);
+            }
+        }
+        result
+    }
+
+    /// Traces every contour separately, in scanline order of their first pixel.
+    pub fn trace_contours(&self, bm: &Bitmap) -> Vec<TracedPath> {
+        let mut contours = self.decompose(bm);
+        let mut traced = Vec::with_capacity(contours.len());
+        for contour in contours.iter_mut() {
+            self.calc_sums(contour);
+            self.calc_lon(contour);
+            self.best_polygon(contour);
+            self.adjust_vertices(contour);
+            if !contour.is_outer {
+                contour.curve.vertex.reverse();
+            }
+            self.smooth(contour);
+            if self.options.opticurve {
+                self.opti_curve(contour);
+            }
+            traced.push(TracedPath { is_outer: contour.is_outer, area: contour.area, curve: self.curve_to_path(&contour.curve) });
+        }
+        traced
+    }
+
+    fn crop_bitmap(&self, bm: &Bitmap) -> Bitmap {
+        let mut out = Bitmap::new(self.width, self.height);
+        for y in 0..self.height.min(bm.height) {
+            for x in 0..self.width.min(bm.width) {
+                out.set(x, y, bm.get(x as i32, y as i32));
+            }
+        }
+        out
+    }
+
+    // --- Path decomposition ---
+
+    fn decompose(&self, bm: &Bitmap) -> Vec<Contour> {
+        let mut work = bm.clone();
+        let mut contours = Vec::new();
+        let size = work.data.len();
+        let mut idx = 0;
+        while idx < size {
+            if !work.data[idx] { idx += 1; continue; }
+            let x = (idx as u32 % work.width) as i64;
+            let y = (idx as u32 / work.width) as i64;
+            // The original bitmap tells us whether we entered a shape or a hole in one
+            let is_outer = bm.get(x as i32, y as i32);
+            let contour = self.find_path(&work, x, y, is_outer);
+            Self::xor_path(&mut work, &contour);
+            if contour.area > self.options.turdsize as i64 {
+                contours.push(contour);
+            }
+        }
+        contours
+    }
+
+    fn find_path(&self, bm: &Bitmap, x0: i64, y0: i64, is_outer: bool) -> Contour {
+        let mut pt = Vec::new();
+        let (mut x, mut y) = (x0, y0);
+        let (mut dirx, mut diry) = (0i64, 1i64);
+        let mut area = 0i64;
+        let mut max_x = x0;
+        loop {
+            pt.push(IPoint { x, y });
+            max_x = max_x.max(x);
+            x += dirx;
+            y += diry;
+            area -= x * diry;
+            if x == x0 && y == y0 { break; }
+
+            let l = bm.get((x + (dirx + diry - 1) / 2) as i32, (y + (diry - dirx - 1) / 2) as i32);
+            let r = bm.get((x + (dirx - diry - 1) / 2) as i32, (y + (diry + dirx - 1) / 2) as i32);
+            if r && !l {
+                let turn_right = match self.options.turn_policy {
+                    TurnPolicy::Right => true,
+                    TurnPolicy::Left => false,
+                    TurnPolicy::Black => is_outer,
+                    TurnPolicy::White => !is_outer,
+                    TurnPolicy::Majority => Self::majority(bm, x, y),
+                    TurnPolicy::Minority => !Self::majority(bm, x, y),
                 };
+                let tmp = dirx;
+                if turn_right { dirx = -diry; diry = tmp; } else { dirx = diry; diry = -tmp; }
+            } else if r {
+                let tmp = dirx; dirx = -diry; diry = tmp;
+            } else if !l {
+                let tmp = dirx; dirx = diry; diry = -tmp;
+            }
+        }
+        Contour { pt, is_outer, area, max_x, sums: Vec::new(), lon: Vec::new(), po: Vec::new(), curve: Curve::new(0) }
+    }
+
+    fn majority(bm: &Bitmap, x: i64, y: i64) -> bool {
+        for i in 2..5i64 {
+            let mut ct = 0;
+            for a in (-i + 1)..=(i - 1) {
+                ct += if bm.get((x + a) as i32, (y + i - 1) as i32) { 1 } else { -1 };
+                ct += if bm.get((x + i - 1) as i32, (y + a - 1) as i32) { 1 } else { -1 };
+                ct += if bm.get((x + a - 1) as i32, (y - i) as i32) { 1 } else { -1 };
+                ct += if bm.get((x - i) as i32, (y + a) as i32) { 1 } else { -1 };
+            }
+            if ct > 0 { return true; }
+            if ct < 0 { return false; }
+        }
+        false
+    }
 
-                for (sx, sy, ex, ey) in cell_segments {
-                    let p1 = Point { x: (x as i32 * 2) + sx, y: (y as i32 * 2) + sy };
-                    let p2 = Point { x: (x as i32 * 2) + ex, y: (y as i32 * 2) + ey };
-                    segments.push((p1, p2));
+    // Inverts the interior of the contour so nested holes and islands are found next
+    fn xor_path(bm: &mut Bitmap, contour: &Contour) {
+        let mut y1 = contour.pt[0].y;
+        for p in contour.pt.iter().skip(1) {
+            if p.y != y1 {
+                let min_y = y1.min(p.y);
+                for j in p.x..contour.max_x {
+                    bm.flip(j as i32, min_y as i32);
                 }
+                y1 = p.y;
             }
         }
+    }
 
-        // 2. Join segments into paths
-        let mut adj = HashMap::new();
-        for (p1, p2) in segments {
-            adj.entry(p1).or_insert_with(Vec::new).push(p2);
-            adj.entry(p2).or_insert_with(Vec::new).push(p1);
+    // --- Optimal polygon ---
+
+    fn calc_sums(&self, c: &mut Contour) {
+        let x0 = c.pt[0].x;
+        let y0 = c.pt[0].y;
+        let mut sums = Vec::with_capacity(c.pt.len() + 1);
+        let mut acc = Sum::default();
+        sums.push(acc);
+        for p in &c.pt {
+            let x = (p.x - x0) as f64;
+            let y = (p.y - y0) as f64;
+            acc = Sum { x: acc.x + x, y: acc.y + y, xy: acc.xy + x * y, x2: acc.x2 + x * x, y2: acc.y2 + y * y };
+            sums.push(acc);
         }
+        c.sums = sums;
+    }
 
-        let mut path_data = String::new();
-        let mut visited = HashSet::new();
+    fn calc_lon(&self, c: &mut Contour) {
+        let n = c.pt.len();
+        let pt = &c.pt;
+        let mut pivk = vec![0usize; n];
+        let mut nc = vec![0usize; n];
 
-        for start_node in adj.keys() {
-            if visited.contains(start_node) { continue; }
+        // nc[i] is the next corner after i
+        let mut k = 0usize;
+        for i in (0..n).rev() {
+            if pt[i].x != pt[k].x && pt[i].y != pt[k].y {
+                k = i + 1;
+            }
+            nc[i] = k;
+        }
 
-            let mut path = Vec::new();
-            let mut current = *start_node;
-            
-            // Find an endpoint for open paths, or just start if closed
-            let mut start = *start_node;
-            if adj.get(&start).unwrap().len() == 1 {
-                 // Already at an endpoint
-            } else {
-                // Try to find an endpoint nearby
-                let mut temp_visited = HashSet::new();
-                let mut stack = vec![start];
-                while let Some(node) = stack.pop() {
-                    if temp_visited.contains(&node) { continue; }
-                    temp_visited.insert(node);
-                    if adj.get(&node).unwrap().len() == 1 {
-                        start = node;
-                        break;
+        for i in (0..n).rev() {
+            let mut ct = [0u32; 4];
+            let next = pt[modi(i as i64 + 1, n)];
+            let dir = (3 + 3 * (next.x - pt[i].x) + (next.y - pt[i].y)) / 2;
+            ct[dir as usize] += 1;
+
+            let mut constraint = [IPoint { x: 0, y: 0 }, IPoint { x: 0, y: 0 }];
+            let mut k = nc[i];
+            let mut k1 = i;
+            let mut found = false;
+            loop {
+                let dir = (3 + 3 * sign(pt[k].x - pt[k1].x) + sign(pt[k].y - pt[k1].y)) / 2;
+                ct[dir as usize] += 1;
+                if ct.iter().all(|&v| v > 0) {
+                    pivk[i] = k1;
+                    found = true;
+                    break;
+                }
+                let cur = IPoint { x: pt[k].x - pt[i].x, y: pt[k].y - pt[i].y };
+                if ixprod(constraint[0], cur) < 0 || ixprod(constraint[1], cur) > 0 {
+                    break;
+                }
+                if cur.x.abs() > 1 || cur.y.abs() > 1 {
+                    let off = IPoint {
+                        x: cur.x + if cur.y >= 0 && (cur.y > 0 || cur.x < 0) { 1 } else { -1 },
+                        y: cur.y + if cur.x <= 0 && (cur.x < 0 || cur.y < 0) { 1 } else { -1 },
+                    };
+                    if ixprod(constraint[0], off) >= 0 { constraint[0] = off; }
+                    let off = IPoint {
+                        x: cur.x + if cur.y <= 0 && (cur.y < 0 || cur.x < 0) { 1 } else { -1 },
+                        y: cur.y + if cur.x >= 0 && (cur.x > 0 || cur.y < 0) { 1 } else { -1 },
+                    };
+                    if ixprod(constraint[1], off) <= 0 { constraint[1] = off; }
+                }
+                k1 = k;
+                k = nc[k1];
+                if !cyclic(k, i, k1) { break; }
+            }
+            if !found {
+                let dk = IPoint { x: sign(pt[k].x - pt[k1].x), y: sign(pt[k].y - pt[k1].y) };
+                let cur = IPoint { x: pt[k1].x - pt[i].x, y: pt[k1].y - pt[i].y };
+                let a = ixprod(constraint[0], cur);
+                let b = ixprod(constraint[0], dk);
+                let c2 = ixprod(constraint[1], cur);
+                let d = ixprod(constraint[1], dk);
+                let mut j = 10_000_000i64;
+                if b < 0 { j = a.div_euclid(-b); }
+                if d > 0 { j = j.min((-c2).div_euclid(d)); }
+                pivk[i] = modi(k1 as i64 + j, n);
+            }
+        }
+
+        let mut lon = vec![0usize; n];
+        let mut j = pivk[n - 1];
+        lon[n - 1] = j;
+        for i in (0..n - 1).rev() {
+            if cyclic(i + 1, pivk[i], j) { j = pivk[i]; }
+            lon[i] = j;
+        }
+        let mut i = n - 1;
+        while cyclic(modi(i as i64 + 1, n), j, lon[i]) {
+            lon[i] = j;
+            if i == 0 { break; }
+            i -= 1;
+        }
+        c.lon = lon;
+    }
+
+    fn penalty3(c: &Contour, i: usize, j: usize) -> f64 {
+        let n = c.pt.len();
+        let sums = &c.sums;
+        let pt = &c.pt;
+        let (j, wrapped) = if j >= n { (j - n, true) } else { (j, false) };
+        let (x, y, x2, xy, y2, k) = if !wrapped {
+            (
+                sums[j + 1].x - sums[i].x,
+                sums[j + 1].y - sums[i].y,
+                sums[j + 1].x2 - sums[i].x2,
+                sums[j + 1].xy - sums[i].xy,
+                sums[j + 1].y2 - sums[i].y2,
+                (j + 1 - i) as f64,
+            )
+        } else {
+            (
+                sums[j + 1].x - sums[i].x + sums[n].x,
+                sums[j + 1].y - sums[i].y + sums[n].y,
+                sums[j + 1].x2 - sums[i].x2 + sums[n].x2,
+                sums[j + 1].xy - sums[i].xy + sums[n].xy,
+                sums[j + 1].y2 - sums[i].y2 + sums[n].y2,
+                (j + 1 + n - i) as f64,
+            )
+        };
+        let px = (pt[i].x + pt[j].x) as f64 / 2.0 - pt[0].x as f64;
+        let py = (pt[i].y + pt[j].y) as f64 / 2.0 - pt[0].y as f64;
+        let ey = (pt[j].x - pt[i].x) as f64;
+        let ex = -((pt[j].y - pt[i].y) as f64);
+        let a = (x2 - 2.0 * x * px) / k + px * px;
+        let b = (xy - x * py - y * px) / k + px * py;
+        let cc = (y2 - 2.0 * y * py) / k + py * py;
+        let s = ex * ex * a + 2.0 * ex * ey * b + ey * ey * cc;
+        s.max(0.0).sqrt()
+    }
+
+    fn best_polygon(&self, c: &mut Contour) {
+        let n = c.pt.len();
+        let mut pen = vec![0.0f64; n + 1];
+        let mut prev = vec![0usize; n + 1];
+        let mut clip0 = vec![0usize; n];
+        let mut clip1 = vec![0usize; n + 1];
+        let mut seg0 = vec![0usize; n + 1];
+        let mut seg1 = vec![0usize; n + 1];
+
+        for (i, clip) in clip0.iter_mut().enumerate() {
+            let mut cl = modi(c.lon[modi(i as i64 - 1, n)] as i64 - 1, n);
+            if cl == i { cl = modi(i as i64 + 1, n); }
+            *clip = if cl < i { n } else { cl };
+        }
+
+        let mut j = 1;
+        for (i, &cl) in clip0.iter().enumerate() {
+            while j <= cl {
+                clip1[j] = i;
+                j += 1;
+            }
+        }
+
+        let mut i = 0;
+        let mut j = 0;
+        while i < n {
+            seg0[j] = i;
+            i = clip0[i];
+            j += 1;
+        }
+        seg0[j] = n;
+        let m = j;
+
+        let mut i = n;
+        for j in (1..=m).rev() {
+            seg1[j] = i;
+            i = clip1[i];
+        }
+        seg1[0] = 0;
+
+        pen[0] = 0.0;
+        for j in 1..=m {
+            for i in seg1[j]..=seg0[j] {
+                let mut best = -1.0;
+                let mut k = seg0[j - 1] as i64;
+                while k >= clip1[i] as i64 {
+                    let this_pen = Self::penalty3(c, k as usize, i) + pen[k as usize];
+                    if best < 0.0 || this_pen < best {
+                        prev[i] = k as usize;
+                        best = this_pen;
                     }
-                    for next in adj.get(&node).unwrap() {
-                        stack.push(*next);
+                    k -= 1;
+                }
+                pen[i] = best;
+            }
+        }
+
+        let mut po = vec![0usize; m];
+        let mut i = n;
+        for j in (0..m).rev() {
+            i = prev[i];
+            po[j] = i;
+        }
+        c.po = po;
+    }
+
+    // --- Vertex adjustment ---
+
+    fn point_slope(c: &Contour, i: i64, j: i64) -> (Point, Point) {
+        let n = c.pt.len() as i64;
+        let sums = &c.sums;
+        let (mut i, mut j, mut r) = (i, j, 0i64);
+        while j >= n { j -= n; r += 1; }
+        while i >= n { i -= n; r -= 1; }
+        while j < 0 { j += n; r -= 1; }
+        while i < 0 { i += n; r += 1; }
+        let (iu, ju, nu, rf) = (i as usize, j as usize, n as usize, r as f64);
+
+        let x = sums[ju + 1].x - sums[iu].x + rf * sums[nu].x;
+        let y = sums[ju + 1].y - sums[iu].y + rf * sums[nu].y;
+        let x2 = sums[ju + 1].x2 - sums[iu].x2 + rf * sums[nu].x2;
+        let xy = sums[ju + 1].xy - sums[iu].xy + rf * sums[nu].xy;
+        let y2 = sums[ju + 1].y2 - sums[iu].y2 + rf * sums[nu].y2;
+        let k = (j + 1 - i + r * n) as f64;
+
+        let ctr = Point::new(x / k, y / k);
+        let mut a = (x2 - x * x / k) / k;
+        let b = (xy - x * y / k) / k;
+        let mut cc = (y2 - y * y / k) / k;
+
+        // Principal axis of the covariance matrix
+        let lambda2 = (a + cc + ((a - cc) * (a - cc) + 4.0 * b * b).sqrt()) / 2.0;
+        a -= lambda2;
+        cc -= lambda2;
+        let dir = if a.abs() >= cc.abs() {
+            let l = (a * a + b * b).sqrt();
+            if l != 0.0 { Point::new(-b / l, a / l) } else { Point::ZERO }
+        } else {
+            let l = (cc * cc + b * b).sqrt();
+            if l != 0.0 { Point::new(-cc / l, b / l) } else { Point::ZERO }
+        };
+        (ctr, dir)
+    }
+
+    fn adjust_vertices(&self, c: &mut Contour) {
+        let m = c.po.len();
+        let n = c.pt.len();
+        let x0 = c.pt[0].x as f64;
+        let y0 = c.pt[0].y as f64;
+
+        let mut q = vec![[[0.0f64; 3]; 3]; m];
+        for (i, qi) in q.iter_mut().enumerate() {
+            let j = c.po[modi(i as i64 + 1, m)];
+            let j = modi(j as i64 - c.po[i] as i64, n) + c.po[i];
+            let (ctr, dir) = Self::point_slope(c, c.po[i] as i64, j as i64);
+            let d = dir.x * dir.x + dir.y * dir.y;
+            if d != 0.0 {
+                let v = [dir.y, -dir.x, dir.x * ctr.y - dir.y * ctr.x];
+                for l in 0..3 {
+                    for k in 0..3 {
+                        qi[l][k] = v[l] * v[k] / d;
                     }
                 }
             }
+        }
+
+        let mut curve = Curve::new(m);
+        for i in 0..m {
+            let s = Point::new(c.pt[c.po[i]].x as f64 - x0, c.pt[c.po[i]].y as f64 - y0);
+            let j = modi(i as i64 - 1, m);
+            let mut qm = [[0.0f64; 3]; 3];
+            for l in 0..3 {
+                for k in 0..3 {
+                    qm[l][k] = q[j][l][k] + q[i][l][k];
+                }
+            }
 
-            current = start;
-            loop {
-                visited.insert(current);
-                path.push(current);
-                
-                let next_options: Vec<_> = adj.get(&current).unwrap()
-                    .iter()
-                    .filter(|p| !visited.contains(*p))
-                    .collect();
-
-                if next_options.is_empty() {
-                    // Check if we can close the loop
-                    let can_close = adj.get(&current).unwrap().iter().any(|p| *p == start && path.len() > 2);
-                    if can_close {
-                        path_data.push_str(&self.format_path(&path, true));
-                    } else {
-                        path_data.push_str(&self.format_path(&path, false));
+            // Minimise the quadratic form; if singular, add a constraint through s
+            let w = loop {
+                let det = qm[0][0] * qm[1][1] - qm[0][1] * qm[1][0];
+                if det != 0.0 {
+                    break Point::new(
+                        (-qm[0][2] * qm[1][1] + qm[1][2] * qm[0][1]) / det,
+                        (qm[0][2] * qm[1][0] - qm[1][2] * qm[0][0]) / det,
+                    );
+                }
+                let (v0, v1) = if qm[0][0] > qm[1][1] {
+                    (-qm[0][1], qm[0][0])
+                } else if qm[1][1] != 0.0 {
+                    (-qm[1][1], qm[1][0])
+                } else {
+                    (1.0, 0.0)
+                };
+                let d = v0 * v0 + v1 * v1;
+                let v = [v0, v1, -v1 * s.y - v0 * s.x];
+                for l in 0..3 {
+                    for k in 0..3 {
+                        qm[l][k] += v[l] * v[k] / d;
                     }
-                    break;
                 }
-                current = *next_options[0];
+            };
+
+            if (w.x - s.x).abs() <= 0.5 && (w.y - s.y).abs() <= 0.5 {
+                curve.vertex[i] = Point::new(w.x + x0, w.y + y0);
+                continue;
+            }
+
+            // Otherwise the optimum lies on the boundary of the unit square around s
+            let mut min = quadform(&qm, s);
+            let mut best = s;
+            if qm[0][0] != 0.0 {
+                for z in 0..2 {
+                    let wy = s.y - 0.5 + z as f64;
+                    let wx = -(qm[0][1] * wy + qm[0][2]) / qm[0][0];
+                    let cand = quadform(&qm, Point::new(wx, wy));
+                    if (wx - s.x).abs() <= 0.5 && cand < min { min = cand; best = Point::new(wx, wy); }
+                }
+            }
+            if qm[1][1] != 0.0 {
+                for z in 0..2 {
+                    let wx = s.x - 0.5 + z as f64;
+                    let wy = -(qm[1][0] * wx + qm[1][2]) / qm[1][1];
+                    let cand = quadform(&qm, Point::new(wx, wy));
+                    if (wy - s.y).abs() <= 0.5 && cand < min { min = cand; best = Point::new(wx, wy); }
+                }
+            }
+            for l in 0..2 {
+                for k in 0..2 {
+                    let cw = Point::new(s.x - 0.5 + l as f64, s.y - 0.5 + k as f64);
+                    let cand = quadform(&qm, cw);
+                    if cand < min { min = cand; best = cw; }
+                }
             }
+            curve.vertex[i] = Point::new(best.x + x0, best.y + y0);
         }
+        c.curve = curve;
+    }
+
+    // --- Smoothing ---
+
+    fn smooth(&self, c: &mut Contour) {
+        let curve = &mut c.curve;
+        let m = curve.vertex.len();
+        for i in 0..m {
+            let j = modi(i as i64 + 1, m);
+            let k = modi(i as i64 + 2, m);
+            let p4 = interval(0.5, curve.vertex[k], curve.vertex[j]);
 
-        path_data
+            let denom = ddenom(curve.vertex[i], curve.vertex[k]);
+            let mut alpha = if denom != 0.0 {
+                let dd = (dpara(curve.vertex[i], curve.vertex[j], curve.vertex[k]) / denom).abs();
+                let a = if dd > 1.0 { 1.0 - 1.0 / dd } else { 0.0 };
+                a / 0.75
+            } else {
+                4.0 / 3.0
+            };
+
+            if alpha >= self.options.alphamax {
+                curve.tag[j] = Tag::Corner;
+                curve.c[j] = [Point::ZERO, curve.vertex[j], p4];
+            } else {
+                alpha = alpha.clamp(0.55, 1.0);
+                let p2 = interval(0.5 + 0.5 * alpha, curve.vertex[i], curve.vertex[j]);
+                let p3 = interval(0.5 + 0.5 * alpha, curve.vertex[k], curve.vertex[j]);
+                curve.tag[j] = Tag::Curve;
+                curve.c[j] = [p2, p3, p4];
+            }
+            curve.alpha[j] = alpha;
+        }
     }
 
-    fn format_path(&self, points: &[Point], closed: bool) -> String {
-        if points.is_empty() { return String::new(); }
-        
-        let mut d = format!("M {} {} ", points[0].x as f64 / 2.0, points[0].y as f64 / 2.0);
-        for i in 1..points.len() {
-            d.push_str(&format!("L {} {} ", points[i].x as f64 / 2.0, points[i].y as f64 / 2.0));
+    // --- Curve optimisation ---
+
+    fn opti_curve(&self, c: &mut Contour) {
+        let curve = &c.curve;
+        let m = curve.vertex.len();
+        if m == 0 { return; }
+        let vert = &curve.vertex;
+
+        let mut convc = vec![0i64; m];
+        for i in 0..m {
+            if curve.tag[i] == Tag::Curve {
+                convc[i] = sign_f(dpara(vert[modi(i as i64 - 1, m)], vert[i], vert[modi(i as i64 + 1, m)]));
+            }
+        }
+
+        let mut areac = vec![0.0f64; m + 1];
+        let mut area = 0.0;
+        let p0 = vert[0];
+        for i in 0..m {
+            let i1 = modi(i as i64 + 1, m);
+            if curve.tag[i1] == Tag::Curve {
+                let alpha = curve.alpha[i1];
+                area += 0.3 * alpha * (4.0 - alpha) * dpara(curve.c[i][2], vert[i1], curve.c[i1][2]) / 2.0;
+                area += dpara(p0, curve.c[i][2], curve.c[i1][2]) / 2.0;
+            }
+            areac[i + 1] = area;
         }
-        if closed {
-            d.push_str("Z ");
+
+        let mut pt = vec![0i64; m + 1];
+        let mut pen = vec![0.0f64; m + 1];
+        let mut len = vec![0usize; m + 1];
+        let mut opt = vec![Opti::default(); m + 1];
+        pt[0] = -1;
+        for j in 1..=m {
+            pt[j] = j as i64 - 1;
+            pen[j] = pen[j - 1];
+            len[j] = len[j - 1] + 1;
+            let mut i = j as i64 - 2;
+            while i >= 0 {
+                let iu = i as usize;
+                let o = match self.opti_penalty(curve, iu, modi(j as i64, m), &convc, &areac) {
+                    Some(o) => o,
+                    None => break,
+                };
+                if len[j] > len[iu] + 1 || (len[j] == len[iu] + 1 && pen[j] > pen[iu] + o.pen) {
+                    pt[j] = i;
+                    pen[j] = pen[iu] + o.pen;
+                    len[j] = len[iu] + 1;
+                    opt[j] = o;
+                }
+                i -= 1;
+            }
         }
-        d
+
+        let om = len[m];
+        let mut ocurve = Curve::new(om);
+        let mut j = m;
+        for i in (0..om).rev() {
+            let jm = modi(j as i64, m);
+            if pt[j] == j as i64 - 1 {
+                ocurve.tag[i] = curve.tag[jm];
+                ocurve.c[i] = curve.c[jm];
+                ocurve.vertex[i] = curve.vertex[jm];
+                ocurve.alpha[i] = curve.alpha[jm];
+            } else {
+                ocurve.tag[i] = Tag::Curve;
+                ocurve.c[i] = [opt[j].c[0], opt[j].c[1], curve.c[jm][2]];
+                ocurve.vertex[i] = interval(opt[j].s, curve.c[jm][2], vert[jm]);
+                ocurve.alpha[i] = opt[j].alpha;
+            }
+            j = pt[j] as usize;
+        }
+        c.curve = ocurve;
+    }
+
+    // Returns None if segments i..j cannot be merged into one bezier
+    fn opti_penalty(&self, curve: &Curve, i: usize, j: usize, convc: &[i64], areac: &[f64]) -> Option<Opti> {
+        let m = curve.vertex.len();
+        let vertex = &curve.vertex;
+        if i == j { return None; }
+
+        let i1 = modi(i as i64 + 1, m);
+        let conv = convc[i1];
+        if conv == 0 { return None; }
+        let d = ddist(vertex[i], vertex[i1]);
+        let mut k = i1;
+        while k != j {
+            let k1 = modi(k as i64 + 1, m);
+            let k2 = modi(k as i64 + 2, m);
+            if convc[k1] != conv { return None; }
+            if sign_f(cprod(vertex[i], vertex[i1], vertex[k1], vertex[k2])) != conv { return None; }
+            if iprod1(vertex[i], vertex[i1], vertex[k1], vertex[k2]) < d * ddist(vertex[k1], vertex[k2]) * -0.999847695156 { return None; }
+            k = k1;
+        }
+
+        let p0 = curve.c[i][2];
+        let mut p1 = vertex[i1];
+        let mut p2 = vertex[j];
+        let p3 = curve.c[j][2];
+
+        let mut area = areac[j] - areac[i];
+        area -= dpara(vertex[0], curve.c[i][2], curve.c[j][2]) / 2.0;
+        if i >= j { area += areac[m]; }
+
+        let a1 = dpara(p0, p1, p2);
+        let a2 = dpara(p0, p1, p3);
+        let a3 = dpara(p0, p2, p3);
+        let a4 = a1 + a3 - a2;
+        if a2 == a1 { return None; }
+
+        let t = a3 / (a3 - a4);
+        let s = a2 / (a2 - a1);
+        let a = a2 * t / 2.0;
+        if a == 0.0 { return None; }
+        let r = area / a;
+        let disc = 4.0 - r / 0.3;
+        if disc < 0.0 { return None; }
+        let alpha = 2.0 - disc.sqrt();
+
+        let mut res = Opti {
+            pen: 0.0,
+            c: [interval(t * alpha, p0, p1), interval(s * alpha, p3, p2)],
+            s,
+            alpha,
+        };
+        p1 = res.c[0];
+        p2 = res.c[1];
+
+        // Vertices must lie close to the new curve
+        let mut k = i1;
+        while k != j {
+            let k1 = modi(k as i64 + 1, m);
+            let tt = tangent(p0, p1, p2, p3, vertex[k], vertex[k1]);
+            if tt < -0.5 { return None; }
+            let pt = bezier(tt, p0, p1, p2, p3);
+            let d = ddist(vertex[k], vertex[k1]);
+            if d == 0.0 { return None; }
+            let d1 = dpara(vertex[k], vertex[k1], pt) / d;
+            if d1.abs() > self.options.opttolerance { return None; }
+            if iprod(vertex[k], vertex[k1], pt) < 0.0 || iprod(vertex[k1], vertex[k], pt) < 0.0 { return None; }
+            res.pen += d1 * d1;
+            k = k1;
+        }
+
+        // And so must the edge midpoints
+        let mut k = i;
+        while k != j {
+            let k1 = modi(k as i64 + 1, m);
+            let tt = tangent(p0, p1, p2, p3, curve.c[k][2], curve.c[k1][2]);
+            if tt < -0.5 { return None; }
+            let pt = bezier(tt, p0, p1, p2, p3);
+            let d = ddist(curve.c[k][2], curve.c[k1][2]);
+            if d == 0.0 { return None; }
+            let mut d1 = dpara(curve.c[k][2], curve.c[k1][2], pt) / d;
+            let mut d2 = dpara(curve.c[k][2], curve.c[k1][2], vertex[k1]) / d;
+            d2 *= 0.75 * curve.alpha[k1];
+            if d2 < 0.0 { d1 = -d1; d2 = -d2; }
+            if d1 < d2 - self.options.opttolerance { return None; }
+            if d1 < d2 { res.pen += (d1 - d2) * (d1 - d2); }
+            k = k1;
+        }
+
+        Some(res)
+    }
+
+    fn curve_to_path(&self, curve: &Curve) -> BezPath {
+        let mut path = BezPath::new();
+        let m = curve.vertex.len();
+        if m == 0 { return path; }
+        path.move_to(curve.c[m - 1][2]);
+        for i in 0..m {
+            match curve.tag[i] {
+                Tag::Curve => path.curve_to(curve.c[i][0], curve.c[i][1], curve.c[i][2]),
+                Tag::Corner => {
+                    path.line_to(curve.c[i][1]);
+                    path.line_to(curve.c[i][2]);
+                }
+            }
+        }
+        path.close_path();
+        path
     }
-}
\ No newline at end of file
+}
+
+// --- Geometry helpers ---
+
+fn modi(a: i64, n: usize) -> usize {
+    a.rem_euclid(n as i64) as usize
+}
+
+fn cyclic(a: usize, b: usize, c: usize) -> bool {
+    if a <= c { a <= b && b < c } else { a <= b || b < c }
+}
+
+fn sign(x: i64) -> i64 {
+    x.signum()
+}
+
+fn sign_f(x: f64) -> i64 {
+    if x > 0.0 { 1 } else if x < 0.0 { -1 } else { 0 }
+}
+
+fn ixprod(p1: IPoint, p2: IPoint) -> i64 {
+    p1.x * p2.y - p1.y * p2.x
+}
+
+fn quadform(q: &[[f64; 3]; 3], w: Point) -> f64 {
+    let v = [w.x, w.y, 1.0];
+    let mut sum = 0.0;
+    for i in 0..3 {
+        for j in 0..3 {
+            sum += v[i] * q[i][j] * v[j];
+        }
+    }
+    sum
+}
+
+fn interval(lambda: f64, a: Point, b: Point) -> Point {
+    Point::new(a.x + lambda * (b.x - a.x), a.y + lambda * (b.y - a.y))
+}
+
+fn ddenom(p0: Point, p2: Point) -> f64 {
+    let rx = -sign_f(p2.y - p0.y) as f64;
+    let ry = sign_f(p2.x - p0.x) as f64;
+    ry * (p2.x - p0.x) - rx * (p2.y - p0.y)
+}
+
+fn dpara(p0: Point, p1: Point, p2: Point) -> f64 {
+    (p1.x - p0.x) * (p2.y - p0.y) - (p2.x - p0.x) * (p1.y - p0.y)
+}
+
+fn cprod(p0: Point, p1: Point, p2: Point, p3: Point) -> f64 {
+    (p1.x - p0.x) * (p3.y - p2.y) - (p3.x - p2.x) * (p1.y - p0.y)
+}
+
+fn iprod(p0: Point, p1: Point, p2: Point) -> f64 {
+    (p1.x - p0.x) * (p2.x - p0.x) + (p1.y - p0.y) * (p2.y - p0.y)
+}
+
+fn iprod1(p0: Point, p1: Point, p2: Point, p3: Point) -> f64 {
+    (p1.x - p0.x) * (p3.x - p2.x) + (p1.y - p0.y) * (p3.y - p2.y)
+}
+
+fn ddist(p: Point, q: Point) -> f64 {
+    p.distance(q)
+}
+
+fn bezier(t: f64, p0: Point, p1: Point, p2: Point, p3: Point) -> Point {
+    let s = 1.0 - t;
+    let a = s * s * s;
+    let b = 3.0 * s * s * t;
+    let c = 3.0 * t * t * s;
+    let d = t * t * t;
+    Point::new(a * p0.x + b * p1.x + c * p2.x + d * p3.x, a * p0.y + b * p1.y + c * p2.y + d * p3.y)
+}
+
+// Parameter t where the curve is parallel to q0-q1, or -1 if there is none
+fn tangent(p0: Point, p1: Point, p2: Point, p3: Point, q0: Point, q1: Point) -> f64 {
+    let a = cprod(p0, p1, q0, q1);
+    let b = cprod(p1, p2, q0, q1);
+    let c = cprod(p2, p3, q0, q1);
+    let qa = a - 2.0 * b + c;
+    let qb = -2.0 * a + 2.0 * b;
+    let qc = a;
+    let d = qb * qb - 4.0 * qa * qc;
+    if qa == 0.0 || d < 0.0 { return -1.0; }
+    let s = d.sqrt();
+    let r1 = (-qb + s) / (2.0 * qa);
+    let r2 = (-qb - s) / (2.0 * qa);
+    if (0.0..=1.0).contains(&r1) { r1 } else if (0.0..=1.0).contains(&r2) { r2 } else { -1.0 }
+}
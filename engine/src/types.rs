@@ -74,6 +74,15 @@ pub struct LayerStyle {
     pub effects: Vec<LayerEffect>,
 }
 
+/// Settings of a blend group. The group's first and last children are the
+/// blended objects, everything in between is generated.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BlendSpec {
+    pub steps: u32,
+    #[serde(default)]
+    pub spine: String, // Path data in group space, empty for a straight blend
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Guide {
     pub orientation: String, // "horizontal" or "vertical"
@@ -1,16 +1,120 @@
-use kurbo::{Point, BezPath, PathEl, Shape, Affine};
+use std::collections::{HashMap, HashSet};
+use kurbo::{Point, BezPath, CubicBez, ParamCurve, PathEl, QuadBez, Rect, Shape, Affine, Vec2};
 use serde::{Serialize, Deserialize};
+use base64::{Engine as _, engine::general_purpose};
 use wasm_bindgen::prelude::*;
+use crate::geometry::{object_path, object_transform, set_local_path, world_path};
+use crate::text::FontRegistry;
 use crate::objects::VectorObject;
+use crate::raster_warp::{warp_image, Interpolation};
 use crate::types::ShapeType;
 use crate::engine::VectorEngine;
 
+// Envelope distortion. Each cell of the mesh is a Coons patch bounded by four
+// cubic curves, whose inner control points are the tangent handles of the
+// mesh points. Paths are mapped segment by segment: the warped segment is
+// fitted with a cubic from the warped end points and tangents, and split in
+// half until the fit is within tolerance.
+
+const LEFT: usize = 0;
+const RIGHT: usize = 1;
+const UP: usize = 2;
+const DOWN: usize = 3;
+const TOLERANCE: f64 = 0.1;
+const MAX_DEPTH: u32 = 10;
+const PRESET_CELLS: usize = 8;
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct WarpMesh {
     pub rows: usize,
     pub cols: usize,
     pub control_points: Vec<Point>, // Flattened 2D array of points (rows+1) * (cols+1)
     pub original_bounds: (f64, f64, f64, f64), // x, y, width, height
+    // Tangent handles per control point as offsets: left, right, up, down.
+    // Points without handles get straight edges towards their neighbours.
+    #[serde(default)]
+    pub handles: Vec<[Vec2; 4]>,
+    #[serde(default)]
+    pub interpolation: String, // Image resampling: "bilinear" (default) or "bicubic"
+}
+
+/// An envelope applied to an object. The undistorted object is kept so the
+/// mesh can be edited again or the envelope released, along with where the
+/// warp left the object so later moves and turns carry over to it.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Envelope {
+    pub mesh: WarpMesh,
+    pub original: VectorObject,
+    pub centre: Point,
+    pub rotation: f64,
+}
+
+impl Envelope {
+    fn new(mesh: &WarpMesh, original: VectorObject, warped: &VectorObject) -> Self {
+        let centre = Point::new(warped.x + warped.width / 2.0, warped.y + warped.height / 2.0);
+        Envelope { mesh: mesh.clone(), original, centre, rotation: warped.rotation }
+    }
+
+    /// How `current` has been moved and turned about its centre since the warp.
+    fn moved(&self, current: &VectorObject) -> Affine {
+        let centre = Point::new(current.x + current.width / 2.0, current.y + current.height / 2.0);
+        Affine::translate(centre.to_vec2()) * Affine::rotate(current.rotation - self.rotation) * Affine::translate(-self.centre.to_vec2())
+    }
+
+    /// The mesh following `current` to where it is now. Only the move is
+    /// carried over, as the mesh's source box stays upright.
+    fn current_mesh(&self, current: &VectorObject) -> WarpMesh {
+        let offset = self.moved(current) * self.centre - self.centre;
+        let mut mesh = self.mesh.clone();
+        for p in mesh.control_points.iter_mut() { *p += offset; }
+        mesh.original_bounds.0 += offset.x;
+        mesh.original_bounds.1 += offset.y;
+        mesh
+    }
+
+    /// The undistorted object for `current`, which carries this envelope: the
+    /// original's geometry, moved and turned as `current` has been, with its
+    /// id, name, style and effects taken from `current`.
+    fn released(&self, current: &VectorObject) -> VectorObject {
+        let original = &self.original;
+        let mut out = current.clone();
+        out.envelope = None;
+        out.shape_type = original.shape_type;
+        out.sides = original.sides;
+        out.inner_radius = original.inner_radius;
+        out.corner_radius = original.corner_radius;
+        out.path_data = original.path_data.clone();
+        out.intelligent_type = original.intelligent_type.clone();
+        out.intelligent_params = original.intelligent_params.clone();
+        out.stroke_points = original.stroke_points.clone();
+        out.text_content = original.text_content.clone();
+        out.sx = original.sx;
+        out.sy = original.sy;
+        out.sw = original.sw;
+        out.sh = original.sh;
+        out.raw_image = original.raw_image.clone();
+        out.raw_rgba = original.raw_rgba.clone();
+        out.raw_rgba_width = original.raw_rgba_width;
+        out.raw_rgba_height = original.raw_rgba_height;
+        out.image = original.image.clone();
+        out.children = original.children.clone();
+        out.warp = original.warp.clone();
+        out.corners = original.corners.clone();
+        out.text_path = original.text_path.clone();
+        out.text_runs = original.text_runs.clone();
+        out.paragraphs = original.paragraphs.clone();
+        let centre = self.moved(current) * Point::new(original.x + original.width / 2.0, original.y + original.height / 2.0);
+        out.width = original.width;
+        out.height = original.height;
+        out.x = centre.x - original.width / 2.0;
+        out.y = centre.y - original.height / 2.0;
+        out.rotation = original.rotation + current.rotation - self.rotation;
+        out
+    }
+}
+
+fn line_cubic(a: Point, b: Point) -> CubicBez {
+    CubicBez::new(a, a.lerp(b, 1.0 / 3.0), a.lerp(b, 2.0 / 3.0), b)
 }
 
 impl WarpMesh {
@@ -28,62 +132,409 @@ impl WarpMesh {
             cols,
             control_points,
             original_bounds: (x, y, width, height),
+            handles: Vec::new(),
+            interpolation: String::new(),
         }
     }
 
-    pub fn warp_point(&self, p: Point) -> Point {
-        let (ox, oy, ow, oh) = self.original_bounds;
-        
-        // Normalize coordinates to [0, 1] relative to original mesh
-        let nx = (p.x - ox) / ow;
-        let ny = (p.y - oy) / oh;
+    fn point(&self, r: usize, c: usize) -> Point {
+        self.control_points[r * (self.cols + 1) + c]
+    }
 
-        let c_float = nx * (self.cols as f64);
-        let r_float = ny * (self.rows as f64);
+    fn handle(&self, r: usize, c: usize, dir: usize) -> Vec2 {
+        if let Some(h) = self.handles.get(r * (self.cols + 1) + c) { return h[dir]; }
+        let neighbour = match dir {
+            LEFT if c > 0 => (r, c - 1),
+            RIGHT if c < self.cols => (r, c + 1),
+            UP if r > 0 => (r - 1, c),
+            DOWN if r < self.rows => (r + 1, c),
+            _ => return Vec2::ZERO,
+        };
+        (self.point(neighbour.0, neighbour.1) - self.point(r, c)) / 3.0
+    }
 
-        let c0 = (c_float.floor() as usize).clamp(0, self.cols - 1);
-        let c1 = (c0 + 1).clamp(0, self.cols);
-        let r0 = (r_float.floor() as usize).clamp(0, self.rows - 1);
-        let r1 = (r0 + 1).clamp(0, self.rows);
+    /// Evaluates the Coons patch of cell (r, c). Parameters outside [0, 1]
+    /// extrapolate, so points just outside the mesh still move smoothly.
+    fn patch_point(&self, r: usize, c: usize, u: f64, v: f64) -> Point {
+        let (p00, p10, p01, p11) = (self.point(r, c), self.point(r, c + 1), self.point(r + 1, c), self.point(r + 1, c + 1));
+        let top = CubicBez::new(p00, p00 + self.handle(r, c, RIGHT), p10 + self.handle(r, c + 1, LEFT), p10);
+        let bottom = CubicBez::new(p01, p01 + self.handle(r + 1, c, RIGHT), p11 + self.handle(r + 1, c + 1, LEFT), p11);
+        let left = CubicBez::new(p00, p00 + self.handle(r, c, DOWN), p01 + self.handle(r + 1, c, UP), p01);
+        let right = CubicBez::new(p10, p10 + self.handle(r, c + 1, DOWN), p11 + self.handle(r + 1, c + 1, UP), p11);
+        let ruled_v = top.eval(u).to_vec2() * (1.0 - v) + bottom.eval(u).to_vec2() * v;
+        let ruled_u = left.eval(v).to_vec2() * (1.0 - u) + right.eval(v).to_vec2() * u;
+        let bilinear = p00.to_vec2() * ((1.0 - u) * (1.0 - v)) + p10.to_vec2() * (u * (1.0 - v))
+            + p01.to_vec2() * ((1.0 - u) * v) + p11.to_vec2() * (u * v);
+        (ruled_v + ruled_u - bilinear).to_point()
+    }
 
-        let u = c_float - c0 as f64;
-        let v = r_float - r0 as f64;
+    pub fn warp_point(&self, p: Point) -> Point {
+        let (ox, oy, ow, oh) = self.original_bounds;
+        if self.rows == 0 || self.cols == 0 || ow == 0.0 || oh == 0.0 { return p; }
 
-        let p00 = self.control_points[r0 * (self.cols + 1) + c0];
-        let p10 = self.control_points[r0 * (self.cols + 1) + c1];
-        let p01 = self.control_points[r1 * (self.cols + 1) + c0];
-        let p11 = self.control_points[r1 * (self.cols + 1) + c1];
+        // Normalize coordinates to [0, 1] relative to original mesh
+        let c_float = (p.x - ox) / ow * self.cols as f64;
+        let r_float = (p.y - oy) / oh * self.rows as f64;
+        let c0 = (c_float.floor().max(0.0) as usize).min(self.cols - 1);
+        let r0 = (r_float.floor().max(0.0) as usize).min(self.rows - 1);
+        self.patch_point(r0, c0, c_float - c0 as f64, r_float - r0 as f64)
+    }
 
-        // Bilinear interpolation
-        let top = p00.to_vec2() * (1.0 - u) + p10.to_vec2() * u;
-        let bottom = p01.to_vec2() * (1.0 - u) + p11.to_vec2() * u;
-        let final_p = top * (1.0 - v) + bottom * v;
+    /// Jacobian of the warp at `p`, as the images of the x and y unit vectors.
+    fn jacobian(&self, p: Point) -> (Vec2, Vec2) {
+        let e = 1e-4 * self.original_bounds.2.abs().max(self.original_bounds.3.abs()).max(1.0);
+        let dx = (self.warp_point(p + Vec2::new(e, 0.0)) - self.warp_point(p - Vec2::new(e, 0.0))) / (2.0 * e);
+        let dy = (self.warp_point(p + Vec2::new(0.0, e)) - self.warp_point(p - Vec2::new(0.0, e))) / (2.0 * e);
+        (dx, dy)
+    }
 
-        Point::new(final_p.x, final_p.y)
+    fn warp_cubic(&self, c: CubicBez, depth: u32, out: &mut BezPath) {
+        let map_tangent = |p: Point, d: Vec2| { let (jx, jy) = self.jacobian(p); jx * d.x + jy * d.y };
+        let (w0, w3) = (self.warp_point(c.p0), self.warp_point(c.p3));
+        let fit = CubicBez::new(w0, w0 + map_tangent(c.p0, c.p1 - c.p0), w3 - map_tangent(c.p3, c.p3 - c.p2), w3);
+        let error = [0.25, 0.5, 0.75].iter().map(|&t| fit.eval(t).distance(self.warp_point(c.eval(t)))).fold(0.0, f64::max);
+        if error <= TOLERANCE || depth >= MAX_DEPTH {
+            out.curve_to(fit.p1, fit.p2, fit.p3);
+        } else {
+            let (a, b) = c.subdivide();
+            self.warp_cubic(a, depth + 1, out);
+            self.warp_cubic(b, depth + 1, out);
+        }
     }
 
+    /// Warps a path. Straight segments bend with the envelope, so every segment
+    /// comes out as one or more cubics.
     pub fn warp_path(&self, path: &BezPath) -> BezPath {
         let mut warped = BezPath::new();
+        let (mut start, mut current) = (Point::ZERO, Point::ZERO);
         for el in path.elements() {
-            match el {
-                PathEl::MoveTo(p) => warped.move_to(self.warp_point(*p)),
-                PathEl::LineTo(p) => warped.line_to(self.warp_point(*p)),
-                PathEl::QuadTo(p1, p2) => warped.quad_to(self.warp_point(*p1), self.warp_point(*p2)),
-                PathEl::CurveTo(p1, p2, p3) => warped.curve_to(self.warp_point(*p1), self.warp_point(*p2), self.warp_point(*p3)),
-                PathEl::ClosePath => warped.close_path(),
+            match *el {
+                PathEl::MoveTo(p) => { warped.move_to(self.warp_point(p)); start = p; current = p; }
+                PathEl::LineTo(p) => { self.warp_cubic(line_cubic(current, p), 0, &mut warped); current = p; }
+                PathEl::QuadTo(p1, p2) => { self.warp_cubic(QuadBez::new(current, p1, p2).raise(), 0, &mut warped); current = p2; }
+                PathEl::CurveTo(p1, p2, p3) => { self.warp_cubic(CubicBez::new(current, p1, p2, p3), 0, &mut warped); current = p3; }
+                PathEl::ClosePath => {
+                    // The implicit closing line has to bend too
+                    if current.distance(start) > 1e-9 { self.warp_cubic(line_cubic(current, start), 0, &mut warped); }
+                    warped.close_path();
+                    current = start;
+                }
             }
         }
         warped
     }
 }
 
+// Warp presets are live effects: the object keeps its own geometry and the
+// preset is turned into a mesh over the object's box whenever it is drawn,
+// exported or converted to a path.
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum WarpStyle {
+    Arc,
+    ArcLower,
+    ArcUpper,
+    Arch,
+    Bulge,
+    Flag,
+    Wave,
+    Fish,
+    Rise,
+    Inflate,
+    Squeeze,
+    Twist,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WarpEffect {
+    pub style: WarpStyle,
+    pub bend: f64, // -1.0 to 1.0
+    #[serde(default)]
+    pub horizontal: f64, // Horizontal distortion, -1.0 to 1.0
+    #[serde(default)]
+    pub vertical: f64, // Vertical distortion, -1.0 to 1.0
+    #[serde(default)]
+    pub vertical_orientation: bool, // Bend along the vertical axis instead
+}
+
+impl WarpEffect {
+    /// Maps a point of the unit square, in box coordinates where (0, 0) is the
+    /// top-left corner. `aspect` is the box's height over its width.
+    fn map(&self, u: f64, v: f64, aspect: f64) -> (f64, f64) {
+        let (u, v, aspect) = if self.vertical_orientation { (v, u, 1.0 / aspect) } else { (u, v, aspect) };
+        // Distortions scale one side relative to the other, like a perspective
+        let u1 = 0.5 + (u - 0.5) * (1.0 + self.vertical * (2.0 * v - 1.0)).max(0.0);
+        let v1 = 0.5 + (v - 0.5) * (1.0 + self.horizontal * (2.0 * u - 1.0)).max(0.0);
+        let (x, y) = self.bend_point(u1, v1, aspect);
+        if self.vertical_orientation { (y, x) } else { (x, y) }
+    }
+
+    fn bend_point(&self, u: f64, v: f64, aspect: f64) -> (f64, f64) {
+        let b = self.bend.clamp(-1.0, 1.0);
+        let hump = |t: f64| 1.0 - (2.0 * t - 1.0).powi(2); // 0 at the edges, 1 in the middle
+        let tau = std::f64::consts::TAU;
+        match self.style {
+            WarpStyle::Arc => {
+                let sweep = b * std::f64::consts::PI;
+                if sweep.abs() < 1e-6 { return (u, v); }
+                // Worked out in units of the box width so the arc stays circular
+                let radius = 1.0 / sweep;
+                let r = radius + (0.5 - v) * aspect;
+                let angle = (u - 0.5) * sweep;
+                (0.5 + r * angle.sin(), (0.5 * aspect + radius - r * angle.cos()) / aspect)
+            }
+            WarpStyle::ArcLower => (u, v + b * 0.5 * v * hump(u)),
+            WarpStyle::ArcUpper => (u, v - b * 0.5 * (1.0 - v) * hump(u)),
+            WarpStyle::Arch => (u, v - b * 0.5 * hump(u)),
+            WarpStyle::Bulge => (u, v + b * 0.5 * (2.0 * v - 1.0) * hump(u)),
+            WarpStyle::Flag => (u, v + b * 0.2 * (tau * u).sin()),
+            WarpStyle::Wave => (u, v + b * 0.2 * (tau * u - std::f64::consts::PI * v).sin()),
+            WarpStyle::Fish => (u, 0.5 + (v - 0.5) * (1.0 + b * 0.5 * (tau * u).sin())),
+            WarpStyle::Rise => (u, v - b * 0.5 * (u * u * (3.0 - 2.0 * u) - 0.5)),
+            WarpStyle::Inflate => (0.5 + (u - 0.5) * (1.0 + b * 0.5 * hump(v)), 0.5 + (v - 0.5) * (1.0 + b * 0.5 * hump(u))),
+            WarpStyle::Squeeze => (0.5 + (u - 0.5) * (1.0 - b * 0.5 * hump(v)), 0.5 + (v - 0.5) * (1.0 + b * 0.25 * hump(u))),
+            WarpStyle::Twist => {
+                let (dx, dy) = (u - 0.5, (v - 0.5) * aspect);
+                let falloff = (1.0 - dx.hypot(dy) / 0.5f64.hypot(0.5 * aspect)).max(0.0);
+                let (sin, cos) = (b * std::f64::consts::PI * falloff).sin_cos();
+                (0.5 + dx * cos - dy * sin, 0.5 + (dx * sin + dy * cos) / aspect)
+            }
+        }
+    }
+
+    /// Samples the preset into a mesh over a `width` x `height` box, with the
+    /// handles taken from the preset's derivatives so the patches follow it.
+    pub fn mesh(&self, width: f64, height: f64) -> WarpMesh {
+        let n = PRESET_CELLS;
+        let mut mesh = WarpMesh::new(n, n, 0.0, 0.0, width, height);
+        let step = 1.0 / n as f64;
+        let aspect = height / width;
+        let at = |u: f64, v: f64| { let (x, y) = self.map(u, v, aspect); Point::new(x * width, y * height) };
+        for r in 0..=n {
+            for c in 0..=n {
+                let (u, v) = (c as f64 * step, r as f64 * step);
+                let e = step * 1e-3;
+                let du = (at(u + e, v) - at(u - e, v)) / (2.0 * e) * (step / 3.0);
+                let dv = (at(u, v + e) - at(u, v - e)) / (2.0 * e) * (step / 3.0);
+                mesh.control_points[r * (n + 1) + c] = at(u, v);
+                mesh.handles.push([-du, du, -dv, dv]);
+            }
+        }
+        mesh
+    }
+
+    /// Applies the warp to a path in the local space of a `width` x `height` box.
+    pub fn apply(&self, path: &BezPath, width: f64, height: f64) -> BezPath {
+        if width <= 0.0 || height <= 0.0 { return path.clone(); }
+        self.mesh(width, height).warp_path(path)
+    }
+}
+
+/// Resamples an image object through the mesh into a new, unrotated image
+/// covering the warped bounds. `image` is left for the host to fill from the
+/// new pixels, see `apply_warp_mesh`.
+fn warp_image_object(obj: &VectorObject, mesh: &WarpMesh) -> Option<VectorObject> {
+    let decoded;
+    let (rgba, width, height) = match &obj.raw_rgba {
+        Some(rgba) => (rgba.as_slice(), obj.raw_rgba_width, obj.raw_rgba_height),
+        None => {
+            decoded = image::load_from_memory(obj.raw_image.as_ref()?).ok()?.to_rgba8();
+            (decoded.as_raw().as_slice(), decoded.width(), decoded.height())
+        }
+    };
+    let interpolation = Interpolation::parse(&mesh.interpolation);
+    let warped = warp_image(obj, rgba, width, height, mesh, interpolation)?;
+
+    let mut png = Vec::new();
+    let buffer = image::RgbaImage::from_raw(warped.width, warped.height, warped.rgba.clone())?;
+    image::DynamicImage::ImageRgba8(buffer).write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png).ok()?;
+
+    let mut out = obj.clone();
+    out.x = warped.bounds.x0;
+    out.y = warped.bounds.y0;
+    out.width = warped.bounds.width();
+    out.height = warped.bounds.height();
+    out.rotation = 0.0;
+    out.sx = 0.0;
+    out.sy = 0.0;
+    out.sw = warped.width as f64;
+    out.sh = warped.height as f64;
+    out.raw_rgba_width = warped.width;
+    out.raw_rgba_height = warped.height;
+    out.raw_rgba = Some(warped.rgba);
+    out.raw_image = Some(png);
+    out.image = None;
+    Some(out)
+}
+
+/// Warps an object whose parent space maps into the mesh's space through
+/// `to_mesh`. The result is drawn in the same parent space.
+fn warp_into(obj: &VectorObject, mesh: &WarpMesh, to_mesh: Affine, fonts: &FontRegistry) -> VectorObject {
+    if obj.brush_id > 0 { return obj.clone(); }
+    if obj.shape_type == ShapeType::Group {
+        let mut group = expand_warp(obj, fonts).unwrap_or_else(|| obj.clone());
+        let inner = to_mesh * object_transform(&group);
+        if let Some(children) = group.children.as_mut() {
+            for child in children.iter_mut() { *child = warp_into(child, mesh, inner, fonts); }
+        }
+        fit_group(&mut group);
+        return group;
+    }
+    // Images and other objects without an outline are left as they are
+    let Ok(mut path) = object_path(obj, fonts) else { return obj.clone() };
+    path.apply_affine(to_mesh * object_transform(obj));
+    let mut warped = mesh.warp_path(&path);
+    warped.apply_affine(to_mesh.inverse());
+    let mut out = obj.clone();
+    out.x = 0.0; out.y = 0.0; out.width = 0.0; out.height = 0.0; out.rotation = 0.0;
+    set_local_path(&mut out, warped);
+    out
+}
+
+/// Fits a group's box to its children again, keeping them in place. Children
+/// are stored relative to the box.
+fn fit_group(group: &mut VectorObject) {
+    let transform = object_transform(group);
+    let Some(children) = group.children.as_mut() else { return };
+    let Some(bounds) = children.iter().map(|c| { let (x0, y0, x1, y1) = c.get_world_bounds(); Rect::new(x0, y0, x1, y1) }).reduce(|a, b| a.union(b)) else { return };
+    for c in children.iter_mut() { c.x -= bounds.x0; c.y -= bounds.y0; }
+    let centre = transform * bounds.center();
+    group.width = bounds.width();
+    group.height = bounds.height();
+    group.x = centre.x - bounds.width() / 2.0;
+    group.y = centre.y - bounds.height() / 2.0;
+}
+
+/// The object with its warp effect baked in, keeping its box and transform, or
+/// `None` when it has no warp or its outline can't be built (e.g. text
+/// without a registered font).
+pub fn expand_warp(obj: &VectorObject, fonts: &FontRegistry) -> Option<VectorObject> {
+    let effect = obj.warp.as_ref()?;
+    let mut out = obj.clone();
+    out.warp = None;
+    if obj.shape_type == ShapeType::Group {
+        let mesh = effect.mesh(obj.width, obj.height);
+        if let Some(children) = out.children.as_mut() {
+            for child in children.iter_mut() { *child = warp_into(child, &mesh, Affine::IDENTITY, fonts); }
+        }
+        return Some(out);
+    }
+    if obj.brush_id > 0 { return None; }
+    let path = object_path(obj, fonts).ok()?;
+    out.path_data = path.to_svg();
+    out.shape_type = ShapeType::Path;
+    out.corners = None;
+    out.intelligent_type.clear();
+    out.intelligent_params.clear();
+    out.text_content.clear();
+    Some(out)
+}
+
+/// A live warp baked into a copy of the object for the canvas, along with the
+/// state of the object it was baked from.
+pub(crate) struct WarpedObject {
+    key: String,
+    pub object: Option<VectorObject>,
+}
+
+/// Everything a baked warp depends on. Gradients and images aren't serialized
+/// with objects, so they're added for the object and its children.
+fn warped_key(obj: &VectorObject) -> String {
+    fn unserialized(obj: &VectorObject, out: &mut Vec<serde_json::Value>) {
+        out.push(serde_json::json!([obj.fill_gradient, obj.stroke_gradient, obj.image.is_some(), obj.raw_image.as_ref().map(Vec::len)]));
+        for child in obj.children.iter().flatten() { unserialized(child, out); }
+    }
+    let mut extra = Vec::new();
+    unserialized(obj, &mut extra);
+    serde_json::json!([obj, extra]).to_string()
+}
+
+impl WarpedObject {
+    pub fn new(obj: &VectorObject, fonts: &FontRegistry) -> Self {
+        WarpedObject { key: warped_key(obj), object: expand_warp(obj, fonts) }
+    }
+
+    /// Whether the baked copy still matches the object, which it may not when
+    /// objects were changed outside a command.
+    pub fn is_current(&self, obj: &VectorObject) -> bool {
+        self.key == warped_key(obj)
+    }
+}
+
+fn collect_warps(objects: &[VectorObject], fonts: &FontRegistry, old: &mut HashMap<u32, WarpedObject>, out: &mut HashMap<u32, WarpedObject>) {
+    for obj in objects {
+        if obj.warp.is_some() {
+            let warped = match old.remove(&obj.id) {
+                Some(w) if w.is_current(obj) => w,
+                _ => WarpedObject::new(obj, fonts),
+            };
+            out.insert(obj.id, warped);
+        } else if let Some(children) = &obj.children {
+            collect_warps(children, fonts, old, out);
+        }
+    }
+}
+
+/// Copies of the objects with every warp effect baked in, for exporters that
+/// only understand plain geometry.
+pub fn expand_warps(objects: &[VectorObject], fonts: &FontRegistry) -> Vec<VectorObject> {
+    objects.iter().map(|obj| {
+        if let Some(expanded) = expand_warp(obj, fonts) { return expanded; }
+        let mut out = obj.clone();
+        if let Some(children) = out.children.as_mut() { *children = expand_warps(children, fonts); }
+        out
+    }).collect()
+}
+
+#[wasm_bindgen]
+impl VectorEngine {
+    /// Sets a warp preset on objects, replacing any previous one.
+    pub(crate) fn set_warp(&mut self, ids: &[u32], params: &serde_json::Value) -> String {
+        let effect: WarpEffect = match serde_json::from_value(params.clone()) {
+            Ok(e) => e,
+            Err(e) => return serde_json::json!({ "error": format!("Invalid warp: {}", e) }).to_string(),
+        };
+        let mut changed = Vec::new();
+        // Images have no outline for a preset to bend; they take a warp mesh
+        for obj in self.objects.iter_mut().filter(|o| ids.contains(&o.id) && o.brush_id == 0 && o.shape_type != ShapeType::Image) {
+            obj.warp = Some(effect.clone());
+            changed.push(obj.id);
+        }
+        if changed.is_empty() { return "{ \"error\": \"Nothing to warp\" }".to_string(); }
+        serde_json::json!({ "success": true, "ids": changed }).to_string()
+    }
+
+    pub(crate) fn remove_warp(&mut self, ids: &[u32]) -> String {
+        let mut changed = Vec::new();
+        for obj in self.objects.iter_mut().filter(|o| ids.contains(&o.id)) {
+            if obj.warp.take().is_some() { changed.push(obj.id); }
+        }
+        serde_json::json!({ "success": true, "ids": changed }).to_string()
+    }
+
+    /// Bakes the live warps of objects changed since the last refresh.
+    pub(crate) fn refresh_warps(&mut self) {
+        let mut old = std::mem::take(&mut self.warped_objects);
+        collect_warps(&self.objects, &self.fonts, &mut old, &mut self.warped_objects);
+    }
+}
+
 #[wasm_bindgen]
 impl VectorEngine {
+    /// Creates a mesh around the selection. A single object that already has an
+    /// envelope gets its current mesh back so it can be edited.
     pub fn create_warp_mesh(&self, rows: usize, cols: usize) -> JsValue {
         if self.selected_ids.is_empty() {
             return JsValue::NULL;
         }
 
+        if let [id] = self.selected_ids[..] {
+            if let Some(obj) = self.objects.iter().find(|o| o.id == id) {
+                if let Some(env) = &obj.envelope { return serde_wasm_bindgen::to_value(&env.current_mesh(obj)).unwrap(); }
+            }
+        }
+
         let mut min_x = f64::INFINITY;
         let mut min_y = f64::INFINITY;
         let mut max_x = f64::NEG_INFINITY;
@@ -109,71 +560,110 @@ impl VectorEngine {
         serde_wasm_bindgen::to_value(&mesh).unwrap()
     }
 
-    pub fn apply_warp_mesh(&mut self, mesh_js: JsValue, save_undo: bool) {
+    /// Warps the selection through the mesh. Images are resampled into new
+    /// image objects above their source (or in place when re-warping one);
+    /// their ids and `image_data_url`s are returned so the host can load them
+    /// with `set_image_object`, as it does for imported images.
+    pub fn apply_warp_mesh(&mut self, mesh_js: JsValue, save_undo: bool) -> String {
         let mesh: WarpMesh = match serde_wasm_bindgen::from_value(mesh_js) {
             Ok(m) => m,
-            Err(_) => return,
+            Err(_) => return "{ \"error\": \"Invalid mesh\" }".to_string(),
         };
 
         if save_undo {
             self.save_state("Apply Warp Mesh");
         }
 
+        let mut images = Vec::new();
         let selected_ids = self.selected_ids.clone();
         for id in selected_ids {
             if let Some(obj_idx) = self.objects.iter().position(|o| o.id == id) {
-                let mut obj = self.objects[obj_idx].clone();
-                
-                // Convert object to path if it's not one
-                let mut path = match obj.shape_type {
-                    ShapeType::Path => BezPath::from_svg(&obj.path_data).unwrap_or_default(),
-                    ShapeType::Rectangle => {
-                        let rect = kurbo::Rect::new(0.0, 0.0, obj.width, obj.height);
-                        rect.to_path(0.1)
-                    },
-                    ShapeType::Circle | ShapeType::Ellipse => {
-                        let ellipse = kurbo::Ellipse::new((obj.width / 2.0, obj.height / 2.0), (obj.width / 2.0, obj.height / 2.0), 0.0);
-                        ellipse.to_path(0.1)
-                    },
-                    _ => continue, // Skip for now
-                };
-
-                // Transform path to world coordinates
-                let transform = Affine::translate((obj.x + obj.width / 2.0, obj.y + obj.height / 2.0))
-                    * Affine::rotate(obj.rotation)
-                    * Affine::translate((-obj.width / 2.0, -obj.height / 2.0));
-                
-                path.apply_affine(transform);
-
-                // Warp the path
+                // Re-warping an envelope starts again from the undistorted object
+                let current = &self.objects[obj_idx];
+                let rewarp = current.envelope.is_some();
+                let source = current.envelope.as_ref().map(|e| e.released(current)).unwrap_or_else(|| current.clone());
+
+                if source.shape_type == ShapeType::Image {
+                    let Some(mut obj) = warp_image_object(&source, &mesh) else { continue };
+                    let png = general_purpose::STANDARD.encode(obj.raw_image.as_deref().unwrap_or_default());
+                    // The envelope keeps the source image's id, so releasing the
+                    // copy can hand back the source when it's still there
+                    let mut original = source;
+                    if let Some(env) = &self.objects[obj_idx].envelope { original.id = env.original.id; }
+                    if !rewarp {
+                        obj.id = self.next_id; self.next_id += 1;
+                        obj.name = format!("{} (Warped)", obj.name);
+                    }
+                    images.push(serde_json::json!({ "id": obj.id, "image_data_url": format!("data:image/png;base64,{}", png) }));
+                    obj.envelope = Some(Box::new(Envelope::new(&mesh, original, &obj)));
+                    if rewarp {
+                        self.objects[obj_idx] = obj;
+                    } else {
+                        // The warped copy takes the source's place in the selection
+                        for s in self.selected_ids.iter_mut().filter(|s| **s == id) { *s = obj.id; }
+                        self.objects.insert(obj_idx + 1, obj);
+                    }
+                    continue;
+                }
+
+                // Groups warp child by child so each keeps its own style
+                if source.shape_type == ShapeType::Group {
+                    let mut obj = warp_into(&source, &mesh, Affine::IDENTITY, &self.fonts);
+                    obj.envelope = Some(Box::new(Envelope::new(&mesh, source, &obj)));
+                    self.objects[obj_idx] = obj;
+                    continue;
+                }
+                let Ok(path) = world_path(&source, &self.fonts) else { continue };
                 let warped_path = mesh.warp_path(&path);
+                let bbox = warped_path.bounding_box();
+
+                let mut obj = source.clone();
+                obj.x = 0.0; obj.y = 0.0; obj.width = 0.0; obj.height = 0.0; obj.rotation = 0.0;
+                set_local_path(&mut obj, warped_path);
 
                 // Warp stroke points if it's a brush stroke
                 if obj.brush_id > 0 && !obj.stroke_points.is_empty() {
+                    let transform = object_transform(&source);
                     for sp in obj.stroke_points.iter_mut() {
-                        let p_world = transform * Point::new(sp.x, sp.y);
-                        let p_warped = mesh.warp_point(p_world);
-                        let p_local = Affine::translate((-warped_path.bounding_box().x0, -warped_path.bounding_box().y0)) * p_warped;
+                        let p_warped = mesh.warp_point(transform * Point::new(sp.x, sp.y));
+                        let p_local = Affine::translate((-bbox.x0, -bbox.y0)) * p_warped;
                         sp.x = p_local.x;
                         sp.y = p_local.y;
                     }
                 }
 
-                // Update object
-                let bbox = warped_path.bounding_box();
-                obj.x = bbox.x0;
-                obj.y = bbox.y0;
-                obj.width = bbox.width();
-                obj.height = bbox.height();
-                obj.rotation = 0.0;
-                obj.shape_type = ShapeType::Path;
-
-                let mut normalized = warped_path.clone();
-                normalized.apply_affine(Affine::translate((-bbox.x0, -bbox.y0)));
-                obj.path_data = normalized.to_svg();
-
+                obj.envelope = Some(Box::new(Envelope::new(&mesh, source, &obj)));
                 self.objects[obj_idx] = obj;
             }
         }
+        serde_json::json!({ "success": true, "images": images }).to_string()
+    }
+
+    /// Removes the envelope from objects, restoring their undistorted geometry.
+    /// A warped copy of an image whose source is still there is deleted and
+    /// the source takes its place in the selection.
+    pub(crate) fn release_envelope(&mut self, ids: &[u32]) -> String {
+        let existing: HashSet<u32> = self.objects.iter().map(|o| o.id).collect();
+        let mut released = Vec::new();
+        let mut copies = Vec::new();
+        for obj in self.objects.iter_mut().filter(|o| ids.contains(&o.id)) {
+            let Some(env) = &obj.envelope else { continue };
+            if env.original.id != obj.id && existing.contains(&env.original.id) {
+                copies.push((obj.id, env.original.id));
+                released.push(env.original.id);
+                continue;
+            }
+            *obj = env.released(obj);
+            released.push(obj.id);
+        }
+        for (copy, source) in copies {
+            self.objects.retain(|o| o.id != copy);
+            if self.selected_ids.contains(&copy) {
+                self.selected_ids.retain(|s| *s != copy && *s != source);
+                self.selected_ids.push(source);
+            }
+        }
+        if released.is_empty() { return "{ \"error\": \"No envelope to release\" }".to_string(); }
+        serde_json::json!({ "success": true, "ids": released }).to_string()
     }
 }
@@ -0,0 +1,323 @@
+use wasm_bindgen::prelude::*;
+use serde::{Serialize, Deserialize};
+use kurbo::{Arc, BezPath, ParamCurve, ParamCurveArclen, PathEl, PathSeg, Point, Vec2};
+use crate::engine::VectorEngine;
+use crate::measure::tangent;
+use crate::path_ops::{contours, contours_to_path};
+use crate::types::ShapeType;
+
+// Variable-width strokes. A profile scales `stroke_width` along each subpath,
+// the way pressure scales a brush dab. Canvas and the exporters have no
+// notion of this, so a profiled stroke is expanded into a filled outline
+// before it is drawn or written out (see stroke.rs).
+
+const ACCURACY: f64 = 1e-3;
+const SAMPLE_STEP: f64 = 4.0; // Arc length between width samples on curves
+const MAX_SAMPLES: usize = 200; // Per segment
+pub(crate) const CORNER_ANGLE: f64 = 0.02; // Radians of tangent change treated as a corner
+const MITER_LIMIT: f64 = 10.0;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct WidthStop {
+    pub offset: f64, // 0..1 along the subpath's length
+    pub width: f64,  // Multiple of stroke_width, like StrokePoint::pressure
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct WidthProfile {
+    pub stops: Vec<WidthStop>,
+}
+
+impl WidthProfile {
+    pub fn new(mut stops: Vec<WidthStop>) -> Self {
+        for s in stops.iter_mut() { s.offset = s.offset.clamp(0.0, 1.0); s.width = s.width.max(0.0); }
+        stops.sort_by(|a, b| a.offset.total_cmp(&b.offset));
+        WidthProfile { stops }
+    }
+
+    /// Named profiles.
+    pub fn preset(name: &str) -> Option<Self> {
+        let sine = |f: fn(f64) -> f64| (0..=8).map(|i| {
+            let offset = i as f64 / 8.0;
+            WidthStop { offset, width: f((offset * std::f64::consts::PI).sin()) }
+        }).collect::<Vec<_>>();
+        let stops = match name {
+            "uniform" => vec![WidthStop { offset: 0.0, width: 1.0 }, WidthStop { offset: 1.0, width: 1.0 }],
+            "taper" | "taper_end" => vec![WidthStop { offset: 0.0, width: 1.0 }, WidthStop { offset: 1.0, width: 0.0 }],
+            "taper_start" => vec![WidthStop { offset: 0.0, width: 0.0 }, WidthStop { offset: 1.0, width: 1.0 }],
+            "taper_both" => vec![
+                WidthStop { offset: 0.0, width: 0.0 }, WidthStop { offset: 0.2, width: 1.0 },
+                WidthStop { offset: 0.8, width: 1.0 }, WidthStop { offset: 1.0, width: 0.0 },
+            ],
+            "bulge" => sine(|s| 0.25 + 0.75 * s),
+            "pinch" => sine(|s| 1.0 - 0.75 * s),
+            _ => return None,
+        };
+        Some(WidthProfile::new(stops))
+    }
+
+    /// Width multiple at a fraction of the length, linear between stops.
+    pub fn at(&self, f: f64) -> f64 {
+        let (Some(first), Some(last)) = (self.stops.first(), self.stops.last()) else { return 1.0 };
+        if f <= first.offset { return first.width; }
+        if f >= last.offset { return last.width; }
+        for w in self.stops.windows(2) {
+            if f <= w[1].offset {
+                let span = w[1].offset - w[0].offset;
+                if span <= 0.0 { return w[1].width; }
+                return w[0].width + (w[1].width - w[0].width) * (f - w[0].offset) / span;
+            }
+        }
+        last.width
+    }
+}
+
+/// A point on the centre line with its unit tangent and half the stroke width.
+#[derive(Clone, Copy)]
+struct Station {
+    p: Point,
+    t: Vec2,
+    h: f64,
+}
+
+impl Station {
+    fn offset(&self) -> Point {
+        self.p + Vec2::new(-self.t.y, self.t.x) * self.h
+    }
+
+    fn reversed(self) -> Self {
+        Station { t: -self.t, ..self }
+    }
+}
+
+/// Stations along one subpath, split into smooth runs. Consecutive runs meet
+/// at a corner; in a closed subpath the last run also meets the first, unless
+/// the subpath has no corners at all and the flag comes back set.
+fn runs(segs: &[PathSeg], closed: bool, half: f64, profile: &WidthProfile) -> (Vec<Vec<Station>>, bool) {
+    let lengths: Vec<f64> = segs.iter().map(|s| s.arclen(ACCURACY)).collect();
+    let total: f64 = lengths.iter().sum();
+    if total <= 1e-9 { return (Vec::new(), false); }
+    let mut runs: Vec<Vec<Station>> = Vec::new();
+    let mut start = 0.0;
+    let mut prev_t: Option<Vec2> = None;
+    for (seg, &len) in segs.iter().zip(&lengths) {
+        if len <= 1e-9 { continue; }
+        // Lines only need samples where the profile bends
+        let mut dists: Vec<f64> = if let PathSeg::Line(_) = seg {
+            let mut d = vec![0.0, len];
+            d.extend(profile.stops.iter().map(|s| s.offset * total - start).filter(|&d| d > 1e-6 && d < len - 1e-6));
+            d.sort_by(f64::total_cmp);
+            d
+        } else {
+            let n = ((len / SAMPLE_STEP).ceil() as usize).clamp(2, MAX_SAMPLES);
+            (0..=n).map(|i| len * i as f64 / n as f64).collect()
+        };
+        let t0 = tangent(seg, 0.0);
+        let corner = prev_t.is_none_or(|pt| pt.cross(t0).atan2(pt.dot(t0)).abs() > CORNER_ANGLE);
+        if corner { runs.push(Vec::new()); } else { dists.remove(0); }
+        let run = runs.last_mut().unwrap();
+        for d in dists {
+            let t = if d <= 0.0 { 0.0 } else if d >= len { 1.0 } else { seg.inv_arclen(d, ACCURACY) };
+            run.push(Station { p: seg.eval(t), t: tangent(seg, t), h: half * profile.at((start + d) / total) });
+        }
+        prev_t = Some(tangent(seg, 1.0));
+        start += len;
+    }
+    // A closed subpath that comes round smoothly starts its first run mid-way
+    // through the last one
+    if closed {
+        let (first, last) = (runs[0][0].t, runs.last().unwrap().last().unwrap().t);
+        if last.cross(first).atan2(last.dot(first)).abs() <= CORNER_ANGLE {
+            if runs.len() == 1 { return (runs, true); }
+            let head = runs.remove(0);
+            runs.last_mut().unwrap().extend(head.into_iter().skip(1));
+        }
+    }
+    (runs, false)
+}
+
+fn reverse_runs(runs: &[Vec<Station>]) -> Vec<Vec<Station>> {
+    runs.iter().rev().map(|r| r.iter().rev().map(|s| s.reversed()).collect()).collect()
+}
+
+/// Smooth curve through the offset points, as Catmull-Rom style cubics.
+/// Stretches of straight centre line stay straight.
+fn curve_through(out: &mut BezPath, run: &[Station], cyclic: bool) {
+    let q: Vec<Point> = run.iter().map(Station::offset).collect();
+    let n = q.len();
+    let slope = |i: usize| -> Vec2 {
+        if cyclic { return (q[(i + 1) % n] - q[(i + n - 1) % n]) / 2.0; }
+        if i == 0 { q[1] - q[0] } else if i == n - 1 { q[n - 1] - q[n - 2] } else { (q[i + 1] - q[i - 1]) / 2.0 }
+    };
+    let count = if cyclic { n } else { n - 1 };
+    for i in 0..count {
+        let j = (i + 1) % n;
+        let (a, b) = (&run[i], &run[j]);
+        if a.t.dot(b.t) > 1.0 - 1e-12 && a.t.cross(b.p - a.p).abs() < 1e-9 { out.line_to(q[j]); continue; }
+        out.curve_to(q[i] + slope(i) / 3.0, q[j] - slope(j) / 3.0, q[j]);
+    }
+}
+
+/// Where the offset lines through `a` (along `tin`) and `b` (along `tout`) meet.
+fn meet(a: Point, tin: Vec2, b: Point, tout: Vec2) -> Option<Point> {
+    let denom = tin.cross(tout);
+    if denom.abs() < 1e-9 { return None; }
+    Some(a + tin * ((b - a).cross(tout) / denom))
+}
+
+/// Joins two offset runs at a corner. With `fold` the inner side runs back out
+/// to the far offset, which nonzero filling hides; without it both sides are
+/// cut back to where the offsets cross.
+fn join(out: &mut BezPath, end: &Station, start: &Station, style: &str, fold: bool) {
+    let (a, b, c, h) = (end.offset(), start.offset(), end.p, end.h.max(start.h));
+    if h <= 1e-9 || a.distance(b) < 1e-9 { out.line_to(b); return; }
+    let inner = (b - a).dot(end.t) < 0.0;
+    let corner = meet(a, end.t, b, start.t).filter(|m| m.distance(c) <= h * MITER_LIMIT);
+    match (inner, style) {
+        // The inner side folds back to where the offsets cross
+        (true, _) | (false, "miter") => {
+            match corner {
+                // Both runs are cut back to the crossing
+                Some(m) if inner && !fold => {
+                    if let Some(PathEl::LineTo(p) | PathEl::QuadTo(_, p) | PathEl::CurveTo(_, _, p)) = out.elements_mut().last_mut() { *p = m; } else { out.line_to(m); }
+                    return;
+                }
+                Some(m) => out.line_to(m),
+                None => {}
+            }
+            out.line_to(b);
+        }
+        (false, "round") => {
+            let (va, vb) = (a - c, b - c);
+            let arc = Arc { center: c, radii: Vec2::new(va.hypot(), va.hypot()), start_angle: va.atan2(), sweep_angle: va.cross(vb).atan2(va.dot(vb)), x_rotation: 0.0 };
+            arc.to_cubic_beziers(0.1, |p1, p2, p| out.curve_to(p1, p2, p));
+        }
+        _ => { out.line_to(b); }
+    }
+}
+
+fn current_point(path: &BezPath) -> Option<Point> {
+    match path.elements().last()? {
+        PathEl::MoveTo(p) | PathEl::LineTo(p) | PathEl::QuadTo(_, p) | PathEl::CurveTo(_, _, p) => Some(*p),
+        PathEl::ClosePath => None,
+    }
+}
+
+/// Follows the left-hand offset of the runs. The far side of an open subpath
+/// continues from its end cap instead of starting a new subpath.
+fn side(out: &mut BezPath, runs: &[Vec<Station>], closed: bool, cyclic: bool, style: &str, first: bool, fold: bool) {
+    let begin = out.elements().len();
+    for (k, run) in runs.iter().enumerate() {
+        let run = if cyclic { &run[..run.len() - 1] } else { &run[..] };
+        let q0 = run[0].offset();
+        if k == 0 {
+            if first { out.move_to(q0); } else if current_point(out).is_none_or(|p| p.distance(q0) > 1e-9) { out.line_to(q0); }
+        } else {
+            join(out, runs[k - 1].last().unwrap(), &run[0], style, fold);
+        }
+        if run.len() > 1 { curve_through(out, run, cyclic); }
+    }
+    if closed && !cyclic {
+        join(out, runs.last().unwrap().last().unwrap(), &runs[0][0], style, fold);
+        // An unfolded ring starts where its last corner was cut off
+        if !fold && first {
+            if let Some(p) = current_point(out) { out.elements_mut()[begin] = PathEl::MoveTo(p); }
+        }
+    }
+}
+
+/// Goes round the end of an open subpath, stopping short of the far side;
+/// the next line drawn closes the cap.
+fn cap(out: &mut BezPath, end: &Station, style: &str) {
+    match style {
+        "round" if end.h > 1e-9 => {
+            let v = end.offset() - end.p;
+            let arc = Arc { center: end.p, radii: Vec2::new(end.h, end.h), start_angle: v.atan2(), sweep_angle: -std::f64::consts::PI, x_rotation: 0.0 };
+            arc.to_cubic_beziers(0.1, |p1, p2, p| out.curve_to(p1, p2, p));
+        }
+        "square" => {
+            let ext = end.t * end.h;
+            out.line_to(end.offset() + ext);
+            out.line_to(end.reversed().offset() + ext);
+        }
+        _ => {}
+    }
+}
+
+/// Filled outline of a path stroked with a varying width.
+pub fn outline(path: &BezPath, width: f64, profile: &WidthProfile, cap_style: &str, join_style: &str) -> BezPath {
+    let mut out = BezPath::new();
+    for contour in contours(path) {
+        let (fwd, cyclic) = runs(&contour.segs, contour.closed, width / 2.0, profile);
+        if fwd.is_empty() { continue; }
+        let back = reverse_runs(&fwd);
+        if contour.closed {
+            // Two rings of opposite direction; nonzero filling leaves the band
+            side(&mut out, &fwd, true, cyclic, join_style, true, true);
+            out.close_path();
+            side(&mut out, &back, true, cyclic, join_style, true, true);
+            out.close_path();
+        } else {
+            side(&mut out, &fwd, false, false, join_style, true, true);
+            cap(&mut out, fwd.last().unwrap().last().unwrap(), cap_style);
+            side(&mut out, &back, false, false, join_style, false, true);
+            cap(&mut out, back.last().unwrap().last().unwrap(), cap_style);
+            out.close_path();
+        }
+    }
+    out
+}
+
+/// Closed subpaths moved sideways by `distance`, to the left of their
+/// direction when positive, keeping their direction. Open subpaths are left
+/// as they are.
+pub fn offset_path(path: &BezPath, distance: f64, join_style: &str) -> BezPath {
+    let mut out = Vec::new();
+    for contour in contours(path) {
+        if !contour.closed || distance.abs() <= 1e-9 { out.push(contour); continue; }
+        let (fwd, cyclic) = runs(&contour.segs, true, distance.abs(), &WidthProfile::default());
+        if fwd.is_empty() { continue; }
+        let mut ring = BezPath::new();
+        if distance > 0.0 {
+            side(&mut ring, &fwd, true, cyclic, join_style, true, false);
+            ring.close_path();
+            out.extend(contours(&ring));
+        } else {
+            side(&mut ring, &reverse_runs(&fwd), true, cyclic, join_style, true, false);
+            ring.close_path();
+            out.extend(contours(&ring).into_iter().map(|mut c| { c.reverse(); c }));
+        }
+    }
+    contours_to_path(&out)
+}
+
+#[wasm_bindgen]
+impl VectorEngine {
+    /// Sets a width profile from a preset name or explicit stops. The
+    /// `none` preset (or no stops) goes back to a uniform stroke.
+    pub(crate) fn set_width_profile(&mut self, ids: &[u32], params: &serde_json::Value) -> String {
+        let stops: Option<Vec<WidthStop>> = match params.get("stops") {
+            Some(v) if !v.is_null() => match serde_json::from_value(v.clone()) {
+                Ok(s) => Some(s),
+                Err(e) => return serde_json::json!({ "error": format!("Invalid width stops: {}", e) }).to_string(),
+            },
+            _ => None,
+        };
+        let preset = params["preset"].as_str().unwrap_or("none");
+        let profile = match (stops, preset) {
+            (Some(s), _) if !s.is_empty() => Some(WidthProfile::new(s)),
+            (_, "none") => None,
+            _ => match WidthProfile::preset(preset) {
+                Some(p) => Some(p),
+                None => return serde_json::json!({ "error": format!("Unknown width profile {}", preset) }).to_string(),
+            },
+        };
+        let mut changed = Vec::new();
+        for obj in self.objects.iter_mut().filter(|o| ids.contains(&o.id) && o.shape_type == ShapeType::Path && o.brush_id == 0) {
+            obj.width_profile = profile.clone();
+            changed.push(obj.id);
+        }
+        if changed.is_empty() { return "{ \"error\": \"Width profiles apply to paths\" }".to_string(); }
+        serde_json::json!({ "success": true, "ids": changed }).to_string()
+    }
+}